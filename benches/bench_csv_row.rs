@@ -0,0 +1,81 @@
+use corken::*;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::Rng;
+
+const TXS: usize = 1_000_000;
+
+fn generate_rows() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+
+    (0..TXS)
+        .map(|tx| {
+            let client: u16 = rng.gen_range(0..1024);
+            match rng.gen_range(0..=4) {
+                0 => format!("deposit,{},{},{:.4}", client, tx, rng.gen::<f64>()),
+                1 => format!("withdrawal,{},{},{:.4}", client, tx, rng.gen::<f64>()),
+                2 => format!("dispute,{},{},", client, rng.gen_range(0..=tx)),
+                3 => format!("resolve,{},{},", client, rng.gen_range(0..=tx)),
+                _ => format!("chargeback,{},{},", client, rng.gen_range(0..=tx)),
+            }
+        })
+        .collect()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    println!("Generating {} rows", TXS);
+
+    let rows = generate_rows();
+    let file = format!("type,client,tx,amount\n{}\n", rows.join("\n"));
+
+    let mut group = c.benchmark_group("csv_ingestion");
+    group.sample_size(10);
+
+    group.bench_function("execute_csv_row", |b| {
+        b.iter_batched(
+            State::default,
+            |mut state| {
+                for row in rows.iter() {
+                    let _ = state.execute_csv_row(row);
+                }
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("csv_reader", |b| {
+        b.iter(|| {
+            let rdr = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(std::io::BufReader::new(file.as_bytes()));
+
+            State::from_iterator(rdr.into_deserialize().filter_map(Result::ok))
+        })
+    });
+
+    #[cfg(feature = "mmap")]
+    {
+        use std::io::Write;
+
+        let mut tmp = tempfile::NamedTempFile::new().unwrap();
+        tmp.write_all(file.as_bytes()).unwrap();
+
+        group.bench_function("buffered_file", |b| {
+            b.iter(|| {
+                let rdr = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(
+                    std::io::BufReader::new(std::fs::File::open(tmp.path()).unwrap()),
+                );
+
+                State::from_iterator(rdr.into_deserialize().filter_map(Result::ok))
+            })
+        });
+
+        group.bench_function("mmap_file", |b| {
+            b.iter(|| State::from_mmap_csv(tmp.path()).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,26 +1,128 @@
+use corken::builder::StateBuilder;
+use corken::money::Money;
 use corken::*;
 use criterion::{criterion_group, criterion_main, Criterion};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_decimal::Decimal;
+use std::hash::BuildHasher;
 
-fn single_engine(mut state: State, txs: Vec<Transaction>) {
+fn single_engine<H: BuildHasher, A: Money>(mut state: State<H, A>, txs: Vec<Transaction<A>>) {
     for tx in txs {
         let _ = state.execute(tx);
     }
 }
 
 fn criterion_benchmark(c: &mut Criterion) {
-    let mut rng = rand::thread_rng();
     println!("Generating {} txs", 1_000_000);
 
-    let txs: Vec<Transaction> = (0..1_000_000).map(|_| rng.gen()).collect();
+    let txs: Vec<Transaction> = StdRng::seed_from_u64(0)
+        .sample_iter(rand::distributions::Standard)
+        .take(1_000_000)
+        .collect();
+    let decimal_txs: Vec<Transaction<Decimal>> = StdRng::seed_from_u64(0)
+        .sample_iter(rand::distributions::Standard)
+        .take(1_000_000)
+        .collect();
 
-    c.bench_function("bench_single_engine", move |b| {
+    let mut group = c.benchmark_group("bench_single_engine");
+
+    group.bench_function("ahash", |b| {
         b.iter_batched(
             || (txs.clone(), State::default()),
             |(txs, state)| single_engine(state, txs),
             criterion::BatchSize::SmallInput,
         )
     });
+
+    group.bench_function("std", |b| {
+        b.iter_batched(
+            || {
+                (
+                    txs.clone(),
+                    State::new_with_hasher(std::collections::hash_map::RandomState::new()),
+                )
+            },
+            |(txs, state)| single_engine(state, txs),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("fnv", |b| {
+        b.iter_batched(
+            || {
+                (
+                    txs.clone(),
+                    State::new_with_hasher(fnv::FnvBuildHasher::default()),
+                )
+            },
+            |(txs, state)| single_engine(state, txs),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("decimal", |b| {
+        b.iter_batched(
+            || {
+                (
+                    decimal_txs.clone(),
+                    StateBuilder::<Decimal>::default().build().unwrap(),
+                )
+            },
+            |(txs, state)| single_engine(state, txs),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+
+    let mut group = c.benchmark_group("bench_execute_silent");
+
+    group.bench_function("execute", |b| {
+        b.iter_batched(
+            || (txs.clone(), State::default()),
+            |(txs, mut state)| {
+                for tx in txs {
+                    let _ = state.execute(tx);
+                }
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("execute_silent", |b| {
+        b.iter_batched(
+            || (txs.clone(), State::default()),
+            |(txs, mut state)| {
+                for tx in txs {
+                    state.execute_silent(tx);
+                }
+            },
+            criterion::BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+
+    let mut group = c.benchmark_group("bench_from_iterator");
+
+    group.bench_function("ungrouped", |b| {
+        b.iter_batched(
+            || txs.clone(),
+            |txs| State::from_iterator(txs.into_iter()),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("grouped", |b| {
+        b.iter_batched(
+            || txs.clone(),
+            |txs| State::from_iterator_grouped(txs.into_iter()),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,24 @@
+fn main() {
+    // NOTE: The schema is compiled with protox, so building the `proto` feature doesnt require
+    // protoc to be installed.
+    #[cfg(feature = "proto")]
+    {
+        println!("cargo:rerun-if-changed=proto/corken.proto");
+
+        let descriptors = protox::compile(["proto/corken.proto"], ["proto"])
+            .expect("failed to compile proto/corken.proto");
+
+        // NOTE: The `Engine` service is only generated along with the gRPC server and client. The
+        // `connect` constructor of the client relies on the 2021 prelude, so clients are built
+        // out of a `Channel` instead.
+        #[cfg(feature = "grpc")]
+        tonic_build::configure()
+            .build_transport(false)
+            .compile_fds(descriptors)
+            .expect("failed to generate the protobuf types");
+        #[cfg(not(feature = "grpc"))]
+        prost_build::Config::new()
+            .compile_fds(descriptors)
+            .expect("failed to generate the protobuf types");
+    }
+}
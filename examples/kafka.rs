@@ -0,0 +1,145 @@
+//! Example consuming CSV encoded transactions from a Kafka topic, one transaction per message in
+//! the standard `type, client, tx, amount` column order.
+//!
+//! Offsets are commited only once the transactions have been executed, so messages are delivered
+//! at least once and the state machine runs in idempotent mode to reject replayed deposits and
+//! withdrawals. Run with:
+//!
+//! `cargo run --example kafka --features kafka -- localhost:9092 transactions`
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::env;
+use std::time::Duration;
+
+use corken::driver::{run_engine, AsyncTransactionSource, Batch, CommitPolicy};
+use corken::error::SourceError;
+use corken::{State, Transaction};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::message::Message;
+use rdkafka::{Offset, TopicPartitionList};
+
+/// Struct represents a source reading transactions from a single Kafka topic.
+struct KafkaSource {
+    consumer: StreamConsumer,
+    topic: String,
+    /// The next offset to be read of every partition a message was received from.
+    positions: BTreeMap<i32, i64>,
+    max_batch: usize,
+}
+
+impl KafkaSource {
+    fn new(brokers: &str, topic: &str) -> Result<Self, KafkaError> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("group.id", "corken")
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()?;
+        consumer.subscribe(&[topic])?;
+
+        Ok(Self {
+            consumer,
+            topic: topic.to_string(),
+            positions: BTreeMap::new(),
+            max_batch: 512,
+        })
+    }
+}
+
+/// Function will parse the payload of a message as a single CSV row.
+fn parse(payload: &[u8]) -> Option<Transaction> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(payload);
+
+    let mut record = csv::StringRecord::new();
+    match rdr.read_record(&mut record) {
+        Ok(true) => Transaction::try_from(&record).ok(),
+        _ => None,
+    }
+}
+
+impl AsyncTransactionSource for KafkaSource {
+    // NOTE: Messages are only ordered within a partition, so the offset of a message records the
+    // position of every partition up to and including it.
+    type Offset = BTreeMap<i32, i64>;
+
+    async fn next_batch(&mut self) -> Result<Batch<Self::Offset>, SourceError> {
+        let mut batch = Vec::new();
+
+        while batch.len() < self.max_batch {
+            // Wait for the first message of the batch, then take whatever else is already
+            // available.
+            let message = if batch.is_empty() {
+                self.consumer.recv().await
+            } else {
+                match tokio::time::timeout(Duration::from_millis(10), self.consumer.recv()).await {
+                    Ok(message) => message,
+                    Err(_) => break,
+                }
+            };
+            let message = message.map_err(|e| SourceError::Io(e.to_string()))?;
+
+            self.positions
+                .insert(message.partition(), message.offset() + 1);
+            match message.payload().and_then(parse) {
+                Some(tx) => batch.push((self.positions.clone(), tx)),
+                // Malformed messages are skipped, they are commited along with the next
+                // transaction of the partition.
+                None => eprintln!(
+                    "Skipped malformed message {}:{}",
+                    message.partition(),
+                    message.offset()
+                ),
+            }
+        }
+
+        Ok(batch)
+    }
+
+    async fn commit(&mut self, offset: Self::Offset) -> Result<(), SourceError> {
+        let mut list = TopicPartitionList::new();
+        for (partition, next) in offset {
+            list.add_partition_offset(&self.topic, partition, Offset::Offset(next))
+                .map_err(|e| SourceError::Io(e.to_string()))?;
+        }
+
+        self.consumer
+            .commit(&list, CommitMode::Sync)
+            .map_err(|e| SourceError::Io(e.to_string()))
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let mut args = env::args().skip(1);
+    let brokers = args.next().unwrap_or_else(|| "localhost:9092".into());
+    let topic = args.next().unwrap_or_else(|| "transactions".into());
+
+    let mut source = KafkaSource::new(&brokers, &topic).expect("Failed to create consumer");
+    let mut state = State::builder()
+        .idempotent(true)
+        .build()
+        .expect("Invalid configuration");
+
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    match run_engine(&mut source, &mut state, CommitPolicy::EveryBatch, shutdown).await {
+        Ok(summary) => eprintln!(
+            "Executed {} transactions, rejected {}",
+            summary.executed, summary.rejected
+        ),
+        Err(e) => eprintln!("{}", e),
+    }
+
+    let mut wtr = csv::Writer::from_writer(std::io::stdout());
+    for account in state.accounts() {
+        wtr.serialize(account).expect("Failed to write account");
+    }
+}
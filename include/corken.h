@@ -0,0 +1,142 @@
+#ifndef CORKEN_H
+#define CORKEN_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdbool.h>
+#include <stdint.h>
+
+// Error codes returned by the bindings. Codes below 100 mirror the variants of `TxError`, while
+// the rest are specific to the boundary.
+enum corken_error_t
+#if __STDC_VERSION__ >= 202311L
+  : uint32_t
+#endif // __STDC_VERSION__ >= 202311L
+ {
+  CORKEN_OK = 0,
+  CORKEN_NOT_ENOUGH_FUNDS = 1,
+  CORKEN_TX_DOESNT_EXIST = 2,
+  CORKEN_INVALID_DISPUTE = 3,
+  CORKEN_UNAUTHORIZED = 4,
+  CORKEN_TX_ALREADY_DISPUTED = 5,
+  CORKEN_TX_NOT_UNDER_DISPUTE = 6,
+  CORKEN_INTERNAL_ERROR = 7,
+  CORKEN_ACCOUNT_LOCKED = 8,
+  CORKEN_CORRUPT_EVENT_LOG = 9,
+  CORKEN_PARSE_ERROR = 10,
+  CORKEN_DISPUTES_DISABLED = 11,
+  CORKEN_DUPLICATE_TX = 12,
+  CORKEN_DISPUTE_WINDOW_EXPIRED = 13,
+  CORKEN_UNSUPPORTED_TYPE = 14,
+  CORKEN_INVARIANT_BREACH = 15,
+  CORKEN_DUPLICATE_CLIENT_ID = 16,
+  CORKEN_ACCOUNT_NOT_FOUND = 17,
+  CORKEN_TRANSACTION_FROZEN = 18,
+  CORKEN_INVALID_CLIENT = 19,
+  CORKEN_INVALID = 20,
+  CORKEN_FOOTER_MISMATCH = 21,
+  CORKEN_VELOCITY_EXCEEDED = 22,
+  CORKEN_CURRENCY_MISMATCH = 23,
+  CORKEN_OUT_OF_ORDER_TIMESTAMP = 24,
+  CORKEN_NEGATIVE_BALANCE_INTEREST = 25,
+  CORKEN_REFUND_EXCEEDS_WITHDRAWAL = 26,
+  CORKEN_ZERO_AMOUNT = 27,
+  CORKEN_INVALID_MERGE_TARGET = 28,
+  CORKEN_BALANCE_LIMIT_EXCEEDED = 29,
+  // A required pointer argument was `NULL`.
+  CORKEN_NULL_POINTER = 100,
+  // An argument was out of range, or a string wasnt valid UTF-8.
+  CORKEN_INVALID_ARGUMENT = 101,
+  // An IO error occured.
+  CORKEN_IO_ERROR = 102,
+  // The library panicked.
+  CORKEN_PANIC = 103,
+};
+#if __STDC_VERSION__ >= 202311L
+typedef enum corken_error_t corken_error_t;
+#else
+typedef uint32_t corken_error_t;
+#endif // __STDC_VERSION__ >= 202311L
+
+// Types of transactions accepted by `corken_execute`.
+enum corken_tx_type_t
+#if __STDC_VERSION__ >= 202311L
+  : uint32_t
+#endif // __STDC_VERSION__ >= 202311L
+ {
+  CORKEN_DEPOSIT = 0,
+  CORKEN_WITHDRAWAL = 1,
+  CORKEN_DISPUTE = 2,
+  CORKEN_RESOLVE = 3,
+  CORKEN_CHARGEBACK = 4,
+};
+#if __STDC_VERSION__ >= 202311L
+typedef enum corken_tx_type_t corken_tx_type_t;
+#else
+typedef uint32_t corken_tx_type_t;
+#endif // __STDC_VERSION__ >= 202311L
+
+// Opaque handle to a state machine.
+typedef struct corken_state_t corken_state_t;
+
+// Struct represents an account, filled in by `corken_account_get`.
+typedef struct {
+  uint16_t client;
+  double available;
+  double held;
+  double total;
+  bool locked;
+} corken_account_t;
+
+// Function will construct a state machine with the default policies.
+//
+// # Returns
+// A state machine that must be released with `corken_state_free`, or `NULL` on failure.
+corken_state_t *corken_state_new(void);
+
+// Function will release a state machine. Passing `NULL` is a no-op.
+//
+// # Safety
+// `state` must be `NULL` or a pointer returned by `corken_state_new` that hasnt been released
+// yet.
+void corken_state_free(corken_state_t *state);
+
+// Function will execute a transaction. `amount` is ignored by all types but deposits and
+// withdrawals.
+//
+// # Arguments
+// * `tx_type` - One of `corken_tx_type_t`.
+// * `out_error` - Set to the outcome of the transaction, may be `NULL`.
+//
+// # Returns
+// Whether the transaction was executed.
+//
+// # Safety
+// `state` must be a live state machine and `out_error` must be `NULL` or valid for writes.
+bool corken_execute(corken_state_t *state,
+                    uint32_t tx_type,
+                    uint16_t client,
+                    uint32_t tx,
+                    double amount,
+                    corken_error_t *out_error);
+
+// Function will copy the account of `client` into `out_account`.
+//
+// # Returns
+// `CORKEN_ACCOUNT_NOT_FOUND` if the client has no account, in which case `out_account` is left
+// untouched.
+//
+// # Safety
+// `state` must be a live state machine and `out_account` must be valid for writes.
+corken_error_t corken_account_get(const corken_state_t *state,
+                                  uint16_t client,
+                                  corken_account_t *out_account);
+
+// Function will write all the accounts, in ascending order of their client id, as a CSV
+// document to the file at `path`, replacing it if it exists.
+//
+// # Safety
+// `state` must be a live state machine and `path` must be a NUL terminated string.
+corken_error_t corken_accounts_export_csv(const corken_state_t *state, const char *path);
+
+#endif  /* CORKEN_H */
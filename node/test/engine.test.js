@@ -0,0 +1,91 @@
+// Tests of the Node.js bindings, run with `npm test` after `npm run build` and from
+// `cargo test --features node`, which points CORKEN_NODE_MODULE at the freshly built module.
+const assert = require('node:assert/strict');
+const fs = require('node:fs');
+const os = require('node:os');
+const path = require('node:path');
+const test = require('node:test');
+
+const { Engine } = require(process.env.CORKEN_NODE_MODULE || '../../corken.node');
+
+const tx = (type, client, id, amount) => ({ type, client, tx: id, amount });
+
+test('deposits', () => {
+  const engine = new Engine();
+
+  const { receipt, error } = engine.execute(tx('deposit', 1, 1, '10.25'));
+  assert.equal(error, undefined);
+  assert.deepEqual(receipt, {
+    type: 'deposit',
+    client: 1,
+    tx: 1,
+    available: '10.25',
+    held: '0',
+    total: '10.25',
+    locked: false,
+  });
+
+  const results = engine.executeBatch([
+    tx('deposit', 2, 2, '1.5'),
+    tx('withdrawal', 2, 3, '5'),
+    tx('deposit', 2, 4, 'abc'),
+  ]);
+  assert.equal(results[0].receipt.total, '1.5');
+  assert.equal(results[1].error.code, 'NotEnoughFunds');
+  assert.equal(results[1].error.tx, 3);
+  assert.equal(results[2].error.code, 'InvalidRecord');
+
+  assert.deepEqual(
+    engine.accounts().map((x) => [x.client, x.available]),
+    [
+      [1, '10.25'],
+      [2, '1.5'],
+    ],
+  );
+});
+
+test('disputes', () => {
+  const engine = new Engine();
+  engine.execute(tx('deposit', 1, 1, '10'));
+
+  assert.equal(engine.execute(tx('dispute', 1, 1)).receipt.held, '10');
+  assert.equal(engine.execute(tx('dispute', 1, 1)).error.code, 'TxAlreadyDisputed');
+  assert.equal(engine.execute(tx('dispute', 2, 1)).error.code, 'Unauthorized');
+
+  const { receipt } = engine.execute(tx('resolve', 1, 1));
+  assert.equal(receipt.available, '10');
+  assert.equal(receipt.held, '0');
+  assert.equal(engine.execute(tx('resolve', 1, 1)).error.code, 'TxDoesntExist');
+});
+
+test('locked account rejection', () => {
+  const engine = new Engine();
+  engine.executeBatch([tx('deposit', 1, 1, '10'), tx('deposit', 1, 2, '5'), tx('dispute', 1, 1)]);
+
+  const { receipt } = engine.execute(tx('chargeback', 1, 1));
+  assert.equal(receipt.total, '5');
+  assert.equal(receipt.locked, true);
+
+  const { error } = engine.execute(tx('withdrawal', 1, 3, '1'));
+  assert.equal(error.code, 'AccountLocked');
+  assert.equal(error.client, 1);
+  assert.deepEqual(engine.accounts()[0], {
+    client: 1,
+    available: '5',
+    held: '0',
+    total: '5',
+    locked: true,
+  });
+});
+
+test('loadCsv', async () => {
+  const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'corken-'));
+  const file = path.join(dir, 'transactions.csv');
+  fs.writeFileSync(file, 'type, client, tx, amount\ndeposit, 1, 1, 2.5\nwithdrawal, 1, 2, 9\n');
+
+  const engine = await Engine.loadCsv(file);
+  assert.ok(engine instanceof Engine);
+  assert.equal(engine.accounts()[0].total, '2.5');
+
+  await assert.rejects(Engine.loadCsv(path.join(dir, 'missing.csv')));
+});
@@ -0,0 +1,88 @@
+"""Tests of the Python bindings, runnable with pytest after `maturin develop` and from
+`cargo test --features python`."""
+
+import corken
+
+
+def tx(type, client, id, amount=None):
+    return {"type": type, "client": client, "tx": id, "amount": amount}
+
+
+def expect_error(state, transaction, code):
+    try:
+        state.execute(transaction)
+    except corken.CorkenError as e:
+        assert e.code == code, e.code
+        assert e.client == transaction["client"]
+        assert e.tx == transaction["tx"]
+    else:
+        raise AssertionError("expected " + code)
+
+
+def test_dispute_lifecycle():
+    state = corken.State()
+    state.execute(tx("deposit", 1, 1, "10.5"))
+    state.execute(tx("deposit", 1, 2, 4))
+    state.execute(tx("dispute", 1, 1))
+
+    accounts = state.accounts()
+    assert accounts["available"] == [4.0]
+    assert accounts["held"] == [10.5]
+    assert accounts["total"] == [14.5]
+
+    expect_error(state, tx("dispute", 1, 1), "TxAlreadyDisputed")
+    state.execute(tx("resolve", 1, 1))
+    expect_error(state, tx("resolve", 1, 1), "TxDoesntExist")
+
+    state.execute(tx("deposit", 1, 3, "1.0"))
+    state.execute(tx("dispute", 1, 3))
+    state.execute(tx("chargeback", 1, 3))
+
+    assert state.accounts() == {
+        "client": [1],
+        "available": [14.5],
+        "held": [0.0],
+        "total": [14.5],
+        "locked": [True],
+    }
+    expect_error(state, tx("deposit", 1, 4, "1.0"), "AccountLocked")
+
+
+def test_execute_many():
+    state = corken.State()
+    results = state.execute_many(
+        [
+            tx("deposit", 2, 1, "5"),
+            tx("withdrawal", 2, 2, "50"),
+            {"type": "deposit", "client": "x", "tx": 3, "amount": "1"},
+            tx("deposit", 1, 4, "1"),
+        ]
+    )
+
+    assert results[0] is None
+    assert results[1].code == "NotEnoughFunds"
+    assert results[1].tx == 2
+    assert results[2].code == "InvalidRecord"
+    assert results[2].client is None
+    assert results[3] is None
+    assert state.accounts()["client"] == [1, 2]
+
+
+def test_from_csv(tmp_path):
+    path = tmp_path / "transactions.csv"
+    path.write_text(
+        "type, client, tx, amount\n"
+        "deposit, 1, 1, 2.0\n"
+        "withdrawal, 1, 2, 5.0\n"
+        "deposit, 2, 3, 1.25\n"
+    )
+
+    state = corken.State.from_csv(str(path))
+    assert state.accounts()["total"] == [2.0, 1.25]
+
+    try:
+        corken.State.from_csv(str(tmp_path / "missing.csv"))
+    except OSError:
+        pass
+    else:
+        raise AssertionError("expected OSError")
@@ -0,0 +1,338 @@
+//! This module contains the all-or-nothing and the fail-fast execution of batches of
+//! transactions.
+
+use super::*;
+
+/// Struct represents a transaction commited by `State::execute_atomic`, along with the account it
+/// moved funds on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Receipt<A = f64> {
+    /// The id of the transaction.
+    pub tx: u32,
+    /// The id of the client whose account the transaction applied to. For disputes, resolves and
+    /// chargebacks this is the owner of the transaction they refer to.
+    pub client: u16,
+    /// The own id of a dispute, resolve or chargeback, if it carried one.
+    pub event_id: Option<u32>,
+    /// The account once the transaction has been commited, before the rest of the group.
+    pub account: Account<A>,
+}
+
+impl<H: BuildHasher + Clone, A: Money> State<H, A> {
+    /// Function will execute a batch of transactions atomically: either all of them get
+    /// commited, or none of them do.
+    ///
+    /// # Arguments
+    /// * `txs` - Transactions to be executed, in order.
+    ///
+    /// # Returns
+    /// If any transaction fails, the errors of all failing transactions are returned in batch
+    /// order and the state is left untouched.
+    ///
+    /// # Notes
+    /// The batch is dry-run on a clone of the state machine, which gets swapped in if every
+    /// transaction succeeds. This costs a full copy of the state per call, so prefer small
+    /// batches. The registered observer and subscribers, if any, are not notified of the
+    /// transactions of the batch.
+    pub fn execute_try_all(&mut self, txs: &[Transaction<A>]) -> Result<(), Vec<TxError>> {
+        let mut dry_run = self.dry_run();
+
+        let errors: Vec<TxError> = txs
+            .iter()
+            .filter_map(|tx| dry_run.execute(tx.clone()).err())
+            .collect();
+
+        if !errors.is_empty() {
+            self.roll_back(&dry_run);
+            return Err(errors);
+        }
+
+        self.swap_in(dry_run);
+
+        Ok(())
+    }
+
+    /// Function will execute a group of transactions that are only meaningful as a unit, such as
+    /// a withdrawal and its fee: either all of them get commited, or none of them do. The group
+    /// may span several clients.
+    ///
+    /// # Arguments
+    /// * `txs` - Transactions to be executed, in order.
+    ///
+    /// # Returns
+    /// The receipts of the transactions in group order, or the index within the group and the
+    /// error of the first failing transaction. On failure the state is left untouched and the
+    /// transactions after the failing one are never executed.
+    ///
+    /// # Notes
+    /// Like `execute_try_all`, the group is staged on a clone of the state machine, and the
+    /// registered observer and subscribers are not notified of its transactions.
+    pub fn execute_atomic(
+        &mut self,
+        txs: &[Transaction<A>],
+    ) -> Result<Vec<Receipt<A>>, (usize, TxError)> {
+        let mut dry_run = self.dry_run();
+
+        let mut receipts = Vec::with_capacity(txs.len());
+        for (idx, tx) in txs.iter().enumerate() {
+            let mut client = tx.client;
+            let result = dry_run.execute_with_events(tx.clone(), |event| {
+                if !matches!(event, AccountEvent::AccountCreated { .. }) {
+                    client = event.client();
+                }
+            });
+            if let Err(e) = result {
+                self.roll_back(&dry_run);
+                return Err((idx, e));
+            }
+            dry_run.trigger_deferred(tx.tx);
+
+            receipts.push(Receipt {
+                tx: tx.tx,
+                client,
+                event_id: tx.event_id,
+                account: dry_run.accounts.get(&client).cloned().unwrap_or(Account {
+                    id: client,
+                    ..Account::default()
+                }),
+            });
+        }
+
+        self.swap_in(dry_run);
+
+        Ok(receipts)
+    }
+
+    /// Returns a clone of the state machine to stage transactions on, without the observer and
+    /// the subscribers.
+    fn dry_run(&mut self) -> Self {
+        let observer = self.observer.take();
+        let subscriptions = std::mem::take(&mut self.subscriptions);
+        let dry_run = self.clone();
+        self.observer = observer;
+        self.subscriptions = subscriptions;
+
+        dry_run
+    }
+
+    /// Function will discard `dry_run`, only keeping the count of the transactions it executed.
+    fn roll_back(&mut self, dry_run: &Self) {
+        self.executed_count = dry_run.executed_count;
+        self.failed_count = dry_run.failed_count;
+    }
+
+    /// Function will replace the state machine with `dry_run`, keeping the observer and the
+    /// subscribers.
+    fn swap_in(&mut self, mut dry_run: Self) {
+        dry_run.observer = self.observer.take();
+        dry_run.subscriptions = std::mem::take(&mut self.subscriptions);
+        *self = dry_run;
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will execute a batch of transactions in order, stopping at the first one that
+    /// fails.
+    ///
+    /// # Arguments
+    /// * `txs` - Iterator over owned `Transactions`.
+    ///
+    /// # Returns
+    /// The index within the batch, the transaction and the error of the first failing
+    /// transaction. The transactions before it stay commited, while it and the ones after it are
+    /// never applied.
+    // NOTE: The failing transaction is handed back as is, so that callers can retry or report it.
+    #[allow(clippy::result_large_err)]
+    pub fn execute_until_error(
+        &mut self,
+        txs: impl IntoIterator<Item = Transaction<A>>,
+    ) -> Result<(), (usize, Transaction<A>, TxError)> {
+        for (idx, tx) in txs.into_iter().enumerate() {
+            if let Err(e) = self.execute(tx.clone()) {
+                return Err((idx, tx, e));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    /// The accounts and cached transactions of a state machine.
+    type Contents = (Vec<Account>, Vec<(u32, Transaction, Option<DisputeState>)>);
+
+    /// Returns the accounts and cached transactions of `state`, in a comparable order.
+    fn contents(state: &State) -> Contents {
+        let accounts = state.accounts_iter_by_id().cloned().collect();
+        let cache = state
+            .sorted_tx_cache()
+            .into_iter()
+            .map(|(id, tx, dispute)| (id, tx.clone(), dispute.cloned()))
+            .collect();
+
+        (accounts, cache)
+    }
+
+    fn deposit(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction::new(
+            TransactionType::Deposit {
+                amount: checked(amount),
+            },
+            client,
+            tx,
+        )
+    }
+
+    fn withdrawal(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction::new(
+            TransactionType::Withdrawal {
+                amount: checked(amount),
+            },
+            client,
+            tx,
+        )
+    }
+
+    #[test]
+    fn test_execute_try_all_rolls_back() {
+        let mut state = State::default();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        state.execute(deposit(2, 2, 5.0)).unwrap();
+        let before = contents(&state);
+
+        assert_eq!(
+            state.execute_try_all(&[
+                deposit(1, 3, 10.0),
+                Transaction::new(TransactionType::Dispute, 2, 2),
+                deposit(3, 4, 1.0),
+                // Only fails because of the deposit earlier in the batch being disputed.
+                withdrawal(2, 5, 1.0),
+                Transaction::new(TransactionType::Resolve, 1, 99),
+            ]),
+            Err(vec![TxError::NotEnoughFunds, TxError::TxDoesntExist])
+        );
+        assert_eq!(contents(&state), before);
+        assert_eq!(state.count_transactions_by_type()["deposit"], 2);
+    }
+
+    #[test]
+    fn test_execute_try_all_commits() {
+        let txs = [
+            deposit(1, 3, 10.0),
+            Transaction::new(TransactionType::Dispute, 2, 2),
+            deposit(3, 4, 1.0),
+            withdrawal(1, 5, 15.0),
+        ];
+
+        let mut state = State::default();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        state.execute(deposit(2, 2, 5.0)).unwrap();
+        state.execute_try_all(&txs).unwrap();
+
+        let mut expected = State::default();
+        expected.execute(deposit(1, 1, 10.0)).unwrap();
+        expected.execute(deposit(2, 2, 5.0)).unwrap();
+        for tx in txs {
+            expected.execute(tx).unwrap();
+        }
+
+        assert_eq!(contents(&state), contents(&expected));
+        assert_eq!(state.accounts[&1].available, 5.0);
+        assert_eq!(state.accounts[&2].held, 5.0);
+    }
+
+    #[test]
+    fn test_execute_atomic_rolls_back() {
+        let mut state = State::default();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        state.execute(deposit(2, 2, 5.0)).unwrap();
+        let before = contents(&state);
+
+        assert_eq!(
+            state.execute_atomic(&[
+                withdrawal(1, 3, 4.0),
+                deposit(2, 4, 1.0),
+                deposit(3, 5, 1.0),
+                Transaction::new(TransactionType::Dispute, 1, 1),
+                withdrawal(2, 6, 0.5),
+                // Only fails because of the withdrawal earlier in the group.
+                withdrawal(1, 7, 6.5),
+            ]),
+            Err((5, TxError::NotEnoughFunds))
+        );
+        assert_eq!(contents(&state), before);
+        assert!(!state.contains_account(3));
+        assert_eq!(state.count_transactions_by_type()["withdrawal"], 0);
+    }
+
+    #[test]
+    fn test_execute_atomic_commits() {
+        let txs = [
+            // A withdrawal and its fee.
+            withdrawal(1, 3, 4.0),
+            withdrawal(1, 4, 0.5),
+            deposit(3, 5, 1.0),
+            Transaction::new(TransactionType::Dispute, 2, 2),
+        ];
+
+        let mut state = State::default();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        state.execute(deposit(2, 2, 5.0)).unwrap();
+        let receipts = state.execute_atomic(&txs);
+
+        let mut expected = State::default();
+        expected.execute(deposit(1, 1, 10.0)).unwrap();
+        expected.execute(deposit(2, 2, 5.0)).unwrap();
+        let mut accounts = Vec::new();
+        for tx in txs {
+            let (id, client) = (tx.tx, tx.client);
+            expected.execute(tx).unwrap();
+            accounts.push((id, expected.accounts[&client].clone()));
+        }
+
+        assert_eq!(contents(&state), contents(&expected));
+        let receipts = receipts.unwrap();
+        assert_eq!(receipts.len(), 4);
+        for (receipt, (tx, account)) in receipts.iter().zip(&accounts) {
+            assert_eq!((receipt.tx, receipt.client), (*tx, account.id));
+            assert_eq!(receipt.account, *account);
+        }
+        assert_eq!(receipts[1].account.available, 5.5);
+        assert_eq!(receipts[3].account.held, 5.0);
+    }
+
+    #[test]
+    fn test_execute_until_error() {
+        let mut state = State::default();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+
+        assert_eq!(
+            state.execute_until_error(vec![
+                deposit(2, 2, 5.0),
+                withdrawal(1, 3, 4.0),
+                withdrawal(2, 4, 6.0),
+                deposit(3, 5, 1.0),
+                withdrawal(1, 6, 1.0),
+            ]),
+            Err((2, withdrawal(2, 4, 6.0), TxError::NotEnoughFunds))
+        );
+
+        let mut expected = State::default();
+        for tx in [
+            deposit(1, 1, 10.0),
+            deposit(2, 2, 5.0),
+            withdrawal(1, 3, 4.0),
+        ] {
+            expected.execute(tx).unwrap();
+        }
+        assert_eq!(contents(&state), contents(&expected));
+        assert!(!state.contains_account(3));
+
+        assert_eq!(state.execute_until_error(vec![deposit(3, 5, 1.0)]), Ok(()));
+        assert_eq!(state.accounts[&3].available, 1.0);
+    }
+}
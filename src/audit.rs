@@ -0,0 +1,383 @@
+//! This module contains the audit trail of the state machine, recording every commited
+//! transaction along with the account it applied to.
+
+use super::*;
+#[cfg(feature = "serde")]
+use crate::snapshot::AccountSnapshot;
+use std::net::IpAddr;
+
+/// Struct represents the context a transaction was submitted in, e.g. by an API gateway, carried
+/// along with it for tracing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TxContext {
+    /// Id correlating the transaction with the request it was submitted by.
+    pub correlation_id: Option<String>,
+    /// The address the transaction was submitted from.
+    pub ip_address: Option<IpAddr>,
+    /// The time at which the transaction was submitted. This is not the `timestamp` of the
+    /// transaction, which is left untouched.
+    pub timestamp: Option<u64>,
+}
+
+/// Struct represents a transaction rejected by `State::execute_with_context`, along with the
+/// context it was submitted in. `source()` returns the rejection.
+#[derive(Debug, thiserror::Error, PartialEq)]
+#[error("Transaction was rejected.")]
+pub struct ContextualTxError {
+    /// The reason the transaction was rejected.
+    #[source]
+    pub source: TxError,
+    /// The context the transaction was submitted in.
+    pub context: TxContext,
+}
+
+/// Struct represents a transaction commited by the state machine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditEntry<A = f64> {
+    /// The logical time at which the transaction was commited.
+    pub seq: u64,
+    /// The commited transaction.
+    pub tx: Transaction<A>,
+    /// The account of the client before the transaction. Accounts created by the transaction are
+    /// represented by an empty account.
+    pub before: Account<A>,
+    /// The account of the client after the transaction.
+    pub after: Account<A>,
+    /// Whether the transaction was raised by an operator through `State::admin_dispute`,
+    /// `State::admin_resolve` or `State::admin_chargeback` rather than by the client.
+    pub administrative: bool,
+    /// The context the transaction was submitted in, if it was executed through
+    /// `State::execute_audited_with_context`.
+    pub context: Option<TxContext>,
+}
+
+impl<A: Money> AuditEntry<A> {
+    /// Returns the amount credited by the transaction if it is an interest transaction, e.g. to
+    /// show it on statements.
+    pub fn credited_interest(&self) -> Option<A> {
+        match self.tx.tx_type {
+            // NOTE: The credited amount has been rounded to the minor unit, which snaps the
+            // difference of float balances back onto it.
+            TransactionType::Interest { .. } => Some(
+                (self.after.total - self.before.total)
+                    .round_to(MINOR_UNIT_DECIMAL_PLACES, RoundingMode::HalfEven),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will execute a transaction, returning its audit entry.
+    ///
+    /// # Arguments
+    /// * `tx` - Transaction to be executed.
+    ///
+    /// # Returns
+    /// Same errors as `execute`. Rejected transactions dont produce an entry.
+    pub fn execute_audited(&mut self, tx: Transaction<A>) -> Result<AuditEntry<A>, TxError> {
+        let client = tx.client;
+        let before = self.accounts.get(&client).cloned().unwrap_or(Account {
+            id: client,
+            ..Account::default()
+        });
+
+        self.execute(tx.clone())?;
+        let after = self.accounts.get(&client).unwrap_or(&before).clone();
+
+        Ok(AuditEntry {
+            seq: self.seq,
+            tx,
+            before,
+            after,
+            administrative: false,
+            context: None,
+        })
+    }
+
+    /// Function will execute a transaction submitted in `ctx`, returning its audit entry with the
+    /// context attached.
+    ///
+    /// # Returns
+    /// Same errors as `execute`, wrapped along with `ctx`.
+    pub fn execute_audited_with_context(
+        &mut self,
+        tx: Transaction<A>,
+        ctx: TxContext,
+    ) -> Result<AuditEntry<A>, ContextualTxError> {
+        match self.execute_audited(tx) {
+            Ok(mut entry) => {
+                entry.context = Some(ctx);
+                Ok(entry)
+            }
+            Err(source) => Err(ContextualTxError {
+                source,
+                context: ctx,
+            }),
+        }
+    }
+
+    /// Function will execute a transaction submitted in `ctx`, returning the account of its
+    /// client. See `execute_audited_with_context` for the audit entry holding the context.
+    ///
+    /// # Returns
+    /// Same errors as `execute`, wrapped along with `ctx`.
+    #[cfg(feature = "serde")]
+    pub fn execute_with_context(
+        &mut self,
+        tx: Transaction<A>,
+        ctx: TxContext,
+    ) -> Result<AccountSnapshot<A>, ContextualTxError> {
+        self.execute_audited_with_context(tx, ctx)
+            .map(|entry| AccountSnapshot::from(&entry.after))
+    }
+
+    /// Function will dispute the cached transaction `tx_id` on behalf of an operator, e.g. the
+    /// fraud team, whichever client it belongs to. The dispute authorizer is bypassed, while
+    /// everything else behaves like a dispute raised by the owner of the transaction.
+    ///
+    /// # Returns
+    /// Same errors as `execute`, except for `TxError::Unauthorized`. The audit entry is flagged
+    /// as `administrative`.
+    pub fn admin_dispute(&mut self, tx_id: u32) -> Result<AuditEntry<A>, TxError> {
+        self.execute_administrative(TransactionType::Dispute, tx_id)
+    }
+
+    /// Function will resolve the dispute on the cached transaction `tx_id` on behalf of an
+    /// operator. See `admin_dispute`.
+    pub fn admin_resolve(&mut self, tx_id: u32) -> Result<AuditEntry<A>, TxError> {
+        self.execute_administrative(TransactionType::Resolve, tx_id)
+    }
+
+    /// Function will charge back the disputed transaction `tx_id` on behalf of an operator. See
+    /// `admin_dispute`.
+    pub fn admin_chargeback(&mut self, tx_id: u32) -> Result<AuditEntry<A>, TxError> {
+        self.execute_administrative(TransactionType::Chargeback, tx_id)
+    }
+
+    /// Function will execute a dispute, resolve or chargeback of `tx_id` as if it had been
+    /// raised by the owner of the transaction, flagging its audit entry as administrative.
+    fn execute_administrative(
+        &mut self,
+        tx_type: TransactionType<A>,
+        tx_id: u32,
+    ) -> Result<AuditEntry<A>, TxError> {
+        let owner = match self.tx_cache.get(&tx_id) {
+            Some(cached) => cached.tx.client,
+            None if self.frozen_tx_ids.contains(&tx_id) => {
+                return Err(TxError::TransactionFrozen(tx_id))
+            }
+            None => return Err(TxError::TxDoesntExist),
+        };
+
+        let mut entry = self.execute_audited(Transaction::new(tx_type, owner, tx_id))?;
+        entry.administrative = true;
+
+        Ok(entry)
+    }
+}
+
+impl State {
+    /// Function will construct the state machine by re-executing the transactions of an audit
+    /// trail in ascending order of their `seq`. The `before` and `after` accounts of the entries
+    /// are ignored.
+    ///
+    /// # Arguments
+    /// * `entries` - Audit entries produced by `execute_audited`, in any order.
+    ///
+    /// # Notes
+    /// The trail is replayed with the default policies, so it must have been produced by a state
+    /// machine with the default policies for the balances to match. Accounts that were only ever
+    /// touched by rejected transactions have no entries and are therefore not recreated.
+    pub fn replay_audit_trail(entries: &[AuditEntry]) -> State {
+        let mut entries: Vec<&AuditEntry> = entries.iter().collect();
+        // NOTE: `sort_by_key` is stable, so entries commited at the same logical time keep their
+        // order.
+        entries.sort_by_key(|x| x.seq);
+
+        Self::from_iterator(entries.into_iter().map(|x| x.tx.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{checked, workload};
+
+    #[test]
+    fn test_admin_dispute() {
+        let mut state = State::default();
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                2,
+                2,
+            ))
+            .unwrap();
+
+        // Regular cross-client disputes are still rejected.
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 2, 1)),
+            Err(TxError::Unauthorized)
+        );
+
+        let entry = state.admin_dispute(1).unwrap();
+        assert!(entry.administrative);
+        assert_eq!(entry.tx, tx(TransactionType::Dispute, 1, 1));
+        assert_eq!((entry.after.available, entry.after.held), (0.0, 10.0));
+        assert_eq!(state.admin_dispute(1), Err(TxError::TxAlreadyDisputed));
+
+        let entry = state.admin_resolve(1).unwrap();
+        assert!(entry.administrative);
+        assert_eq!(state.accounts[&1].available, 10.0);
+
+        state.admin_dispute(2).unwrap();
+        let entry = state.admin_chargeback(2).unwrap();
+        assert!(entry.administrative && entry.after.locked);
+        assert_eq!(state.accounts[&2].total, 0.0);
+
+        assert_eq!(state.admin_dispute(99), Err(TxError::TxDoesntExist));
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                3,
+            ))
+            .unwrap();
+        assert_eq!(state.admin_resolve(3), Err(TxError::TxNotUnderDispute));
+
+        let entry = state
+            .execute_audited(tx(TransactionType::Dispute, 1, 3))
+            .unwrap();
+        assert!(!entry.administrative);
+    }
+
+    #[test]
+    fn test_execute_audited_with_context() {
+        let mut state = State::default();
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        let ctx = TxContext {
+            correlation_id: Some("req-42".to_string()),
+            ip_address: Some(IpAddr::from([10, 0, 0, 1])),
+            timestamp: Some(1_700_000_000),
+        };
+
+        let entry = state
+            .execute_audited_with_context(
+                tx(
+                    TransactionType::Deposit {
+                        amount: checked(10.0),
+                    },
+                    1,
+                    1,
+                ),
+                ctx.clone(),
+            )
+            .unwrap();
+        assert_eq!(entry.context.as_ref(), Some(&ctx));
+        assert_eq!(entry.tx.timestamp, None);
+
+        let e = state
+            .execute_audited_with_context(
+                tx(
+                    TransactionType::Withdrawal {
+                        amount: checked(50.0),
+                    },
+                    1,
+                    2,
+                ),
+                ctx.clone(),
+            )
+            .unwrap_err();
+        assert_eq!(e.source, TxError::NotEnoughFunds);
+        assert_eq!(e.context.correlation_id.as_deref(), Some("req-42"));
+        assert_eq!(
+            std::error::Error::source(&e).unwrap().to_string(),
+            TxError::NotEnoughFunds.to_string()
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let account = state
+                .execute_with_context(
+                    tx(
+                        TransactionType::Deposit {
+                            amount: checked(2.5),
+                        },
+                        1,
+                        3,
+                    ),
+                    TxContext::default(),
+                )
+                .unwrap();
+            assert_eq!(Account::from(account).available, 12.5);
+
+            let e = state
+                .execute_with_context(tx(TransactionType::Dispute, 1, 99), ctx)
+                .unwrap_err();
+            assert_eq!(e.source, TxError::TxDoesntExist);
+            assert_eq!(e.context.correlation_id.as_deref(), Some("req-42"));
+        }
+    }
+
+    #[test]
+    fn test_replay_audit_trail() {
+        let mut state = State::default();
+        let mut trail: Vec<AuditEntry> = workload(1000)
+            .into_iter()
+            .filter_map(|tx| state.execute_audited(tx).ok())
+            .collect();
+        assert!(trail.len() > 300);
+
+        // The last entry of every client holds its final account.
+        let mut last = HashMap::new();
+        for entry in trail.iter() {
+            last.insert(entry.tx.client, &entry.after);
+        }
+        for (client, after) in last {
+            assert_eq!(after, &state.accounts[&client]);
+        }
+
+        // Entries are replayed by `seq`, not by their position in the slice.
+        trail.reverse();
+        let replayed = State::replay_audit_trail(&trail);
+
+        for account in state.accounts() {
+            let replayed = match replayed.accounts.get(&account.id) {
+                Some(replayed) => replayed,
+                None => {
+                    assert_eq!(
+                        account,
+                        &Account {
+                            id: account.id,
+                            ..Account::default()
+                        }
+                    );
+                    continue;
+                }
+            };
+
+            assert_eq!(replayed.available.to_bits(), account.available.to_bits());
+            assert_eq!(replayed.held.to_bits(), account.held.to_bits());
+            assert_eq!(replayed.total.to_bits(), account.total.to_bits());
+            assert_eq!(replayed.locked, account.locked);
+        }
+        // NOTE: Rejected transactions still advance the clock, so only the cached transactions
+        // themselves are compared.
+        assert_eq!(replayed.sorted_tx_cache(), state.sorted_tx_cache());
+    }
+}
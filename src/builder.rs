@@ -0,0 +1,870 @@
+//! This module contains the builder used to configure the policies of the state machine.
+
+use super::*;
+use std::sync::Arc;
+
+/// Enum represents how disputes referencing withdrawals are handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WithdrawalDisputePolicy {
+    /// Withdrawals cannot be disputed, disputing one returns `TxError::TxDoesntExist`.
+    Reject,
+    /// Withdrawals can be disputed. The disputed amount gets added to the `held` and `total`
+    /// balances, a resolve removes it again while a chargeback returns it to `available`.
+    Allow,
+}
+
+/// Enum represents which transactions are accepted for a locked account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockedAccountPolicy {
+    /// All transactions for a locked account are rejected with `TxError::AccountLocked`.
+    RejectAll,
+    /// Deposits are still accepted for locked accounts, everything else is rejected.
+    AllowDeposits,
+}
+
+/// Enum represents how long after a transaction has been commited it can still be disputed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisputeWindow {
+    /// Transactions can be disputed for as long as they are cached.
+    Unlimited,
+    /// Transactions can only be disputed within the given amount of transactions processed by the
+    /// state machine after it. With a custom `Clock` the window is measured in its ticks instead.
+    Transactions(u64),
+}
+
+/// Enum represents how transactions whose timestamp is behind the latest timestamp of their client
+/// are handled. Transactions without a timestamp are always accepted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutOfOrderPolicy {
+    /// Out of order transactions are executed as usual.
+    Ignore,
+    /// Out of order transactions are executed as usual, but reported to the observer through
+    /// `StateObserver::on_out_of_order`.
+    Warn,
+    /// Out of order transactions are rejected with `TxError::OutOfOrderTimestamp`.
+    Reject,
+}
+
+/// Enum represents how interest transactions are handled for accounts whose `available` balance is
+/// negative.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NegativeInterestPolicy {
+    /// Interest transactions are commited, but accrue nothing.
+    AccrueNothing,
+    /// Interest transactions are rejected with `TxError::NegativeBalanceInterest`.
+    Reject,
+}
+
+/// Enum represents how deposits and withdrawals of a zero amount are handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZeroAmountPolicy {
+    /// Zero amounts are executed as usual, creating the account and caching the transaction.
+    Allow,
+    /// Zero amounts are counted as successful, but never create an account nor get cached.
+    Ignore,
+    /// Zero amounts are rejected with `TxError::ZeroAmount`, and so are zero refunds, holds and
+    /// interest rates.
+    Reject,
+}
+
+/// Struct contains the policies the state machine has been configured with.
+#[derive(Clone, Debug)]
+pub(crate) struct Config<A = f64> {
+    pub(crate) disputes: bool,
+    pub(crate) withdrawal_disputes: WithdrawalDisputePolicy,
+    pub(crate) locked_accounts: LockedAccountPolicy,
+    pub(crate) prevent_negative_available: bool,
+    pub(crate) dispute_window: DisputeWindow,
+    pub(crate) idempotent: bool,
+    pub(crate) check_held: bool,
+    pub(crate) credit_limit: A,
+    pub(crate) velocity_limit: Option<(A, u64)>,
+    pub(crate) dispute_policy: Arc<dyn DisputePolicy<A>>,
+    pub(crate) dispute_authorizer: Arc<dyn DisputeAuthorizer<A>>,
+    pub(crate) custom_handlers: HashMap<String, Arc<dyn CustomHandler<A>>>,
+    pub(crate) clock: Box<dyn Clock>,
+    pub(crate) footer_marker: Option<String>,
+    pub(crate) max_decimal_places: Option<u32>,
+    pub(crate) zero_amounts: ZeroAmountPolicy,
+    pub(crate) reserve_zero_client: bool,
+    pub(crate) no_create_on_error: bool,
+    pub(crate) clear_dispute_notes: bool,
+    pub(crate) out_of_order: OutOfOrderPolicy,
+    pub(crate) timestamp_tolerance: u64,
+    pub(crate) rounding: RoundingMode,
+    pub(crate) negative_interest: NegativeInterestPolicy,
+    pub(crate) deferred_disputes: Option<usize>,
+    pub(crate) balance_limit: Option<A>,
+}
+
+impl<A: Money> Default for Config<A> {
+    fn default() -> Self {
+        Self {
+            disputes: true,
+            withdrawal_disputes: WithdrawalDisputePolicy::Reject,
+            locked_accounts: LockedAccountPolicy::RejectAll,
+            prevent_negative_available: false,
+            dispute_window: DisputeWindow::Unlimited,
+            idempotent: false,
+            check_held: false,
+            credit_limit: A::ZERO,
+            velocity_limit: None,
+            dispute_policy: Arc::new(StandardPolicy),
+            dispute_authorizer: Arc::new(OwnerOnly),
+            custom_handlers: HashMap::new(),
+            clock: Box::new(CounterClock::default()),
+            footer_marker: None,
+            max_decimal_places: None,
+            zero_amounts: ZeroAmountPolicy::Allow,
+            reserve_zero_client: false,
+            no_create_on_error: false,
+            clear_dispute_notes: false,
+            out_of_order: OutOfOrderPolicy::Ignore,
+            timestamp_tolerance: 0,
+            rounding: RoundingMode::HalfEven,
+            negative_interest: NegativeInterestPolicy::AccrueNothing,
+            deferred_disputes: None,
+            balance_limit: None,
+        }
+    }
+}
+
+/// Struct is used to build a `State` with non-default policies. All options default to the
+/// behaviour of `State::default()`.
+///
+/// The builder is `Clone` so that a configuration can be used as a template for multiple state
+/// machines. Use `StateBuilder::<rust_decimal::Decimal>::default()` to build a state machine with
+/// decimal amounts.
+#[derive(Clone, Debug)]
+pub struct StateBuilder<A = f64> {
+    config: Config<A>,
+}
+
+impl<A: Money> Default for StateBuilder<A> {
+    fn default() -> Self {
+        Self {
+            config: Config::default(),
+        }
+    }
+}
+
+impl State {
+    /// Function returns a builder that can be used to configure the policies of a new `State`.
+    pub fn builder() -> StateBuilder {
+        StateBuilder::default()
+    }
+}
+
+impl<A: Money> StateBuilder<A> {
+    /// Enables or disables disputes. When disabled, disputes, resolves and chargebacks are
+    /// rejected with `TxError::DisputesDisabled` and transactions are never cached.
+    pub fn disputes(mut self, enabled: bool) -> Self {
+        self.config.disputes = enabled;
+        self
+    }
+
+    /// Sets how disputes referencing withdrawals are handled.
+    pub fn withdrawal_disputes(mut self, policy: WithdrawalDisputePolicy) -> Self {
+        self.config.withdrawal_disputes = policy;
+        self
+    }
+
+    /// Sets which transactions are accepted for locked accounts.
+    pub fn locked_accounts(mut self, policy: LockedAccountPolicy) -> Self {
+        self.config.locked_accounts = policy;
+        self
+    }
+
+    /// When enabled, disputes that would drive the `available` balance of an account negative are
+    /// rejected with `TxError::NotEnoughFunds`.
+    pub fn prevent_negative_available(mut self, enabled: bool) -> Self {
+        self.config.prevent_negative_available = enabled;
+        self
+    }
+
+    /// Sets the clock the logical time of the state machine is read from. Defaults to a
+    /// `CounterClock`, ticking once per transaction.
+    ///
+    /// # Notes
+    /// Clones of the builder clone the clock, so each state machine built gets its own copy.
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.config.clock = Box::new(clock);
+        self
+    }
+
+    /// Sets how long after being commited a transaction can still be disputed. Disputes outside
+    /// of the window are rejected with `TxError::DisputeWindowExpired`.
+    pub fn dispute_window(mut self, window: DisputeWindow) -> Self {
+        self.config.dispute_window = window;
+        self
+    }
+
+    /// When enabled, the state machine remembers the ids of all deposits and withdrawals it has
+    /// commited, and rejects transactions reusing them with `TxError::DuplicateTx`.
+    pub fn idempotent(mut self, enabled: bool) -> Self {
+        self.config.idempotent = enabled;
+        self
+    }
+
+    /// When enabled, transactions that would leave the `held` balance of an account above its
+    /// `total` balance are rejected with `TxError::InvariantBreach`. Note that with the default
+    /// policies, disputing a deposit whose funds have already been withdrawn legitimately leads to
+    /// such a state, so this is mostly useful as a guard while developing custom policies.
+    pub fn check_held_invariant(mut self, enabled: bool) -> Self {
+        self.config.check_held = enabled;
+        self
+    }
+
+    /// Sets the amount by which withdrawals are allowed to drive the `available` balance of an
+    /// account negative.
+    pub fn default_credit_limit(mut self, limit: A) -> Self {
+        self.config.credit_limit = limit;
+        self
+    }
+
+    /// Caps the sum of the withdrawals of every client to `amount` within any `window`
+    /// consecutive transactions processed by the state machine, or ticks of its `Clock`.
+    /// Withdrawals that would exceed the cap are rejected with `TxError::VelocityExceeded`.
+    ///
+    /// # Notes
+    /// The withdrawals within the window are not part of snapshots, so a state machine restored
+    /// from one starts with an empty window for every client.
+    pub fn withdrawal_velocity_limit(mut self, amount: A, window: u64) -> Self {
+        self.config.velocity_limit = Some((amount, window));
+        self
+    }
+
+    /// Sets the policy deciding how disputes, resolves and chargebacks move balances around.
+    pub fn dispute_policy<P: DisputePolicy<A> + 'static>(mut self, policy: P) -> Self {
+        self.config.dispute_policy = Arc::new(policy);
+        self
+    }
+
+    /// Sets the authorizer deciding which clients may dispute, resolve and charge back a cached
+    /// transaction. Defaults to `OwnerOnly`.
+    pub fn dispute_authorizer<P: DisputeAuthorizer<A> + 'static>(mut self, authorizer: P) -> Self {
+        self.config.dispute_authorizer = Arc::new(authorizer);
+        self
+    }
+
+    /// Registers `handler` to execute transactions whose `type` is `tag`. Registering a handler
+    /// for a tag replaces any handler previously registered for it. Handlers cannot override the
+    /// standard transaction types, `build` fails with `BuildError::Conflict` if `tag` is one of
+    /// them.
+    pub fn custom_handler<H: CustomHandler<A> + 'static>(
+        mut self,
+        tag: impl Into<String>,
+        handler: H,
+    ) -> Self {
+        self.config
+            .custom_handlers
+            .insert(tag.into(), Arc::new(handler));
+        self
+    }
+
+    /// Rejects deposits and withdrawals whose amount has more than `places` decimal places with
+    /// `TxError::Invalid`. Amounts are not limited by default.
+    pub fn max_decimal_places(mut self, places: u32) -> Self {
+        self.config.max_decimal_places = Some(places);
+        self
+    }
+
+    /// Sets how deposits and withdrawals of a zero amount are handled. Defaults to
+    /// `ZeroAmountPolicy::Allow`.
+    pub fn zero_amounts(mut self, policy: ZeroAmountPolicy) -> Self {
+        self.config.zero_amounts = policy;
+        self
+    }
+
+    /// When enabled, deposits and withdrawals of a zero amount are rejected with
+    /// `TxError::ZeroAmount`. Shorthand for `zero_amounts` with `ZeroAmountPolicy::Reject`, or
+    /// `ZeroAmountPolicy::Allow` when disabled.
+    pub fn reject_zero_amounts(self, enabled: bool) -> Self {
+        self.zero_amounts(if enabled {
+            ZeroAmountPolicy::Reject
+        } else {
+            ZeroAmountPolicy::Allow
+        })
+    }
+
+    /// When enabled, client id `0` is treated as reserved and all its transactions are rejected
+    /// with `TxError::InvalidClient`, without creating an account.
+    pub fn reserve_zero_client(mut self, enabled: bool) -> Self {
+        self.config.reserve_zero_client = enabled;
+        self
+    }
+
+    /// When enabled, a rejected transaction of a client without an account doesnt leave an empty
+    /// account behind, and doesnt emit an `AccountEvent::AccountCreated`.
+    pub fn no_create_on_error(mut self, enabled: bool) -> Self {
+        self.config.no_create_on_error = enabled;
+        self
+    }
+
+    /// When enabled, the notes attached to a transaction with `State::add_dispute_note` are
+    /// dropped once its dispute is resolved or charged back. Notes are kept by default.
+    pub fn clear_dispute_notes_on_resolve(mut self, enabled: bool) -> Self {
+        self.config.clear_dispute_notes = enabled;
+        self
+    }
+
+    /// Sets how transactions whose timestamp is more than `tolerance` milliseconds behind the
+    /// latest timestamp of their client are handled. Out of order transactions are ignored by
+    /// default.
+    pub fn out_of_order_timestamps(mut self, policy: OutOfOrderPolicy, tolerance: u64) -> Self {
+        self.config.out_of_order = policy;
+        self.config.timestamp_tolerance = tolerance;
+        self
+    }
+
+    /// Sets how amounts computed by the state machine, i.e. accrued interest, are rounded to the
+    /// `MINOR_UNIT`. Defaults to `RoundingMode::HalfEven`.
+    pub fn rounding_mode(mut self, mode: RoundingMode) -> Self {
+        self.config.rounding = mode;
+        self
+    }
+
+    /// Sets how interest transactions are handled for accounts with a negative `available`
+    /// balance. Defaults to `NegativeInterestPolicy::AccrueNothing`.
+    pub fn negative_interest(mut self, policy: NegativeInterestPolicy) -> Self {
+        self.config.negative_interest = policy;
+        self
+    }
+
+    /// Caps the `total` balance of every account at `limit`, e.g. to comply with e-money
+    /// holding limits. Deposits, refunds, interest and custom transactions pushing the `total`
+    /// balance past the limit are rejected with `TxError::BalanceLimitExceeded`, while disputes,
+    /// resolves and chargebacks are exempt since they dont bring in new money. Individual
+    /// accounts can be given their own limit with `State::set_balance_limit`.
+    pub fn balance_limit(mut self, limit: A) -> Self {
+        self.config.balance_limit = Some(limit);
+        self
+    }
+
+    /// When set, a dispute referencing a transaction that hasnt been processed yet is parked
+    /// instead of being rejected with `TxError::TxDoesntExist`, and applied as soon as a deposit
+    /// of the same client with the same id is commited. This accomodates out of order streams.
+    ///
+    /// # Notes
+    /// Parked disputes are kept in memory until their deposit arrives, which may be never, so at
+    /// most `cap` of them are parked at once. Disputes arriving once the cap is reached are
+    /// rejected with `TxError::TxDoesntExist` as usual, and a second dispute of a parked one is
+    /// rejected with `TxError::DuplicateTx`. A parked dispute that gets rejected once its deposit
+    /// arrives is dropped and reported to `StateObserver::on_execute_error`, the deposit itself is
+    /// still commited.
+    pub fn deferred_disputes(mut self, cap: usize) -> Self {
+        self.config.deferred_disputes = Some(cap);
+        self
+    }
+
+    /// Treats transactions whose `type` is `marker` as a footer row carrying the expected sum of
+    /// the `total` balances of all accounts in its `amount` column. The `client` and `tx` columns
+    /// of the footer are ignored. Footers never change the state, but are rejected with
+    /// `TxError::FooterMismatch` if the expected sum doesnt match the computed one.
+    pub fn footer_marker(mut self, marker: impl Into<String>) -> Self {
+        self.config.footer_marker = Some(marker.into());
+        self
+    }
+
+    /// Function will validate the configured policies and build the state machine.
+    ///
+    /// # Returns
+    /// Returns a `BuildError` if the combination of policies is invalid.
+    pub fn build(self) -> Result<State<ahash::RandomState, A>, BuildError> {
+        let config = self.config;
+
+        if !config.credit_limit.is_finite() || config.credit_limit.is_negative() {
+            return Err(BuildError::InvalidCreditLimit);
+        }
+
+        if let Some(limit) = config.balance_limit {
+            if !limit.is_finite() || limit.is_negative() {
+                return Err(BuildError::InvalidBalanceLimit);
+            }
+        }
+
+        if let Some((amount, window)) = config.velocity_limit {
+            if !amount.is_finite() || amount.is_negative() || window == 0 {
+                return Err(BuildError::InvalidVelocityLimit);
+            }
+        }
+
+        if config.prevent_negative_available && config.credit_limit > A::ZERO {
+            return Err(BuildError::Conflict(
+                "negative available prevention requires a zero credit limit",
+            ));
+        }
+
+        if config
+            .custom_handlers
+            .keys()
+            .any(|tag| STANDARD_TAGS.contains(&tag.as_str()))
+        {
+            return Err(BuildError::Conflict(
+                "custom handlers cannot override a standard transaction type",
+            ));
+        }
+
+        if let Some(marker) = &config.footer_marker {
+            if STANDARD_TAGS.contains(&marker.as_str()) {
+                return Err(BuildError::Conflict(
+                    "footer marker cannot be a standard transaction type",
+                ));
+            }
+
+            if config.custom_handlers.contains_key(marker) {
+                return Err(BuildError::Conflict(
+                    "footer marker cannot be handled by a custom handler",
+                ));
+            }
+        }
+
+        if config.dispute_window == DisputeWindow::Transactions(0) {
+            return Err(BuildError::InvalidDisputeWindow);
+        }
+
+        if !config.disputes {
+            if config.withdrawal_disputes != WithdrawalDisputePolicy::Reject {
+                return Err(BuildError::Conflict(
+                    "withdrawal disputes require disputes to be enabled",
+                ));
+            }
+
+            if config.dispute_window != DisputeWindow::Unlimited {
+                return Err(BuildError::Conflict(
+                    "a dispute window requires disputes to be enabled",
+                ));
+            }
+
+            if config.deferred_disputes.is_some() {
+                return Err(BuildError::Conflict(
+                    "deferred disputes require disputes to be enabled",
+                ));
+            }
+        }
+
+        Ok(State {
+            config,
+            ..State::new_with_hasher(ahash::RandomState::default())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    fn deposit(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction {
+            tx_type: TransactionType::Deposit {
+                amount: checked(amount),
+            },
+            client,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    fn withdrawal(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction {
+            tx_type: TransactionType::Withdrawal {
+                amount: checked(amount),
+            },
+            client,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    fn dispute(tx_type: TransactionType, client: u16, tx: u32) -> Transaction {
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    #[test]
+    fn test_builder_defaults() {
+        let mut state = State::builder().build().unwrap();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        assert_eq!(
+            state.execute(withdrawal(1, 2, 20.0)),
+            Err(TxError::NotEnoughFunds)
+        );
+    }
+
+    #[test]
+    fn test_disputes_disabled() {
+        let mut state = State::builder().disputes(false).build().unwrap();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+
+        assert!(state.tx_cache.is_empty());
+        assert_eq!(
+            state.execute(dispute(TransactionType::Dispute, 1, 1)),
+            Err(TxError::DisputesDisabled)
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_disputes() {
+        let mut state = State::default();
+        state.execute(deposit(1, 1, 120.0)).unwrap();
+        state.execute(withdrawal(1, 2, 75.5)).unwrap();
+        assert_eq!(
+            state.execute(dispute(TransactionType::Dispute, 1, 2)),
+            Err(TxError::TxDoesntExist)
+        );
+
+        let template = State::builder().withdrawal_disputes(WithdrawalDisputePolicy::Allow);
+
+        let mut state = template.clone().build().unwrap();
+        state.execute(deposit(1, 1, 120.0)).unwrap();
+        state.execute(withdrawal(1, 2, 75.5)).unwrap();
+        state
+            .execute(dispute(TransactionType::Dispute, 1, 2))
+            .unwrap();
+
+        {
+            let account = state.accounts.get(&1).unwrap();
+            assert_eq!(account.total, 120.0);
+            assert_eq!(account.held, 75.5);
+            assert_eq!(account.available, 44.5);
+        }
+
+        state
+            .execute(dispute(TransactionType::Chargeback, 1, 2))
+            .unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.total, 120.0);
+        assert_eq!(account.held, 0.0);
+        assert_eq!(account.available, 120.0);
+        assert!(account.locked);
+
+        // Resolving a withdrawal dispute leaves the withdrawal in place.
+        let mut state = template.build().unwrap();
+        state.execute(deposit(1, 1, 120.0)).unwrap();
+        state.execute(withdrawal(1, 2, 75.5)).unwrap();
+        state
+            .execute(dispute(TransactionType::Dispute, 1, 2))
+            .unwrap();
+        state
+            .execute(dispute(TransactionType::Resolve, 1, 2))
+            .unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.total, 44.5);
+        assert_eq!(account.held, 0.0);
+        assert_eq!(account.available, 44.5);
+    }
+
+    #[test]
+    fn test_locked_account_deposits() {
+        let lock = |state: &mut State| {
+            state.execute(deposit(1, 1, 10.0)).unwrap();
+            state
+                .execute(dispute(TransactionType::Dispute, 1, 1))
+                .unwrap();
+            state
+                .execute(dispute(TransactionType::Chargeback, 1, 1))
+                .unwrap();
+        };
+
+        let mut state = State::default();
+        lock(&mut state);
+        assert_eq!(
+            state.execute(deposit(1, 2, 5.0)),
+            Err(TxError::AccountLocked)
+        );
+
+        let mut state = State::builder()
+            .locked_accounts(LockedAccountPolicy::AllowDeposits)
+            .build()
+            .unwrap();
+        lock(&mut state);
+        state.execute(deposit(1, 2, 5.0)).unwrap();
+        assert_eq!(
+            state.execute(withdrawal(1, 3, 5.0)),
+            Err(TxError::AccountLocked)
+        );
+        assert_eq!(state.accounts.get(&1).unwrap().available, 5.0);
+    }
+
+    #[test]
+    fn test_check_held_invariant() {
+        // Disputing a deposit whose funds have already been withdrawn holds more than the total.
+        let mut state = State::builder().check_held_invariant(true).build().unwrap();
+        state.execute(deposit(1, 1, 120.0)).unwrap();
+        state.execute(withdrawal(1, 2, 120.0)).unwrap();
+        assert_eq!(
+            state.execute(dispute(TransactionType::Dispute, 1, 1)),
+            Err(TxError::InvariantBreach)
+        );
+        assert_eq!(state.accounts.get(&1).unwrap().held, 0.0);
+        assert!(state.tx_cache[&1].dispute.is_none());
+
+        // Overdrawing an account with a partially disputed balance.
+        let mut state = State::builder()
+            .check_held_invariant(true)
+            .default_credit_limit(10.0)
+            .build()
+            .unwrap();
+        state.execute(deposit(1, 1, 100.0)).unwrap();
+        state.execute(deposit(1, 2, 50.0)).unwrap();
+        state
+            .execute(dispute(TransactionType::Dispute, 1, 1))
+            .unwrap();
+        state.execute(withdrawal(1, 3, 50.0)).unwrap();
+        assert_eq!(
+            state.execute(withdrawal(1, 4, 5.0)),
+            Err(TxError::InvariantBreach)
+        );
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.held, 100.0);
+        assert_eq!(account.total, 100.0);
+
+        // The same sequence is accepted when the check is disabled.
+        let mut state = State::builder().default_credit_limit(10.0).build().unwrap();
+        state.execute(deposit(1, 1, 100.0)).unwrap();
+        state.execute(deposit(1, 2, 50.0)).unwrap();
+        state
+            .execute(dispute(TransactionType::Dispute, 1, 1))
+            .unwrap();
+        state.execute(withdrawal(1, 3, 50.0)).unwrap();
+        state.execute(withdrawal(1, 4, 5.0)).unwrap();
+    }
+
+    #[test]
+    fn test_prevent_negative_available() {
+        let mut state = State::builder()
+            .prevent_negative_available(true)
+            .build()
+            .unwrap();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        state.execute(withdrawal(1, 2, 5.0)).unwrap();
+
+        assert_eq!(
+            state.execute(dispute(TransactionType::Dispute, 1, 1)),
+            Err(TxError::NotEnoughFunds)
+        );
+        assert_eq!(state.accounts.get(&1).unwrap().available, 5.0);
+    }
+
+    #[test]
+    fn test_dispute_window() {
+        let mut state = State::builder()
+            .dispute_window(DisputeWindow::Transactions(2))
+            .build()
+            .unwrap();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        state.execute(deposit(1, 2, 10.0)).unwrap();
+        state.execute(deposit(1, 3, 10.0)).unwrap();
+
+        assert_eq!(
+            state.execute(dispute(TransactionType::Dispute, 1, 1)),
+            Err(TxError::DisputeWindowExpired)
+        );
+        state
+            .execute(dispute(TransactionType::Dispute, 1, 3))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_no_create_on_error() {
+        let mut state = State::default();
+        assert_eq!(
+            state.execute(withdrawal(1, 1, 10.0)),
+            Err(TxError::NotEnoughFunds)
+        );
+        assert!(state.contains_account(1));
+
+        let mut state = State::builder().no_create_on_error(true).build().unwrap();
+        let mut events = Vec::new();
+        assert_eq!(
+            state.execute_with_events(withdrawal(1, 1, 10.0), |x| events.push(x)),
+            Err(TxError::NotEnoughFunds)
+        );
+        assert_eq!(
+            state.execute(dispute(TransactionType::Dispute, 2, 1)),
+            Err(TxError::TxDoesntExist)
+        );
+        assert!(events.is_empty());
+        assert!(!state.contains_account(1));
+        assert!(!state.contains_account(2));
+        assert_eq!(state.accounts().count(), 0);
+
+        // Accounts are still created by transactions that get commited, and kept afterwards.
+        state
+            .execute_with_events(deposit(1, 2, 10.0), |x| events.push(x))
+            .unwrap();
+        assert!(matches!(
+            events[..],
+            [
+                AccountEvent::AccountCreated { client: 1 },
+                AccountEvent::Deposited { client: 1, .. }
+            ]
+        ));
+        assert_eq!(
+            state.execute(withdrawal(1, 3, 20.0)),
+            Err(TxError::NotEnoughFunds)
+        );
+        assert!(state.contains_account(1));
+    }
+
+    #[test]
+    fn test_reserve_zero_client() {
+        let mut state = State::builder().reserve_zero_client(true).build().unwrap();
+        assert_eq!(
+            state.execute(deposit(0, 1, 10.0)),
+            Err(TxError::InvalidClient)
+        );
+        assert_eq!(state.accounts().count(), 0);
+
+        state.execute(deposit(1, 2, 10.0)).unwrap();
+
+        let mut state = State::builder().build().unwrap();
+        state.execute(deposit(0, 1, 10.0)).unwrap();
+        assert_eq!(state.accounts.get(&0).unwrap().total, 10.0);
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let mut state = State::builder().idempotent(true).build().unwrap();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        assert_eq!(
+            state.execute(deposit(1, 1, 10.0)),
+            Err(TxError::DuplicateTx)
+        );
+        assert_eq!(
+            state.execute(withdrawal(1, 1, 5.0)),
+            Err(TxError::DuplicateTx)
+        );
+        // Rejected transactions dont mark their id as seen.
+        assert_eq!(
+            state.execute(withdrawal(1, 2, 50.0)),
+            Err(TxError::NotEnoughFunds)
+        );
+        state.execute(withdrawal(1, 2, 5.0)).unwrap();
+
+        assert_eq!(state.accounts.get(&1).unwrap().total, 5.0);
+    }
+
+    #[test]
+    fn test_default_credit_limit() {
+        let mut state = State::builder().default_credit_limit(50.0).build().unwrap();
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        state.execute(withdrawal(1, 2, 60.0)).unwrap();
+        assert_eq!(
+            state.execute(withdrawal(1, 3, 0.5)),
+            Err(TxError::NotEnoughFunds)
+        );
+        assert_eq!(state.accounts.get(&1).unwrap().available, -50.0);
+    }
+
+    #[test]
+    fn test_withdrawal_velocity_limit() {
+        let mut state = State::builder()
+            .withdrawal_velocity_limit(10.0, 5)
+            .build()
+            .unwrap();
+        state.execute(deposit(1, 1, 100.0)).unwrap();
+        state.execute(withdrawal(1, 2, 4.0)).unwrap();
+        state.execute(withdrawal(1, 3, 4.0)).unwrap();
+        assert_eq!(
+            state.execute(withdrawal(1, 4, 4.0)),
+            Err(TxError::VelocityExceeded)
+        );
+        // The limit is per client.
+        state.execute(deposit(2, 5, 100.0)).unwrap();
+        state.execute(withdrawal(2, 6, 10.0)).unwrap();
+        // The first withdrawal has left the window by now, but the second one hasnt.
+        assert_eq!(
+            state.execute(withdrawal(1, 7, 6.5)),
+            Err(TxError::VelocityExceeded)
+        );
+        // Rejected withdrawals dont count towards the limit.
+        state.execute(withdrawal(1, 8, 10.0)).unwrap();
+        assert_eq!(state.accounts.get(&1).unwrap().available, 82.0);
+
+        // The same withdrawals succeed when spread out over the window.
+        let mut state = State::builder()
+            .withdrawal_velocity_limit(10.0, 4)
+            .build()
+            .unwrap();
+        state.execute(deposit(1, 1, 100.0)).unwrap();
+        for tx in 0..3 {
+            state.execute(withdrawal(1, 10 + tx, 4.0)).unwrap();
+            state.execute(deposit(2, 20 + tx, 1.0)).unwrap();
+            state.execute(deposit(3, 30 + tx, 1.0)).unwrap();
+        }
+        assert_eq!(state.accounts.get(&1).unwrap().available, 88.0);
+    }
+
+    #[test]
+    fn test_invalid_combinations() {
+        assert_eq!(
+            State::builder().default_credit_limit(-1.0).build().err(),
+            Some(BuildError::InvalidCreditLimit)
+        );
+        assert_eq!(
+            State::builder()
+                .withdrawal_velocity_limit(10.0, 0)
+                .build()
+                .err(),
+            Some(BuildError::InvalidVelocityLimit)
+        );
+        assert_eq!(
+            State::builder()
+                .withdrawal_velocity_limit(f64::NAN, 10)
+                .build()
+                .err(),
+            Some(BuildError::InvalidVelocityLimit)
+        );
+        assert!(State::builder()
+            .default_credit_limit(0.0)
+            .withdrawal_velocity_limit(0.0, 10)
+            .build()
+            .is_ok());
+        assert_eq!(
+            State::builder()
+                .dispute_window(DisputeWindow::Transactions(0))
+                .build()
+                .err(),
+            Some(BuildError::InvalidDisputeWindow)
+        );
+        assert!(matches!(
+            State::builder()
+                .prevent_negative_available(true)
+                .default_credit_limit(10.0)
+                .build(),
+            Err(BuildError::Conflict(_))
+        ));
+        assert!(matches!(
+            State::builder()
+                .disputes(false)
+                .withdrawal_disputes(WithdrawalDisputePolicy::Allow)
+                .build(),
+            Err(BuildError::Conflict(_))
+        ));
+        assert!(matches!(
+            State::builder()
+                .disputes(false)
+                .dispute_window(DisputeWindow::Transactions(10))
+                .build(),
+            Err(BuildError::Conflict(_))
+        ));
+    }
+}
@@ -0,0 +1,227 @@
+//! This module contains the clock providing the logical time of the state machine.
+
+use crate::{Money, State};
+use std::fmt;
+use std::hash::BuildHasher;
+
+/// Trait implemented by the clocks the state machine reads its logical time from. The time is
+/// read once per executed transaction and is what dispute windows are measured in.
+///
+/// Clocks are expected to be monotonic. A clock going backwards is treated as standing still.
+/// Implementors only need to derive `Clone`, which is required so that builders configured with a
+/// clock stay `Clone`.
+pub trait Clock: CloneClock + fmt::Debug + Send + Sync {
+    /// Returns the current logical time.
+    fn now(&mut self) -> u64;
+
+    /// Called with the timestamp of every transaction carrying one, right before `now` is read
+    /// for it. Does nothing by default.
+    fn observe(&mut self, _timestamp: u64) {}
+}
+
+/// Helper trait used to clone boxed clocks. It is implemented for every `Clock + Clone`.
+pub trait CloneClock {
+    /// Function will clone the clock into a new box.
+    fn clone_box(&self) -> Box<dyn Clock>;
+}
+
+impl<T: Clock + Clone + 'static> CloneClock for T {
+    fn clone_box(&self) -> Box<dyn Clock> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Clock> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Clock that ticks once every time it is read, so that the logical time equals the amount of
+/// transactions executed. This is the default clock of the state machine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CounterClock {
+    ticks: u64,
+}
+
+impl CounterClock {
+    /// Function will construct a counter that continues counting after `ticks`.
+    pub fn starting_at(ticks: u64) -> Self {
+        Self { ticks }
+    }
+}
+
+impl Clock for CounterClock {
+    fn now(&mut self) -> u64 {
+        self.ticks += 1;
+        self.ticks
+    }
+}
+
+/// Clock reading the logical time off the timestamps of the transactions, in milliseconds since
+/// the Unix epoch. Dispute windows and velocity windows are then measured in milliseconds, and
+/// time stands still for transactions without a timestamp.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimestampClock {
+    latest: u64,
+}
+
+impl Clock for TimestampClock {
+    fn now(&mut self) -> u64 {
+        self.latest
+    }
+
+    fn observe(&mut self, timestamp: u64) {
+        self.latest = self.latest.max(timestamp);
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will stop the logical time of the state machine, so that transactions executed
+    /// until `resume_clock` is called are all commited at the time of the last transaction. This
+    /// keeps bulk backfills from expiring dispute windows and velocity windows by their volume
+    /// alone.
+    ///
+    /// # Notes
+    /// The clock isnt read while frozen, so a `CounterClock` continues counting where it stopped
+    /// once resumed, while a clock tracking real time jumps ahead. Whether the clock is frozen
+    /// isnt part of snapshots.
+    pub fn freeze_clock(&mut self) {
+        self.clock_frozen = true;
+    }
+
+    /// Function will resume the logical time of the state machine after `freeze_clock`.
+    pub fn resume_clock(&mut self) {
+        self.clock_frozen = false;
+    }
+
+    /// Returns whether the logical time has been frozen by `freeze_clock`.
+    pub fn is_clock_frozen(&self) -> bool {
+        self.clock_frozen
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::DisputeWindow;
+    use crate::test::checked;
+    use crate::*;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    /// Clock whose time is set by the test through a shared handle.
+    #[derive(Clone, Debug, Default)]
+    struct MockClock(Arc<AtomicU64>);
+
+    impl MockClock {
+        fn set(&self, now: u64) {
+            self.0.store(now, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&mut self) -> u64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_counter_clock() {
+        let mut clock = CounterClock::default();
+        assert_eq!((clock.now(), clock.now()), (1, 2));
+        assert_eq!(CounterClock::starting_at(10).now(), 11);
+    }
+
+    #[test]
+    fn test_mock_clock_expires_dispute_window() {
+        let clock = MockClock::default();
+        let mut state = State::builder()
+            .clock(clock.clone())
+            .dispute_window(DisputeWindow::Transactions(10))
+            .build()
+            .unwrap();
+        let tx = |tx_type, tx| Transaction::new(tx_type, 1, tx);
+
+        clock.set(100);
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+            ))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                2,
+            ))
+            .unwrap();
+
+        // Any amount of transactions can be executed without time passing.
+        clock.set(110);
+        state.execute(tx(TransactionType::Dispute, 1)).unwrap();
+
+        clock.set(111);
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 2)),
+            Err(TxError::DisputeWindowExpired)
+        );
+
+        // A clock going backwards doesnt reopen the window.
+        clock.set(50);
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 2)),
+            Err(TxError::DisputeWindowExpired)
+        );
+    }
+
+    #[test]
+    fn test_frozen_clock_backfill() {
+        let backfill = |state: &mut State| {
+            for tx in 1..=1000 {
+                state
+                    .execute(Transaction::new(
+                        TransactionType::Deposit {
+                            amount: checked(1.0),
+                        },
+                        1,
+                        tx,
+                    ))
+                    .unwrap();
+            }
+        };
+        let builder = State::builder().dispute_window(DisputeWindow::Transactions(10));
+
+        let mut state = builder.clone().build().unwrap();
+        backfill(&mut state);
+        assert_eq!(
+            state.execute(Transaction::new(TransactionType::Dispute, 1, 1)),
+            Err(TxError::DisputeWindowExpired)
+        );
+
+        let mut state = builder.build().unwrap();
+        state.freeze_clock();
+        assert!(state.is_clock_frozen());
+        backfill(&mut state);
+        assert!(state.tx_cache.values().all(|x| x.seq == 0));
+
+        // The clock continues from where it was frozen, so every deposit is still disputable.
+        state.resume_clock();
+        assert!(!state.is_clock_frozen());
+        for tx in (1..=1000).step_by(111) {
+            state
+                .execute(Transaction::new(TransactionType::Dispute, 1, tx))
+                .unwrap();
+        }
+        assert_eq!(state.seq, 10);
+        assert_eq!(
+            state.execute(Transaction::new(TransactionType::Dispute, 1, 999)),
+            Err(TxError::DisputeWindowExpired)
+        );
+    }
+}
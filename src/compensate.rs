@@ -0,0 +1,171 @@
+//! This module contains compensating transactions, reversing a commited deposit that was made in
+//! error without going through the dispute flow.
+
+use super::*;
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will reverse a commited deposit, debiting its amount from the `available` and
+    /// `total` balances of the account. Unlike a chargeback, the account doesnt get locked.
+    ///
+    /// # Arguments
+    /// * `original_tx_id` - Id of the deposit to reverse.
+    /// * `client` - Id of the client owning the deposit.
+    ///
+    /// # Returns
+    /// Returns `TxError::TxDoesntExist` unless the deposit is cached, `TxError::Unauthorized` if it
+    /// belongs to another client, `TxError::TxAlreadyDisputed` while it is under dispute,
+    /// `TxError::AccountLocked` if the account is locked and `TxError::NotEnoughFunds` if the
+    /// amount is no longer available. If an error is returned no data has been modified.
+    ///
+    /// # Notes
+    /// The deposit is removed from the cache, so it can neither be disputed nor compensated again.
+    /// The registered observer, if any, is not notified.
+    pub fn execute_compensating(
+        &mut self,
+        original_tx_id: u32,
+        client: u16,
+    ) -> Result<(), TxError> {
+        self.execute_compensating_with_events(original_tx_id, client, |_| {})
+    }
+
+    /// Function will reverse a commited deposit like `execute_compensating`, passing the
+    /// `AccountEvent::Compensated` it commits to `sink`, so that it gets recorded along with the
+    /// events of regular transactions.
+    pub fn execute_compensating_with_events(
+        &mut self,
+        original_tx_id: u32,
+        client: u16,
+        mut sink: impl FnMut(AccountEvent<A>),
+    ) -> Result<(), TxError> {
+        let original = self
+            .tx_cache
+            .get(&original_tx_id)
+            .ok_or(TxError::TxDoesntExist)?;
+
+        let amount = match original.tx.tx_type {
+            TransactionType::Deposit { amount } => amount.get(),
+            _ => return Err(TxError::TxDoesntExist),
+        };
+
+        if original.tx.client != client {
+            return Err(TxError::Unauthorized);
+        }
+
+        if original.dispute == Some(DisputeState::Disputed) {
+            return Err(TxError::TxAlreadyDisputed);
+        }
+
+        let account = self
+            .accounts
+            .get_mut(&client)
+            .ok_or(TxError::AccountNotFound)?;
+
+        if account.locked {
+            return Err(TxError::AccountLocked);
+        }
+
+        if account.available + A::tolerance() < amount {
+            return Err(TxError::NotEnoughFunds);
+        }
+
+        if self.config.check_held && account.held > account.total - amount + A::tolerance() {
+            return Err(TxError::InvariantBreach);
+        }
+
+        account.available -= amount;
+        account.total -= amount;
+        account.normalize();
+
+        self.tx_cache.remove(&original_tx_id);
+
+        sink(AccountEvent::Compensated {
+            client,
+            tx: original_tx_id,
+            amount,
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    fn deposit(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction::new(
+            TransactionType::Deposit {
+                amount: checked(amount),
+            },
+            client,
+            tx,
+        )
+    }
+
+    #[test]
+    fn test_execute_compensating() {
+        let mut state = State::default();
+        let mut events = Vec::new();
+
+        let txs = [
+            deposit(1, 1, 10.0),
+            deposit(1, 2, 5.0),
+            deposit(2, 3, 1.0),
+            deposit(1, 4, 2.0),
+            Transaction::new(TransactionType::Dispute, 1, 4),
+        ];
+        for tx in txs {
+            state.execute_with_events(tx, |e| events.push(e)).unwrap();
+        }
+
+        assert_eq!(
+            state.execute_compensating(99, 1),
+            Err(TxError::TxDoesntExist)
+        );
+        assert_eq!(state.execute_compensating(2, 2), Err(TxError::Unauthorized));
+        assert_eq!(
+            state.execute_compensating(4, 1),
+            Err(TxError::TxAlreadyDisputed)
+        );
+
+        state
+            .execute_compensating_with_events(2, 1, |e| events.push(e))
+            .unwrap();
+        assert_eq!(
+            events.last(),
+            Some(&AccountEvent::Compensated {
+                client: 1,
+                tx: 2,
+                amount: 5.0,
+            })
+        );
+        assert_eq!(state.accounts[&1].available, 10.0);
+        assert_eq!(state.accounts[&1].total, 12.0);
+        assert!(!state.tx_cache.contains_key(&2));
+        assert_eq!(
+            state.execute_compensating(2, 1),
+            Err(TxError::TxDoesntExist)
+        );
+
+        // The deposit can no longer be reversed once its funds have been withdrawn.
+        let withdrawal = TransactionType::Withdrawal {
+            amount: checked(8.0),
+        };
+        state
+            .execute_with_events(Transaction::new(withdrawal, 1, 5), |e| events.push(e))
+            .unwrap();
+        let before = state.accounts[&1].clone();
+        assert_eq!(
+            state.execute_compensating(1, 1),
+            Err(TxError::NotEnoughFunds)
+        );
+        assert_eq!(state.accounts[&1], before);
+        assert!(state.tx_cache.contains_key(&1));
+
+        // The compensation is part of the event log, so replaying it reproduces the balances.
+        let replayed = State::from_events(events.into_iter()).unwrap();
+        assert_eq!(replayed.accounts[&1], state.accounts[&1]);
+        assert_eq!(replayed.accounts[&2], state.accounts[&2]);
+    }
+}
@@ -0,0 +1,644 @@
+//! This module contains currencies, and a state machine segregating balances per currency.
+//!
+//! Each currency is processed by its own `State`, so an account holds one set of balances per
+//! currency and no conversion ever takes place between them.
+
+use super::*;
+use crate::fallible::{FailureKind, FallibleFailure};
+use std::fmt;
+use std::num::NonZeroU16;
+use std::str::FromStr;
+
+/// Struct represents an ISO 4217 currency code, e.g. `USD`.
+///
+/// Codes are made of three ASCII letters, which are packed into two bytes so that tagging a
+/// transaction with its currency doesnt grow it. Currencies are ordered alphabetically.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Currency(NonZeroU16);
+
+/// Number of decimal places of the minor unit of ISO 4217 currencies, in alphabetical order of
+/// their code. Currencies missing from the table keep the full precision of the engine when
+/// serialized.
+const MINOR_UNIT_SCALES: [(&[u8; 3], u32); 47] = [
+    (b"AED", 2),
+    (b"AUD", 2),
+    (b"BHD", 3),
+    (b"BIF", 0),
+    (b"BRL", 2),
+    (b"CAD", 2),
+    (b"CHF", 2),
+    (b"CLF", 4),
+    (b"CLP", 0),
+    (b"CNY", 2),
+    (b"CZK", 2),
+    (b"DJF", 0),
+    (b"DKK", 2),
+    (b"EUR", 2),
+    (b"GBP", 2),
+    (b"GNF", 0),
+    (b"HKD", 2),
+    (b"HUF", 2),
+    (b"IDR", 2),
+    (b"ILS", 2),
+    (b"INR", 2),
+    (b"IQD", 3),
+    (b"ISK", 0),
+    (b"JOD", 3),
+    (b"JPY", 0),
+    (b"KMF", 0),
+    (b"KRW", 0),
+    (b"KWD", 3),
+    (b"LYD", 3),
+    (b"MXN", 2),
+    (b"NOK", 2),
+    (b"NZD", 2),
+    (b"OMR", 3),
+    (b"PLN", 2),
+    (b"PYG", 0),
+    (b"RWF", 0),
+    (b"SEK", 2),
+    (b"SGD", 2),
+    (b"TND", 3),
+    (b"TRY", 2),
+    (b"UGX", 0),
+    (b"USD", 2),
+    (b"VND", 0),
+    (b"VUV", 0),
+    (b"XAF", 0),
+    (b"XOF", 0),
+    (b"ZAR", 2),
+];
+
+/// Error returned when parsing a string that isnt a currency code.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("invalid currency code `{0}`")]
+pub struct ParseCurrencyError(String);
+
+impl Currency {
+    /// Returns the three letters of the currency code.
+    fn letters(self) -> [u8; 3] {
+        let code = self.0.get() - 1;
+        // NOTE: Every letter is below 26, so the casts never truncate.
+        [
+            b'A' + (code / 676) as u8,
+            b'A' + (code / 26 % 26) as u8,
+            b'A' + (code % 26) as u8,
+        ]
+    }
+
+    /// Returns the number of decimal places of the minor unit of the currency, e.g. `0` for `JPY`
+    /// and `3` for `BHD`, if it is a known ISO 4217 currency.
+    pub fn decimal_places(self) -> Option<u32> {
+        let letters = self.letters();
+        MINOR_UNIT_SCALES
+            .binary_search_by(|(code, _)| code.cmp(&&letters))
+            .ok()
+            .map(|i| MINOR_UNIT_SCALES[i].1)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = ParseCurrencyError;
+
+    /// Parses a currency code, ignoring surrounding whitespace and the case of its letters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let letters = match s.trim().as_bytes() {
+            code @ [_, _, _] if code.iter().all(u8::is_ascii_alphabetic) => code,
+            _ => return Err(ParseCurrencyError(s.to_string())),
+        };
+
+        let code = letters.iter().fold(0, |code, x| {
+            code * 26 + u16::from(x.to_ascii_uppercase() - b'A')
+        });
+
+        Ok(Self(NonZeroU16::new(code + 1).unwrap()))
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.letters()
+            .iter()
+            .try_for_each(|x| write!(f, "{}", *x as char))
+    }
+}
+
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Currency({})", self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Currency {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        String::deserialize(de)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Function will deserialize an optional currency, treating empty strings as a missing currency.
+#[cfg(feature = "serde")]
+pub(crate) fn deserialize_currency<'de, D: serde::Deserializer<'de>>(
+    de: D,
+) -> Result<Option<Currency>, D::Error> {
+    match Option::<String>::deserialize(de)? {
+        Some(x) if !x.trim().is_empty() => x.parse().map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+/// Struct represents a state machine segregating balances per currency. Transactions without a
+/// currency are processed in the base currency.
+///
+/// Disputes, resolves and chargebacks settle in the currency of the transaction they refer to,
+/// and are rejected with `TxError::CurrencyMismatch` if they carry another currency. Withdrawals
+/// in a currency the client holds no balance in are rejected with `TxError::CurrencyMismatch` as
+/// well. Chargebacks only lock the balances of the client in the currency of the chargeback.
+pub struct MultiCurrencyState {
+    base: Currency,
+    states: BTreeMap<Currency, State>,
+    decimal_places: BTreeMap<Currency, u32>,
+    factory: Box<dyn Fn() -> State + Send + Sync>,
+}
+
+/// Struct represents the balances of a client in a single currency.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CurrencyAccount<'a> {
+    pub currency: Currency,
+    pub account: &'a Account,
+    /// The number of decimal places the balances are serialized with, if any.
+    pub decimal_places: Option<u32>,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for CurrencyAccount<'_> {
+    /// Serializes the account with a `currency` field following the `client` field. With
+    /// `decimal_places`, balances are rounded half to even and written as strings with exactly
+    /// that many decimal places, e.g. `"1500"` or `"2.500"`. The `total` written is the `total`
+    /// balance rounded once, rather than the sum of the rounded `available` and `held` balances,
+    /// so that it matches the sum checked by footers. The columns can therefore be off by one
+    /// minor unit.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let account = self.account;
+
+        let mut s = serializer.serialize_struct("Account", 6)?;
+        s.serialize_field("client", &account.id)?;
+        s.serialize_field("currency", &self.currency)?;
+        match self.decimal_places {
+            Some(places) => {
+                for (name, balance) in [
+                    ("available", account.available),
+                    ("held", account.held),
+                    ("total", account.total),
+                ] {
+                    let balance = balance.round_to(places, RoundingMode::HalfEven);
+                    let balance = format!("{:.*}", places as usize, without_negative_zero(balance));
+                    s.serialize_field(name, &balance)?;
+                }
+            }
+            None => {
+                s.serialize_field("available", &without_negative_zero(account.available))?;
+                s.serialize_field("held", &without_negative_zero(account.held))?;
+                s.serialize_field("total", &without_negative_zero(account.total))?;
+            }
+        }
+        s.serialize_field("locked", &account.locked)?;
+        s.end()
+    }
+}
+
+impl MultiCurrencyState {
+    /// Function will construct a state machine processing every currency with the default
+    /// policies.
+    ///
+    /// # Arguments
+    /// * `base` - Currency of the transactions that dont carry one.
+    pub fn new(base: Currency) -> Self {
+        Self::with_factory(base, State::default)
+    }
+
+    /// Function will construct a state machine processing every currency with a state machine
+    /// returned by `factory`, which gets called the first time a currency is seen.
+    pub fn with_factory(
+        base: Currency,
+        factory: impl Fn() -> State + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            base,
+            states: BTreeMap::new(),
+            decimal_places: BTreeMap::new(),
+            factory: Box::new(factory),
+        }
+    }
+
+    /// Returns the currency of the transactions that dont carry one.
+    pub fn base_currency(&self) -> Currency {
+        self.base
+    }
+
+    /// Function will serialize the balances in `currency` with `places` decimal places, overriding
+    /// the scale of its minor unit. See `Currency::decimal_places`.
+    pub fn set_decimal_places(&mut self, currency: Currency, places: u32) {
+        self.decimal_places.insert(currency, places);
+    }
+
+    /// Returns the number of decimal places the balances in `currency` are serialized with, if
+    /// any. Currencies that have neither been configured with `set_decimal_places` nor are known
+    /// ISO 4217 currencies are serialized with the full precision of the engine.
+    pub fn decimal_places(&self, currency: Currency) -> Option<u32> {
+        self.decimal_places
+            .get(&currency)
+            .copied()
+            .or_else(|| currency.decimal_places())
+    }
+
+    /// Returns the state machine processing `currency`, if any transaction has been seen in it.
+    pub fn state(&self, currency: Currency) -> Option<&State> {
+        self.states.get(&currency)
+    }
+
+    /// Function will execute a transaction in the currency it settles in.
+    ///
+    /// # Returns
+    /// Same errors as `State::execute`, along with `TxError::CurrencyMismatch`.
+    pub fn execute(&mut self, tx: Transaction) -> Result<(), TxError> {
+        let currency = self.settlement_currency(&tx)?;
+
+        if matches!(tx.tx_type, TransactionType::Withdrawal { .. })
+            && !self
+                .states
+                .get(&currency)
+                .is_some_and(|x| x.contains_account(tx.client))
+        {
+            return Err(TxError::CurrencyMismatch);
+        }
+
+        let factory = &self.factory;
+        self.states
+            .entry(currency)
+            .or_insert_with(factory)
+            .execute(tx)
+    }
+
+    /// Function returns the currency `tx` settles in.
+    fn settlement_currency(&self, tx: &Transaction) -> Result<Currency, TxError> {
+        let is_dispute = matches!(
+            tx.tx_type,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+        );
+        if !is_dispute {
+            return Ok(tx.currency.unwrap_or(self.base));
+        }
+
+        let caches = |currency: &Currency| {
+            self.states
+                .get(currency)
+                .is_some_and(|x| x.tx_cache.contains_key(&tx.tx))
+        };
+        let referenced = self.states.keys().copied().find(caches);
+
+        match (tx.currency, referenced) {
+            (Some(currency), _) if caches(&currency) => Ok(currency),
+            (Some(_), Some(_)) => Err(TxError::CurrencyMismatch),
+            (Some(currency), None) => Ok(currency),
+            (None, referenced) => Ok(referenced.unwrap_or(self.base)),
+        }
+    }
+
+    /// Returns the balances of every client in every currency, in ascending order of their
+    /// `client` id and then of their currency.
+    pub fn accounts(&self) -> Vec<CurrencyAccount<'_>> {
+        let mut accounts: Vec<_> = self
+            .states
+            .iter()
+            .flat_map(|(currency, state)| {
+                let decimal_places = self.decimal_places(*currency);
+                state.accounts.values().map(move |account| CurrencyAccount {
+                    currency: *currency,
+                    account,
+                    decimal_places,
+                })
+            })
+            .collect();
+        accounts.sort_by_key(|x| (x.account.id, x.currency));
+
+        accounts
+    }
+
+    /// Function will construct the state machine and replay all the transactions from the
+    /// fallible iterator passed in, like `State::from_fallible_iterator`.
+    ///
+    /// # Arguments
+    /// * `base` - Currency of the transactions that dont carry one.
+    /// * `txs` - Iterator over owned `Transactions` or the errors encountered producing them.
+    pub fn from_fallible_iterator<E>(
+        base: Currency,
+        txs: impl Iterator<Item = Result<Transaction, E>>,
+    ) -> (Self, Vec<FallibleFailure<E>>) {
+        let mut this = Self::new(base);
+        let mut failures = Vec::new();
+
+        for (position, tx) in txs.enumerate() {
+            let kind = match tx {
+                Ok(tx) => {
+                    let (client, id) = (tx.client, tx.tx);
+                    let tag = tx.tx_type.tag().to_string();
+                    match this.execute(tx) {
+                        Ok(()) => continue,
+                        Err(error) => FailureKind::Rejected {
+                            client,
+                            tx: id,
+                            tag,
+                            error,
+                        },
+                    }
+                }
+                Err(e) => FailureKind::Upstream(e),
+            };
+
+            failures.push(FallibleFailure { position, kind });
+        }
+
+        (this, failures)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    fn currency(code: &str) -> Currency {
+        code.parse().unwrap()
+    }
+
+    fn tx(tx_type: TransactionType, client: u16, tx: u32, code: &str) -> Transaction {
+        Transaction::new(tx_type, client, tx).with_currency(currency(code))
+    }
+
+    /// Returns the balances of `client` in `code` as `(available, held, total, locked)`.
+    fn balances(state: &MultiCurrencyState, client: u16, code: &str) -> (f64, f64, f64, bool) {
+        let account = &state.state(currency(code)).unwrap().accounts[&client];
+        (
+            account.available,
+            account.held,
+            account.total,
+            account.locked,
+        )
+    }
+
+    #[test]
+    fn test_currency() {
+        assert_eq!(currency(" usd ").to_string(), "USD");
+        assert_eq!(format!("{:?}", currency("ZZZ")), "Currency(ZZZ)");
+        assert!(currency("AAA") < currency("AAB") && currency("EUR") < currency("GBP"));
+        assert_eq!(std::mem::size_of::<Option<Currency>>(), 2);
+
+        for code in ["", "US", "USDT", "U$D", "€UR"] {
+            assert_eq!(
+                code.parse::<Currency>(),
+                Err(ParseCurrencyError(code.into()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_segregated_balances() {
+        let mut state = MultiCurrencyState::new(currency("USD"));
+        let txs = [
+            Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                1,
+                2,
+                "EUR",
+            ),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                3,
+                "EUR",
+            ),
+            // Settles in EUR, the currency of the disputed deposit.
+            Transaction::new(TransactionType::Dispute, 1, 2),
+        ];
+        for tx in txs {
+            state.execute(tx).unwrap();
+        }
+
+        assert_eq!(balances(&state, 1, "USD"), (10.0, 0.0, 10.0, false));
+        assert_eq!(balances(&state, 1, "EUR"), (1.0, 5.0, 6.0, false));
+
+        assert_eq!(
+            state.execute(tx(TransactionType::Resolve, 1, 2, "USD")),
+            Err(TxError::CurrencyMismatch)
+        );
+        assert_eq!(
+            state.execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(1.0)
+                },
+                1,
+                4,
+                "GBP"
+            )),
+            Err(TxError::CurrencyMismatch)
+        );
+        assert!(state.state(currency("GBP")).is_none());
+        assert_eq!(
+            state.execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(2.0)
+                },
+                1,
+                5,
+                "EUR"
+            )),
+            Err(TxError::NotEnoughFunds)
+        );
+
+        // The chargeback only locks the EUR balances.
+        state
+            .execute(tx(TransactionType::Chargeback, 1, 2, "EUR"))
+            .unwrap();
+        assert_eq!(balances(&state, 1, "EUR"), (1.0, 0.0, 1.0, true));
+        state
+            .execute(Transaction::new(
+                TransactionType::Withdrawal {
+                    amount: checked(4.0),
+                },
+                1,
+                6,
+            ))
+            .unwrap();
+        assert_eq!(balances(&state, 1, "USD"), (6.0, 0.0, 6.0, false));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialization() {
+        let rdr = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(
+            "type, client, tx, amount, currency\n\
+                 deposit, 2, 1, 1.5, gbp\n\
+                 deposit, 1, 2, 2.0,\n\
+                 deposit, 1, 3, 3.0, EUR\n\
+                 deposit, 1, 4, 1.0, EURO\n"
+                .as_bytes(),
+        );
+
+        let (state, failures) =
+            MultiCurrencyState::from_fallible_iterator(currency("USD"), rdr.into_deserialize());
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].position, 3);
+
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        for account in state.accounts() {
+            wtr.serialize(account).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(wtr.into_inner().unwrap()).unwrap(),
+            "client,currency,available,held,total,locked\n\
+             1,EUR,3.00,0.00,3.00,false\n\
+             1,USD,2.00,0.00,2.00,false\n\
+             2,GBP,1.50,0.00,1.50,false\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_decimal_places() {
+        assert!(MINOR_UNIT_SCALES.windows(2).all(|x| x[0].0 < x[1].0));
+        assert_eq!(currency("jpy").decimal_places(), Some(0));
+        assert_eq!(currency("BHD").decimal_places(), Some(3));
+        assert_eq!(currency("ZZZ").decimal_places(), None);
+
+        let mut state = MultiCurrencyState::new(currency("USD"));
+        let txs = [
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(1500.0),
+                },
+                1,
+                1,
+                "JPY",
+            ),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(2.5),
+                },
+                1,
+                2,
+                "JPY",
+            ),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(1.2345),
+                },
+                1,
+                3,
+                "BHD",
+            ),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(0.25),
+                },
+                1,
+                4,
+                "BHD",
+            ),
+            Transaction::new(TransactionType::Dispute, 1, 4),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(1.2345),
+                },
+                1,
+                5,
+                "ZZZ",
+            ),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(1.2345),
+                },
+                1,
+                6,
+                "XAU",
+            ),
+        ];
+        for tx in txs {
+            state.execute(tx).unwrap();
+        }
+        state.set_decimal_places(currency("XAU"), 1);
+
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        for account in state.accounts() {
+            wtr.serialize(account).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(wtr.into_inner().unwrap()).unwrap(),
+            "client,currency,available,held,total,locked\n\
+             1,BHD,1.234,0.250,1.484,false\n\
+             1,JPY,1502,0,1502,false\n\
+             1,XAU,1.2,0.0,1.2,false\n\
+             1,ZZZ,1.2345,0.0,1.2345,false\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_decimal_places_total() {
+        let mut state = MultiCurrencyState::with_factory(currency("USD"), || {
+            State::builder().footer_marker("footer").build().unwrap()
+        });
+        let deposit = |id| {
+            let amount = checked(0.5);
+            tx(TransactionType::Deposit { amount }, 1, id, "JPY")
+        };
+        state.execute(deposit(1)).unwrap();
+        state.execute(deposit(2)).unwrap();
+        state
+            .execute(Transaction::new(TransactionType::Dispute, 1, 2))
+            .unwrap();
+
+        // Both halves round down to even, but the total is rounded on its own.
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        for account in state.accounts() {
+            wtr.serialize(account).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(wtr.into_inner().unwrap()).unwrap(),
+            "client,currency,available,held,total,locked\n1,JPY,0,0,1,false\n"
+        );
+
+        // A footer carrying the written total is accepted.
+        let footer = |amount| {
+            let tag = "footer".to_string();
+            tx(TransactionType::Other { tag, amount }, 0, 0, "JPY")
+        };
+        state.execute(footer(Some(1.0))).unwrap();
+        assert!(matches!(
+            state.execute(footer(Some(0.0))),
+            Err(TxError::FooterMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,338 @@
+//! This module contains the extension point used to execute custom transaction types.
+
+use super::*;
+use std::fmt;
+
+/// Struct represents the raw fields of a transaction with a custom type.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CustomRecord<'a, A = f64> {
+    /// The `type` of the transaction.
+    pub tag: &'a str,
+    /// The client the transaction belongs to.
+    pub client: u16,
+    /// The id of the transaction.
+    pub tx: u32,
+    /// The amount of the transaction, if any.
+    pub amount: Option<A>,
+}
+
+/// Trait implemented by types able to execute custom transaction types.
+pub trait CustomHandler<A = f64>: fmt::Debug + Send + Sync {
+    /// Function will execute `record` against `account`.
+    ///
+    /// # Returns
+    /// If an error is returned, or if the balances of `account` no longer satisfy
+    /// `total == held + available` once the handler returns, none of the changes are commited.
+    fn handle(
+        &self,
+        record: &CustomRecord<A>,
+        account: &mut AccountHandle<A>,
+    ) -> Result<(), TxError>;
+}
+
+/// Struct represents restricted mutable access to an account, handed out to a `CustomHandler`.
+/// Changes are only commited once the state machine has verified that the balances are still
+/// consistent.
+#[derive(Debug)]
+pub struct AccountHandle<A = f64> {
+    account: Account<A>,
+}
+
+impl<A: Money> AccountHandle<A> {
+    /// Returns the id of the client this account belongs to.
+    pub fn id(&self) -> u16 {
+        self.account.id
+    }
+
+    /// Returns the available balance of the account.
+    pub fn available(&self) -> A {
+        self.account.available
+    }
+
+    /// Returns the held balance of the account.
+    pub fn held(&self) -> A {
+        self.account.held
+    }
+
+    /// Returns the total balance of the account.
+    pub fn total(&self) -> A {
+        self.account.total
+    }
+
+    /// Adds `amount` to the `available` and `total` balances.
+    pub fn credit(&mut self, amount: A) {
+        self.account.available += amount;
+        self.account.total += amount;
+    }
+
+    /// Removes `amount` from the `available` and `total` balances.
+    pub fn debit(&mut self, amount: A) {
+        self.account.available -= amount;
+        self.account.total -= amount;
+    }
+
+    /// Moves `amount` from the `available` balance to the `held` balance.
+    pub fn hold(&mut self, amount: A) {
+        self.account.available -= amount;
+        self.account.held += amount;
+    }
+
+    /// Moves `amount` from the `held` balance back to the `available` balance.
+    pub fn release(&mut self, amount: A) {
+        self.account.held -= amount;
+        self.account.available += amount;
+    }
+
+    /// Sets the `available` balance directly. Callers are responsible for keeping the balances
+    /// consistent.
+    pub fn set_available(&mut self, amount: A) {
+        self.account.available = amount;
+    }
+
+    /// Sets the `held` balance directly. Callers are responsible for keeping the balances
+    /// consistent.
+    pub fn set_held(&mut self, amount: A) {
+        self.account.held = amount;
+    }
+
+    /// Sets the `total` balance directly. Callers are responsible for keeping the balances
+    /// consistent.
+    pub fn set_total(&mut self, amount: A) {
+        self.account.total = amount;
+    }
+
+    /// Locks the account.
+    pub fn lock(&mut self) {
+        self.account.locked = true;
+    }
+}
+
+/// Function will run `handler` against a copy of `account`, commiting the changes only if the
+/// handler succeeded and the balances are still consistent.
+///
+/// # Returns
+/// Returns the changes that have been commited, or `TxError::InvariantBreach` if the handler left
+/// the balances in an inconsistent state. If `check_held` is set, a `held` balance exceeding the
+/// `total` balance is considered inconsistent as well.
+pub(crate) fn run_handler<A: Money>(
+    handler: &dyn CustomHandler<A>,
+    record: &CustomRecord<A>,
+    account: &mut Account<A>,
+    check_held: bool,
+    balance_limit: Option<A>,
+) -> Result<HoldOutcome<A>, TxError> {
+    let mut handle = AccountHandle {
+        account: account.clone(),
+    };
+    handler.handle(record, &mut handle)?;
+
+    let updated = handle.account;
+    if !updated.available.is_finite()
+        || !updated.held.is_finite()
+        || !updated.total.is_finite()
+        || !updated.is_balanced()
+        || (check_held && !updated.is_held_within_total())
+    {
+        return Err(TxError::InvariantBreach);
+    }
+
+    if updated.total > account.total {
+        check_balance_limit(account, updated.total - account.total, balance_limit)?;
+    }
+
+    let outcome = HoldOutcome {
+        available: updated.available - account.available,
+        held: updated.held - account.held,
+        total: updated.total - account.total,
+        lock: updated.locked && !account.locked,
+    };
+
+    *account = updated;
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    /// Credits the amount of the transaction, plus a 10% bonus.
+    #[derive(Debug)]
+    struct Bonus;
+
+    impl CustomHandler for Bonus {
+        fn handle(
+            &self,
+            record: &CustomRecord,
+            account: &mut AccountHandle,
+        ) -> Result<(), TxError> {
+            let amount = record
+                .amount
+                .ok_or_else(|| TxError::ParseError("missing amount".into()))?;
+            account.credit(amount * 1.1);
+            Ok(())
+        }
+    }
+
+    /// Credits `available` without touching `total`.
+    #[derive(Debug)]
+    struct Broken;
+
+    impl CustomHandler for Broken {
+        fn handle(&self, _: &CustomRecord, account: &mut AccountHandle) -> Result<(), TxError> {
+            account.set_available(account.available() + 100.0);
+            Ok(())
+        }
+    }
+
+    fn custom(tag: &str, client: u16, tx: u32, amount: Option<f64>) -> Transaction {
+        Transaction {
+            tx_type: TransactionType::Other {
+                tag: tag.into(),
+                amount,
+            },
+            client,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    #[test]
+    fn test_bonus_handler() {
+        let mut state = State::builder()
+            .custom_handler("bonus", Bonus)
+            .build()
+            .unwrap();
+        let mut events = Vec::new();
+
+        state
+            .execute_with_events(custom("bonus", 1, 1, Some(10.0)), |e| events.push(e))
+            .unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 11.0);
+        assert_eq!(account.total, 11.0);
+        assert!(matches!(
+            events.last(),
+            Some(AccountEvent::Adjusted { available, .. }) if *available == 11.0
+        ));
+
+        assert_eq!(
+            state.execute(custom("bonus", 1, 2, None)),
+            Err(TxError::ParseError("missing amount".into()))
+        );
+
+        // Custom transactions are never cached, so they cannot be disputed.
+        assert_eq!(
+            state.execute(Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            }),
+            Err(TxError::TxDoesntExist)
+        );
+    }
+
+    #[test]
+    fn test_handler_balance_limit() {
+        let mut state = State::builder()
+            .custom_handler("bonus", Bonus)
+            .balance_limit(12.0)
+            .build()
+            .unwrap();
+
+        state.execute(custom("bonus", 1, 1, Some(10.0))).unwrap();
+        assert!(matches!(
+            state.execute(custom("bonus", 1, 2, Some(1.0))),
+            Err(TxError::BalanceLimitExceeded { .. })
+        ));
+        assert_eq!(state.accounts[&1].total, 11.0);
+    }
+
+    #[test]
+    fn test_broken_handler() {
+        let mut state = State::builder()
+            .custom_handler("broken", Broken)
+            .build()
+            .unwrap();
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            state.execute(custom("broken", 1, 2, None)),
+            Err(TxError::InvariantBreach)
+        );
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 5.0);
+        assert_eq!(account.total, 5.0);
+    }
+
+    #[test]
+    fn test_unsupported_type() {
+        let mut state = State::builder()
+            .custom_handler("bonus", Bonus)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            state.execute(custom("transfer", 1, 1, Some(10.0))),
+            Err(TxError::UnsupportedType)
+        );
+        assert_eq!(state.accounts().count(), 0);
+    }
+
+    #[test]
+    fn test_standard_type_handler() {
+        for tag in ["deposit", "hold"] {
+            assert_eq!(
+                State::builder().custom_handler(tag, Bonus).build().err(),
+                Some(BuildError::Conflict(
+                    "custom handlers cannot override a standard transaction type"
+                ))
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_standard_types_unaffected() {
+        let mut state = State::builder()
+            .custom_handler("bonus", Bonus)
+            .build()
+            .unwrap();
+
+        for row in [
+            "deposit, 1, 1, 10.0",
+            "withdrawal, 1, 2, 2.5",
+            "bonus, 1, 3, 10.0",
+            "dispute, 1, 1,",
+        ] {
+            state.execute_csv_row(row).unwrap();
+        }
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 8.5);
+        assert_eq!(account.held, 10.0);
+        assert_eq!(account.total, 18.5);
+    }
+}
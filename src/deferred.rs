@@ -0,0 +1,281 @@
+//! This module contains transactions whose execution is deferred until another transaction has
+//! been executed.
+
+use super::*;
+
+/// Enum represents the outcome of executing a transaction that may be, or may trigger, a deferred
+/// transaction.
+#[derive(Debug, PartialEq)]
+pub enum ExecuteOutcome<A = f64> {
+    /// The transaction was executed without triggering any deferred transaction.
+    Executed,
+    /// The transaction was queued until its trigger is executed.
+    Deferred,
+    /// The transaction was executed and triggered deferred transactions, listed in execution order
+    /// along with their result. Deferred transactions triggered by those are listed as well.
+    DeferredTrigger(Vec<(Transaction<A>, Result<(), TxError>)>),
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will defer the execution of `tx` until a transaction with the id
+    /// `execute_at_tx_id` has been executed successfully. Transactions deferred on the same id are
+    /// executed in the order they were deferred, right after their trigger.
+    ///
+    /// # Arguments
+    /// * `tx` - Transaction to be executed.
+    /// * `execute_at_tx_id` - Id of the transaction triggering the execution of `tx`.
+    ///
+    /// # Returns
+    /// Returns `ExecuteOutcome::Deferred` once queued. If a deposit or withdrawal with the id
+    /// `execute_at_tx_id` is already cached, the trigger has already been processed and `tx` is
+    /// executed right away, returning the same as `execute_triggering`.
+    ///
+    /// # Notes
+    /// Pending deferred transactions arent part of snapshots, and a rejected trigger doesnt
+    /// release them.
+    pub fn execute_deferred(
+        &mut self,
+        tx: Transaction<A>,
+        execute_at_tx_id: u32,
+    ) -> Result<ExecuteOutcome<A>, TxError> {
+        if self.tx_cache.contains_key(&execute_at_tx_id) {
+            return self.execute_triggering(tx);
+        }
+
+        self.pending_deferred
+            .entry(execute_at_tx_id)
+            .or_default()
+            .push(tx);
+
+        Ok(ExecuteOutcome::Deferred)
+    }
+
+    /// Function will execute a transaction along with any deferred transaction it triggers. This
+    /// is what `execute` does, but reporting the triggered transactions.
+    ///
+    /// # Returns
+    /// Same errors as `execute`, in which case no deferred transaction is triggered.
+    pub fn execute_triggering(&mut self, tx: Transaction<A>) -> Result<ExecuteOutcome<A>, TxError> {
+        let id = tx.tx;
+        self.execute_with_events(tx, |_| {})?;
+
+        let triggered = self.trigger_deferred(id);
+        if triggered.is_empty() {
+            Ok(ExecuteOutcome::Executed)
+        } else {
+            Ok(ExecuteOutcome::DeferredTrigger(triggered))
+        }
+    }
+
+    /// Returns the number of transactions waiting on their trigger.
+    pub fn pending_deferred_count(&self) -> usize {
+        self.pending_deferred.values().map(Vec::len).sum()
+    }
+
+    /// Returns the number of disputes parked until their deposit is commited. See
+    /// `StateBuilder::deferred_disputes`.
+    pub fn pending_dispute_count(&self) -> usize {
+        self.pending_disputes.len()
+    }
+
+    /// Function will execute the transactions deferred on `tx_id`, and in turn those deferred on
+    /// the ones that succeed.
+    pub(crate) fn trigger_deferred(
+        &mut self,
+        tx_id: u32,
+    ) -> Vec<(Transaction<A>, Result<(), TxError>)> {
+        let mut results = Vec::new();
+        let mut queue: VecDeque<Transaction<A>> = match self.pending_deferred.remove(&tx_id) {
+            Some(txs) => txs.into(),
+            None => return results,
+        };
+
+        while let Some(tx) = queue.pop_front() {
+            let result = self.execute_with_events(tx.clone(), |_| {});
+            if result.is_ok() {
+                if let Some(txs) = self.pending_deferred.remove(&tx.tx) {
+                    queue.extend(txs);
+                }
+            }
+
+            results.push((tx, result));
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use std::sync::Mutex;
+
+    fn deposit(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction::new(
+            TransactionType::Deposit {
+                amount: checked(amount),
+            },
+            client,
+            tx,
+        )
+    }
+
+    #[test]
+    fn test_execute_deferred() {
+        let mut state = State::default();
+
+        assert_eq!(
+            state.execute_deferred(deposit(2, 100, 5.0), 3),
+            Ok(ExecuteOutcome::Deferred)
+        );
+        assert_eq!(
+            state.execute_deferred(deposit(2, 101, 1.0), 100),
+            Ok(ExecuteOutcome::Deferred)
+        );
+        assert_eq!(state.pending_deferred_count(), 2);
+
+        for tx in 1..=2 {
+            assert_eq!(
+                state.execute_triggering(deposit(1, tx, 1.0)),
+                Ok(ExecuteOutcome::Executed)
+            );
+        }
+        assert!(!state.contains_account(2));
+
+        // A rejected trigger doesnt release the deferred transactions.
+        let withdrawal = TransactionType::Withdrawal {
+            amount: checked(50.0),
+        };
+        assert_eq!(
+            state.execute_triggering(Transaction::new(withdrawal, 1, 3)),
+            Err(TxError::NotEnoughFunds)
+        );
+        assert_eq!(state.pending_deferred_count(), 2);
+
+        // The deferred deposit executes exactly when its trigger does, and triggers the next one.
+        assert_eq!(
+            state.execute_triggering(deposit(1, 3, 1.0)),
+            Ok(ExecuteOutcome::DeferredTrigger(vec![
+                (deposit(2, 100, 5.0), Ok(())),
+                (deposit(2, 101, 1.0), Ok(())),
+            ]))
+        );
+        assert_eq!(state.accounts[&2].available, 6.0);
+        assert_eq!(state.pending_deferred_count(), 0);
+
+        // Plain `execute` triggers deferred transactions as well.
+        state.execute_deferred(deposit(3, 200, 2.0), 4).unwrap();
+        state.execute(deposit(1, 4, 1.0)).unwrap();
+        assert_eq!(state.accounts[&3].available, 2.0);
+
+        // Transactions deferred on an already cached transaction execute right away.
+        assert_eq!(
+            state.execute_deferred(deposit(3, 201, 2.0), 1),
+            Ok(ExecuteOutcome::Executed)
+        );
+        assert_eq!(state.accounts[&3].available, 4.0);
+    }
+
+    #[test]
+    fn test_deferred_disputes() {
+        let mut state = State::builder().deferred_disputes(2).build().unwrap();
+        let dispute = |client, tx| Transaction::new(TransactionType::Dispute, client, tx);
+
+        // The dispute precedes its deposit, and is applied once the deposit arrives.
+        state.execute(dispute(1, 1)).unwrap();
+        assert_eq!(state.pending_dispute_count(), 1);
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        assert_eq!(state.pending_dispute_count(), 0);
+        assert_eq!(
+            (state.accounts[&1].available, state.accounts[&1].held),
+            (0.0, 10.0)
+        );
+        state
+            .execute(Transaction::new(TransactionType::Resolve, 1, 1))
+            .unwrap();
+        assert_eq!(state.accounts[&1].available, 10.0);
+
+        // Only a deposit of the same client releases the dispute.
+        state.execute(dispute(2, 5)).unwrap();
+        state.execute(deposit(3, 5, 4.0)).unwrap();
+        assert_eq!(state.accounts[&3].held, 0.0);
+        assert_eq!(state.pending_dispute_count(), 1);
+
+        // Disputes are rejected as usual once the cap is reached.
+        state.execute(dispute(2, 6)).unwrap();
+        assert_eq!(state.execute(dispute(2, 7)), Err(TxError::TxDoesntExist));
+
+        // Disputes arent parked by default.
+        let mut state = State::default();
+        assert_eq!(state.execute(dispute(1, 1)), Err(TxError::TxDoesntExist));
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        assert_eq!(state.accounts[&1].held, 0.0);
+
+        assert!(State::builder()
+            .disputes(false)
+            .deferred_disputes(2)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_deferred_dispute_bookkeeping() {
+        #[derive(Debug, Default)]
+        struct Rejections(Mutex<Vec<(Transaction, String)>>);
+
+        impl StateObserver for Rejections {
+            fn on_execute_error(&self, tx: &Transaction, err: &TxError) {
+                self.0.lock().unwrap().push((tx.clone(), err.to_string()));
+            }
+        }
+
+        /// Only lets clients dispute their own transactions, except for a suspended one.
+        #[derive(Debug)]
+        struct Suspended(u16);
+
+        impl DisputeAuthorizer for Suspended {
+            fn may_dispute(&self, client: u16, disputed: &CachedTx) -> bool {
+                client == disputed.transaction().client() && client != self.0
+            }
+        }
+
+        let rejections = Arc::new(Rejections::default());
+        let mut state = State::builder()
+            .deferred_disputes(4)
+            .dispute_authorizer(Suspended(2))
+            .build()
+            .unwrap();
+        let dispute = |client, tx| Transaction::new(TransactionType::Dispute, client, tx);
+
+        // Parked disputes are counted and their own ids remembered when they are parked.
+        state.execute(dispute(1, 1).with_event_id(100)).unwrap();
+        assert_eq!(state.count_transactions_by_type()["dispute"], 1);
+        assert_eq!(
+            state.execute(dispute(1, 2).with_event_id(100)),
+            Err(TxError::DuplicateTx)
+        );
+
+        // A dispute parked for the same deposit doesnt replace the first one.
+        assert_eq!(state.execute(dispute(1, 1)), Err(TxError::DuplicateTx));
+        assert_eq!(state.pending_dispute_count(), 1);
+
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        assert_eq!(state.accounts[&1].held, 10.0);
+        assert_eq!(state.open_disputes()[0].dispute_event_id(), Some(100));
+        assert_eq!(state.count_transactions_by_type()["dispute"], 1);
+
+        // A parked dispute rejected once its deposit arrives is reported, the deposit isnt.
+        state.register_observer(rejections.clone());
+        state.execute(dispute(2, 4)).unwrap();
+        state.execute(deposit(2, 4, 5.0)).unwrap();
+        assert_eq!(
+            (state.accounts[&2].available, state.accounts[&2].held),
+            (5.0, 0.0)
+        );
+        assert_eq!(
+            *rejections.0.lock().unwrap(),
+            [(dispute(2, 4), TxError::Unauthorized.to_string())]
+        );
+    }
+}
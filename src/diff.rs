@@ -0,0 +1,262 @@
+//! This module contains helpers used to observe the changes a transaction commits to an account.
+
+use super::*;
+use std::collections::BTreeMap;
+
+/// Struct represents the changes a single transaction commited to an account.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AccountDiff<A = f64> {
+    /// The id of the client the transaction applied to.
+    pub client: u16,
+    /// Delta applied to the `available` balance.
+    pub available_delta: A,
+    /// Delta applied to the `held` balance.
+    pub held_delta: A,
+    /// Delta applied to the `total` balance.
+    pub total_delta: A,
+    /// The new lock state of the account, if it changed.
+    pub locked_changed: Option<bool>,
+}
+
+/// Struct represents an account whose balances or lock state changed during a batch of
+/// transactions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountDelta<A = f64> {
+    /// The id of the client the account belongs to.
+    pub client: u16,
+    /// The account as it was before the batch. Accounts created by the batch are represented by
+    /// an empty account.
+    pub before: Account<A>,
+    /// The account as it is after the batch.
+    pub after: Account<A>,
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will execute a transaction, returning the changes it commited to the account of
+    /// the client.
+    ///
+    /// # Arguments
+    /// * `tx` - Transaction to be executed.
+    ///
+    /// # Returns
+    /// Same errors as `execute`. Accounts created by the transaction are diffed against an empty
+    /// account.
+    pub fn execute_returning_diff(
+        &mut self,
+        tx: Transaction<A>,
+    ) -> Result<AccountDiff<A>, TxError> {
+        let client = tx.client;
+        let before = self.accounts.get(&client).cloned().unwrap_or_default();
+
+        self.execute(tx)?;
+
+        Ok(self.diff_since(client, &before))
+    }
+
+    /// Returns the changes commited to the account of `client` since it was in the `before`
+    /// state. A missing account is treated as unchanged.
+    pub(crate) fn diff_since(&self, client: u16, before: &Account<A>) -> AccountDiff<A> {
+        let after = self.accounts.get(&client).unwrap_or(before);
+
+        AccountDiff {
+            client,
+            available_delta: after.available - before.available,
+            held_delta: after.held - before.held,
+            total_delta: after.total - before.total,
+            locked_changed: if after.locked != before.locked {
+                Some(after.locked)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Function will execute a batch of transactions, returning the accounts that changed along
+    /// with their state before and after the batch.
+    ///
+    /// # Arguments
+    /// * `txs` - Iterator over owned `Transactions`.
+    ///
+    /// # Returns
+    /// The changed accounts in ascending order of their `id`. Accounts touched by the batch whose
+    /// net change is nil are left out.
+    ///
+    /// # Notes
+    /// Like `from_iterator`, transactions that error out are skipped.
+    pub fn execute_batch_with_deltas(
+        &mut self,
+        txs: impl IntoIterator<Item = Transaction<A>>,
+    ) -> Vec<AccountDelta<A>> {
+        let mut before = BTreeMap::new();
+
+        for tx in txs {
+            let accounts = &self.accounts;
+            before.entry(tx.client).or_insert_with(|| {
+                accounts.get(&tx.client).cloned().unwrap_or(Account {
+                    id: tx.client,
+                    ..Account::default()
+                })
+            });
+
+            let _ = self.execute(tx);
+        }
+
+        before
+            .into_iter()
+            .filter_map(|(client, before)| {
+                let after = self.accounts.get(&client)?;
+
+                (*after != before).then(|| AccountDelta {
+                    client,
+                    before,
+                    after: after.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    fn tx(tx_type: TransactionType, tx: u32) -> Transaction {
+        Transaction {
+            tx_type,
+            client: 1,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    fn diff(available_delta: f64, held_delta: f64, total_delta: f64) -> AccountDiff {
+        AccountDiff {
+            client: 1,
+            available_delta,
+            held_delta,
+            total_delta,
+            locked_changed: None,
+        }
+    }
+
+    #[test]
+    fn test_execute_returning_diff() {
+        let mut state = State::default();
+
+        assert_eq!(
+            state.execute_returning_diff(tx(
+                TransactionType::Deposit {
+                    amount: checked(100.0)
+                },
+                1
+            )),
+            Ok(diff(100.0, 0.0, 100.0))
+        );
+        assert_eq!(
+            state.execute_returning_diff(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(25.0)
+                },
+                2
+            )),
+            Ok(diff(-25.0, 0.0, -25.0))
+        );
+        assert_eq!(
+            state.execute_returning_diff(tx(TransactionType::Dispute, 1)),
+            Ok(diff(-100.0, 100.0, 0.0))
+        );
+        assert_eq!(
+            state.execute_returning_diff(tx(TransactionType::Resolve, 1)),
+            Ok(diff(100.0, -100.0, 0.0))
+        );
+
+        state
+            .execute_returning_diff(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                3,
+            ))
+            .unwrap();
+        state
+            .execute_returning_diff(tx(TransactionType::Dispute, 3))
+            .unwrap();
+        assert_eq!(
+            state.execute_returning_diff(tx(TransactionType::Chargeback, 3)),
+            Ok(AccountDiff {
+                locked_changed: Some(true),
+                ..diff(0.0, -10.0, -10.0)
+            })
+        );
+
+        assert_eq!(
+            state.execute_returning_diff(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0)
+                },
+                4
+            )),
+            Err(TxError::AccountLocked)
+        );
+    }
+
+    #[test]
+    fn test_execute_batch_with_deltas() {
+        let deposit = |client, tx, amount| {
+            Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(amount),
+                },
+                client,
+                tx,
+            )
+        };
+        let withdrawal = |client, tx, amount| {
+            Transaction::new(
+                TransactionType::Withdrawal {
+                    amount: checked(amount),
+                },
+                client,
+                tx,
+            )
+        };
+
+        let mut state = State::default();
+        for client in 1..=4 {
+            state.execute(deposit(client, client as u32, 10.0)).unwrap();
+        }
+
+        let account = |state: &State, client| state.accounts[&client].clone();
+        let (one, two, three) = (account(&state, 1), account(&state, 2), account(&state, 3));
+
+        let deltas = state.execute_batch_with_deltas(vec![
+            deposit(3, 10, 5.0),
+            withdrawal(1, 11, 2.5),
+            // Rejected, so account 2 is left untouched.
+            withdrawal(2, 12, 100.0),
+            deposit(5, 13, 1.0),
+            Transaction::new(TransactionType::Dispute, 3, 3),
+            Transaction::new(TransactionType::Resolve, 3, 3),
+            Transaction::new(TransactionType::Dispute, 1, 1),
+        ]);
+
+        assert_eq!(
+            deltas.iter().map(|x| x.client).collect::<Vec<_>>(),
+            vec![1, 3, 5]
+        );
+        assert_eq!(deltas[0].before, one);
+        assert_eq!(deltas[0].after.available, -2.5);
+        assert_eq!(deltas[0].after.held, 10.0);
+        assert_eq!(deltas[0].after.total, 7.5);
+        assert_eq!(deltas[1].before, three);
+        assert_eq!(deltas[1].after.total, 15.0);
+        assert_eq!(deltas[2].before.total, 0.0);
+        assert_eq!(deltas[2].after.total, 1.0);
+        assert_eq!(account(&state, 2), two);
+        assert_eq!(account(&state, 4).total, 10.0);
+    }
+}
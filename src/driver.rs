@@ -0,0 +1,375 @@
+//! This module contains the driver feeding transactions from asynchronous, offset based sources
+//! such as message buses into the state machine.
+//!
+//! Sources are read with at-least-once semantics: an offset is only commited once every
+//! transaction up to and including it has been handled, so a crash between executing a batch and
+//! commiting it replays the batch on restart. Replayed deposits and withdrawals are rejected with
+//! `TxError::DuplicateTx` when the state machine is in idempotent mode, making the replay safe.
+//!
+//! # Notes
+//! Idempotent mode doesnt deduplicate disputes, resolves and chargebacks. A replayed dispute is
+//! rejected as already disputed, but a replayed dispute whose resolution was also replayed opens
+//! the dispute again.
+
+use super::*;
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+/// Type represents a batch of transactions along with their offsets.
+pub type Batch<O, A = f64> = Vec<(O, Transaction<A>)>;
+
+/// Trait implemented by asynchronous sources yielding transactions along with their offset.
+pub trait AsyncTransactionSource<A = f64> {
+    /// The position of a transaction within the source.
+    type Offset: Clone + Send;
+
+    /// Function will read the next batch of transactions from the source.
+    ///
+    /// # Returns
+    /// Returns an empty batch once the source has been exhausted. Sources that never end, such as
+    /// message buses, are expected to wait for new transactions instead. Malformed messages
+    /// should be skipped by the source, any error is treated as the source having failed.
+    fn next_batch(
+        &mut self,
+    ) -> impl Future<Output = Result<Batch<Self::Offset, A>, SourceError>> + Send;
+
+    /// Function will mark every transaction up to and including `offset` as handled, so that it
+    /// isnt read again once the source is restarted.
+    fn commit(
+        &mut self,
+        offset: Self::Offset,
+    ) -> impl Future<Output = Result<(), SourceError>> + Send;
+}
+
+/// Enum represents how often `run_engine` commits the offsets of handled transactions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommitPolicy {
+    /// Commit once per batch, after its last transaction has been handled.
+    EveryBatch,
+    /// Commit after every handled transaction.
+    EveryTransaction,
+}
+
+/// Struct represents the transactions handled by `run_engine`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunSummary {
+    /// The number of transactions commited by the state machine.
+    pub executed: usize,
+    /// The number of transactions rejected by the state machine.
+    pub rejected: usize,
+}
+
+/// Function will read batches of transactions from `source`, execute them and commit their
+/// offsets until the source is exhausted or `shutdown` completes.
+///
+/// # Arguments
+/// * `source` - Source of the transactions.
+/// * `state` - State machine the transactions are executed against.
+/// * `policy` - How often offsets are commited.
+/// * `shutdown` - Future completing once the driver should stop, e.g. on ctrl-c.
+///
+/// # Returns
+/// Returns the first error returned by the source, in which case the offsets of the current
+/// batch may not have been commited. Rejected transactions count as handled and are commited
+/// along with the rest of the batch.
+///
+/// # Notes
+/// On shutdown the transaction being executed is finished and the offsets of the handled prefix
+/// of the batch are commited, the rest of the batch is left to be read again.
+pub async fn run_engine<S, H, A>(
+    source: &mut S,
+    state: &mut State<H, A>,
+    policy: CommitPolicy,
+    shutdown: impl Future<Output = ()>,
+) -> Result<RunSummary, SourceError>
+where
+    S: AsyncTransactionSource<A>,
+    H: BuildHasher,
+    A: Money,
+{
+    let mut shutdown = pin!(shutdown);
+    let mut summary = RunSummary::default();
+
+    loop {
+        let batch = {
+            let mut next = pin!(source.next_batch());
+            let batch = std::future::poll_fn(|cx| {
+                if shutdown.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+
+                next.as_mut().poll(cx).map(Some)
+            })
+            .await;
+
+            match batch {
+                Some(batch) => batch?,
+                None => return Ok(summary),
+            }
+        };
+
+        if batch.is_empty() {
+            return Ok(summary);
+        }
+
+        let mut handled = None;
+        let mut stopped = false;
+        for (offset, tx) in batch {
+            match state.execute(tx) {
+                Ok(()) => summary.executed += 1,
+                Err(_) => summary.rejected += 1,
+            }
+
+            if policy == CommitPolicy::EveryTransaction {
+                source.commit(offset).await?;
+            } else {
+                handled = Some(offset);
+            }
+
+            // NOTE: The shutdown future is polled without being woken, as it is only checked
+            // between transactions.
+            let mut cx = Context::from_waker(Waker::noop());
+            if shutdown.as_mut().poll(&mut cx).is_ready() {
+                stopped = true;
+                break;
+            }
+        }
+
+        if let Some(offset) = handled {
+            source.commit(offset).await?;
+        }
+
+        if stopped {
+            return Ok(summary);
+        }
+    }
+}
+
+/// Struct represents an in-memory source, mainly useful for testing the commit behaviour of
+/// consumers. Offsets are the positions of the transactions.
+#[derive(Clone, Debug)]
+pub struct MemorySource<A = f64> {
+    txs: Vec<Transaction<A>>,
+    batch_size: usize,
+    position: usize,
+    committed: usize,
+    failing_commits: usize,
+}
+
+impl<A: Clone> MemorySource<A> {
+    /// Function will construct a source yielding `txs` in batches of `batch_size` transactions.
+    pub fn new(txs: Vec<Transaction<A>>, batch_size: usize) -> Self {
+        Self {
+            txs,
+            batch_size: batch_size.max(1),
+            position: 0,
+            committed: 0,
+            failing_commits: 0,
+        }
+    }
+
+    /// Returns the number of transactions that have been commited.
+    pub fn committed(&self) -> usize {
+        self.committed
+    }
+
+    /// Function will make the next `n` commits fail with `SourceError::Io`, simulating a crash
+    /// after a batch has been executed but before its offsets were commited.
+    pub fn fail_next_commits(&mut self, n: usize) {
+        self.failing_commits = n;
+    }
+
+    /// Function will rewind the source to the last commited offset, as if the consumer had been
+    /// restarted.
+    pub fn restart(&mut self) {
+        self.position = self.committed;
+    }
+}
+
+impl<A: Clone + Send> AsyncTransactionSource<A> for MemorySource<A> {
+    type Offset = usize;
+
+    async fn next_batch(&mut self) -> Result<Batch<usize, A>, SourceError> {
+        let end = (self.position + self.batch_size).min(self.txs.len());
+        let batch = (self.position..end)
+            .zip(self.txs[self.position..end].iter().cloned())
+            .collect();
+        self.position = end;
+
+        Ok(batch)
+    }
+
+    async fn commit(&mut self, offset: usize) -> Result<(), SourceError> {
+        if self.failing_commits > 0 {
+            self.failing_commits -= 1;
+            return Err(SourceError::Io("commit failed".into()));
+        }
+
+        self.committed = self.committed.max(offset + 1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use std::future::{pending, ready};
+
+    fn deposit(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction {
+            tx_type: TransactionType::Deposit {
+                amount: checked(amount),
+            },
+            client,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    fn withdrawal(client: u16, tx: u32, amount: f64) -> Transaction {
+        Transaction {
+            tx_type: TransactionType::Withdrawal {
+                amount: checked(amount),
+            },
+            client,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    fn transactions() -> Vec<Transaction> {
+        vec![
+            deposit(1, 1, 10.0),
+            deposit(2, 2, 5.0),
+            withdrawal(1, 3, 20.0),
+            withdrawal(1, 4, 2.5),
+            Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 2,
+                tx: 2,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            },
+            deposit(2, 5, 1.0),
+            withdrawal(2, 6, 1.0),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_run_engine() {
+        for policy in [CommitPolicy::EveryBatch, CommitPolicy::EveryTransaction] {
+            let mut source = MemorySource::new(transactions(), 3);
+            let mut state = State::default();
+
+            let summary = run_engine(&mut source, &mut state, policy, pending())
+                .await
+                .unwrap();
+            assert_eq!(
+                summary,
+                RunSummary {
+                    executed: 6,
+                    rejected: 1
+                }
+            );
+            assert_eq!(source.committed(), 7);
+            assert_eq!(state.accounts[&1].available, 7.5);
+            assert_eq!(state.accounts[&2].held, 5.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_commits_handled_prefix() {
+        let mut source = MemorySource::new(transactions(), 3);
+        let mut state = State::default();
+
+        let summary = run_engine(&mut source, &mut state, CommitPolicy::EveryBatch, ready(()))
+            .await
+            .unwrap();
+        assert_eq!(summary, RunSummary::default());
+        assert_eq!(source.committed(), 0);
+
+        // A shutdown during a batch stops after the current transaction.
+        let mut polled = false;
+        let shutdown = std::future::poll_fn(|_| {
+            if polled {
+                Poll::Ready(())
+            } else {
+                polled = true;
+                Poll::Pending
+            }
+        });
+        let summary = run_engine(&mut source, &mut state, CommitPolicy::EveryBatch, shutdown)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            RunSummary {
+                executed: 1,
+                rejected: 0
+            }
+        );
+        assert_eq!(source.committed(), 1);
+
+        // The rest of the batch is read again on restart.
+        source.restart();
+        run_engine(&mut source, &mut state, CommitPolicy::EveryBatch, pending())
+            .await
+            .unwrap();
+        assert_eq!(source.committed(), 7);
+        assert_eq!(state.accounts[&1].available, 7.5);
+    }
+
+    #[tokio::test]
+    async fn test_crash_before_commit_replays_idempotently() {
+        let mut source = MemorySource::new(transactions(), 4);
+        let mut state = State::builder().idempotent(true).build().unwrap();
+
+        // The first batch is executed, but the consumer crashes before commiting it.
+        source.fail_next_commits(1);
+        let result = run_engine(&mut source, &mut state, CommitPolicy::EveryBatch, pending()).await;
+        assert!(matches!(result, Err(SourceError::Io(_))));
+        assert_eq!(source.committed(), 0);
+        let before = state.accounts[&1].clone();
+
+        // On restart the whole batch is delivered again and rejected, either as duplicates or for
+        // the same reason as the first time.
+        source.restart();
+        let summary = run_engine(&mut source, &mut state, CommitPolicy::EveryBatch, pending())
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            RunSummary {
+                executed: 3,
+                rejected: 4
+            }
+        );
+        assert_eq!(source.committed(), 7);
+        assert_eq!(state.accounts[&1], before);
+        assert_eq!(state.accounts[&2].available, 0.0);
+        assert_eq!(state.accounts[&2].held, 5.0);
+
+        // Without idempotent mode the replay counts the batch twice.
+        let mut source = MemorySource::new(transactions(), 4);
+        let mut state = State::default();
+        source.fail_next_commits(1);
+        let result = run_engine(&mut source, &mut state, CommitPolicy::EveryBatch, pending()).await;
+        assert!(result.is_err());
+
+        source.restart();
+        run_engine(&mut source, &mut state, CommitPolicy::EveryBatch, pending())
+            .await
+            .unwrap();
+        assert_eq!(state.accounts[&1].available, 15.0);
+    }
+}
@@ -0,0 +1,473 @@
+//! This module contains the ledger effects describing the double-entry movement performed by a
+//! transaction.
+
+use super::*;
+
+/// Enum represents one side of a double-entry movement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bucket {
+    /// Funds outside of the engine, e.g. the bank account a deposit came from.
+    External,
+    /// The `available` balance of the account.
+    Available,
+    /// The `held` balance of the account.
+    Held,
+}
+
+/// Enum represents the semantic kind of a ledger effect.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EffectKind {
+    /// Funds have been deposited into the account.
+    Deposit,
+    /// Funds have been withdrawn from the account.
+    Withdrawal,
+    /// Funds have been placed on hold by a dispute.
+    DisputeHold,
+    /// Funds have been released from hold by a resolved dispute.
+    DisputeRelease,
+    /// Funds on hold have been reversed by a chargeback.
+    Chargeback,
+    /// Interest has been credited to the account.
+    Interest,
+    /// A withdrawal has been refunded to the account.
+    Refund,
+    /// Funds have been placed on hold manually.
+    ManualHold,
+    /// Funds have been released from a manual hold.
+    ManualRelease,
+    /// Custom transaction executed by a `CustomHandler`, carrying the tag of the transaction.
+    Custom(String),
+}
+
+impl EffectKind {
+    /// Returns a human readable description of the effect, e.g. `"dispute hold"`. Custom effects
+    /// are described by their tag.
+    pub fn description(&self) -> &str {
+        match self {
+            EffectKind::Deposit => "deposit",
+            EffectKind::Withdrawal => "withdrawal",
+            EffectKind::DisputeHold => "dispute hold",
+            EffectKind::DisputeRelease => "dispute release",
+            EffectKind::Chargeback => "chargeback",
+            EffectKind::Interest => "interest",
+            EffectKind::Refund => "refund",
+            EffectKind::ManualHold => "manual hold",
+            EffectKind::ManualRelease => "manual release",
+            EffectKind::Custom(tag) => tag,
+        }
+    }
+}
+
+/// Struct represents the double-entry movement commited by a single transaction.
+///
+/// # Notes
+/// `from`, `to` and `amount` describe the primary movement of the transaction. Policies can move
+/// additional funds, e.g. the fee charged by `ChargebackFeePolicy`, so the deltas are the
+/// authoritative record of what changed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LedgerEffect<A = f64> {
+    /// The id of the client the transaction applied to.
+    pub client: u16,
+    /// The id of the transaction.
+    pub tx: u32,
+    /// The semantic kind of the movement.
+    pub kind: EffectKind,
+    /// The bucket funds have been moved out of.
+    pub from: Bucket,
+    /// The bucket funds have been moved into.
+    pub to: Bucket,
+    /// The amount moved from `from` to `to`.
+    pub amount: A,
+    /// Delta applied to the `available` balance.
+    pub available_delta: A,
+    /// Delta applied to the `held` balance.
+    pub held_delta: A,
+    /// Delta applied to the `total` balance.
+    pub total_delta: A,
+    /// The lock state of the account after the transaction.
+    pub locked: bool,
+    /// The id of the input row the transaction was read from, if it was tagged with one.
+    pub row_id: Option<u64>,
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will execute a transaction, returning the ledger effect it commited.
+    ///
+    /// # Arguments
+    /// * `tx` - Transaction to be executed.
+    ///
+    /// # Returns
+    /// Same errors as `execute`.
+    pub fn execute_with_effect(&mut self, tx: Transaction<A>) -> Result<LedgerEffect<A>, TxError> {
+        let (client, row_id) = (tx.client, tx.row_id);
+        let before = self.accounts.get(&client).cloned().unwrap_or_default();
+        let ignored = match tx.tx_type {
+            TransactionType::Deposit { .. } => Some((
+                tx.tx,
+                EffectKind::Deposit,
+                Bucket::External,
+                Bucket::Available,
+            )),
+            TransactionType::Withdrawal { .. } => Some((
+                tx.tx,
+                EffectKind::Withdrawal,
+                Bucket::Available,
+                Bucket::External,
+            )),
+            _ => None,
+        };
+
+        let mut event = None;
+        self.execute_with_events(tx, |e| {
+            if !matches!(e, AccountEvent::AccountCreated { .. }) {
+                event = Some(e);
+            }
+        })?;
+
+        let after = self.accounts.get(&client).unwrap_or(&before);
+        let available_delta = after.available - before.available;
+        let held_delta = after.held - before.held;
+        let total_delta = after.total - before.total;
+
+        // Every commited transaction emits exactly one event besides `AccountCreated`, except for
+        // the zero amounts ignored by `ZeroAmountPolicy::Ignore`, which move nothing.
+        let event = match (event, ignored) {
+            (Some(event), _) => event,
+            (None, Some((tx, kind, from, to))) => {
+                return Ok(LedgerEffect {
+                    client,
+                    tx,
+                    kind,
+                    from,
+                    to,
+                    amount: A::ZERO,
+                    available_delta,
+                    held_delta,
+                    total_delta,
+                    locked: after.locked,
+                    row_id,
+                })
+            }
+            (None, None) => return Err(TxError::InternalError),
+        };
+
+        let (tx, kind, from, to, amount) = match event {
+            AccountEvent::Deposited { tx, amount, .. } => (
+                tx,
+                EffectKind::Deposit,
+                Bucket::External,
+                Bucket::Available,
+                amount,
+            ),
+            AccountEvent::Withdrawn { tx, amount, .. } => (
+                tx,
+                EffectKind::Withdrawal,
+                Bucket::Available,
+                Bucket::External,
+                amount,
+            ),
+            AccountEvent::DisputeOpened { tx, amount, .. } => {
+                let from = source_of(available_delta);
+                (tx, EffectKind::DisputeHold, from, Bucket::Held, amount)
+            }
+            AccountEvent::DisputeResolved { tx, amount, .. } => {
+                let to = source_of(-available_delta);
+                (tx, EffectKind::DisputeRelease, Bucket::Held, to, amount)
+            }
+            AccountEvent::ChargedBack { tx, amount, .. } => (
+                tx,
+                EffectKind::Chargeback,
+                Bucket::Held,
+                Bucket::External,
+                amount,
+            ),
+            AccountEvent::Adjusted { tx, tag, .. } => {
+                let (from, to, amount) = if !total_delta.is_negligible() {
+                    if total_delta.is_negative() {
+                        (Bucket::Available, Bucket::External, -total_delta)
+                    } else {
+                        (Bucket::External, Bucket::Available, total_delta)
+                    }
+                } else if held_delta.is_negative() {
+                    (Bucket::Held, Bucket::Available, -held_delta)
+                } else {
+                    (Bucket::Available, Bucket::Held, held_delta)
+                };
+
+                (tx, EffectKind::Custom(tag), from, to, amount)
+            }
+            AccountEvent::InterestCredited { tx, amount, .. } => (
+                tx,
+                EffectKind::Interest,
+                Bucket::External,
+                Bucket::Available,
+                amount,
+            ),
+            AccountEvent::Refunded { tx, amount, .. } => (
+                tx,
+                EffectKind::Refund,
+                Bucket::External,
+                Bucket::Available,
+                amount,
+            ),
+            AccountEvent::HoldPlaced { tx, amount, .. } => (
+                tx,
+                EffectKind::ManualHold,
+                Bucket::Available,
+                Bucket::Held,
+                amount,
+            ),
+            AccountEvent::HoldReleased { tx, amount, .. } => (
+                tx,
+                EffectKind::ManualRelease,
+                Bucket::Held,
+                Bucket::Available,
+                amount,
+            ),
+            AccountEvent::AccountCreated { .. } | AccountEvent::Compensated { .. } => {
+                unreachable!()
+            }
+        };
+
+        Ok(LedgerEffect {
+            client,
+            tx,
+            kind,
+            from,
+            to,
+            amount,
+            available_delta,
+            held_delta,
+            total_delta,
+            locked: after.locked,
+            row_id,
+        })
+    }
+}
+
+/// Returns the bucket funds placed on hold came from, given the delta applied to `available`.
+/// Holds that dont reduce `available`, like disputes on withdrawals, are funded externally.
+fn source_of<A: Money>(available_delta: A) -> Bucket {
+    if available_delta.is_negative() && !available_delta.is_negligible() {
+        Bucket::Available
+    } else {
+        Bucket::External
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    fn tx(tx_type: TransactionType, tx: u32) -> Transaction {
+        Transaction::new(tx_type, 1, tx)
+    }
+
+    fn effect(kind: EffectKind, tx: u32, from: Bucket, to: Bucket, amount: f64) -> LedgerEffect {
+        LedgerEffect {
+            client: 1,
+            tx,
+            kind,
+            from,
+            to,
+            amount,
+            available_delta: 0.0,
+            held_delta: 0.0,
+            total_delta: 0.0,
+            locked: false,
+            row_id: None,
+        }
+    }
+
+    #[test]
+    fn test_execute_with_effect() {
+        let mut state = State::default();
+
+        assert_eq!(
+            state.execute_with_effect(tx(
+                TransactionType::Deposit {
+                    amount: checked(100.0)
+                },
+                1
+            )),
+            Ok(LedgerEffect {
+                available_delta: 100.0,
+                total_delta: 100.0,
+                ..effect(
+                    EffectKind::Deposit,
+                    1,
+                    Bucket::External,
+                    Bucket::Available,
+                    100.0
+                )
+            })
+        );
+        assert_eq!(
+            state.execute_with_effect(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(25.0)
+                },
+                2
+            )),
+            Ok(LedgerEffect {
+                available_delta: -25.0,
+                total_delta: -25.0,
+                ..effect(
+                    EffectKind::Withdrawal,
+                    2,
+                    Bucket::Available,
+                    Bucket::External,
+                    25.0
+                )
+            })
+        );
+        assert_eq!(
+            state.execute_with_effect(tx(TransactionType::Dispute, 1)),
+            Ok(LedgerEffect {
+                available_delta: -100.0,
+                held_delta: 100.0,
+                ..effect(
+                    EffectKind::DisputeHold,
+                    1,
+                    Bucket::Available,
+                    Bucket::Held,
+                    100.0
+                )
+            })
+        );
+        assert_eq!(
+            state.execute_with_effect(tx(TransactionType::Resolve, 1)),
+            Ok(LedgerEffect {
+                available_delta: 100.0,
+                held_delta: -100.0,
+                ..effect(
+                    EffectKind::DisputeRelease,
+                    1,
+                    Bucket::Held,
+                    Bucket::Available,
+                    100.0
+                )
+            })
+        );
+
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                3,
+            ))
+            .unwrap();
+        state.execute(tx(TransactionType::Dispute, 3)).unwrap();
+        assert_eq!(
+            state.execute_with_effect(tx(TransactionType::Chargeback, 3)),
+            Ok(LedgerEffect {
+                held_delta: -10.0,
+                total_delta: -10.0,
+                locked: true,
+                ..effect(
+                    EffectKind::Chargeback,
+                    3,
+                    Bucket::Held,
+                    Bucket::External,
+                    10.0
+                )
+            })
+        );
+
+        assert_eq!(
+            state.execute_with_effect(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0)
+                },
+                4
+            )),
+            Err(TxError::AccountLocked)
+        );
+    }
+
+    #[test]
+    fn test_custom_effect() {
+        #[derive(Debug)]
+        struct Bonus;
+
+        impl CustomHandler for Bonus {
+            fn handle(
+                &self,
+                record: &CustomRecord,
+                account: &mut AccountHandle,
+            ) -> Result<(), TxError> {
+                account.credit(record.amount.unwrap_or_default());
+                Ok(())
+            }
+        }
+
+        let mut state = State::builder()
+            .custom_handler("bonus", Bonus)
+            .build()
+            .unwrap();
+
+        let bonus = TransactionType::Other {
+            tag: "bonus".into(),
+            amount: Some(5.0),
+        };
+        let effect = state.execute_with_effect(tx(bonus, 1)).unwrap();
+        assert_eq!(effect.kind.description(), "bonus");
+        assert_eq!(
+            (effect.from, effect.to),
+            (Bucket::External, Bucket::Available)
+        );
+        assert_eq!(effect.amount, 5.0);
+        assert_eq!(effect.total_delta, 5.0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_row_id() {
+        let data = "row_id, type, client, tx, amount\n\
+                    a1, deposit, 1, 1, 10.0\n\
+                    17, deposit, 1, 2, 5.0\n\
+                    18, withdrawal, 1, 3, 2.5\n\
+                    , dispute, 1, 2,\n";
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+        let mut records = reader.deserialize::<Transaction>();
+        assert!(records.next().unwrap().is_err());
+
+        let mut state = State::default();
+        let effects: Vec<_> = records
+            .map(|x| state.execute_with_effect(x.unwrap()).unwrap())
+            .map(|x| (x.tx, x.kind, x.row_id))
+            .collect();
+        assert_eq!(
+            effects,
+            [
+                (2, EffectKind::Deposit, Some(17)),
+                (3, EffectKind::Withdrawal, Some(18)),
+                (2, EffectKind::DisputeHold, None),
+            ]
+        );
+
+        let effect = state
+            .execute_with_effect(
+                tx(
+                    TransactionType::Deposit {
+                        amount: checked(1.0),
+                    },
+                    4,
+                )
+                .with_row_id(19),
+            )
+            .unwrap();
+        assert_eq!(effect.row_id, Some(19));
+    }
+
+    #[test]
+    fn test_descriptions() {
+        assert_eq!(EffectKind::Deposit.description(), "deposit");
+        assert_eq!(EffectKind::DisputeHold.description(), "dispute hold");
+        assert_eq!(EffectKind::DisputeRelease.description(), "dispute release");
+    }
+}
@@ -1,4 +1,53 @@
+use std::io;
+
+/// Crate-wide result type, defaulting to `CorkenError`.
+pub type Result<T, E = CorkenError> = std::result::Result<T, E>;
+
+/// Enum represents any error returned by the library, wrapping the more specific errors with the
+/// context they occured in. `source()` returns the wrapped error.
+///
+/// # Example
+/// ```
+/// use corken::error::{CorkenError, TxError};
+/// use std::error::Error;
+///
+/// fn describe(e: &CorkenError) -> String {
+///     match e {
+///         CorkenError::Tx { tx, source: TxError::NotEnoughFunds, .. } => {
+///             format!("tx {} bounced", tx)
+///         }
+///         // Both enums are non exhaustive, so downstream matches need a wildcard arm.
+///         CorkenError::Tx { source, .. } => source.to_string(),
+///         _ => e.to_string(),
+///     }
+/// }
+///
+/// let e = CorkenError::Tx { client: 1, tx: 7, source: TxError::NotEnoughFunds };
+/// assert_eq!(describe(&e), "tx 7 bounced");
+/// assert_eq!(e.source().unwrap().to_string(), "Account doesnt have enough funds");
+/// ```
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum CorkenError {
+    #[error("Transaction {tx} of client {client} was rejected.")]
+    Tx {
+        client: u16,
+        tx: u32,
+        #[source]
+        source: TxError,
+    },
+    #[error("Failed to ingest transactions.")]
+    Ingest(#[from] SourceError),
+    #[error("IO error.")]
+    Io(#[from] io::Error),
+    #[error("Failed to restore snapshot.")]
+    Snapshot(#[from] SnapshotError),
+    #[error("Invalid configuration.")]
+    Build(#[from] BuildError),
+}
+
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum TxError {
     #[error("Account doesnt have enough funds")]
     NotEnoughFunds,
@@ -16,4 +65,134 @@ pub enum TxError {
     InternalError,
     #[error("Account is locked.")]
     AccountLocked,
+    #[error("Event log is corrupt.")]
+    CorruptEventLog,
+    #[error("Failed to parse transaction: {0}")]
+    ParseError(String),
+    #[error("Disputes are disabled.")]
+    DisputesDisabled,
+    #[error("Transaction has already been processed.")]
+    DuplicateTx,
+    #[error("Transaction can no longer be disputed.")]
+    DisputeWindowExpired,
+    #[error("No handler registered for this transaction type.")]
+    UnsupportedType,
+    #[error("Transaction would leave the account in an inconsistent state.")]
+    InvariantBreach,
+    #[error("Client id is already in use.")]
+    DuplicateClientId,
+    #[error("Account doesnt exist.")]
+    AccountNotFound,
+    #[error("Transaction {0} has been frozen and can no longer be disputed.")]
+    TransactionFrozen(u32),
+    #[error("Client id 0 is reserved.")]
+    InvalidClient,
+    #[error("Invalid transaction: {0}")]
+    Invalid(crate::validate::ValidationError),
+    #[error("Footer expects a total of {expected}, but the accounts total {actual}.")]
+    FooterMismatch { expected: String, actual: String },
+    #[error("Withdrawal exceeds the velocity limit of the account.")]
+    VelocityExceeded,
+    #[error("Transaction doesnt match the currency of the balance it refers to.")]
+    CurrencyMismatch,
+    #[error("Timestamp {timestamp} is behind the latest timestamp {latest} of the client.")]
+    OutOfOrderTimestamp { timestamp: u64, latest: u64 },
+    #[error("Interest cannot accrue on a negative balance.")]
+    NegativeBalanceInterest,
+    #[error("Refunds exceed the amount of the withdrawal they refer to.")]
+    RefundExceedsWithdrawal,
+    #[error("Amount is zero.")]
+    ZeroAmount,
+    #[error("Accounts must be merged into one of the two merged clients.")]
+    InvalidMergeTarget,
+    #[error("Total balance of {attempted} would exceed the limit of {limit}.")]
+    BalanceLimitExceeded { limit: String, attempted: String },
+}
+
+impl TxError {
+    /// Returns the name of the variant, e.g. `NotEnoughFunds`. Used as a stable error code by the
+    /// language bindings.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TxError::NotEnoughFunds => "NotEnoughFunds",
+            TxError::TxDoesntExist => "TxDoesntExist",
+            TxError::InvalidDispute => "InvalidDispute",
+            TxError::Unauthorized => "Unauthorized",
+            TxError::TxAlreadyDisputed => "TxAlreadyDisputed",
+            TxError::TxNotUnderDispute => "TxNotUnderDispute",
+            TxError::InternalError => "InternalError",
+            TxError::AccountLocked => "AccountLocked",
+            TxError::CorruptEventLog => "CorruptEventLog",
+            TxError::ParseError(_) => "ParseError",
+            TxError::DisputesDisabled => "DisputesDisabled",
+            TxError::DuplicateTx => "DuplicateTx",
+            TxError::DisputeWindowExpired => "DisputeWindowExpired",
+            TxError::UnsupportedType => "UnsupportedType",
+            TxError::InvariantBreach => "InvariantBreach",
+            TxError::DuplicateClientId => "DuplicateClientId",
+            TxError::AccountNotFound => "AccountNotFound",
+            TxError::TransactionFrozen(_) => "TransactionFrozen",
+            TxError::InvalidClient => "InvalidClient",
+            TxError::Invalid(_) => "Invalid",
+            TxError::FooterMismatch { .. } => "FooterMismatch",
+            TxError::VelocityExceeded => "VelocityExceeded",
+            TxError::CurrencyMismatch => "CurrencyMismatch",
+            TxError::OutOfOrderTimestamp { .. } => "OutOfOrderTimestamp",
+            TxError::NegativeBalanceInterest => "NegativeBalanceInterest",
+            TxError::RefundExceedsWithdrawal => "RefundExceedsWithdrawal",
+            TxError::ZeroAmount => "ZeroAmount",
+            TxError::InvalidMergeTarget => "InvalidMergeTarget",
+            TxError::BalanceLimitExceeded { .. } => "BalanceLimitExceeded",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum BuildError {
+    #[error("Credit limit must be a finite, non-negative amount.")]
+    InvalidCreditLimit,
+    #[error("Balance limit must be a finite, non-negative amount.")]
+    InvalidBalanceLimit,
+    #[error("Dispute window must be at least one transaction long.")]
+    InvalidDisputeWindow,
+    #[error("Velocity limit must be a finite, non-negative amount over at least one transaction.")]
+    InvalidVelocityLimit,
+    #[error("Conflicting policies: {0}.")]
+    Conflict(&'static str),
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SnapshotError {
+    #[error("Unsupported snapshot version {0}.")]
+    UnsupportedVersion(u32),
+    #[error("Snapshot is corrupt: {0}")]
+    Corrupt(String),
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum RecordError {
+    #[error("Missing field `{0}`.")]
+    MissingField(&'static str),
+    #[error("Invalid field `{field}`: {reason}.")]
+    InvalidField { field: &'static str, reason: String },
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ProtoError {
+    #[error("Missing field `{0}`.")]
+    MissingField(&'static str),
+    #[error("Unknown transaction type {0}.")]
+    UnknownType(i32),
+    #[error("Invalid field `{field}`: {reason}.")]
+    InvalidField { field: &'static str, reason: String },
+    #[error("Failed to decode message: {0}")]
+    Decode(String),
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SourceError {
+    #[error("Failed to parse transaction: {0}")]
+    Parse(String),
+    #[error("Failed to read from source: {0}")]
+    Io(String),
 }
@@ -0,0 +1,705 @@
+//! This module contains the typed events emitted by the state machine when transactions are
+//! commited.
+
+use super::*;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// Enum represents a single change commited to an account by the state machine. Events are emitted
+/// in commit order, and replaying them in order reproduces the balances of every account.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[cfg_attr(feature = "serde", serde(tag = "event"))]
+pub enum AccountEvent<A = f64> {
+    /// A new account has been materialized by the state machine.
+    ///
+    /// # Notes
+    /// Accounts get created as soon as a transaction for a new client is seen, even if the
+    /// transaction itself ends up rejected. This event is therefore emitted whenever an account is
+    /// created, so that the set of accounts can be reproduced from the event stream.
+    AccountCreated { client: u16 },
+    /// `amount` has been added to the `available` and `total` balances.
+    Deposited { client: u16, tx: u32, amount: A },
+    /// `amount` has been removed from the `available` and `total` balances.
+    Withdrawn { client: u16, tx: u32, amount: A },
+    /// Transaction `tx` is now under dispute and `amount` has been placed on hold. For deposits
+    /// the amount is moved from `available` to `held`. `event_id` is the own id of the dispute,
+    /// if it had one.
+    DisputeOpened {
+        client: u16,
+        tx: u32,
+        amount: A,
+        #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+        event_id: Option<u32>,
+    },
+    /// Dispute on `tx` has been resolved and `amount` has been released from `held`. For deposits
+    /// the amount is moved back to `available`. `event_id` is the own id of the resolve, if it had
+    /// one.
+    DisputeResolved {
+        client: u16,
+        tx: u32,
+        amount: A,
+        #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+        event_id: Option<u32>,
+    },
+    /// Dispute on `tx` ended in a chargeback, `amount` has been released from `held` and the
+    /// account lock state is now `locked`. For deposits the amount is removed from `total`.
+    /// `event_id` is the own id of the chargeback, if it had one.
+    ChargedBack {
+        client: u16,
+        tx: u32,
+        amount: A,
+        locked: bool,
+        #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+        event_id: Option<u32>,
+    },
+    /// Custom transaction `tx` of type `tag` has been executed by a `CustomHandler`. The balance
+    /// fields are deltas, and the account lock state is now `locked`.
+    Adjusted {
+        client: u16,
+        tx: u32,
+        tag: String,
+        available: A,
+        held: A,
+        total: A,
+        locked: bool,
+    },
+    /// Interest transaction `tx` has credited `amount` to the `available` and `total` balances.
+    /// The amount has already been rounded, so it can be shown as is on statements.
+    InterestCredited { client: u16, tx: u32, amount: A },
+    /// Refund `tx` of withdrawal `of_tx` has added `amount` to the `available` and `total`
+    /// balances.
+    Refunded {
+        client: u16,
+        tx: u32,
+        of_tx: u32,
+        amount: A,
+    },
+    /// Manual hold `tx` has moved `amount` from `available` to `held`.
+    HoldPlaced { client: u16, tx: u32, amount: A },
+    /// Release `tx` of the manual hold `of_tx` has moved `amount` from `held` back to
+    /// `available`.
+    HoldReleased {
+        client: u16,
+        tx: u32,
+        of_tx: u32,
+        amount: A,
+    },
+    /// Deposit `tx` has been reversed by a compensating transaction, `amount` has been removed
+    /// from the `available` and `total` balances.
+    Compensated { client: u16, tx: u32, amount: A },
+}
+
+impl<A> AccountEvent<A> {
+    /// Returns the id of the client this event applies to.
+    pub fn client(&self) -> u16 {
+        match *self {
+            AccountEvent::AccountCreated { client }
+            | AccountEvent::Deposited { client, .. }
+            | AccountEvent::Withdrawn { client, .. }
+            | AccountEvent::DisputeOpened { client, .. }
+            | AccountEvent::DisputeResolved { client, .. }
+            | AccountEvent::ChargedBack { client, .. }
+            | AccountEvent::Adjusted { client, .. }
+            | AccountEvent::InterestCredited { client, .. }
+            | AccountEvent::Refunded { client, .. }
+            | AccountEvent::HoldPlaced { client, .. }
+            | AccountEvent::HoldReleased { client, .. }
+            | AccountEvent::Compensated { client, .. } => client,
+        }
+    }
+}
+
+impl State {
+    /// Function will construct the state machine by applying all the events from the iterator
+    /// passed in.
+    ///
+    /// # Arguments
+    /// * `events` - Iterator over owned `AccountEvent`s, in commit order.
+    ///
+    /// # Returns
+    /// Returns `TxError::CorruptEventLog` as soon as an event cannot be applied. Unlike
+    /// `from_iterator`, errors are not swallowed because they indicate a broken event log rather
+    /// than a rejected transaction.
+    ///
+    /// # Notes
+    /// Events dont carry the logical time at which they were commited, so dispute windows of the
+    /// rebuilt state machine start counting from the rebuild.
+    pub fn from_events(events: impl Iterator<Item = AccountEvent>) -> Result<Self, TxError> {
+        let mut this = Self::default();
+        for event in events {
+            this.apply_event(&event)?;
+        }
+
+        Ok(this)
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will apply a single event to the state machine without re-running any of the
+    /// validation done by `execute`, as events are assumed to have been validated when they were
+    /// produced. Balance movements of dispute events are recomputed by the configured
+    /// `DisputePolicy`, so the state machine must be configured with the same policy that produced
+    /// the events.
+    ///
+    /// # Arguments
+    /// * `event` - Event to be applied.
+    ///
+    /// # Returns
+    /// Returns `TxError::CorruptEventLog` if the event is structurally impossible given the
+    /// current state, for example if it references an unknown account, resolves a transaction
+    /// that isnt under dispute, compensates a deposit that isnt cached, or carries an amount that
+    /// doesnt match the dispute policy. If an error is returned no data has been modified.
+    ///
+    /// # Notes
+    /// The own ids of disputes, resolves and chargebacks are recorded for duplicate detection, as
+    /// are the ids of every other transaction when the state machine is in idempotent mode.
+    pub fn apply_event(&mut self, event: &AccountEvent<A>) -> Result<(), TxError> {
+        if let AccountEvent::AccountCreated { client } = *event {
+            if self.accounts.contains_key(&client) {
+                return Err(TxError::CorruptEventLog);
+            }
+
+            self.accounts.insert(
+                client,
+                Account {
+                    id: client,
+                    ..Account::default()
+                },
+            );
+
+            return Ok(());
+        }
+
+        let account = self
+            .accounts
+            .get_mut(&event.client())
+            .ok_or(TxError::CorruptEventLog)?;
+
+        match *event {
+            AccountEvent::AccountCreated { .. } => unreachable!(),
+            AccountEvent::Deposited { client, tx, amount } => {
+                let checked = Amount::new(amount).map_err(|_| TxError::CorruptEventLog)?;
+                account.available += amount;
+                account.total += amount;
+
+                if self.config.disputes {
+                    cache_tx(
+                        &mut self.tx_cache,
+                        Transaction {
+                            tx_type: TransactionType::Deposit { amount: checked },
+                            client,
+                            tx,
+                            currency: None,
+                            timestamp: None,
+                            row_id: None,
+                            event_id: None,
+                        },
+                        self.seq,
+                    );
+                }
+            }
+            AccountEvent::Withdrawn { client, tx, amount } => {
+                let checked = Amount::new(amount).map_err(|_| TxError::CorruptEventLog)?;
+                account.available -= amount;
+                account.total -= amount;
+
+                self.refundable.insert(
+                    tx,
+                    RefundableWithdrawal {
+                        client,
+                        amount,
+                        refunded: A::ZERO,
+                    },
+                );
+
+                if self.config.withdrawal_disputes == WithdrawalDisputePolicy::Allow {
+                    cache_tx(
+                        &mut self.tx_cache,
+                        Transaction {
+                            tx_type: TransactionType::Withdrawal { amount: checked },
+                            client,
+                            tx,
+                            currency: None,
+                            timestamp: None,
+                            row_id: None,
+                            event_id: None,
+                        },
+                        self.seq,
+                    );
+                }
+            }
+            AccountEvent::DisputeOpened {
+                client,
+                tx,
+                amount,
+                event_id,
+            } => {
+                let disputed = self.tx_cache.get_mut(&tx).ok_or(TxError::CorruptEventLog)?;
+
+                if disputed.tx.client != client || disputed.dispute.is_some() {
+                    return Err(TxError::CorruptEventLog);
+                }
+
+                let outcome = self
+                    .config
+                    .dispute_policy
+                    .on_dispute(account, disputed)
+                    .map_err(|_| TxError::CorruptEventLog)?;
+
+                if outcome.held != amount {
+                    return Err(TxError::CorruptEventLog);
+                }
+
+                outcome.apply_dispute(account);
+                disputed.dispute = Some(DisputeState::Disputed);
+                disputed.dispute_event_id = event_id;
+            }
+            AccountEvent::DisputeResolved {
+                client, tx, amount, ..
+            }
+            | AccountEvent::ChargedBack {
+                client, tx, amount, ..
+            } => {
+                let disputed = match self.tx_cache.get(&tx) {
+                    Some(disputed)
+                        if disputed.tx.client == client
+                            && disputed.dispute == Some(DisputeState::Disputed) =>
+                    {
+                        disputed
+                    }
+                    _ => return Err(TxError::CorruptEventLog),
+                };
+
+                let policy = &self.config.dispute_policy;
+                let outcome = match *event {
+                    AccountEvent::ChargedBack { .. } => policy.on_chargeback(account, disputed),
+                    _ => policy.on_resolve(account, disputed),
+                }
+                .map_err(|_| TxError::CorruptEventLog)?;
+
+                if -outcome.held != amount {
+                    return Err(TxError::CorruptEventLog);
+                }
+
+                outcome.apply_dispute(account);
+                if let AccountEvent::ChargedBack { locked, .. } = *event {
+                    account.locked = locked;
+                }
+
+                self.tx_cache.remove(&tx);
+            }
+            AccountEvent::Adjusted {
+                available,
+                held,
+                total,
+                locked,
+                ..
+            } => {
+                let outcome = HoldOutcome {
+                    available,
+                    held,
+                    total,
+                    lock: false,
+                };
+
+                if !outcome.is_balanced() {
+                    return Err(TxError::CorruptEventLog);
+                }
+
+                outcome.apply(account);
+                account.locked = locked;
+            }
+            AccountEvent::InterestCredited { amount, .. } => {
+                Amount::new(amount).map_err(|_| TxError::CorruptEventLog)?;
+                account.available += amount;
+                account.total += amount;
+            }
+            AccountEvent::Refunded {
+                client,
+                of_tx,
+                amount,
+                ..
+            } => {
+                let withdrawal = match self.refundable.get_mut(&of_tx) {
+                    Some(withdrawal)
+                        if withdrawal.client == client
+                            && withdrawal.refunded + amount
+                                <= withdrawal.amount + A::tolerance() =>
+                    {
+                        withdrawal
+                    }
+                    _ => return Err(TxError::CorruptEventLog),
+                };
+
+                withdrawal.refunded += amount;
+                account.available += amount;
+                account.total += amount;
+            }
+            AccountEvent::HoldPlaced { client, tx, amount } => {
+                if self.manual_holds.contains_key(&tx) {
+                    return Err(TxError::CorruptEventLog);
+                }
+
+                account.available -= amount;
+                account.held += amount;
+                self.manual_holds.insert(tx, ManualHold { client, amount });
+            }
+            AccountEvent::HoldReleased {
+                client,
+                of_tx,
+                amount,
+                ..
+            } => {
+                match self.manual_holds.get(&of_tx) {
+                    Some(hold) if hold.client == client && hold.amount == amount => {}
+                    _ => return Err(TxError::CorruptEventLog),
+                }
+
+                self.manual_holds.remove(&of_tx);
+                account.held -= amount;
+                account.available += amount;
+            }
+            AccountEvent::Compensated { client, tx, amount } => {
+                let original = self.tx_cache.get(&tx).ok_or(TxError::CorruptEventLog)?;
+                let deposited = match original.tx.tx_type {
+                    TransactionType::Deposit { amount } => amount.get(),
+                    _ => return Err(TxError::CorruptEventLog),
+                };
+
+                if original.tx.client != client || original.dispute.is_some() || deposited != amount
+                {
+                    return Err(TxError::CorruptEventLog);
+                }
+
+                account.available -= amount;
+                account.total -= amount;
+
+                self.tx_cache.remove(&tx);
+            }
+        }
+
+        // NOTE: Keep the duplicate detection in sync with `execute`, which records the ids of every
+        // transaction it commits. Compensations dont go through `execute`, so they arent recorded.
+        match *event {
+            AccountEvent::AccountCreated { .. } | AccountEvent::Compensated { .. } => {}
+            AccountEvent::DisputeOpened { event_id, .. }
+            | AccountEvent::DisputeResolved { event_id, .. }
+            | AccountEvent::ChargedBack { event_id, .. } => {
+                self.dispute_event_ids.extend(event_id);
+            }
+            AccountEvent::Deposited { tx, .. }
+            | AccountEvent::Withdrawn { tx, .. }
+            | AccountEvent::Adjusted { tx, .. }
+            | AccountEvent::InterestCredited { tx, .. }
+            | AccountEvent::Refunded { tx, .. }
+            | AccountEvent::HoldPlaced { tx, .. }
+            | AccountEvent::HoldReleased { tx, .. } => {
+                if self.config.idempotent {
+                    self.seen.insert(tx);
+                }
+            }
+        }
+
+        account.normalize();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{checked, workload};
+
+    use std::collections::HashMap;
+
+    #[derive(Default, Debug, PartialEq)]
+    struct Balances {
+        available: f64,
+        held: f64,
+        total: f64,
+        locked: bool,
+    }
+
+    fn reduce(events: &[AccountEvent]) -> HashMap<u16, Balances> {
+        let mut accounts: HashMap<u16, Balances> = HashMap::new();
+
+        for event in events {
+            let account = accounts.entry(event.client()).or_default();
+
+            match *event {
+                AccountEvent::AccountCreated { .. } => {}
+                AccountEvent::Deposited { amount, .. }
+                | AccountEvent::InterestCredited { amount, .. }
+                | AccountEvent::Refunded { amount, .. } => {
+                    account.available += amount;
+                    account.total += amount;
+                }
+                AccountEvent::Withdrawn { amount, .. }
+                | AccountEvent::Compensated { amount, .. } => {
+                    account.available -= amount;
+                    account.total -= amount;
+                }
+                AccountEvent::DisputeOpened { amount, .. }
+                | AccountEvent::HoldPlaced { amount, .. } => {
+                    account.available -= amount;
+                    account.held += amount;
+                }
+                AccountEvent::DisputeResolved { amount, .. }
+                | AccountEvent::HoldReleased { amount, .. } => {
+                    account.held -= amount;
+                    account.available += amount;
+                }
+                AccountEvent::ChargedBack { amount, locked, .. } => {
+                    account.held -= amount;
+                    account.total -= amount;
+                    account.locked = locked;
+                }
+                AccountEvent::Adjusted {
+                    available,
+                    held,
+                    total,
+                    locked,
+                    ..
+                } => {
+                    account.available += available;
+                    account.held += held;
+                    account.total += total;
+                    account.locked = locked;
+                }
+            }
+        }
+
+        accounts
+    }
+
+    #[test]
+    fn test_events_reproduce_balances() {
+        let mut state = State::default();
+        let mut events = Vec::new();
+
+        for tx in workload(1000) {
+            let _ = state.execute_with_events(tx, |e| events.push(e));
+        }
+
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, AccountEvent::DisputeResolved { .. })));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, AccountEvent::ChargedBack { .. })));
+
+        let reduced = reduce(&events);
+        assert_eq!(reduced.len(), state.accounts().count());
+
+        for account in state.accounts() {
+            let balances = reduced.get(&account.id).unwrap();
+            assert_eq!(
+                balances,
+                &Balances {
+                    available: account.available,
+                    held: account.held,
+                    total: account.total,
+                    locked: account.locked,
+                }
+            );
+        }
+    }
+
+    /// Returns the `workload` with own ids given to every dispute, resolve and chargeback.
+    fn workload_with_event_ids(len: u32) -> Vec<Transaction> {
+        workload(len)
+            .into_iter()
+            .zip(len..)
+            .map(|(tx, id)| {
+                if tx.tx_type.is_dispute() {
+                    tx.with_event_id(id)
+                } else {
+                    tx
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_from_events_round_trip() {
+        let mut state = State::default();
+        let mut events = Vec::new();
+
+        for tx in workload_with_event_ids(1000) {
+            let _ = state.execute_with_events(tx, |e| events.push(e));
+        }
+
+        // Make sure there is at least one open dispute to carry over.
+        assert!(state
+            .tx_cache
+            .values()
+            .any(|x| matches!(x.dispute, Some(DisputeState::Disputed))));
+
+        let rebuilt = State::from_events(events.into_iter()).unwrap();
+        assert_eq!(
+            rebuilt.accounts,
+            State::from_iterator(workload(1000).into_iter()).accounts
+        );
+        assert_eq!(rebuilt.accounts, state.accounts);
+        let strip = |state: &State| {
+            state
+                .tx_cache
+                .iter()
+                .map(|(id, x)| (*id, (x.tx.clone(), x.dispute, x.dispute_event_id)))
+                .collect::<HashMap<_, _>>()
+        };
+        assert_eq!(strip(&rebuilt), strip(&state));
+        assert!(!state.dispute_event_ids.is_empty());
+        assert_eq!(rebuilt.dispute_event_ids, state.dispute_event_ids);
+        assert_eq!(rebuilt.seen, state.seen);
+    }
+
+    #[test]
+    fn test_apply_events_idempotent() {
+        let mut state = State::builder().idempotent(true).build().unwrap();
+        let mut events = Vec::new();
+
+        for tx in workload_with_event_ids(1000) {
+            let _ = state.execute_with_events(tx, |e| events.push(e));
+        }
+
+        let mut rebuilt = State::builder().idempotent(true).build().unwrap();
+        for event in &events {
+            rebuilt.apply_event(event).unwrap();
+        }
+
+        assert!(!state.seen.is_empty());
+        assert_eq!(rebuilt.seen, state.seen);
+        assert_eq!(rebuilt.dispute_event_ids, state.dispute_event_ids);
+        assert_eq!(rebuilt.accounts, state.accounts);
+    }
+
+    #[test]
+    fn test_apply_corrupt_event() {
+        let mut state = State::default();
+
+        assert_eq!(
+            state.apply_event(&AccountEvent::Deposited {
+                client: 1,
+                tx: 1,
+                amount: 10.0
+            }),
+            Err(TxError::CorruptEventLog)
+        );
+
+        state
+            .apply_event(&AccountEvent::AccountCreated { client: 1 })
+            .unwrap();
+
+        assert_eq!(
+            state.apply_event(&AccountEvent::AccountCreated { client: 1 }),
+            Err(TxError::CorruptEventLog)
+        );
+
+        assert_eq!(
+            state.apply_event(&AccountEvent::DisputeResolved {
+                client: 1,
+                tx: 1,
+                amount: 10.0,
+                event_id: None,
+            }),
+            Err(TxError::CorruptEventLog)
+        );
+
+        state
+            .apply_event(&AccountEvent::Deposited {
+                client: 1,
+                tx: 1,
+                amount: 10.0,
+            })
+            .unwrap();
+
+        // The deposit isnt under dispute, so it cant be charged back.
+        assert_eq!(
+            state.apply_event(&AccountEvent::ChargedBack {
+                client: 1,
+                tx: 1,
+                amount: 10.0,
+                locked: true,
+                event_id: None,
+            }),
+            Err(TxError::CorruptEventLog)
+        );
+
+        // Compensations can only reverse a cached deposit of the same client and amount.
+        for (client, tx, amount) in [(1, 2, 10.0), (1, 1, 5.0)] {
+            assert_eq!(
+                state.apply_event(&AccountEvent::Compensated { client, tx, amount }),
+                Err(TxError::CorruptEventLog)
+            );
+        }
+
+        assert_eq!(
+            state.apply_event(&AccountEvent::InterestCredited {
+                client: 1,
+                tx: 3,
+                amount: -1.0,
+            }),
+            Err(TxError::CorruptEventLog)
+        );
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 10.0);
+        assert!(!account.locked);
+    }
+
+    #[test]
+    fn test_rejected_tx_emits_nothing() {
+        let mut state = State::default();
+        let mut events = Vec::new();
+
+        state
+            .execute_with_events(
+                Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(10.0),
+                    },
+                    client: 1,
+                    tx: 1,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                },
+                |e| events.push(e),
+            )
+            .unwrap();
+
+        assert!(state
+            .execute_with_events(
+                Transaction {
+                    tx_type: TransactionType::Withdrawal {
+                        amount: checked(20.0)
+                    },
+                    client: 1,
+                    tx: 2,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                },
+                |e| events.push(e),
+            )
+            .is_err());
+
+        assert_eq!(
+            events,
+            vec![
+                AccountEvent::AccountCreated { client: 1 },
+                AccountEvent::Deposited {
+                    client: 1,
+                    tx: 1,
+                    amount: 10.0
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! This module contains aggregate views over the funds held by pending disputes.
+
+use super::*;
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Returns the sum of the `held` balances of all accounts, i.e. the total liability from
+    /// pending disputes.
+    pub fn total_dispute_exposure(&self) -> A {
+        self.accounts.values().fold(A::ZERO, |sum, x| sum + x.held)
+    }
+
+    /// Returns the client with the largest `held` balance along with that balance, or `None` if no
+    /// account has funds on hold. Ties are broken in favour of the lowest client id.
+    pub fn max_single_dispute_exposure(&self) -> Option<(u16, A)> {
+        self.accounts
+            .values()
+            .filter(|x| x.held > A::ZERO && !x.held.is_negligible())
+            .map(|x| (x.id, x.held))
+            .fold(None, |max, (id, held)| match max {
+                Some((max_id, max_held))
+                    if max_held > held || (max_held == held && max_id < id) =>
+                {
+                    max
+                }
+                _ => Some((id, held)),
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    #[test]
+    fn test_dispute_exposure() {
+        let mut state = State::default();
+        assert_eq!(state.total_dispute_exposure(), 0.0);
+        assert_eq!(state.max_single_dispute_exposure(), None);
+
+        for (client, amount) in [(1, 10.0), (2, 25.5), (3, 7.25), (4, 25.5), (5, 100.0)] {
+            let deposit = TransactionType::Deposit {
+                amount: checked(amount),
+            };
+            state
+                .execute(Transaction::new(deposit, client, client as u32))
+                .unwrap();
+        }
+        assert_eq!(state.max_single_dispute_exposure(), None);
+
+        for client in 1..=4 {
+            state
+                .execute(Transaction::new(
+                    TransactionType::Dispute,
+                    client,
+                    client as u32,
+                ))
+                .unwrap();
+        }
+
+        let sum: f64 = state.accounts().map(|x| x.held).sum();
+        assert_eq!(state.total_dispute_exposure(), sum);
+        assert_eq!(state.total_dispute_exposure(), 68.25);
+        assert_eq!(state.max_single_dispute_exposure(), Some((2, 25.5)));
+
+        state
+            .execute(Transaction::new(TransactionType::Resolve, 2, 2))
+            .unwrap();
+        assert_eq!(state.max_single_dispute_exposure(), Some((4, 25.5)));
+        assert_eq!(state.total_dispute_exposure(), 42.75);
+    }
+}
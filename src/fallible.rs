@@ -0,0 +1,208 @@
+//! This module contains helpers for replaying transactions from sources that can fail.
+
+use super::*;
+use std::fmt;
+
+/// Struct represents a transaction that failed to be executed when replaying a fallible iterator.
+#[derive(Debug, PartialEq)]
+pub struct FallibleFailure<E> {
+    /// Zero based position of the item in the iterator.
+    pub position: usize,
+    /// The reason the item was skipped.
+    pub kind: FailureKind<E>,
+}
+
+/// Enum represents the reason an item of a fallible iterator was skipped.
+#[derive(Debug, PartialEq)]
+pub enum FailureKind<E> {
+    /// The iterator yielded an error instead of a transaction.
+    Upstream(E),
+    /// The transaction was rejected by the state machine.
+    Rejected {
+        client: u16,
+        tx: u32,
+        /// The `type` of the transaction.
+        tag: String,
+        error: TxError,
+    },
+}
+
+impl<E: fmt::Display> fmt::Display for FallibleFailure<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FailureKind::Upstream(e) => write!(f, "#{}: {}", self.position, e),
+            FailureKind::Rejected {
+                client,
+                tx,
+                tag,
+                error,
+            } => write!(
+                f,
+                "#{}: {} {} of client {} rejected: {}",
+                self.position, tag, tx, client, error
+            ),
+        }
+    }
+}
+
+/// Struct represents a skipped item as a flat record, e.g. a row of the errors file written by
+/// `corken --errors-out`. The transaction fields are left empty for upstream errors.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct RejectionRecord<'a> {
+    pub client: Option<u16>,
+    pub tx: Option<u32>,
+    /// The `type` of the transaction.
+    #[serde(rename = "type")]
+    pub tag: Option<&'a str>,
+    /// The reason the item was skipped.
+    pub error: String,
+}
+
+#[cfg(feature = "serde")]
+impl<'a, E: fmt::Display> From<&'a FallibleFailure<E>> for RejectionRecord<'a> {
+    fn from(failure: &'a FallibleFailure<E>) -> Self {
+        match &failure.kind {
+            FailureKind::Upstream(e) => RejectionRecord {
+                client: None,
+                tx: None,
+                tag: None,
+                error: e.to_string(),
+            },
+            FailureKind::Rejected {
+                client,
+                tx,
+                tag,
+                error,
+            } => RejectionRecord {
+                client: Some(*client),
+                tx: Some(*tx),
+                tag: Some(tag),
+                error: error.to_string(),
+            },
+        }
+    }
+}
+
+impl State {
+    /// Function will construct the state machine and replay all the transactions from the
+    /// fallible iterator passed in.
+    ///
+    /// # Arguments
+    /// * `txs` - Iterator over owned `Transactions` or the errors encountered producing them.
+    ///
+    /// # Returns
+    /// Returns the state machine along with every item that was skipped, in iterator order. Both
+    /// upstream errors and transactions rejected by the state machine are recorded with their
+    /// position, so that a report can interleave them with the input.
+    pub fn from_fallible_iterator<E>(
+        txs: impl Iterator<Item = Result<Transaction, E>>,
+    ) -> (Self, Vec<FallibleFailure<E>>) {
+        Self::from_fallible_iterator_with(txs, |_, _| {})
+    }
+
+    /// Function behaves like `from_fallible_iterator`, additionally calling `before` with the
+    /// state machine right before every transaction gets executed.
+    pub(crate) fn from_fallible_iterator_with<E>(
+        txs: impl Iterator<Item = Result<Transaction, E>>,
+        mut before: impl FnMut(&Self, &Transaction),
+    ) -> (Self, Vec<FallibleFailure<E>>) {
+        let mut this = Self::default();
+        let mut failures = Vec::new();
+
+        for (position, tx) in txs.enumerate() {
+            let kind = match tx {
+                Ok(tx) => {
+                    before(&this, &tx);
+
+                    let (client, id) = (tx.client, tx.tx);
+                    let tag = tx.tx_type.tag().to_string();
+                    match this.execute(tx) {
+                        Ok(()) => continue,
+                        Err(error) => FailureKind::Rejected {
+                            client,
+                            tx: id,
+                            tag,
+                            error,
+                        },
+                    }
+                }
+                Err(e) => FailureKind::Upstream(e),
+            };
+
+            failures.push(FallibleFailure { position, kind });
+        }
+
+        (this, failures)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{checked, workload};
+
+    #[test]
+    fn test_from_fallible_iterator() {
+        let txs = vec![
+            Ok(Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            )),
+            Err("bad row"),
+            Ok(Transaction::new(
+                TransactionType::Withdrawal {
+                    amount: checked(20.0),
+                },
+                1,
+                2,
+            )),
+            Ok(Transaction::new(TransactionType::Dispute, 1, 1)),
+            Err("another bad row"),
+        ];
+
+        let (state, failures) = State::from_fallible_iterator(txs.into_iter());
+
+        assert_eq!(
+            failures,
+            vec![
+                FallibleFailure {
+                    position: 1,
+                    kind: FailureKind::Upstream("bad row"),
+                },
+                FallibleFailure {
+                    position: 2,
+                    kind: FailureKind::Rejected {
+                        client: 1,
+                        tx: 2,
+                        tag: "withdrawal".into(),
+                        error: TxError::NotEnoughFunds,
+                    },
+                },
+                FallibleFailure {
+                    position: 4,
+                    kind: FailureKind::Upstream("another bad row"),
+                },
+            ]
+        );
+        assert_eq!(state.accounts.get(&1).unwrap().held, 10.0);
+        assert_eq!(failures[0].to_string(), "#1: bad row");
+        assert_eq!(
+            failures[1].to_string(),
+            "#2: withdrawal 2 of client 1 rejected: Account doesnt have enough funds"
+        );
+    }
+
+    #[test]
+    fn test_matches_from_iterator() {
+        let (state, _) = State::from_fallible_iterator(workload(1000).into_iter().map(Ok::<_, ()>));
+        let expected = State::from_iterator(workload(1000).into_iter());
+
+        assert_eq!(state.accounts, expected.accounts);
+        assert_eq!(state.tx_cache, expected.tx_cache);
+    }
+}
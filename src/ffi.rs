@@ -0,0 +1,425 @@
+//! This module contains the C bindings of the state machine, built with the `ffi` feature. The
+//! matching header is `include/corken.h`, generated with `cbindgen`.
+//!
+//! # Ownership
+//! * A state machine returned by `corken_state_new` is owned by the caller and must be released
+//!   exactly once with `corken_state_free`. It must not be used after it has been released.
+//! * Every other function only borrows the state machine for the duration of the call, and must
+//!   not be called concurrently with another call on the same state machine.
+//! * Out parameters and strings are borrowed for the duration of the call, the library never
+//!   keeps pointers handed to it.
+//!
+//! No function unwinds into the caller. Panics are caught at the boundary and reported as
+//! `CORKEN_PANIC`, after which the state machine should be released as its state is unspecified.
+#![allow(non_camel_case_types)]
+
+use super::*;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Opaque handle to a state machine.
+pub struct corken_state_t {
+    state: State,
+}
+
+/// Types of transactions accepted by `corken_execute`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum corken_tx_type_t {
+    CORKEN_DEPOSIT = 0,
+    CORKEN_WITHDRAWAL = 1,
+    CORKEN_DISPUTE = 2,
+    CORKEN_RESOLVE = 3,
+    CORKEN_CHARGEBACK = 4,
+}
+
+/// Error codes returned by the bindings. Codes below 100 mirror the variants of `TxError`, while
+/// the rest are specific to the boundary.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum corken_error_t {
+    CORKEN_OK = 0,
+    CORKEN_NOT_ENOUGH_FUNDS = 1,
+    CORKEN_TX_DOESNT_EXIST = 2,
+    CORKEN_INVALID_DISPUTE = 3,
+    CORKEN_UNAUTHORIZED = 4,
+    CORKEN_TX_ALREADY_DISPUTED = 5,
+    CORKEN_TX_NOT_UNDER_DISPUTE = 6,
+    CORKEN_INTERNAL_ERROR = 7,
+    CORKEN_ACCOUNT_LOCKED = 8,
+    CORKEN_CORRUPT_EVENT_LOG = 9,
+    CORKEN_PARSE_ERROR = 10,
+    CORKEN_DISPUTES_DISABLED = 11,
+    CORKEN_DUPLICATE_TX = 12,
+    CORKEN_DISPUTE_WINDOW_EXPIRED = 13,
+    CORKEN_UNSUPPORTED_TYPE = 14,
+    CORKEN_INVARIANT_BREACH = 15,
+    CORKEN_DUPLICATE_CLIENT_ID = 16,
+    CORKEN_ACCOUNT_NOT_FOUND = 17,
+    CORKEN_TRANSACTION_FROZEN = 18,
+    CORKEN_INVALID_CLIENT = 19,
+    CORKEN_INVALID = 20,
+    CORKEN_FOOTER_MISMATCH = 21,
+    CORKEN_VELOCITY_EXCEEDED = 22,
+    CORKEN_CURRENCY_MISMATCH = 23,
+    CORKEN_OUT_OF_ORDER_TIMESTAMP = 24,
+    CORKEN_NEGATIVE_BALANCE_INTEREST = 25,
+    CORKEN_REFUND_EXCEEDS_WITHDRAWAL = 26,
+    CORKEN_ZERO_AMOUNT = 27,
+    CORKEN_INVALID_MERGE_TARGET = 28,
+    CORKEN_BALANCE_LIMIT_EXCEEDED = 29,
+    /// A required pointer argument was `NULL`.
+    CORKEN_NULL_POINTER = 100,
+    /// An argument was out of range, or a string wasnt valid UTF-8.
+    CORKEN_INVALID_ARGUMENT = 101,
+    /// An IO error occured.
+    CORKEN_IO_ERROR = 102,
+    /// The library panicked.
+    CORKEN_PANIC = 103,
+}
+
+/// Struct represents an account, filled in by `corken_account_get`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct corken_account_t {
+    pub client: u16,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+}
+
+impl From<&TxError> for corken_error_t {
+    fn from(e: &TxError) -> Self {
+        match e {
+            TxError::NotEnoughFunds => Self::CORKEN_NOT_ENOUGH_FUNDS,
+            TxError::TxDoesntExist => Self::CORKEN_TX_DOESNT_EXIST,
+            TxError::InvalidDispute => Self::CORKEN_INVALID_DISPUTE,
+            TxError::Unauthorized => Self::CORKEN_UNAUTHORIZED,
+            TxError::TxAlreadyDisputed => Self::CORKEN_TX_ALREADY_DISPUTED,
+            TxError::TxNotUnderDispute => Self::CORKEN_TX_NOT_UNDER_DISPUTE,
+            TxError::InternalError => Self::CORKEN_INTERNAL_ERROR,
+            TxError::AccountLocked => Self::CORKEN_ACCOUNT_LOCKED,
+            TxError::CorruptEventLog => Self::CORKEN_CORRUPT_EVENT_LOG,
+            TxError::ParseError(_) => Self::CORKEN_PARSE_ERROR,
+            TxError::DisputesDisabled => Self::CORKEN_DISPUTES_DISABLED,
+            TxError::DuplicateTx => Self::CORKEN_DUPLICATE_TX,
+            TxError::DisputeWindowExpired => Self::CORKEN_DISPUTE_WINDOW_EXPIRED,
+            TxError::UnsupportedType => Self::CORKEN_UNSUPPORTED_TYPE,
+            TxError::InvariantBreach => Self::CORKEN_INVARIANT_BREACH,
+            TxError::DuplicateClientId => Self::CORKEN_DUPLICATE_CLIENT_ID,
+            TxError::AccountNotFound => Self::CORKEN_ACCOUNT_NOT_FOUND,
+            TxError::TransactionFrozen(_) => Self::CORKEN_TRANSACTION_FROZEN,
+            TxError::InvalidClient => Self::CORKEN_INVALID_CLIENT,
+            TxError::Invalid(_) => Self::CORKEN_INVALID,
+            TxError::FooterMismatch { .. } => Self::CORKEN_FOOTER_MISMATCH,
+            TxError::VelocityExceeded => Self::CORKEN_VELOCITY_EXCEEDED,
+            TxError::CurrencyMismatch => Self::CORKEN_CURRENCY_MISMATCH,
+            TxError::OutOfOrderTimestamp { .. } => Self::CORKEN_OUT_OF_ORDER_TIMESTAMP,
+            TxError::NegativeBalanceInterest => Self::CORKEN_NEGATIVE_BALANCE_INTEREST,
+            TxError::RefundExceedsWithdrawal => Self::CORKEN_REFUND_EXCEEDS_WITHDRAWAL,
+            TxError::ZeroAmount => Self::CORKEN_ZERO_AMOUNT,
+            TxError::InvalidMergeTarget => Self::CORKEN_INVALID_MERGE_TARGET,
+            TxError::BalanceLimitExceeded { .. } => Self::CORKEN_BALANCE_LIMIT_EXCEEDED,
+        }
+    }
+}
+
+/// Function will run `f`, returning `on_panic` instead of unwinding into the caller if it panics.
+fn guard<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+/// Function will construct a state machine with the default policies.
+///
+/// # Returns
+/// A state machine that must be released with `corken_state_free`, or `NULL` on failure.
+#[no_mangle]
+pub extern "C" fn corken_state_new() -> *mut corken_state_t {
+    guard(ptr::null_mut(), || {
+        Box::into_raw(Box::new(corken_state_t {
+            state: State::default(),
+        }))
+    })
+}
+
+/// Function will release a state machine. Passing `NULL` is a no-op.
+///
+/// # Safety
+/// `state` must be `NULL` or a pointer returned by `corken_state_new` that hasnt been released
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn corken_state_free(state: *mut corken_state_t) {
+    if !state.is_null() {
+        guard((), || drop(Box::from_raw(state)));
+    }
+}
+
+/// Function will execute a transaction. `amount` is ignored by all types but deposits and
+/// withdrawals.
+///
+/// # Arguments
+/// * `tx_type` - One of `corken_tx_type_t`.
+/// * `out_error` - Set to the outcome of the transaction, may be `NULL`.
+///
+/// # Returns
+/// Whether the transaction was executed.
+///
+/// # Safety
+/// `state` must be a live state machine and `out_error` must be `NULL` or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn corken_execute(
+    state: *mut corken_state_t,
+    tx_type: u32,
+    client: u16,
+    tx: u32,
+    amount: f64,
+    out_error: *mut corken_error_t,
+) -> bool {
+    let code = guard(corken_error_t::CORKEN_PANIC, || {
+        let state = match state.as_mut() {
+            Some(state) => &mut state.state,
+            None => return corken_error_t::CORKEN_NULL_POINTER,
+        };
+
+        let tx_type = match tx_type {
+            x if x == corken_tx_type_t::CORKEN_DEPOSIT as u32 => match Amount::new(amount) {
+                Ok(amount) => TransactionType::Deposit { amount },
+                Err(e) => return corken_error_t::from(&TxError::Invalid(e)),
+            },
+            x if x == corken_tx_type_t::CORKEN_WITHDRAWAL as u32 => match Amount::new(amount) {
+                Ok(amount) => TransactionType::Withdrawal { amount },
+                Err(e) => return corken_error_t::from(&TxError::Invalid(e)),
+            },
+            x if x == corken_tx_type_t::CORKEN_DISPUTE as u32 => TransactionType::Dispute,
+            x if x == corken_tx_type_t::CORKEN_RESOLVE as u32 => TransactionType::Resolve,
+            x if x == corken_tx_type_t::CORKEN_CHARGEBACK as u32 => TransactionType::Chargeback,
+            _ => return corken_error_t::CORKEN_INVALID_ARGUMENT,
+        };
+
+        match state.execute(Transaction::new(tx_type, client, tx)) {
+            Ok(()) => corken_error_t::CORKEN_OK,
+            Err(e) => corken_error_t::from(&e),
+        }
+    });
+
+    if let Some(out_error) = out_error.as_mut() {
+        *out_error = code;
+    }
+
+    code == corken_error_t::CORKEN_OK
+}
+
+/// Function will copy the account of `client` into `out_account`.
+///
+/// # Returns
+/// `CORKEN_ACCOUNT_NOT_FOUND` if the client has no account, in which case `out_account` is left
+/// untouched.
+///
+/// # Safety
+/// `state` must be a live state machine and `out_account` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn corken_account_get(
+    state: *const corken_state_t,
+    client: u16,
+    out_account: *mut corken_account_t,
+) -> corken_error_t {
+    guard(corken_error_t::CORKEN_PANIC, || {
+        let (state, out_account) = match (state.as_ref(), out_account.as_mut()) {
+            (Some(state), Some(out_account)) => (&state.state, out_account),
+            _ => return corken_error_t::CORKEN_NULL_POINTER,
+        };
+
+        match state.accounts.get(&client) {
+            Some(x) => {
+                *out_account = corken_account_t {
+                    client: x.id,
+                    available: x.available,
+                    held: x.held,
+                    total: x.total,
+                    locked: x.locked,
+                };
+
+                corken_error_t::CORKEN_OK
+            }
+            None => corken_error_t::CORKEN_ACCOUNT_NOT_FOUND,
+        }
+    })
+}
+
+/// Function will write all the accounts, in ascending order of their client id, as a CSV
+/// document to the file at `path`, replacing it if it exists.
+///
+/// # Safety
+/// `state` must be a live state machine and `path` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn corken_accounts_export_csv(
+    state: *const corken_state_t,
+    path: *const c_char,
+) -> corken_error_t {
+    guard(corken_error_t::CORKEN_PANIC, || {
+        if path.is_null() {
+            return corken_error_t::CORKEN_NULL_POINTER;
+        }
+
+        let state = match state.as_ref() {
+            Some(state) => &state.state,
+            None => return corken_error_t::CORKEN_NULL_POINTER,
+        };
+
+        let path = match CStr::from_ptr(path).to_str() {
+            Ok(path) => path,
+            Err(_) => return corken_error_t::CORKEN_INVALID_ARGUMENT,
+        };
+
+        let result = csv::Writer::from_path(path).and_then(|mut wtr| {
+            state
+                .accounts_iter_by_id()
+                .try_for_each(|x| wtr.serialize(x))?;
+            wtr.flush()?;
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => corken_error_t::CORKEN_OK,
+            Err(_) => corken_error_t::CORKEN_IO_ERROR,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_lifecycle() {
+        unsafe {
+            let state = corken_state_new();
+            assert!(!state.is_null());
+
+            let mut error = corken_error_t::CORKEN_OK;
+            let deposit = corken_tx_type_t::CORKEN_DEPOSIT as u32;
+            let withdrawal = corken_tx_type_t::CORKEN_WITHDRAWAL as u32;
+            assert!(corken_execute(state, deposit, 1, 1, 10.0, &mut error));
+            assert_eq!(error, corken_error_t::CORKEN_OK);
+            assert!(!corken_execute(state, withdrawal, 1, 2, 50.0, &mut error));
+            assert_eq!(error, corken_error_t::CORKEN_NOT_ENOUGH_FUNDS);
+            assert!(!corken_execute(state, 42, 1, 3, 1.0, &mut error));
+            assert_eq!(error, corken_error_t::CORKEN_INVALID_ARGUMENT);
+            assert!(corken_execute(
+                state,
+                corken_tx_type_t::CORKEN_DISPUTE as u32,
+                1,
+                1,
+                0.0,
+                ptr::null_mut()
+            ));
+
+            let mut account = corken_account_t::default();
+            assert_eq!(
+                corken_account_get(state, 1, &mut account),
+                corken_error_t::CORKEN_OK
+            );
+            assert_eq!(
+                account,
+                corken_account_t {
+                    client: 1,
+                    available: 0.0,
+                    held: 10.0,
+                    total: 10.0,
+                    locked: false,
+                }
+            );
+            assert_eq!(
+                corken_account_get(state, 2, &mut account),
+                corken_error_t::CORKEN_ACCOUNT_NOT_FOUND
+            );
+            assert_eq!(account.client, 1);
+
+            corken_state_free(state);
+        }
+    }
+
+    #[test]
+    fn test_null_pointers() {
+        unsafe {
+            let mut error = corken_error_t::CORKEN_OK;
+            assert!(!corken_execute(
+                ptr::null_mut(),
+                corken_tx_type_t::CORKEN_DEPOSIT as u32,
+                1,
+                1,
+                1.0,
+                &mut error
+            ));
+            assert_eq!(error, corken_error_t::CORKEN_NULL_POINTER);
+
+            let state = corken_state_new();
+            assert_eq!(
+                corken_account_get(state, 1, ptr::null_mut()),
+                corken_error_t::CORKEN_NULL_POINTER
+            );
+            assert_eq!(
+                corken_accounts_export_csv(state, ptr::null()),
+                corken_error_t::CORKEN_NULL_POINTER
+            );
+
+            corken_state_free(state);
+            corken_state_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_guard() {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        let code = guard(corken_error_t::CORKEN_PANIC, || panic!("boom"));
+        panic::set_hook(previous);
+
+        assert_eq!(code, corken_error_t::CORKEN_PANIC);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_export_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("accounts.csv");
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let state = corken_state_new();
+            for client in [2, 1] {
+                corken_execute(
+                    state,
+                    corken_tx_type_t::CORKEN_DEPOSIT as u32,
+                    client,
+                    client as u32,
+                    1.5,
+                    ptr::null_mut(),
+                );
+            }
+
+            assert_eq!(
+                corken_accounts_export_csv(state, c_path.as_ptr()),
+                corken_error_t::CORKEN_OK
+            );
+
+            let missing =
+                CString::new(dir.path().join("missing/accounts.csv").to_str().unwrap()).unwrap();
+            assert_eq!(
+                corken_accounts_export_csv(state, missing.as_ptr()),
+                corken_error_t::CORKEN_IO_ERROR
+            );
+
+            corken_state_free(state);
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "client,available,held,total,locked\n1,1.5,0.0,1.5,false\n2,1.5,0.0,1.5,false\n"
+        );
+    }
+}
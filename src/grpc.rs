@@ -0,0 +1,140 @@
+//! This module contains a gRPC service exposing a shared state machine, built with the `grpc`
+//! feature and started by `corken serve-grpc`.
+//!
+//! The service and its messages are defined in `proto/corken.proto`. Rejected transactions are
+//! returned as `Rejection`s rather than as errors, while requests that cannot be served at all,
+//! such as looking up a client without an account, fail with the matching gRPC status.
+
+// NOTE: `Status` is the error type of every tonic handler, so its size cannot be avoided.
+#![allow(clippy::result_large_err)]
+
+use super::*;
+use crate::proto::engine_server::{Engine, EngineServer};
+use crate::proto::{self, execute_result, ClientId, ExecuteResult, Summary};
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_stream::StreamExt;
+use tonic::{Request, Response, Status, Streaming};
+
+/// Struct represents the gRPC service, executing requests one at a time against a shared state
+/// machine.
+#[derive(Clone)]
+pub struct EngineService {
+    state: Arc<Mutex<State>>,
+}
+
+impl EngineService {
+    /// Function will construct a service backed by `state`, which may be shared with other
+    /// services such as the HTTP service of the `server` feature.
+    pub fn new(state: Arc<Mutex<State>>) -> Self {
+        Self { state }
+    }
+
+    /// Returns the service wrapped in the tonic server, ready to be added to a router.
+    pub fn into_server(self) -> EngineServer<Self> {
+        EngineServer::new(self)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, State>, Status> {
+        self.state
+            .lock()
+            .map_err(|_| Status::internal("state machine is poisoned"))
+    }
+}
+
+#[tonic::async_trait]
+impl Engine for EngineService {
+    async fn execute(
+        &self,
+        request: Request<proto::Transaction>,
+    ) -> Result<Response<ExecuteResult>, Status> {
+        let result = self.lock()?.execute_message(request.into_inner());
+
+        Ok(Response::new(result))
+    }
+
+    async fn execute_stream(
+        &self,
+        request: Request<Streaming<proto::Transaction>>,
+    ) -> Result<Response<Summary>, Status> {
+        let mut stream = request.into_inner();
+        let mut summary = Summary::default();
+
+        // NOTE: The lock is only held while executing a message, so that other requests can be
+        // served while a bulk load waits on its client.
+        while let Some(msg) = stream.next().await {
+            let result = self.lock()?.execute_message(msg?);
+            match result.result {
+                Some(execute_result::Result::Account(_)) => summary.executed += 1,
+                _ => summary.rejected += 1,
+            }
+        }
+
+        Ok(Response::new(summary))
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<ClientId>,
+    ) -> Result<Response<proto::Account>, Status> {
+        let client = request.into_inner().client;
+        let client = u16::try_from(client).map_err(|_| {
+            Status::invalid_argument(format!("client {} doesnt fit in 16 bits", client))
+        })?;
+
+        let state = self.lock()?;
+        let account = state
+            .accounts
+            .get(&client)
+            .ok_or_else(|| Status::not_found(format!("client {} has no account", client)))?;
+
+        Ok(Response::new(proto::Account::from(account)))
+    }
+
+    type ListAccountsStream =
+        tokio_stream::Iter<std::vec::IntoIter<Result<proto::Account, Status>>>;
+
+    async fn list_accounts(
+        &self,
+        _: Request<()>,
+    ) -> Result<Response<Self::ListAccountsStream>, Status> {
+        // NOTE: The accounts are copied out up front, so that the lock isnt held while the client
+        // reads the stream.
+        let accounts: Vec<_> = self
+            .lock()?
+            .accounts_iter_by_id()
+            .map(|x| Ok(proto::Account::from(x)))
+            .collect();
+
+        Ok(Response::new(tokio_stream::iter(accounts)))
+    }
+}
+
+/// Function will serve the gRPC service over `listener` until `shutdown` completes. In-flight
+/// requests are allowed to finish, after which a checkpoint of the state is written into
+/// `snapshot`, if set.
+pub async fn serve(
+    listener: TcpListener,
+    state: Arc<Mutex<State>>,
+    snapshot: Option<&Path>,
+    shutdown: impl Future<Output = ()> + Send,
+) -> io::Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(EngineService::new(state.clone()).into_server())
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown)
+        .await
+        .map_err(io::Error::other)?;
+
+    if let Some(path) = snapshot {
+        let state = state
+            .lock()
+            .map_err(|_| io::Error::other("state machine is poisoned"))?;
+        state.save_checkpoint_to_file(path)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! This module contains the rendering of accounts with their held balance split into the part
+//! pending on holds and the part held by open disputes.
+
+use super::*;
+use serde::ser::SerializeStruct;
+
+/// Struct is a serialization adapter writing an account with `pending_hold` and `disputed_hold`
+/// fields ahead of the `held` field they sum up to. Returned by `Account::with_hold_columns`.
+#[derive(Clone, Copy, Debug)]
+pub struct WithHolds<'a, A = f64>(&'a Account<A>);
+
+impl<A: Money> Account<A> {
+    /// Returns an adapter serializing the account with its held balance split into
+    /// `pending_hold` and `disputed_hold` fields, in addition to the usual fields.
+    pub fn with_hold_columns(&self) -> WithHolds<'_, A> {
+        WithHolds(self)
+    }
+}
+
+impl<A: Money> Serialize for WithHolds<'_, A> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let account = self.0;
+
+        let mut s = serializer.serialize_struct("Account", 7)?;
+        s.serialize_field("client", &account.id)?;
+        s.serialize_field("available", &without_negative_zero(account.available))?;
+        s.serialize_field(
+            "pending_hold",
+            &without_negative_zero(account.pending_hold()),
+        )?;
+        s.serialize_field(
+            "disputed_hold",
+            &without_negative_zero(account.disputed_hold),
+        )?;
+        s.serialize_field("held", &without_negative_zero(account.held))?;
+        s.serialize_field("total", &without_negative_zero(account.total))?;
+        s.serialize_field("locked", &account.locked)?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::custom::{AccountHandle, CustomHandler, CustomRecord};
+    use crate::test::checked;
+
+    /// Places the amount of the transaction on hold.
+    #[derive(Debug)]
+    struct DepositHold;
+
+    impl CustomHandler for DepositHold {
+        fn handle(
+            &self,
+            record: &CustomRecord,
+            account: &mut AccountHandle,
+        ) -> Result<(), TxError> {
+            account.hold(record.amount.unwrap_or_default());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_hold_split() {
+        let mut state = State::builder()
+            .custom_handler("review_hold", DepositHold)
+            .build()
+            .unwrap();
+        let hold = TransactionType::Other {
+            tag: "review_hold".into(),
+            amount: Some(4.0),
+        };
+        let txs = [
+            (
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+            ),
+            (
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                2,
+            ),
+            (hold, 3),
+            (TransactionType::Dispute, 2),
+        ];
+        for (tx_type, tx) in txs {
+            state.execute(Transaction::new(tx_type, 1, tx)).unwrap();
+        }
+
+        let account = &state.accounts[&1];
+        assert_eq!(account.available(), 6.0);
+        assert_eq!(account.pending_hold(), 4.0);
+        assert_eq!(account.disputed_hold(), 5.0);
+        assert_eq!(account.held(), 9.0);
+
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(account.with_hold_columns()).unwrap();
+        assert_eq!(
+            String::from_utf8(wtr.into_inner().unwrap()).unwrap(),
+            "client,available,pending_hold,disputed_hold,held,total,locked\n\
+             1,6.0,4.0,5.0,9.0,15.0,false\n"
+        );
+
+        // The disputed part is released along with the dispute, and rebuilt out of snapshots.
+        let restored = State::from_snapshot(state.snapshot()).unwrap();
+        assert_eq!(restored.accounts[&1].disputed_hold(), 5.0);
+        assert_eq!(restored.accounts[&1].pending_hold(), 4.0);
+
+        state
+            .execute(Transaction::new(TransactionType::Resolve, 1, 2))
+            .unwrap();
+        let account = &state.accounts[&1];
+        assert_eq!(account.disputed_hold(), 0.0);
+        assert_eq!(account.pending_hold(), 4.0);
+        assert_eq!(account.available(), 11.0);
+    }
+}
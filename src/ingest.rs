@@ -0,0 +1,286 @@
+//! This module contains helpers for feeding CSV encoded transactions into the state machine.
+
+use super::*;
+use std::str;
+use std::str::FromStr;
+
+/// Columns assumed for rows that arent read through a `csv::Reader` with headers.
+pub(crate) const HEADERS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// Function will build a transaction out of the fields of a record in the standard
+/// `type, client, tx, amount` column order. Fields are trimmed, and an empty or missing `amount` is
+/// treated as no amount. Refunds and releases cannot be read this way, as the column order has no
+/// `of_tx`.
+///
+/// # Arguments
+/// * `field` - Returns the field at the given index, or `None` if the record is too short.
+pub(crate) fn parse_record<'a, A: Money>(
+    field: impl Fn(usize) -> Result<Option<&'a str>, RecordError>,
+) -> Result<Transaction<A>, RecordError> {
+    let get = |i: usize| -> Result<Option<&'a str>, RecordError> {
+        Ok(field(i)?.map(str::trim).filter(|x| !x.is_empty()))
+    };
+    let required = |i: usize| get(i)?.ok_or(RecordError::MissingField(HEADERS[i]));
+
+    let tag = required(0)?;
+    let client = parse(HEADERS[1], required(1)?)?;
+    let tx = parse(HEADERS[2], required(2)?)?;
+    let amount = get(3)?.map(|x| parse(HEADERS[3], x)).transpose()?;
+    let has_amount = amount.is_some();
+
+    let raw = RawTransactionType {
+        tag: tag.to_string(),
+        amount,
+        of_tx: None,
+    };
+    let tx_type = TransactionType::try_from(raw).map_err(|reason| {
+        if tag == RELEASE || (tag == REFUND && has_amount) {
+            RecordError::MissingField("of_tx")
+        } else if has_amount {
+            RecordError::InvalidField {
+                field: HEADERS[3],
+                reason,
+            }
+        } else {
+            RecordError::MissingField(HEADERS[3])
+        }
+    })?;
+
+    Ok(Transaction::new(tx_type, client, tx))
+}
+
+/// Function will parse the value of `field`.
+fn parse<T: FromStr>(field: &'static str, value: &str) -> Result<T, RecordError> {
+    value.parse().map_err(|_| RecordError::InvalidField {
+        field,
+        reason: format!("`{}` is not a valid number", value),
+    })
+}
+
+impl<A: Money> TryFrom<&csv::StringRecord> for Transaction<A> {
+    type Error = RecordError;
+
+    /// Function will convert a record in the standard `type, client, tx, amount` column order.
+    fn try_from(record: &csv::StringRecord) -> Result<Self, Self::Error> {
+        parse_record(|i| Ok(record.get(i)))
+    }
+}
+
+impl<A: Money> TryFrom<&csv::ByteRecord> for Transaction<A> {
+    type Error = RecordError;
+
+    /// Function will convert a record in the standard `type, client, tx, amount` column order.
+    fn try_from(record: &csv::ByteRecord) -> Result<Self, Self::Error> {
+        parse_record(|i| {
+            record
+                .get(i)
+                .map(str::from_utf8)
+                .transpose()
+                .map_err(|_| RecordError::InvalidField {
+                    field: HEADERS[i],
+                    reason: "not valid UTF-8".into(),
+                })
+        })
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will parse a single CSV row and execute it. The row is expected to follow the
+    /// standard `type, client, tx, amount` column order and must not be a header row.
+    ///
+    /// # Arguments
+    /// * `row` - A single CSV row, with or without a trailing newline.
+    ///
+    /// # Returns
+    /// Returns `TxError::ParseError` if the row cannot be parsed into a `Transaction`, otherwise
+    /// returns the result of `execute`.
+    ///
+    /// # Notes
+    /// The row is split on commas rather than read through a `csv::Reader`, so quoted fields
+    /// arent supported.
+    pub fn execute_csv_row(&mut self, row: &str) -> Result<(), TxError> {
+        if row.trim().is_empty() {
+            return Err(TxError::ParseError("empty row".into()));
+        }
+
+        let record = csv::StringRecord::from(row.split(',').map(str::trim).collect::<Vec<_>>());
+        let tx = Transaction::try_from(&record).map_err(|e| TxError::ParseError(e.to_string()))?;
+
+        self.execute(tx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    #[test]
+    fn test_execute_csv_row() {
+        let mut state = State::default();
+        state.execute_csv_row("deposit, 1, 1, 10.0\n").unwrap();
+        state.execute_csv_row("withdrawal,1,2,2.5").unwrap();
+        state.execute_csv_row("dispute, 1, 1,").unwrap();
+        state.execute_csv_row("resolve, 1, 1").unwrap();
+        state.execute_csv_row("interest, 1, 4, 200").unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 7.65);
+        assert_eq!(account.total, 7.65);
+        assert_eq!(account.held, 0.0);
+
+        assert_eq!(
+            state.execute_csv_row("withdrawal, 1, 3, 100.0"),
+            Err(TxError::NotEnoughFunds)
+        );
+    }
+
+    #[test]
+    fn test_execute_csv_row_parse_errors() {
+        let mut state = State::default();
+
+        for row in [
+            "",
+            "deposit, 1, 1, abc",
+            "deposit, 1, 1,",
+            "deposit, x, 1, 1.0",
+            "interest, 1, 1, 1.5",
+            "interest, 1, 1,",
+            "refund, 1, 2, 1.0",
+            "release, 1, 2,",
+        ] {
+            assert!(
+                matches!(state.execute_csv_row(row), Err(TxError::ParseError(_))),
+                "{}",
+                row
+            );
+        }
+
+        // Unknown types parse fine, but are rejected unless a handler has been registered.
+        assert_eq!(
+            state.execute_csv_row("transfer, 1, 1, 1.0"),
+            Err(TxError::UnsupportedType)
+        );
+
+        assert_eq!(state.accounts().count(), 0);
+    }
+
+    #[test]
+    fn test_footer_row() {
+        let mut state = State::builder().footer_marker("footer").build().unwrap();
+        for row in [
+            "deposit, 1, 1, 10.0",
+            "deposit, 2, 2, 5.5",
+            "withdrawal, 1, 3, 2.0",
+        ] {
+            state.execute_csv_row(row).unwrap();
+        }
+
+        state.execute_csv_row("footer, 0, 0, 13.5").unwrap();
+        assert_eq!(
+            state.execute_csv_row("footer, 0, 0, 14.0"),
+            Err(TxError::FooterMismatch {
+                expected: "14".into(),
+                actual: "13.5".into(),
+            })
+        );
+        assert!(matches!(
+            state.execute_csv_row("footer, 0, 0,"),
+            Err(TxError::ParseError(_))
+        ));
+
+        // Footers dont touch the accounts.
+        assert_eq!(state.accounts().count(), 2);
+
+        for marker in ["deposit", "release"] {
+            assert_eq!(
+                State::builder().footer_marker(marker).build().err(),
+                Some(BuildError::Conflict(
+                    "footer marker cannot be a standard transaction type"
+                ))
+            );
+        }
+    }
+
+    fn record(fields: &[&str]) -> Result<Transaction, RecordError> {
+        Transaction::try_from(&csv::StringRecord::from(fields))
+    }
+
+    #[test]
+    fn test_try_from_record() {
+        let tx = |tx_type| Ok(Transaction::new(tx_type, 1, 2));
+
+        assert_eq!(
+            record(&["deposit", "1", "2", "1.5"]),
+            tx(TransactionType::Deposit {
+                amount: checked(1.5)
+            })
+        );
+        assert_eq!(
+            record(&[" withdrawal ", " 1", "2 ", " 0.25 "]),
+            tx(TransactionType::Withdrawal {
+                amount: checked(0.25)
+            })
+        );
+        assert_eq!(
+            record(&["dispute", "1", "2", ""]),
+            tx(TransactionType::Dispute)
+        );
+        assert_eq!(record(&["resolve", "1", "2"]), tx(TransactionType::Resolve));
+        assert_eq!(
+            record(&["chargeback", "1", "2", ""]),
+            tx(TransactionType::Chargeback)
+        );
+        assert_eq!(
+            record(&["transfer", "1", "2", "3"]),
+            tx(TransactionType::Other {
+                tag: "transfer".into(),
+                amount: Some(3.0),
+            })
+        );
+
+        let bytes = csv::ByteRecord::from(vec!["deposit", "1", "2", "1.5"]);
+        assert_eq!(
+            Transaction::try_from(&bytes),
+            tx(TransactionType::Deposit {
+                amount: checked(1.5)
+            })
+        );
+    }
+
+    #[test]
+    fn test_try_from_record_errors() {
+        assert_eq!(record(&[]), Err(RecordError::MissingField("type")));
+        assert_eq!(
+            record(&["deposit", "1"]),
+            Err(RecordError::MissingField("tx"))
+        );
+        assert_eq!(
+            record(&["deposit", "1", "2", " "]),
+            Err(RecordError::MissingField("amount"))
+        );
+        assert_eq!(
+            record(&["deposit", "70000", "2", "1.0"]),
+            Err(RecordError::InvalidField {
+                field: "client",
+                reason: "`70000` is not a valid number".into(),
+            })
+        );
+        assert_eq!(
+            record(&["withdrawal", "1", "2", "1.0.0"]),
+            Err(RecordError::InvalidField {
+                field: "amount",
+                reason: "`1.0.0` is not a valid number".into(),
+            })
+        );
+
+        let bytes = csv::ByteRecord::from(vec![&b"deposit"[..], b"1", b"\xff", b"1.0"]);
+        assert_eq!(
+            Transaction::<f64>::try_from(&bytes),
+            Err(RecordError::InvalidField {
+                field: "tx",
+                reason: "not valid UTF-8".into(),
+            })
+        );
+    }
+}
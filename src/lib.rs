@@ -1,30 +1,324 @@
 #![doc = include_str!("../README.md")]
+/// All-or-nothing and fail-fast execution of batches of transactions.
+pub mod atomic;
+/// Audit trail of the transactions commited by the state machine.
+pub mod audit;
+/// Builder used to configure the policies of the state machine.
+pub mod builder;
+/// Clock providing the logical time of the state machine.
+pub mod clock;
+/// Compensating transactions reversing deposits made in error.
+pub mod compensate;
+/// Currencies and the segregation of balances per currency.
+pub mod currency;
+/// Extension point used to execute custom transaction types.
+pub mod custom;
+/// Transactions deferred until another transaction has been executed.
+pub mod deferred;
+/// Helpers used to observe the changes a transaction commits to an account.
+pub mod diff;
+/// Driver feeding transactions from asynchronous, offset based sources.
+#[cfg(feature = "async")]
+pub mod driver;
+/// Ledger effects describing the double-entry movement performed by a transaction.
+pub mod effect;
 /// Errors returned by the state machine.
 pub mod error;
+/// Typed events emitted by the state machine for event sourcing.
+pub mod events;
+/// Aggregate views over the funds held by pending disputes.
+pub mod exposure;
+/// Helpers for replaying transactions from sources that can fail.
+pub mod fallible;
+/// C bindings of the state machine.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+/// gRPC service exposing a shared state machine.
+#[cfg(feature = "grpc")]
+pub mod grpc;
+/// Rendering of accounts with their held balance split into pending and disputed holds.
+#[cfg(feature = "serde")]
+pub mod holds;
+/// Helpers for feeding CSV encoded transactions into the state machine.
+#[cfg(feature = "serde")]
+pub mod ingest;
+/// Estimates of the memory consumed by the state machine.
+pub mod memory;
+/// Memory-mapped ingestion of large CSV files.
+#[cfg(feature = "mmap")]
+pub mod mmap;
+/// Abstraction over the representation of monetary amounts.
+pub mod money;
+/// MessagePack encoding of snapshots and exported accounts.
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+/// Ingestion of transactions split across several CSV inputs.
+#[cfg(feature = "serde")]
+pub mod multi;
+/// Node.js bindings of the state machine.
+#[cfg(feature = "node")]
+pub mod node;
+/// Freeform notes attached to disputes.
+pub mod notes;
+/// Pluggable monitoring of the transactions executed by the state machine.
+pub mod observer;
+/// Parallel ingestion of a CSV document split into chunks.
+#[cfg(feature = "serde")]
+pub mod parallel;
+/// Settlement snapshots cut at period boundaries found in the timestamps of transactions.
+pub mod period;
+/// Policies deciding how disputes move balances around.
+pub mod policy;
+/// Protobuf encoding of transactions and accounts.
+#[cfg(feature = "proto")]
+pub mod proto;
+/// Python bindings of the state machine.
+#[cfg(feature = "python")]
+pub mod python;
 /// Contains some trait impls necessary for generating random transactions for benchmarking.
 pub mod rand_utils;
+/// Ranking of accounts by their balance.
+pub mod ranking;
+/// Redacted rendering of transactions used for compliant logging.
+pub mod redact;
+/// Replay of transactions surviving panics raised by individual transactions.
+pub mod resilient;
+/// JSON Schemas of the transaction and account formats.
+#[cfg(feature = "schemars")]
+pub mod schema;
+/// HTTP service exposing a shared state machine.
+#[cfg(feature = "server")]
+pub mod server;
+/// Serializable snapshots of the state machine, used for checkpointing.
+#[cfg(feature = "serde")]
+pub mod snapshot;
+/// Abstraction over the sources transactions can be read from.
+pub mod source;
+/// Per account statements of the transactions commited by the state machine.
+#[cfg(feature = "statement-logging")]
+pub mod statement;
+/// Counters of the transactions executed by the state machine.
+pub mod stats;
+/// Rendering of accounts with a string status in place of the `locked` flag.
+#[cfg(feature = "serde")]
+pub mod status;
+/// Helpers for feeding CSV encoded transactions from async streams into the state machine.
+#[cfg(feature = "async")]
+pub mod stream;
+/// Subscriptions pushing the changes commited to an account to callbacks.
+pub mod subscribe;
+/// Timestamps carried by transactions, and their chronological validation.
+pub mod timestamp;
+/// Unix domain socket server writing the accounts of the state machine.
+#[cfg(all(unix, feature = "unix-socket"))]
+pub mod unix;
+/// State-independent validation of transactions.
+pub mod validate;
+/// JavaScript bindings of the state machine.
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+#[cfg(feature = "serde")]
 use serde::Deserialize;
+#[cfg(feature = "serde")]
 use serde::Serialize;
 
-use ahash::AHashMap;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+#[cfg(feature = "serde")]
+use std::io::Write;
+use std::iter;
+use std::sync::Arc;
 
+use builder::*;
+use clock::*;
+use currency::Currency;
+use custom::*;
 use error::*;
+use events::AccountEvent;
+use money::*;
+use observer::StateObserver;
+use policy::*;
+use stats::TypeCounts;
+
+/// The smallest unit of currency the engine cares about. Amounts are assumed to have at most four
+/// decimal places of precision.
+pub const MINOR_UNIT: f64 = 0.0001;
+
+/// The decimal places of the `MINOR_UNIT`, which amounts computed by the engine are rounded to.
+const MINOR_UNIT_DECIMAL_PLACES: u32 = 4;
 
 /// Struct represents a transaction and contains its state.
-#[derive(Clone, Debug, Deserialize)]
-pub struct Transaction {
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawTransaction<A>"))]
+#[cfg_attr(feature = "serde", serde(bound(deserialize = "A: Money")))]
+pub struct Transaction<A = f64> {
     /// Represents the transaction type.
-    #[serde(flatten)]
-    tx_type: TransactionType,
+    tx_type: TransactionType<A>,
     /// Represents a client id.
     client: u16,
-    /// Represents a transaction id.
+    /// Represents a transaction id. For disputes, resolves and chargebacks this is the id of the
+    /// transaction they refer to.
+    tx: u32,
+    /// Represents the currency of the transaction, if any. The state machine itself ignores it,
+    /// balances are only segregated per currency by `MultiCurrencyState`.
+    currency: Option<Currency>,
+    /// Represents the time the transaction was stamped with upstream, in milliseconds since the
+    /// Unix epoch. Transactions without a timestamp are always accepted.
+    timestamp: Option<u64>,
+    /// Represents the id upstream tagged the input row of the transaction with, if any. The state
+    /// machine itself ignores it, it is only echoed in the `LedgerEffect` of the transaction.
+    row_id: Option<u64>,
+    /// Represents the own id of a dispute, resolve or chargeback, if it carries one. Serialized
+    /// files carry it in the `tx` column, the transaction referred to then being in a `ref_tx`
+    /// column.
+    event_id: Option<u32>,
+}
+
+/// Struct represents a transaction as read from a serialized file, before the `tx` and `ref_tx`
+/// columns of disputes, resolves and chargebacks have been told apart.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+struct RawTransaction<A> {
+    #[serde(flatten)]
+    tx_type: TransactionType<A>,
+    client: u16,
     tx: u32,
+    #[serde(default, deserialize_with = "currency::deserialize_currency")]
+    currency: Option<Currency>,
+    #[serde(default, deserialize_with = "timestamp::deserialize_timestamp")]
+    timestamp: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_row_id")]
+    row_id: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_ref_tx")]
+    ref_tx: Option<u32>,
+}
+
+#[cfg(feature = "serde")]
+impl<A: Money> TryFrom<RawTransaction<A>> for Transaction<A> {
+    type Error = String;
+
+    fn try_from(raw: RawTransaction<A>) -> Result<Self, Self::Error> {
+        // NOTE: Rows without a `ref_tx` keep referring to the disputed transaction through `tx`,
+        // so that old and new style rows can be mixed in the same file.
+        let (tx, event_id) = match raw.ref_tx {
+            None => (raw.tx, None),
+            Some(ref_tx) if raw.tx_type.is_dispute() => (ref_tx, Some(raw.tx)),
+            Some(_) => {
+                return Err(format!(
+                    "unexpected ref_tx for `{}`, only disputes, resolves and chargebacks refer to \
+                     another transaction through it",
+                    raw.tx_type.tag()
+                ))
+            }
+        };
+
+        Ok(Self {
+            tx_type: raw.tx_type,
+            client: raw.client,
+            tx,
+            currency: raw.currency,
+            timestamp: raw.timestamp,
+            row_id: raw.row_id,
+            event_id,
+        })
+    }
+}
+
+impl<A> Transaction<A> {
+    /// Function will construct a new transaction.
+    ///
+    /// # Arguments
+    /// * `tx_type` - The type of the transaction.
+    /// * `client` - The id of the client the transaction belongs to.
+    /// * `tx` - The id of the transaction.
+    pub fn new(tx_type: TransactionType<A>, client: u16, tx: u32) -> Self {
+        Self {
+            tx_type,
+            client,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    /// Returns the transaction tagged with `currency`.
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = Some(currency);
+        self
+    }
+
+    /// Returns the transaction stamped with `timestamp`, in milliseconds since the Unix epoch.
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Returns the transaction tagged with the id of the input row it was read from.
+    pub fn with_row_id(mut self, row_id: u64) -> Self {
+        self.row_id = Some(row_id);
+        self
+    }
+
+    /// Returns the dispute, resolve or chargeback given its own id `event_id`. See `event_id`.
+    pub fn with_event_id(mut self, event_id: u32) -> Self {
+        self.event_id = Some(event_id);
+        self
+    }
+
+    /// Returns the type of the transaction.
+    pub fn tx_type(&self) -> &TransactionType<A> {
+        &self.tx_type
+    }
+
+    /// Returns the id of the client the transaction belongs to.
+    pub fn client(&self) -> u16 {
+        self.client
+    }
+
+    /// Returns the id of the transaction.
+    pub fn tx(&self) -> u32 {
+        self.tx
+    }
+
+    /// Returns the currency of the transaction, if any.
+    pub fn currency(&self) -> Option<Currency> {
+        self.currency
+    }
+
+    /// Returns the timestamp of the transaction in milliseconds since the Unix epoch, if any.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    /// Returns the id of the input row the transaction was read from, if any.
+    pub fn row_id(&self) -> Option<u64> {
+        self.row_id
+    }
+
+    /// Returns the own id of a dispute, resolve or chargeback, if it carries one. Those are
+    /// unique, a second transaction carrying the same one is rejected with `TxError::DuplicateTx`.
+    /// `tx` is the id of the transaction referred to either way.
+    pub fn event_id(&self) -> Option<u32> {
+        self.event_id
+    }
 }
 
 /// Enum represents the state of a transaction dispute.
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum DisputeState {
     /// The transaction is currently being disputed.
     Disputed,
@@ -34,60 +328,602 @@ pub enum DisputeState {
 
 /// Represents a transaction type. This would be deserialized from a `type` field in a serialized
 /// file.
-#[derive(Clone, Debug, Deserialize)]
-#[serde(rename_all = "lowercase")]
-#[serde(tag = "type")]
-pub enum TransactionType {
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawTransactionType<A>"))]
+#[cfg_attr(feature = "serde", serde(bound(deserialize = "A: Money")))]
+pub enum TransactionType<A = f64> {
     /// Represents a deposit transaction, this enum field also embeds the amount thats been
     /// deposited.
-    Deposit { amount: f64 },
+    Deposit { amount: Amount<A> },
     /// Represents a withdrawal transaction, this enum field also embeds the amount thats been
     /// withdrawn.
-    Withdrawal { amount: f64 },
+    Withdrawal { amount: Amount<A> },
     /// Represents a dispute transaction.
     Dispute,
     /// Represents a resolve transaction.
     Resolve,
     /// Represents a chargeback transaction.
     Chargeback,
+    /// Represents an interest accrual, crediting the `available` balance with `rate_bps` basis
+    /// points of itself. The credited amount is computed at execution time, rounded to the
+    /// `MINOR_UNIT` with the configured `RoundingMode`. Serialized files carry the rate in the
+    /// `amount` column, in whole basis points.
+    Interest { rate_bps: u32 },
+    /// Represents a refund of `amount` against the earlier withdrawal `of_tx` of the same client,
+    /// crediting it back to the `available` balance. A withdrawal can be refunded several times,
+    /// as long as the refunds dont add up to more than was withdrawn. Serialized files carry the
+    /// referenced withdrawal in an `of_tx` column.
+    Refund { of_tx: u32, amount: A },
+    /// Represents a manual hold placing `amount` of the `available` balance on hold, e.g. while a
+    /// review is pending. Manual holds are tracked separately from disputes, and count towards
+    /// the pending part of the `held` balance.
+    Hold { amount: A },
+    /// Represents the release of the manual hold `of_tx` of the same client, moving its whole
+    /// amount back to the `available` balance. Serialized files carry the released hold in an
+    /// `of_tx` column.
+    Release { of_tx: u32 },
+    /// Represents a transaction with an unknown `type`. These are only executed if a
+    /// `CustomHandler` has been registered for `tag`, otherwise they get rejected with
+    /// `TxError::UnsupportedType`.
+    Other { tag: String, amount: Option<A> },
+}
+
+const DEPOSIT: &str = "deposit";
+const WITHDRAWAL: &str = "withdrawal";
+const DISPUTE: &str = "dispute";
+const RESOLVE: &str = "resolve";
+const CHARGEBACK: &str = "chargeback";
+const INTEREST: &str = "interest";
+const REFUND: &str = "refund";
+const HOLD: &str = "hold";
+const RELEASE: &str = "release";
+
+/// The `type` of every standard transaction as it appears in a serialized file. Any other `type`
+/// is read as `TransactionType::Other`.
+pub(crate) const STANDARD_TAGS: [&str; 9] = [
+    DEPOSIT, WITHDRAWAL, DISPUTE, RESOLVE, CHARGEBACK, INTEREST, REFUND, HOLD, RELEASE,
+];
+
+impl<A> TransactionType<A> {
+    /// Returns the `type` of the transaction as it appears in a serialized file, e.g. `deposit`.
+    pub fn tag(&self) -> &str {
+        match self {
+            TransactionType::Deposit { .. } => DEPOSIT,
+            TransactionType::Withdrawal { .. } => WITHDRAWAL,
+            TransactionType::Dispute => DISPUTE,
+            TransactionType::Resolve => RESOLVE,
+            TransactionType::Chargeback => CHARGEBACK,
+            TransactionType::Interest { .. } => INTEREST,
+            TransactionType::Refund { .. } => REFUND,
+            TransactionType::Hold { .. } => HOLD,
+            TransactionType::Release { .. } => RELEASE,
+            TransactionType::Other { tag, .. } => tag,
+        }
+    }
+
+    /// Returns whether the transaction is a dispute, resolve or chargeback, referring to the
+    /// transaction it applies to through its `tx`.
+    pub fn is_dispute(&self) -> bool {
+        matches!(
+            self,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+        )
+    }
+}
+
+/// Struct represents the raw `type`, `amount` and `of_tx` fields of a serialized transaction,
+/// before they get validated into a `TransactionType`.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+struct RawTransactionType<A> {
+    #[serde(rename = "type")]
+    tag: String,
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    amount: Option<A>,
+    #[serde(default, deserialize_with = "deserialize_of_tx")]
+    of_tx: Option<u32>,
+}
+
+/// Function will deserialize an optional number, treating empty strings as a missing number.
+/// `what` names the field in error messages.
+#[cfg(feature = "serde")]
+fn deserialize_number<'de, D, T>(de: D, what: &str) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::de::DeserializeOwned + std::str::FromStr,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    #[serde(bound(deserialize = "T: serde::de::DeserializeOwned"))]
+    enum Number<T> {
+        Number(T),
+        Text(String),
+    }
+
+    match Option::<Number<T>>::deserialize(de)? {
+        None => Ok(None),
+        Some(Number::Number(x)) => Ok(Some(x)),
+        Some(Number::Text(x)) if x.trim().is_empty() => Ok(None),
+        Some(Number::Text(x)) => x
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid {} `{}`", what, x))),
+    }
+}
+
+/// Function will deserialize an optional amount, treating empty strings as a missing amount.
+#[cfg(feature = "serde")]
+fn deserialize_amount<'de, D: serde::Deserializer<'de>, A: Money>(
+    de: D,
+) -> Result<Option<A>, D::Error> {
+    deserialize_number(de, "amount")
+}
+
+/// Function will deserialize the optional id of the transaction referenced by a refund.
+#[cfg(feature = "serde")]
+fn deserialize_of_tx<'de, D: serde::Deserializer<'de>>(de: D) -> Result<Option<u32>, D::Error> {
+    deserialize_number(de, "of_tx")
+}
+
+/// Function will deserialize the optional id of the transaction referenced by a dispute, resolve
+/// or chargeback.
+#[cfg(feature = "serde")]
+fn deserialize_ref_tx<'de, D: serde::Deserializer<'de>>(de: D) -> Result<Option<u32>, D::Error> {
+    deserialize_number(de, "ref_tx")
+}
+
+/// Function will deserialize the optional id of the input row of a transaction.
+#[cfg(feature = "serde")]
+fn deserialize_row_id<'de, D: serde::Deserializer<'de>>(de: D) -> Result<Option<u64>, D::Error> {
+    deserialize_number(de, "row_id")
+}
+
+#[cfg(feature = "serde")]
+impl<A: Money> TryFrom<RawTransactionType<A>> for TransactionType<A> {
+    type Error = String;
+
+    fn try_from(raw: RawTransactionType<A>) -> Result<Self, Self::Error> {
+        let amount = || {
+            raw.amount
+                .ok_or_else(|| format!("missing amount for `{}`", raw.tag))
+        };
+        let checked_amount = || amount().and_then(|x| Amount::new(x).map_err(|e| e.to_string()));
+        let of_tx = || {
+            raw.of_tx
+                .ok_or_else(|| format!("missing of_tx for `{}`", raw.tag))
+        };
+
+        Ok(match raw.tag.as_str() {
+            DEPOSIT => TransactionType::Deposit {
+                amount: checked_amount()?,
+            },
+            WITHDRAWAL => TransactionType::Withdrawal {
+                amount: checked_amount()?,
+            },
+            DISPUTE => TransactionType::Dispute,
+            RESOLVE => TransactionType::Resolve,
+            CHARGEBACK => TransactionType::Chargeback,
+            INTEREST => {
+                let rate = amount()?;
+                let rate_bps = rate.to_string().parse().map_err(|_| {
+                    format!("invalid interest rate `{}`, expected basis points", rate)
+                })?;
+                TransactionType::Interest { rate_bps }
+            }
+            REFUND => TransactionType::Refund {
+                of_tx: of_tx()?,
+                amount: amount()?,
+            },
+            HOLD => TransactionType::Hold { amount: amount()? },
+            RELEASE => TransactionType::Release { of_tx: of_tx()? },
+            _ => TransactionType::Other {
+                amount: raw.amount,
+                tag: raw.tag,
+            },
+        })
+    }
+}
+
+/// Function will serialize a balance of an account, writing negative zero as zero.
+#[cfg(feature = "serde")]
+fn serialize_balance<S: serde::Serializer, A: Money>(
+    balance: &A,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    without_negative_zero(*balance).serialize(serializer)
 }
 
 /// Struct represents an account in the state machine.
-#[derive(Serialize, Default)]
-pub struct Account {
+#[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(bound(serialize = "A: Money")))]
+#[cfg_attr(feature = "schemars", schemars(rename = "Account"))]
+pub struct Account<A = f64> {
     /// Field contains the ID of the client/account. This field gets renamed to `client` when
     /// serialized.
-    #[serde(rename = "client")]
+    #[cfg_attr(feature = "serde", serde(rename = "client"))]
     id: u16,
     /// Represents the available balance of this account. This is the balance that the account can
     /// withdraw, or use.
-    available: f64,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_balance"))]
+    available: A,
     /// Represents the held balance of this account. When a client files a dispute, this balance
     /// gets increased while the `available` balance gets decreased. The client cannot use this
     /// this balance.
-    held: f64,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_balance"))]
+    held: A,
+    /// Represents the part of the `held` balance placed on hold by open disputes. The rest is
+    /// pending, i.e. held by manual holds and custom transactions.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    disputed_hold: A,
     /// Represents the total value/balance of this account.
-    total: f64,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_balance"))]
+    total: A,
     /// Represents whether this account is locked or not.
     locked: bool,
 }
 
+impl<A: Money> Account<A> {
+    /// Returns the id of the client this account belongs to.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the available balance of the account.
+    pub fn available(&self) -> A {
+        self.available
+    }
+
+    /// Returns the held balance of the account.
+    pub fn held(&self) -> A {
+        self.held
+    }
+
+    /// Returns the total balance of the account.
+    pub fn total(&self) -> A {
+        self.total
+    }
+
+    /// Returns the part of the `held` balance placed on hold by open disputes.
+    pub fn disputed_hold(&self) -> A {
+        self.disputed_hold
+    }
+
+    /// Returns the part of the `held` balance that isnt due to disputes, i.e. funds placed on hold
+    /// manually or by custom transactions. Together with `disputed_hold` it sums up to `held`.
+    pub fn pending_hold(&self) -> A {
+        self.held - self.disputed_hold
+    }
+
+    /// Returns whether the account is locked.
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Function will snap balances that landed within `Money::tolerance` of zero to exactly zero,
+    /// so that float residuals and `-0.0` dont leak into the output.
+    pub(crate) fn normalize(&mut self) {
+        for balance in [
+            &mut self.available,
+            &mut self.held,
+            &mut self.disputed_hold,
+            &mut self.total,
+        ] {
+            if balance.is_negligible() {
+                *balance = A::ZERO;
+            }
+        }
+    }
+
+    /// Returns whether the `held` balance of the account doesnt exceed its `total` balance.
+    pub(crate) fn is_held_within_total(&self) -> bool {
+        self.held <= self.total + A::tolerance()
+    }
+
+    /// Returns whether the balances of the account satisfy `total == held + available`.
+    pub(crate) fn is_balanced(&self) -> bool {
+        (self.total - (self.held + self.available)).is_negligible()
+    }
+}
+
+/// Struct represents a transaction cached by the state machine so that it can be disputed later
+/// on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedTx<A = f64> {
+    /// The cached transaction.
+    tx: Transaction<A>,
+    /// The dispute state of the transaction. If `None`, the transaction is not under dispute.
+    dispute: Option<DisputeState>,
+    /// The own id of the dispute opened on the transaction, if it carried one.
+    dispute_event_id: Option<u32>,
+    /// The logical time at which the transaction was commited.
+    seq: u64,
+}
+
+impl<A> CachedTx<A> {
+    /// Returns the cached transaction.
+    pub fn transaction(&self) -> &Transaction<A> {
+        &self.tx
+    }
+
+    /// Returns the dispute state of the cached transaction.
+    pub fn dispute_state(&self) -> Option<&DisputeState> {
+        self.dispute.as_ref()
+    }
+
+    /// Returns the own id of the dispute opened on the cached transaction, if it carried one.
+    pub fn dispute_event_id(&self) -> Option<u32> {
+        self.dispute_event_id
+    }
+}
+
+/// Struct represents a withdrawal recorded by the state machine so that it can be refunded later
+/// on.
+#[derive(Clone, Debug, PartialEq)]
+struct RefundableWithdrawal<A> {
+    /// The client the withdrawal belongs to.
+    client: u16,
+    /// The amount withdrawn.
+    amount: A,
+    /// The amount refunded so far.
+    refunded: A,
+}
+
+/// Struct represents a manual hold placed by the state machine, until it gets released.
+#[derive(Clone, Debug, PartialEq)]
+struct ManualHold<A> {
+    /// The client the hold has been placed on.
+    client: u16,
+    /// The amount on hold.
+    amount: A,
+}
+
+/// Struct identifies a transaction executed by the state machine. The type tells disputes,
+/// resolves and chargebacks apart from the transaction they refer to, whose id they share.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ProcessedTx {
+    /// The id of the transaction.
+    tx: u32,
+    /// The client of the transaction.
+    client: u16,
+    /// The key the type of the transaction is counted under.
+    kind: &'static str,
+}
+
+impl ProcessedTx {
+    /// Returns the identity of `tx`.
+    fn of<A>(tx: &Transaction<A>) -> Self {
+        Self {
+            tx: tx.tx,
+            client: tx.client,
+            kind: TypeCounts::key(&tx.tx_type),
+        }
+    }
+}
+
 /// Struct represents the state machine that can consume transactions. This state machine is
 /// infinitely parallelizable.
-pub struct State {
+///
+/// The state machine is generic over the hasher used by its internal hash maps, defaulting to
+/// `ahash`. Use `State::new_with_hasher` to swap it out. It is also generic over the type used to
+/// represent amounts, defaulting to `f64`. Use `StateBuilder` to build a state machine using
+/// `rust_decimal::Decimal` amounts instead.
+#[derive(Clone)]
+pub struct State<H = ahash::RandomState, A = f64> {
     /// Hashmap of all accounts indexed by the `id` field.
-    accounts: AHashMap<u16, Account>,
-    /// A cache of disputable transactions paired with a dispute state. If the dispute state is
-    /// `None`, then the transaction is not under dispute.
+    accounts: HashMap<u16, Account<A>, H>,
+    /// A cache of disputable transactions paired with a dispute state.
     /// This cache is indexed by `Transaction::tx`.
-    tx_cache: AHashMap<u32, (Transaction, Option<DisputeState>)>,
+    tx_cache: HashMap<u32, CachedTx<A>, H>,
+    /// Withdrawals that can be refunded, indexed by `Transaction::tx`. Unlike the `tx_cache`,
+    /// every withdrawal commited is recorded here, regardless of the dispute policies.
+    refundable: HashMap<u32, RefundableWithdrawal<A>, H>,
+    /// Manual holds that havent been released yet, indexed by `Transaction::tx`.
+    manual_holds: HashMap<u32, ManualHold<A>, H>,
+    /// Set of deposit and withdrawal ids that have been commited. Only populated in idempotent
+    /// mode.
+    seen: HashSet<u32, H>,
+    /// Set of the own ids of the disputes, resolves and chargebacks that have been commited.
+    dispute_event_ids: HashSet<u32, H>,
+    /// Set of transaction ids that have been frozen by `freeze_tx_cache_before` and can no longer
+    /// be disputed.
+    frozen_tx_ids: HashSet<u32, H>,
+    /// Per client logical times and amounts of the withdrawals commited within the velocity
+    /// window. Only populated when a withdrawal velocity limit is configured.
+    recent_withdrawals: HashMap<u16, VecDeque<(u64, A)>, H>,
+    /// Per client overdraft limits, overriding the default credit limit of the configuration.
+    overdraft_limits: HashMap<u16, A, H>,
+    /// Per client limits of the `total` balance, overriding the default balance limit of the
+    /// configuration.
+    balance_limits: HashMap<u16, A, H>,
+    /// Freeform notes attached to cached transactions by `add_dispute_note`, indexed by
+    /// `Transaction::tx`.
+    dispute_notes: HashMap<u32, Vec<String>, H>,
+    /// Transactions deferred by `execute_deferred`, indexed by the id of the transaction
+    /// triggering them.
+    pending_deferred: BTreeMap<u32, Vec<Transaction<A>>>,
+    /// Disputes parked until the deposit they refer to is commited, indexed by the client and id
+    /// of that deposit. Only populated with `StateBuilder::deferred_disputes`.
+    pending_disputes: HashMap<(u16, u32), Transaction<A>, H>,
+    /// Logical time of the last transaction executed, as read from the configured `Clock`.
+    seq: u64,
+    /// The last transaction executed, whether it was commited or rejected.
+    last_tx: Option<ProcessedTx>,
+    /// Whether the logical time has been frozen by `freeze_clock`.
+    clock_frozen: bool,
+    /// Latest timestamp carried by a transaction, across all clients.
+    latest_timestamp: Option<u64>,
+    /// Per client latest timestamps carried by their transactions.
+    client_timestamps: HashMap<u16, u64, H>,
+    /// Number of transactions executed successfully, per type.
+    type_counts: TypeCounts,
+    /// Number of transactions executed, whatever their outcome.
+    executed_count: u64,
+    /// Number of transactions executed that have been rejected.
+    failed_count: u64,
+    /// Observer notified of every transaction executed, if any.
+    observer: Option<Arc<dyn StateObserver<A> + Send + Sync>>,
+    /// Callbacks subscribed to the changes of an account, indexed by the id of its client.
+    subscriptions: subscribe::Subscriptions<A>,
+    /// Id of the next subscription registered by `subscribe`.
+    next_subscription: u64,
+    /// Per client statements of the transactions commited to their account, in commit order.
+    #[cfg(feature = "statement-logging")]
+    statements: HashMap<u16, Vec<statement::StatementLine<A>>, H>,
+    /// Policies this state machine has been configured with.
+    config: Config<A>,
+}
+
+/// Function will check that crediting `amount` to `account` keeps its `total` balance within
+/// `limit`, if any.
+fn check_balance_limit<A: Money>(
+    account: &Account<A>,
+    amount: A,
+    limit: Option<A>,
+) -> Result<(), TxError> {
+    match limit {
+        Some(limit) if account.total + amount > limit + A::tolerance() => {
+            Err(TxError::BalanceLimitExceeded {
+                limit: limit.to_string(),
+                attempted: (account.total + amount).to_string(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Function will open a dispute of `disputed` on `account`, the account of its owner, once the
+/// dispute `tx` has been matched with it.
+fn open_dispute<A: Money>(
+    config: &Config<A>,
+    seq: u64,
+    account: &mut Account<A>,
+    disputed: &mut CachedTx<A>,
+    tx: &Transaction<A>,
+    sink: &mut dyn FnMut(AccountEvent<A>),
+) -> Result<(), TxError> {
+    if !config.dispute_authorizer.may_dispute(tx.client, disputed) {
+        return Err(TxError::Unauthorized);
+    }
+
+    if disputed.dispute.is_some() {
+        return Err(TxError::TxAlreadyDisputed);
+    }
+
+    if let DisputeWindow::Transactions(window) = config.dispute_window {
+        if seq - disputed.seq > window {
+            return Err(TxError::DisputeWindowExpired);
+        }
+    }
+
+    // NOTE: The spec doesnt specifically state what transactions can be disputed. Based on the
+    // logic described in there for disputes, it is safe to assume that at least Deposit
+    // transactions can be disputed.
+    let outcome = config.dispute_policy.on_dispute(account, disputed)?;
+    if !outcome.is_balanced() {
+        return Err(TxError::InternalError);
+    }
+
+    if config.prevent_negative_available
+        && (account.available + outcome.available + A::tolerance()).is_negative()
+    {
+        return Err(TxError::NotEnoughFunds);
+    }
+
+    if config.check_held && !outcome.keeps_held_within_total(account) {
+        return Err(TxError::InvariantBreach);
+    }
+
+    outcome.apply_dispute(account);
+    disputed.dispute = Some(DisputeState::Disputed);
+    disputed.dispute_event_id = tx.event_id;
+
+    sink(AccountEvent::DisputeOpened {
+        client: account.id,
+        tx: tx.tx,
+        amount: outcome.held,
+        event_id: tx.event_id,
+    });
+
+    Ok(())
+}
+
+/// Function will insert a transaction into the `tx_cache`, making it the target of any future
+/// dispute, resolve or chargeback referencing its transaction id.
+///
+/// # Notes
+/// Transaction ids are not required to be unique, so a deposit can share its id with an earlier
+/// one. In that case disputes always target the most recently cached deposit, with one exception:
+/// if the earlier deposit is currently under dispute, it stays cached so that the resolve or
+/// chargeback lands on the same deposit that was disputed. The newer deposit is still commited,
+/// but it cannot be disputed.
+fn cache_tx<H: BuildHasher, A>(
+    tx_cache: &mut HashMap<u32, CachedTx<A>, H>,
+    tx: Transaction<A>,
+    seq: u64,
+) {
+    let cached = CachedTx {
+        tx,
+        dispute: None,
+        dispute_event_id: None,
+        seq,
+    };
+
+    match tx_cache.entry(cached.tx.tx) {
+        Entry::Occupied(entry) if entry.get().dispute.is_some() => {}
+        Entry::Occupied(mut entry) => {
+            entry.insert(cached);
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(cached);
+        }
+    }
 }
 
 impl Default for State {
     fn default() -> State {
+        State::new_with_hasher(ahash::RandomState::default())
+    }
+}
+
+impl<H: BuildHasher + Clone, A: Money> State<H, A> {
+    /// Function will construct an empty state machine whose hash maps use `hasher`.
+    ///
+    /// # Arguments
+    /// * `hasher` - Hasher used for all the internal hash maps.
+    pub fn new_with_hasher(hasher: H) -> Self {
         State {
-            accounts: AHashMap::with_capacity(1024),
-            tx_cache: AHashMap::with_capacity(1024),
+            accounts: HashMap::with_capacity_and_hasher(1024, hasher.clone()),
+            tx_cache: HashMap::with_capacity_and_hasher(1024, hasher.clone()),
+            refundable: HashMap::with_hasher(hasher.clone()),
+            manual_holds: HashMap::with_hasher(hasher.clone()),
+            seen: HashSet::with_hasher(hasher.clone()),
+            dispute_event_ids: HashSet::with_hasher(hasher.clone()),
+            frozen_tx_ids: HashSet::with_hasher(hasher.clone()),
+            recent_withdrawals: HashMap::with_hasher(hasher.clone()),
+            overdraft_limits: HashMap::with_hasher(hasher.clone()),
+            balance_limits: HashMap::with_hasher(hasher.clone()),
+            dispute_notes: HashMap::with_hasher(hasher.clone()),
+            pending_deferred: BTreeMap::new(),
+            pending_disputes: HashMap::with_hasher(hasher.clone()),
+            seq: 0,
+            last_tx: None,
+            clock_frozen: false,
+            latest_timestamp: None,
+            #[cfg(feature = "statement-logging")]
+            statements: HashMap::with_hasher(hasher.clone()),
+            client_timestamps: HashMap::with_hasher(hasher),
+            type_counts: TypeCounts::default(),
+            executed_count: 0,
+            failed_count: 0,
+            observer: None,
+            subscriptions: HashMap::new(),
+            next_subscription: 0,
+            config: Config::default(),
         }
     }
 }
@@ -109,7 +945,7 @@ impl State {
             cfg_if::cfg_if! {
                 if #[cfg(debug_assertions)] {
                     if let Err(e) = this.execute(tx.clone()) {
-                        eprintln!("{:?} {:?}", tx, e);
+                        eprintln!("{} {:?}", tx.redacted(), e);
                     }
                 } else {
                     let _ = this.execute(tx.clone());
@@ -120,6 +956,41 @@ impl State {
         this
     }
 
+    /// Function will construct the state machine and replay at most `n` transactions from the
+    /// iterator passed in. The iterator is not advanced past the `n`th transaction, which makes
+    /// this handy for sampling the start of a huge input.
+    ///
+    /// # Arguments
+    /// * `txs` - Iterator over owned `Transactions`.
+    /// * `n` - Maximum number of transactions to consume, rejected transactions included.
+    pub fn from_iterator_limit(txs: impl Iterator<Item = Transaction>, n: usize) -> Self {
+        Self::from_iterator(txs.take(n))
+    }
+
+    /// Function will construct the state machine and replay all the transactions from the iterator
+    /// passed in, grouped by client. All the transactions of one client are executed back to back,
+    /// which keeps their account hot in the CPU caches when processing large batches.
+    ///
+    /// # Arguments
+    /// * `txs` - Iterator over owned `Transactions`.
+    ///
+    /// # Notes
+    /// The relative order of the transactions of each client is preserved, so disputes are still
+    /// executed after the transactions they reference. The order across clients is not, so this
+    /// should only be used when clients are independent of each other. In particular, dispute
+    /// windows and idempotent mode depend on the global order and can reject different
+    /// transactions than `from_iterator` would. All transactions are buffered before any of them
+    /// are executed.
+    pub fn from_iterator_grouped(txs: impl Iterator<Item = Transaction>) -> Self {
+        let mut txs: Vec<Transaction> = txs.collect();
+        // NOTE: `sort_by_key` is stable, which is what preserves the per-client order.
+        txs.sort_by_key(|tx| tx.client);
+
+        Self::from_iterator(txs.into_iter())
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
     /// Function will execute a transaction, returning an error if the transaction failed to be
     /// commited.
     ///
@@ -129,210 +1000,1465 @@ impl State {
     /// # Returns
     /// This function will return a `TxError` if various checks fail. If an error is returned, you
     /// can safely assume that no account data has been modified.
-    pub fn execute(&mut self, tx: Transaction) -> Result<(), TxError> {
-        // negative amounts are not allowed as they can flip balances.
-        if matches!(tx.tx_type, TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } if amount < 0.0)
-        {
-            return Err(TxError::InternalError);
+    pub fn execute(&mut self, tx: Transaction<A>) -> Result<(), TxError> {
+        if self.pending_deferred.is_empty() {
+            return self.execute_with_events(tx, |_| {});
         }
 
-        let mut account = self.accounts.entry(tx.client).or_insert(Account {
-            id: tx.client,
-            ..Account::default()
-        });
+        // NOTE: Deferred transactions are executed on a best effort basis, their results are
+        // only reported by `execute_triggering`.
+        let id = tx.tx;
+        self.execute_with_events(tx, |_| {})?;
+        self.trigger_deferred(id);
 
-        // NOTE (assumption): if an account gets locked, we probably want to ignore all future txs
-        // from them until the account is manually unlocked.
-        if account.locked {
-            return Err(TxError::AccountLocked);
-        }
+        Ok(())
+    }
 
-        match tx.tx_type {
-            TransactionType::Deposit { amount } => {
-                account.available += amount;
-                account.total += amount;
-            }
-            TransactionType::Withdrawal { amount } => {
-                if account.available < amount {
-                    return Err(TxError::NotEnoughFunds);
+    /// Function will execute a transaction, discarding the result.
+    ///
+    /// # Notes
+    /// This trades all observability for throughput: rejected transactions are neither returned
+    /// nor logged, in any build mode. Only use this to replay append-only logs whose transactions
+    /// are known to be valid, or where silently dropping rejections is acceptable.
+    #[inline(always)]
+    pub fn execute_silent(&mut self, tx: Transaction<A>) {
+        let _ = self.execute(tx);
+    }
+
+    /// Function will execute a transaction, passing every `AccountEvent` commited by it to `sink`.
+    ///
+    /// # Arguments
+    /// * `tx` - Transaction to be executed.
+    /// * `sink` - Callback receiving the events in commit order.
+    ///
+    /// # Returns
+    /// Same as `execute`. Events are only emitted for changes that have been commited, so a
+    /// rejected transaction emits at most an `AccountEvent::AccountCreated`, and none at all with
+    /// `StateBuilder::no_create_on_error`.
+    pub fn execute_with_events(
+        &mut self,
+        tx: Transaction<A>,
+        sink: impl FnMut(AccountEvent<A>),
+    ) -> Result<(), TxError> {
+        self.last_tx = Some(ProcessedTx::of(&tx));
+
+        let result = match self.observer.clone() {
+            Some(observer) => {
+                observer.on_execute_start(&tx);
+
+                let before = self.accounts.get(&tx.client).cloned().unwrap_or_default();
+                let result = self.commit_notifying(tx.clone(), sink);
+
+                match &result {
+                    Ok(()) => {
+                        observer.on_execute_success(&tx, &self.diff_since(tx.client, &before))
+                    }
+                    Err(e) => observer.on_execute_error(&tx, e),
                 }
 
-                account.available -= amount;
-                account.total -= amount;
+                result
             }
-            TransactionType::Dispute => {
-                let (disputed_tx, dispute_status) = self
-                    .tx_cache
-                    .get_mut(&tx.tx)
-                    .ok_or(TxError::TxDoesntExist)?;
+            None => self.commit_notifying(tx, sink),
+        };
 
-                if tx.client != disputed_tx.client {
-                    return Err(TxError::Unauthorized);
-                }
+        self.executed_count += 1;
+        if result.is_err() {
+            self.failed_count += 1;
+        }
 
-                if dispute_status.is_some() {
-                    return Err(TxError::TxAlreadyDisputed);
-                }
+        result
+    }
 
-                let disputed_amount = match disputed_tx.tx_type {
-                    TransactionType::Deposit { amount } => amount,
-                    _ => return Err(TxError::InvalidDispute),
-                };
+    /// Function will execute a transaction, passing every `AccountEvent` commited by it to `sink`,
+    /// without notifying the observer.
+    fn commit(
+        &mut self,
+        tx: Transaction<A>,
+        mut sink: impl FnMut(AccountEvent<A>),
+    ) -> Result<(), TxError> {
+        #[cfg(feature = "statement-logging")]
+        let (tag, mut lines) = (tx.tx_type.tag().to_string(), Vec::new());
+        #[cfg(feature = "statement-logging")]
+        let mut sink = |event: AccountEvent<A>| {
+            lines.extend(statement::PendingLine::of(&event));
+            sink(event);
+        };
 
-                // NOTE: The spec doesnt specifically state what transactions can be disputed.
-                // Based on the logic described in there for disputes, it is safe to assume that at
-                // least Deposit transactions can be disputed.
-                if matches!(disputed_tx.tx_type, TransactionType::Deposit { .. }) {
-                    account.available -= disputed_amount;
-                    account.held += disputed_amount;
-                } else {
-                    // The spec doesnt specify how we should handle the balance transfer for
-                    // withdrawal disputes so we kinda just wing it here.
-                    //
-                    // For deposit transaction disputes the available balance is decreased while
-                    // the held balance is increased, total balance is not affected. If we get a
-                    // withdrawal dispute we can just increase the held balance and total balance.
-                    account.held += disputed_amount;
-                    account.total += disputed_amount;
-                }
+        let result = self.commit_unlogged(tx, &mut sink);
 
-                *dispute_status = Some(DisputeState::Disputed);
-            }
-            TransactionType::Resolve => {
-                let (disputed_tx, dispute_status) = self
-                    .tx_cache
-                    .get_mut(&tx.tx)
-                    .ok_or(TxError::TxDoesntExist)?;
+        #[cfg(feature = "statement-logging")]
+        if result.is_ok() {
+            self.log_statement(&tag, lines);
+        }
 
-                if tx.client != disputed_tx.client {
-                    return Err(TxError::Unauthorized);
-                }
+        result
+    }
 
-                let disputed_amount = match disputed_tx.tx_type {
-                    TransactionType::Deposit { amount } => amount,
-                    _ => return Err(TxError::InvalidDispute),
-                };
+    /// Function will execute a transaction like `commit`, without logging it to the statements.
+    fn commit_unlogged(
+        &mut self,
+        tx: Transaction<A>,
+        mut sink: impl FnMut(AccountEvent<A>),
+    ) -> Result<(), TxError> {
+        if !self.config.no_create_on_error {
+            return self.apply(tx, sink);
+        }
 
-                if !matches!(dispute_status, Some(DisputeState::Disputed)) {
-                    return Err(TxError::TxNotUnderDispute);
+        // NOTE: Every other event is only emitted once all checks have passed, so holding back
+        // `AccountCreated` until the next event keeps rejected transactions silent.
+        let client = tx.client;
+        let mut created = None;
+        let result = self.apply(tx, |event| match event {
+            AccountEvent::AccountCreated { .. } => created = Some(event),
+            event => {
+                if let Some(created) = created.take() {
+                    sink(created);
                 }
+                sink(event);
+            }
+        });
+
+        if result.is_err() && created.is_some() {
+            self.accounts.remove(&client);
+            self.recent_withdrawals.remove(&client);
+        }
 
-                account.held -= disputed_amount;
-                account.available += disputed_amount;
+        result
+    }
 
-                *dispute_status = Some(DisputeState::Resolved);
+    /// Function will execute a transaction, passing every `AccountEvent` commited by it to `sink`.
+    /// An account is created for new clients even if the transaction gets rejected.
+    fn apply(
+        &mut self,
+        tx: Transaction<A>,
+        mut sink: impl FnMut(AccountEvent<A>),
+    ) -> Result<(), TxError> {
+        if let TransactionType::Other { tag, amount } = &tx.tx_type {
+            if self.config.footer_marker.as_ref() == Some(tag) {
+                return self.check_footer(*amount);
             }
-            TransactionType::Chargeback => {
-                let (disputed_tx, dispute_status) = self
-                    .tx_cache
-                    .get_mut(&tx.tx)
-                    .ok_or(TxError::TxDoesntExist)?;
+        }
 
-                if tx.client != disputed_tx.client {
-                    return Err(TxError::Unauthorized);
-                }
+        self.check_timestamp(&tx)?;
 
-                let disputed_amount = match disputed_tx.tx_type {
-                    TransactionType::Deposit { amount } => amount,
-                    _ => return Err(TxError::InvalidDispute),
-                };
+        // NOTE: A clock going backwards would make dispute windows underflow, so time stands
+        // still instead.
+        if !self.clock_frozen {
+            if let Some(timestamp) = tx.timestamp {
+                self.config.clock.observe(timestamp);
+            }
+            self.seq = self.config.clock.now().max(self.seq);
+        }
 
-                if !matches!(dispute_status, Some(DisputeState::Disputed)) {
-                    return Err(TxError::TxNotUnderDispute);
-                }
+        let handlers = &self.config.custom_handlers;
+        tx.validate_with(
+            self.config.disputes,
+            self.config.max_decimal_places,
+            self.config.zero_amounts == ZeroAmountPolicy::Reject,
+            self.config.reserve_zero_client,
+            |tag| handlers.contains_key(tag),
+        )?;
 
-                account.held -= disputed_amount;
-                account.total -= disputed_amount;
-                account.locked = true;
-                *dispute_status = Some(DisputeState::Resolved);
+        if self.config.zero_amounts == ZeroAmountPolicy::Ignore {
+            if let TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } =
+                tx.tx_type
+            {
+                if amount.get() == A::ZERO {
+                    self.type_counts.record(&tx.tx_type);
+                    return Ok(());
+                }
             }
         }
 
-        // Transactions with disputes that have been resolved can now be safely removed from
-        // `tx_cache` because they can never be disputed again.
-        if matches!(
-            tx.tx_type,
-            TransactionType::Resolve | TransactionType::Chargeback
-        ) {
-            self.tx_cache.remove(&tx.tx);
+        let is_dispute = tx.tx_type.is_dispute();
+
+        if !is_dispute && self.config.idempotent && self.seen.contains(&tx.tx) {
+            return Err(TxError::DuplicateTx);
         }
 
-        if matches!(tx.tx_type, TransactionType::Deposit { .. }) {
-            self.tx_cache.insert(tx.tx, (tx, None));
+        if let Some(id) = tx.event_id.filter(|_| is_dispute) {
+            if self.dispute_event_ids.contains(&id) {
+                return Err(TxError::DuplicateTx);
+            }
         }
 
-        // NOTE: Sanity check
-        debug_assert!((account.total - (account.held + account.available)).abs() < f64::EPSILON);
+        // NOTE: Balances move on the account of the owner of a disputed transaction, which is only
+        // another client's when the dispute authorizer lets them dispute it.
+        let holder = if is_dispute {
+            let authorizer = &self.config.dispute_authorizer;
+            self.tx_cache
+                .get(&tx.tx)
+                .filter(|x| x.tx.client != tx.client && authorizer.may_dispute(tx.client, x))
+                .map_or(tx.client, |x| x.tx.client)
+        } else {
+            tx.client
+        };
 
-        Ok(())
-    }
+        let balance_limit = self
+            .balance_limits
+            .get(&holder)
+            .copied()
+            .or(self.config.balance_limit);
 
-    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
-        self.accounts.values()
-    }
-}
+        let account = match self.accounts.entry(holder) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                sink(AccountEvent::AccountCreated { client: holder });
+                entry.insert(Account {
+                    id: holder,
+                    ..Account::default()
+                })
+            }
+        };
 
-#[cfg(test)]
-pub mod test {
-    use super::*;
+        // NOTE (assumption): if an account gets locked, we probably want to ignore all future txs
+        // from them until the account is manually unlocked.
+        if account.locked
+            && !(self.config.locked_accounts == LockedAccountPolicy::AllowDeposits
+                && matches!(tx.tx_type, TransactionType::Deposit { .. }))
+        {
+            return Err(TxError::AccountLocked);
+        }
 
-    #[test]
-    fn test_deposit() {
-        let mut state = State::default();
-        state
-            .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
-                client: 1,
-                tx: 1,
-            })
+        let mut parked = None;
+        match tx.tx_type {
+            TransactionType::Deposit { amount } => {
+                let amount = amount.get();
+                check_balance_limit(account, amount, balance_limit)?;
+
+                account.available += amount;
+                account.total += amount;
+
+                sink(AccountEvent::Deposited {
+                    client: tx.client,
+                    tx: tx.tx,
+                    amount,
+                });
+
+                parked = self.pending_disputes.remove(&(tx.client, tx.tx));
+            }
+            TransactionType::Withdrawal { amount } => {
+                let amount = amount.get();
+
+                // NOTE: `available` can drift by a few ulps after many operations, so we allow
+                // a tolerance of half the minor unit. Otherwise withdrawing the exact balance can
+                // spuriously fail.
+                let limit = self
+                    .overdraft_limits
+                    .get(&tx.client)
+                    .copied()
+                    .unwrap_or(self.config.credit_limit);
+
+                if account.available + limit + A::tolerance() < amount {
+                    return Err(TxError::NotEnoughFunds);
+                }
+
+                if self.config.check_held && account.held > account.total - amount + A::tolerance()
+                {
+                    return Err(TxError::InvariantBreach);
+                }
+
+                if let Some((limit, window)) = self.config.velocity_limit {
+                    let recent = self.recent_withdrawals.entry(tx.client).or_default();
+                    while matches!(recent.front(), Some((seq, _)) if self.seq - seq >= window) {
+                        recent.pop_front();
+                    }
+
+                    let withdrawn = recent.iter().fold(A::ZERO, |sum, (_, x)| sum + *x);
+                    if withdrawn + amount > limit + A::tolerance() {
+                        return Err(TxError::VelocityExceeded);
+                    }
+
+                    recent.push_back((self.seq, amount));
+                }
+
+                account.available -= amount;
+                account.total -= amount;
+
+                sink(AccountEvent::Withdrawn {
+                    client: tx.client,
+                    tx: tx.tx,
+                    amount,
+                });
+            }
+            TransactionType::Dispute => match self.tx_cache.get_mut(&tx.tx) {
+                Some(disputed) => {
+                    open_dispute(&self.config, self.seq, account, disputed, &tx, &mut sink)?
+                }
+                None if self.frozen_tx_ids.contains(&tx.tx) => {
+                    return Err(TxError::TransactionFrozen(tx.tx))
+                }
+                None if self.pending_disputes.contains_key(&(tx.client, tx.tx)) => {
+                    return Err(TxError::DuplicateTx)
+                }
+                None => match self.config.deferred_disputes {
+                    Some(cap) if self.pending_disputes.len() < cap => {
+                        self.pending_disputes.insert((tx.client, tx.tx), tx.clone());
+                    }
+                    _ => return Err(TxError::TxDoesntExist),
+                },
+            },
+            TransactionType::Resolve | TransactionType::Chargeback => {
+                let frozen = &self.frozen_tx_ids;
+                let disputed = self.tx_cache.get_mut(&tx.tx).ok_or_else(|| {
+                    if frozen.contains(&tx.tx) {
+                        TxError::TransactionFrozen(tx.tx)
+                    } else {
+                        TxError::TxDoesntExist
+                    }
+                })?;
+
+                if !self
+                    .config
+                    .dispute_authorizer
+                    .may_dispute(tx.client, disputed)
+                {
+                    return Err(TxError::Unauthorized);
+                }
+
+                if !matches!(disputed.dispute, Some(DisputeState::Disputed)) {
+                    return Err(TxError::TxNotUnderDispute);
+                }
+
+                let policy = &self.config.dispute_policy;
+                let outcome = if matches!(tx.tx_type, TransactionType::Resolve) {
+                    policy.on_resolve(account, disputed)?
+                } else {
+                    policy.on_chargeback(account, disputed)?
+                };
+
+                if !outcome.is_balanced() {
+                    return Err(TxError::InternalError);
+                }
+
+                if self.config.check_held && !outcome.keeps_held_within_total(account) {
+                    return Err(TxError::InvariantBreach);
+                }
+
+                outcome.apply_dispute(account);
+                disputed.dispute = Some(DisputeState::Resolved);
+
+                if matches!(tx.tx_type, TransactionType::Resolve) {
+                    sink(AccountEvent::DisputeResolved {
+                        client: account.id,
+                        tx: tx.tx,
+                        amount: -outcome.held,
+                        event_id: tx.event_id,
+                    });
+                } else {
+                    sink(AccountEvent::ChargedBack {
+                        client: account.id,
+                        tx: tx.tx,
+                        amount: -outcome.held,
+                        locked: account.locked,
+                        event_id: tx.event_id,
+                    });
+                }
+            }
+            TransactionType::Interest { rate_bps } => {
+                // NOTE: Interest only accrues on `available`, funds on hold dont earn any until
+                // they are released.
+                let amount = if account.available.is_negative() {
+                    if self.config.negative_interest == NegativeInterestPolicy::Reject {
+                        return Err(TxError::NegativeBalanceInterest);
+                    }
+
+                    A::ZERO
+                } else {
+                    account
+                        .available
+                        .mul_bps(rate_bps)
+                        .round_to(MINOR_UNIT_DECIMAL_PLACES, self.config.rounding)
+                };
+                check_balance_limit(account, amount, balance_limit)?;
+
+                account.available += amount;
+                account.total += amount;
+
+                sink(AccountEvent::InterestCredited {
+                    client: tx.client,
+                    tx: tx.tx,
+                    amount,
+                });
+            }
+            TransactionType::Refund { of_tx, amount } => {
+                let withdrawal = self
+                    .refundable
+                    .get_mut(&of_tx)
+                    .ok_or(TxError::TxDoesntExist)?;
+
+                if withdrawal.client != tx.client {
+                    return Err(TxError::Unauthorized);
+                }
+
+                if withdrawal.refunded + amount > withdrawal.amount + A::tolerance() {
+                    return Err(TxError::RefundExceedsWithdrawal);
+                }
+
+                check_balance_limit(account, amount, balance_limit)?;
+
+                withdrawal.refunded += amount;
+                account.available += amount;
+                account.total += amount;
+
+                sink(AccountEvent::Refunded {
+                    client: tx.client,
+                    tx: tx.tx,
+                    of_tx,
+                    amount,
+                });
+            }
+            TransactionType::Hold { amount } => {
+                if self.manual_holds.contains_key(&tx.tx) {
+                    return Err(TxError::DuplicateTx);
+                }
+
+                if account.available + A::tolerance() < amount {
+                    return Err(TxError::NotEnoughFunds);
+                }
+
+                account.available -= amount;
+                account.held += amount;
+                self.manual_holds.insert(
+                    tx.tx,
+                    ManualHold {
+                        client: tx.client,
+                        amount,
+                    },
+                );
+
+                sink(AccountEvent::HoldPlaced {
+                    client: tx.client,
+                    tx: tx.tx,
+                    amount,
+                });
+            }
+            TransactionType::Release { of_tx } => {
+                let hold = self
+                    .manual_holds
+                    .get(&of_tx)
+                    .ok_or(TxError::TxDoesntExist)?;
+
+                if hold.client != tx.client {
+                    return Err(TxError::Unauthorized);
+                }
+
+                let amount = hold.amount;
+                self.manual_holds.remove(&of_tx);
+                account.held -= amount;
+                account.available += amount;
+
+                sink(AccountEvent::HoldReleased {
+                    client: tx.client,
+                    tx: tx.tx,
+                    of_tx,
+                    amount,
+                });
+            }
+            TransactionType::Other { ref tag, amount } => {
+                let record = CustomRecord {
+                    tag,
+                    client: tx.client,
+                    tx: tx.tx,
+                    amount,
+                };
+
+                let handler = &self.config.custom_handlers[tag];
+                let outcome = custom::run_handler(
+                    handler.as_ref(),
+                    &record,
+                    account,
+                    self.config.check_held,
+                    balance_limit,
+                )?;
+
+                sink(AccountEvent::Adjusted {
+                    client: tx.client,
+                    tx: tx.tx,
+                    tag: tag.clone(),
+                    available: outcome.available,
+                    held: outcome.held,
+                    total: outcome.total,
+                    locked: account.locked,
+                });
+            }
+        }
+
+        account.normalize();
+
+        // NOTE: Sanity check
+        debug_assert!(account.is_balanced());
+
+        if !is_dispute && self.config.idempotent {
+            self.seen.insert(tx.tx);
+        }
+
+        if let Some(id) = tx.event_id.filter(|_| is_dispute) {
+            self.dispute_event_ids.insert(id);
+        }
+
+        self.type_counts.record(&tx.tx_type);
+
+        if let TransactionType::Withdrawal { amount } = tx.tx_type {
+            self.refundable.insert(
+                tx.tx,
+                RefundableWithdrawal {
+                    client: tx.client,
+                    amount: amount.get(),
+                    refunded: A::ZERO,
+                },
+            );
+        }
+
+        match tx.tx_type {
+            // Transactions with disputes that have been resolved can now be safely removed from
+            // `tx_cache` because they can never be disputed again.
+            TransactionType::Resolve | TransactionType::Chargeback => {
+                self.tx_cache.remove(&tx.tx);
+
+                if self.config.clear_dispute_notes {
+                    self.dispute_notes.remove(&tx.tx);
+                }
+            }
+            TransactionType::Deposit { .. } if self.config.disputes => {
+                cache_tx(&mut self.tx_cache, tx, self.seq);
+            }
+            TransactionType::Withdrawal { .. }
+                if self.config.withdrawal_disputes == WithdrawalDisputePolicy::Allow =>
+            {
+                cache_tx(&mut self.tx_cache, tx, self.seq);
+            }
+            _ => {}
+        }
+
+        // NOTE: The parked dispute can only be applied once its deposit has been cached. It has
+        // been checked and counted when it was parked, so only the dispute itself is applied. The
+        // deposit has been commited either way, so a rejection is only reported to the observer.
+        if let Some(dispute) = parked {
+            let result = match (
+                self.accounts.get_mut(&dispute.client),
+                self.tx_cache.get_mut(&dispute.tx),
+            ) {
+                (Some(account), Some(disputed)) => open_dispute(
+                    &self.config,
+                    self.seq,
+                    account,
+                    disputed,
+                    &dispute,
+                    &mut sink,
+                )
+                .map(|()| account.normalize()),
+                _ => Err(TxError::TxDoesntExist),
+            };
+
+            if let (Err(e), Some(observer)) = (result, &self.observer) {
+                observer.on_execute_error(&dispute, &e);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &Account<A>> {
+        self.accounts.values()
+    }
+
+    /// Function returns whether `client` has an account, without creating one.
+    pub fn contains_account(&self, client: u16) -> bool {
+        self.accounts.contains_key(&client)
+    }
+
+    /// Returns the number of accounts of the state machine.
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// Returns an iterator over all accounts in ascending order of their `id`.
+    ///
+    /// # Notes
+    /// The ids are heapified up front in O(n) time and O(n) space, and every account yielded
+    /// costs O(log n). Consuming only the first `k` accounts is therefore O(n + k log n), while
+    /// consuming all of them is O(n log n).
+    pub fn accounts_iter_by_id(&self) -> impl Iterator<Item = &Account<A>> {
+        let mut ids: BinaryHeap<Reverse<u16>> =
+            self.accounts.keys().copied().map(Reverse).collect();
+
+        iter::from_fn(move || ids.pop().map(|Reverse(id)| &self.accounts[&id]))
+    }
+
+    /// Function will compare the `expected` sum of the `total` balances of all accounts carried by
+    /// a footer row against the computed one.
+    fn check_footer(&self, expected: Option<A>) -> Result<(), TxError> {
+        let expected =
+            expected.ok_or_else(|| TxError::ParseError("missing amount for footer".into()))?;
+        let actual = self.accounts.values().fold(A::ZERO, |sum, x| sum + x.total);
+
+        if !(expected - actual).is_negligible() {
+            return Err(TxError::FooterMismatch {
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Function will allow withdrawals of `client` to drive its `available` balance down to
+    /// `-limit`, overriding the default credit limit the state machine has been configured with.
+    /// The limit can be set before the account exists.
+    ///
+    /// # Returns
+    /// Returns `BuildError::InvalidCreditLimit` if `limit` is negative or not finite, and
+    /// `BuildError::Conflict` if a non-zero limit is set while negative available prevention is
+    /// enabled.
+    pub fn set_overdraft_limit(&mut self, client: u16, limit: A) -> Result<(), BuildError> {
+        if !limit.is_finite() || limit.is_negative() {
+            return Err(BuildError::InvalidCreditLimit);
+        }
+
+        if self.config.prevent_negative_available && limit > A::ZERO {
+            return Err(BuildError::Conflict(
+                "negative available prevention requires a zero credit limit",
+            ));
+        }
+
+        self.overdraft_limits.insert(client, limit);
+
+        Ok(())
+    }
+
+    /// Function will cap the `total` balance of the account of `client` at `limit`, overriding the
+    /// default balance limit the state machine has been configured with. The limit can be set
+    /// before the account exists. See `StateBuilder::balance_limit`.
+    ///
+    /// # Returns
+    /// Returns `BuildError::InvalidBalanceLimit` if `limit` is negative or not finite.
+    ///
+    /// # Notes
+    /// Accounts already holding more than their new limit keep their balance, only transactions
+    /// adding to it are rejected.
+    pub fn set_balance_limit(&mut self, client: u16, limit: A) -> Result<(), BuildError> {
+        if !limit.is_finite() || limit.is_negative() {
+            return Err(BuildError::InvalidBalanceLimit);
+        }
+
+        self.balance_limits.insert(client, limit);
+
+        Ok(())
+    }
+
+    /// Function will move the account of `old_id` over to `new_id`, along with every cached
+    /// transaction, refundable withdrawal, manual hold, overdraft limit and balance limit of the
+    /// client, so that disputes, refunds and releases can be raised against previous transactions
+    /// using the new id.
+    ///
+    /// # Returns
+    /// Returns `TxError::AccountNotFound` if `old_id` has no account, and
+    /// `TxError::DuplicateClientId` if `new_id` already has one.
+    pub fn migrate_client_id(&mut self, old_id: u16, new_id: u16) -> Result<(), TxError> {
+        if !self.accounts.contains_key(&old_id) {
+            return Err(TxError::AccountNotFound);
+        }
+
+        if self.accounts.contains_key(&new_id) {
+            return Err(TxError::DuplicateClientId);
+        }
+
+        let mut account = self.accounts.remove(&old_id).unwrap();
+        account.id = new_id;
+        self.accounts.insert(new_id, account);
+
+        for cached in self.tx_cache.values_mut() {
+            if cached.tx.client == old_id {
+                cached.tx.client = new_id;
+            }
+        }
+
+        for withdrawal in self.refundable.values_mut() {
+            if withdrawal.client == old_id {
+                withdrawal.client = new_id;
+            }
+        }
+
+        for hold in self.manual_holds.values_mut() {
+            if hold.client == old_id {
+                hold.client = new_id;
+            }
+        }
+
+        if let Some(limit) = self.overdraft_limits.remove(&old_id) {
+            self.overdraft_limits.insert(new_id, limit);
+        }
+
+        if let Some(limit) = self.balance_limits.remove(&old_id) {
+            self.balance_limits.insert(new_id, limit);
+        }
+
+        #[cfg(feature = "statement-logging")]
+        if let Some(statement) = self.statements.remove(&old_id) {
+            self.statements.insert(new_id, statement);
+        }
+
+        Ok(())
+    }
+
+    /// Function will merge the accounts of `client_a` and `client_b`, e.g. once they turn out to
+    /// belong to the same person. The balances of both accounts are summed into the account of
+    /// `target_client`, which must be one of the two, and the other account is removed. Every
+    /// cached transaction, refundable withdrawal, manual hold and parked dispute of the removed
+    /// client is moved over to the target, so that disputes, refunds and releases of its previous
+    /// transactions are raised by the target.
+    ///
+    /// # Returns
+    /// Returns `TxError::InvalidMergeTarget` if `target_client` isnt one of two distinct clients,
+    /// `TxError::AccountNotFound` if either client has no account, `TxError::AccountLocked` if
+    /// either account is locked, and `TxError::TxAlreadyDisputed` if either client has a
+    /// transaction under dispute. Nothing is merged in that case.
+    ///
+    /// # Notes
+    /// The overdraft limit, balance limit and statement of the removed client are dropped, the
+    /// target keeps its own. The merged balance isnt checked against the balance limit.
+    pub fn merge_accounts(
+        &mut self,
+        client_a: u16,
+        client_b: u16,
+        target_client: u16,
+    ) -> Result<(), TxError> {
+        let source = match target_client {
+            _ if client_a == client_b => return Err(TxError::InvalidMergeTarget),
+            x if x == client_a => client_b,
+            x if x == client_b => client_a,
+            _ => return Err(TxError::InvalidMergeTarget),
+        };
+
+        for client in [client_a, client_b] {
+            match self.accounts.get(&client) {
+                None => return Err(TxError::AccountNotFound),
+                Some(account) if account.locked => return Err(TxError::AccountLocked),
+                Some(_) => {}
+            }
+        }
+
+        if self.tx_cache.values().any(|x| {
+            (x.tx.client == client_a || x.tx.client == client_b)
+                && x.dispute == Some(DisputeState::Disputed)
+        }) {
+            return Err(TxError::TxAlreadyDisputed);
+        }
+
+        let merged = self.accounts.remove(&source).unwrap();
+        let account = self.accounts.get_mut(&target_client).unwrap();
+        account.available += merged.available;
+        account.held += merged.held;
+        account.disputed_hold += merged.disputed_hold;
+        account.total += merged.total;
+        account.normalize();
+
+        for cached in self.tx_cache.values_mut() {
+            if cached.tx.client == source {
+                cached.tx.client = target_client;
+            }
+        }
+
+        for withdrawal in self.refundable.values_mut() {
+            if withdrawal.client == source {
+                withdrawal.client = target_client;
+            }
+        }
+
+        for hold in self.manual_holds.values_mut() {
+            if hold.client == source {
+                hold.client = target_client;
+            }
+        }
+
+        // NOTE: Disputes parked by the source wait for a deposit that will now be committed to the
+        // target. A dispute the target parked for the same transaction takes precedence.
+        let parked: Vec<_> = self
+            .pending_disputes
+            .keys()
+            .filter(|(client, _)| *client == source)
+            .copied()
+            .collect();
+        for key in parked {
+            let mut dispute = self.pending_disputes.remove(&key).unwrap();
+            dispute.client = target_client;
+            self.pending_disputes
+                .entry((target_client, key.1))
+                .or_insert(dispute);
+        }
+
+        // NOTE: Both clients withdrew from what is now the same account, so the velocity limit
+        // counts the withdrawals of both.
+        if let Some(recent) = self.recent_withdrawals.remove(&source) {
+            let target = self.recent_withdrawals.entry(target_client).or_default();
+            target.extend(recent);
+            target.make_contiguous().sort_by_key(|(seq, _)| *seq);
+        }
+
+        if let Some(timestamp) = self.client_timestamps.remove(&source) {
+            let latest = self.client_timestamps.entry(target_client).or_default();
+            *latest = (*latest).max(timestamp);
+        }
+
+        self.overdraft_limits.remove(&source);
+        self.balance_limits.remove(&source);
+        #[cfg(feature = "statement-logging")]
+        self.statements.remove(&source);
+
+        Ok(())
+    }
+
+    /// Function will zero the balances of the account of `client` and unlock it, leaving it
+    /// registered. Every cached transaction, refundable withdrawal and manual hold of the client
+    /// is dropped along with them, so that none of them can be disputed, refunded or released
+    /// against the emptied account. Does nothing if `client` has no account.
+    pub fn reset_account(&mut self, client: u16) {
+        let account = match self.accounts.get_mut(&client) {
+            Some(account) => account,
+            None => return,
+        };
+
+        *account = Account {
+            id: client,
+            ..Account::default()
+        };
+
+        self.forget_transactions_of(|x| x == client);
+    }
+
+    /// Function will remove every locked account, e.g. to hand them over for review after a
+    /// fraud sweep. Every cached transaction, refundable withdrawal and manual hold of their
+    /// clients is dropped along with them, open disputes included, so later transactions of those
+    /// clients start over on a new account.
+    ///
+    /// # Returns
+    /// The removed accounts in ascending order of their `id`.
+    pub fn drain_locked_accounts(&mut self) -> Vec<Account<A>> {
+        let mut drained = Vec::new();
+        self.accounts.retain(|_, account| {
+            if account.locked {
+                drained.push(account.clone());
+            }
+
+            !account.locked
+        });
+        drained.sort_by_key(|x| x.id);
+
+        self.forget_transactions_of(|client| {
+            drained.binary_search_by_key(&client, |x| x.id).is_ok()
+        });
+
+        drained
+    }
+
+    /// Function will drop every cached transaction, refundable withdrawal and manual hold of the
+    /// clients `forget` returns true for.
+    fn forget_transactions_of(&mut self, forget: impl Fn(u16) -> bool) {
+        self.tx_cache.retain(|_, cached| !forget(cached.tx.client));
+        self.refundable
+            .retain(|_, withdrawal| !forget(withdrawal.client));
+        self.manual_holds.retain(|_, hold| !forget(hold.client));
+        self.pending_disputes
+            .retain(|(client, _), _| !forget(*client));
+    }
+
+    /// Function will freeze every cached transaction with an id of at most `max_tx_id`, making it
+    /// permanently non-disputable. Disputing a frozen transaction returns
+    /// `TxError::TransactionFrozen`.
+    ///
+    /// # Returns
+    /// Returns the number of transactions that have been frozen.
+    ///
+    /// # Notes
+    /// Transactions that are currently under dispute are left in the cache so that the dispute can
+    /// still be resolved or charged back. Frozen ids are not included in snapshots.
+    pub fn freeze_tx_cache_before(&mut self, max_tx_id: u32) -> usize {
+        let frozen = &mut self.frozen_tx_ids;
+        let before = frozen.len();
+
+        self.tx_cache.retain(|&id, cached| {
+            if id > max_tx_id || matches!(cached.dispute, Some(DisputeState::Disputed)) {
+                return true;
+            }
+
+            frozen.insert(id);
+            false
+        });
+
+        frozen.len() - before
+    }
+
+    /// Function will evict every cached transaction with an id more than `window` below the
+    /// highest cached id, i.e. with an id below `max_tx_id - window`, unless it is currently under
+    /// dispute. Disputing an evicted transaction returns `TxError::TxDoesntExist`.
+    ///
+    /// # Returns
+    /// Returns the number of transactions that have been evicted.
+    ///
+    /// # Notes
+    /// Unlike `freeze_tx_cache_before`, evicted ids are forgotten rather than remembered as frozen,
+    /// so the memory of the cache stays bounded by the window.
+    pub fn consolidate_tx_cache(&mut self, window: u32) -> usize {
+        let threshold = match self.tx_cache.keys().max() {
+            Some(max_tx_id) => max_tx_id.saturating_sub(window),
+            None => return 0,
+        };
+
+        let before = self.tx_cache.len();
+        self.tx_cache.retain(|&id, cached| {
+            id >= threshold || matches!(cached.dispute, Some(DisputeState::Disputed))
+        });
+
+        before - self.tx_cache.len()
+    }
+
+    /// Function will serialize all accounts into `wtr`, flushing the writer after every `chunk`
+    /// accounts.
+    ///
+    /// # Arguments
+    /// * `wtr` - CSV writer the accounts will be written to.
+    /// * `chunk` - Number of accounts to write between flushes, `0` is treated as `1`.
+    ///
+    /// # Returns
+    /// Returns the first serialization or IO error encountered. Accounts from chunks that have
+    /// already been flushed will have been written out.
+    #[cfg(feature = "serde")]
+    pub fn write_accounts_chunked<W: Write>(
+        &self,
+        wtr: &mut csv::Writer<W>,
+        chunk: usize,
+    ) -> csv::Result<()> {
+        let chunk = chunk.max(1);
+
+        for (idx, account) in self.accounts().enumerate() {
+            wtr.serialize(account)?;
+
+            if (idx + 1) % chunk == 0 {
+                wtr.flush()?;
+            }
+        }
+
+        wtr.flush()?;
+
+        Ok(())
+    }
+
+    /// Function returns all cached transactions together with their dispute state, sorted by
+    /// transaction id in ascending order.
+    ///
+    /// # Notes
+    /// The cache is an unordered hash map, so this allocates and sorts a `Vec` on every call.
+    ///
+    /// # Example
+    /// ```
+    /// use corken::money::Amount;
+    /// use corken::{State, Transaction, TransactionType};
+    ///
+    /// let mut state = State::default();
+    /// for (tx, amount) in [(7, 1.0), (3, 2.0)] {
+    ///     let amount = Amount::new(amount).unwrap();
+    ///     let deposit = TransactionType::Deposit { amount };
+    ///     state.execute(Transaction::new(deposit, 1, tx)).unwrap();
+    /// }
+    /// state
+    ///     .execute(Transaction::new(TransactionType::Dispute, 1, 7))
+    ///     .unwrap();
+    ///
+    /// for (id, tx, dispute) in state.sorted_tx_cache() {
+    ///     println!("{} {:?} {:?}", id, tx.tx_type(), dispute);
+    /// }
+    /// ```
+    pub fn sorted_tx_cache(&self) -> Vec<(u32, &Transaction<A>, Option<&DisputeState>)> {
+        let mut cache = self
+            .tx_cache
+            .iter()
+            .map(|(id, cached)| (*id, &cached.tx, cached.dispute.as_ref()))
+            .collect::<Vec<_>>();
+
+        cache.sort_unstable_by_key(|(id, ..)| *id);
+        cache
+    }
+
+    /// Returns the cached transactions currently under dispute, in ascending order of their id.
+    pub fn open_disputes(&self) -> Vec<&CachedTx<A>> {
+        let mut disputes: Vec<_> = self
+            .tx_cache
+            .values()
+            .filter(|x| x.dispute == Some(DisputeState::Disputed))
+            .collect();
+
+        disputes.sort_unstable_by_key(|x| x.tx.tx);
+        disputes
+    }
+
+    /// Function returns a graph of clients pointing to the set of transaction ids they own that
+    /// have a dispute history.
+    ///
+    /// # Notes
+    /// Only transactions still present in the `tx_cache` are considered, transactions whose
+    /// disputes got resolved or charged back are evicted from the cache and thus wont show up
+    /// here. Clients without any disputed transactions are not present in the graph.
+    ///
+    /// Currently a client can only dispute their own transactions, so this graph never contains
+    /// edges between two different clients. Once multi-party disputes exist, this is the place to
+    /// add them.
+    pub fn transaction_graph(&self) -> HashMap<u16, HashSet<u32>> {
+        let mut graph: HashMap<u16, HashSet<u32>> = HashMap::new();
+
+        for (tx_id, cached) in self.tx_cache.iter() {
+            if cached.dispute.is_some() {
+                graph.entry(cached.tx.client).or_default().insert(*tx_id);
+            }
+        }
+
+        graph
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Returns `amount` as an `Amount`, panicking if it is negative or not finite.
+    pub fn checked<A: Money>(amount: A) -> Amount<A> {
+        Amount::new(amount).unwrap()
+    }
+
+    /// Generates a deterministic workload of `len` transactions over a handful of clients, with
+    /// enough disputes, resolves and chargebacks referencing earlier deposits to exercise every
+    /// code path of the state machine. Amounts are multiples of `0.25` so that they are exactly
+    /// representable.
+    pub fn workload(len: u32) -> Vec<Transaction> {
+        let mut rng = StdRng::seed_from_u64(0xC0FFEE);
+        let mut deposits: Vec<(u16, u32)> = Vec::new();
+
+        (0..len)
+            .map(|tx| {
+                let client = rng.gen_range(1..=10);
+                let kind = rng.gen_range(0..10);
+
+                // Dispute-like transactions mostly reference an earlier deposit of the same
+                // client, so that they actually get applied.
+                let (client, tx) = match kind {
+                    0..=5 => (client, tx),
+                    _ if deposits.is_empty() || rng.gen_bool(0.1) => {
+                        (client, rng.gen_range(0..=tx))
+                    }
+                    _ => deposits[rng.gen_range(0..deposits.len())],
+                };
+
+                let tx_type = match kind {
+                    0..=3 => {
+                        deposits.push((client, tx));
+                        TransactionType::Deposit {
+                            amount: checked(rng.gen_range(0..400) as f64 / 4.0),
+                        }
+                    }
+                    4..=5 => TransactionType::Withdrawal {
+                        amount: checked(rng.gen_range(0..400) as f64 / 4.0),
+                    },
+                    6..=7 => TransactionType::Dispute,
+                    8 => TransactionType::Resolve,
+                    _ => TransactionType::Chargeback,
+                };
+
+                Transaction {
+                    tx_type,
+                    client,
+                    tx,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_deposit() {
+        let mut state = State::default();
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.total, 120.0);
+        assert_eq!(account.available, 120.0);
+    }
+
+    #[test]
+    fn test_withdrawals() {
+        let mut state = State::default();
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            state.execute(Transaction {
+                tx_type: TransactionType::Withdrawal {
+                    amount: checked(240.0)
+                },
+                client: 1,
+                tx: 2,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            }),
+            Err(TxError::NotEnoughFunds)
+        );
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Withdrawal {
+                    amount: checked(120.0),
+                },
+                client: 1,
+                tx: 2,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.total, 0.0);
+        assert_eq!(account.available, 0.0);
+    }
+
+    #[test]
+    fn test_withdraw_full_balance_after_drift() {
+        let mut state = State::default();
+        for tx in 0..10 {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(0.1),
+                    },
+                    client: 1,
+                    tx,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
+        // 0.1 summed ten times is slightly less than 1.0.
+        assert!(state.accounts.get(&1).unwrap().available < 1.0);
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Withdrawal {
+                    amount: checked(1.0),
+                },
+                client: 1,
+                tx: 10,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert!(account.available.abs() < MINOR_UNIT);
+        assert!(account.total.abs() < MINOR_UNIT);
+    }
+
+    #[test]
+    fn test_withdraw_full_balance_is_exactly_zero() {
+        let mut state = State::default();
+        for (tx, amount) in [(1, 0.1), (2, 0.2)] {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(amount),
+                    },
+                    client: 1,
+                    tx,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
+        // 0.1 + 0.2 is not exactly 0.3.
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Withdrawal {
+                    amount: checked(0.3),
+                },
+                client: 1,
+                tx: 3,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.available.to_bits(), 0.0f64.to_bits());
+        assert_eq!(account.total.to_bits(), 0.0f64.to_bits());
+        assert_eq!(account.held.to_bits(), 0.0f64.to_bits());
+    }
+
+    #[test]
+    fn test_deposit_dispute() {
+        let mut state = State::default();
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        {
+            let account = state.accounts.get(&1).unwrap();
+            assert_eq!(account.total, 120.0);
+            assert_eq!(account.held, 120.0);
+            assert_eq!(account.available, 0.0);
+        }
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Resolve,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
             .unwrap();
 
         let account = state.accounts.get(&1).unwrap();
         assert_eq!(account.total, 120.0);
+        assert_eq!(account.held, 0.0);
         assert_eq!(account.available, 120.0);
     }
 
     #[test]
-    fn test_withdrawals() {
+    fn test_dispute_chargeback() {
+        let mut state = State::default();
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Chargeback,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.total, 0.0);
+        assert_eq!(account.held, 0.0);
+        assert_eq!(account.available, 0.0);
+        assert!(account.locked);
+    }
+
+    #[test]
+    fn test_chargeback_after_resolve() {
         let mut state = State::default();
         state
             .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Resolve,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
         assert_eq!(
             state.execute(Transaction {
-                tx_type: TransactionType::Withdrawal { amount: 240.0 },
+                tx_type: TransactionType::Chargeback,
                 client: 1,
-                tx: 2,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             }),
-            Err(TxError::NotEnoughFunds)
+            Err(TxError::TxDoesntExist)
         );
+    }
 
+    #[test]
+    fn test_double_chargeback() {
+        let mut state = State::default();
         state
             .execute(Transaction {
-                tx_type: TransactionType::Withdrawal { amount: 120.0 },
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
                 client: 1,
-                tx: 2,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
-        let account = state.accounts.get(&1).unwrap();
-        assert_eq!(account.total, 0.0);
-        assert_eq!(account.available, 0.0);
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Chargeback,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            state.execute(Transaction {
+                tx_type: TransactionType::Chargeback,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            }),
+            Err(TxError::AccountLocked)
+        );
     }
 
     #[test]
-    fn test_deposit_dispute() {
+    fn test_double_resolve() {
         let mut state = State::default();
         state
             .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
@@ -341,38 +2467,332 @@ pub mod test {
                 tx_type: TransactionType::Dispute,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
-        {
-            let account = state.accounts.get(&1).unwrap();
-            assert_eq!(account.total, 120.0);
-            assert_eq!(account.held, 120.0);
-            assert_eq!(account.available, 0.0);
-        }
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Resolve,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            state.execute(Transaction {
+                tx_type: TransactionType::Resolve,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            }),
+            Err(TxError::TxDoesntExist)
+        );
+    }
+
+    #[test]
+    fn test_double_dispute() {
+        let mut state = State::default();
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Resolve,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            state.execute(Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            }),
+            Err(TxError::TxDoesntExist)
+        );
+    }
 
+    #[test]
+    fn test_dispute_from_different_uid() {
+        let mut state = State::default();
         state
             .execute(Transaction {
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
+                client: 1,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            state.execute(Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 2,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            }),
+            Err(TxError::Unauthorized)
+        );
+
+        assert_eq!(
+            state.execute(Transaction {
                 tx_type: TransactionType::Resolve,
+                client: 2,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            }),
+            Err(TxError::Unauthorized)
+        );
+
+        assert_eq!(
+            state.execute(Transaction {
+                tx_type: TransactionType::Chargeback,
+                client: 2,
+                tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            }),
+            Err(TxError::Unauthorized)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_withdrawal_dispute() {
+        let mut state = State::default();
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Withdrawal {
+                    amount: checked(75.5),
+                },
+                client: 1,
+                tx: 2,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Dispute,
+                client: 1,
+                tx: 2,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
         let account = state.accounts.get(&1).unwrap();
         assert_eq!(account.total, 120.0);
-        assert_eq!(account.held, 0.0);
-        assert_eq!(account.available, 120.0);
+        assert_eq!(account.held, 75.5);
+        assert_eq!(account.available, 44.5);
+    }
+
+    #[test]
+    fn test_negative_txs() {
+        assert_eq!(
+            Amount::new(-120.0),
+            Err(validate::ValidationError::NegativeAmount)
+        );
+
+        let mut state = State::default();
+        state
+            .execute(Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        assert_eq!(
+            state.execute(Transaction::new(
+                TransactionType::Hold { amount: -120.0 },
+                1,
+                2
+            )),
+            Err(TxError::InternalError)
+        );
+        assert_eq!(state.accounts[&1].available, 120.0);
+    }
+
+    #[test]
+    fn test_zero_amounts() {
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        let deposit = |client, id, amount| {
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(amount),
+                },
+                client,
+                id,
+            )
+        };
+        let withdrawal = |client, id, amount| {
+            tx(
+                TransactionType::Withdrawal {
+                    amount: checked(amount),
+                },
+                client,
+                id,
+            )
+        };
+        let state = |policy| State::builder().zero_amounts(policy).build().unwrap();
+
+        // Allow keeps zero amounts, caching them like any other transaction.
+        let mut allow = state(ZeroAmountPolicy::Allow);
+        allow.execute(deposit(1, 1, 0.0)).unwrap();
+        allow.execute(withdrawal(2, 2, 0.0)).unwrap();
+        assert!(allow.accounts.contains_key(&1) && allow.accounts.contains_key(&2));
+        assert!(allow.tx_cache.contains_key(&1));
+        assert_eq!(
+            State::default().config.zero_amounts,
+            ZeroAmountPolicy::Allow
+        );
+
+        // Ignore counts them as successful, without creating accounts nor caching them.
+        let mut ignore = state(ZeroAmountPolicy::Ignore);
+        ignore.execute(deposit(1, 1, 0.0)).unwrap();
+        ignore.execute(withdrawal(2, 2, 0.0)).unwrap();
+        assert!(ignore.accounts.is_empty());
+        assert!(ignore.tx_cache.is_empty());
+        assert!(ignore.refundable.is_empty());
+        assert_eq!(ignore.count_transactions_by_type()["deposit"], 1);
+        assert_eq!(ignore.count_transactions_by_type()["withdrawal"], 1);
+        assert_eq!(ignore.total_transactions_succeeded(), 2);
+        assert_eq!(
+            ignore.execute(tx(TransactionType::Dispute, 1, 1)),
+            Err(TxError::TxDoesntExist)
+        );
+
+        ignore.execute(deposit(3, 3, 5.0)).unwrap();
+        ignore.execute(deposit(3, 4, 0.0)).unwrap();
+        ignore.execute(withdrawal(3, 5, 0.0)).unwrap();
+        assert_eq!(ignore.accounts[&3].total, 5.0);
+        assert_eq!(ignore.tx_cache.len(), 1);
+        let effect = ignore.execute_with_effect(deposit(4, 6, 0.0)).unwrap();
+        assert_eq!((effect.amount, effect.total_delta), (0.0, 0.0));
+        assert!(!ignore.accounts.contains_key(&4));
+
+        // Reject refuses them, without creating accounts.
+        let mut reject = state(ZeroAmountPolicy::Reject);
+        assert_eq!(reject.execute(deposit(1, 1, 0.0)), Err(TxError::ZeroAmount));
+        assert_eq!(
+            reject.execute(withdrawal(2, 2, 0.0)),
+            Err(TxError::ZeroAmount)
+        );
+        assert!(reject.accounts.is_empty() && reject.tx_cache.is_empty());
+        reject.execute(deposit(3, 3, 5.0)).unwrap();
+        assert_eq!(
+            reject.execute(withdrawal(3, 4, 0.0)),
+            Err(TxError::ZeroAmount)
+        );
+        assert_eq!(reject.accounts[&3].total, 5.0);
+        assert_eq!(reject.total_transactions_failed(), 3);
     }
 
     #[test]
-    fn test_dispute_chargeback() {
+    fn test_dispute_after_withdrawal() {
         let mut state = State::default();
         state
             .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Withdrawal {
+                    amount: checked(120.0),
+                },
+                client: 1,
+                tx: 2,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
@@ -381,6 +2801,10 @@ pub mod test {
                 tx_type: TransactionType::Dispute,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
@@ -389,24 +2813,33 @@ pub mod test {
                 tx_type: TransactionType::Chargeback,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
         let account = state.accounts.get(&1).unwrap();
-        assert_eq!(account.total, 0.0);
+        assert_eq!(account.total, -120.0);
         assert_eq!(account.held, 0.0);
-        assert_eq!(account.available, 0.0);
-        assert!(account.locked);
+        assert_eq!(account.available, -120.0);
     }
 
     #[test]
-    fn test_chargeback_after_resolve() {
+    fn test_account_lock() {
         let mut state = State::default();
         state
             .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0),
+                },
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
@@ -415,308 +2848,1523 @@ pub mod test {
                 tx_type: TransactionType::Dispute,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
         state
             .execute(Transaction {
-                tx_type: TransactionType::Resolve,
+                tx_type: TransactionType::Chargeback,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
         assert_eq!(
             state.execute(Transaction {
-                tx_type: TransactionType::Chargeback,
+                tx_type: TransactionType::Deposit {
+                    amount: checked(120.0)
+                },
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             }),
-            Err(TxError::TxDoesntExist)
+            Err(TxError::AccountLocked)
         );
     }
 
     #[test]
-    fn test_double_chargeback() {
+    fn test_transaction_graph() {
         let mut state = State::default();
+        for (client, tx) in [(1, 1), (1, 2), (2, 3), (2, 4), (3, 5)] {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(10.0),
+                    },
+                    client,
+                    tx,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
+        assert!(state.transaction_graph().is_empty());
+
+        for (client, tx) in [(1, 1), (1, 2), (2, 4)] {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Dispute,
+                    client,
+                    tx,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
+        // Resolved disputes are evicted from the cache.
         state
             .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
+                tx_type: TransactionType::Resolve,
                 client: 1,
-                tx: 1,
+                tx: 2,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
+        let graph = state.transaction_graph();
+        assert_eq!(graph.len(), 2);
+        assert_eq!(graph[&1], HashSet::from([1]));
+        assert_eq!(graph[&2], HashSet::from([4]));
+        assert!(!graph.contains_key(&3));
+    }
+
+    #[test]
+    fn test_overdraft_limit() {
+        let mut state = State::default();
+        state.set_overdraft_limit(1, 50.0).unwrap();
+
+        for client in [1, 2] {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(10.0),
+                    },
+                    client,
+                    tx: client as u32,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
+        let withdraw = |client, tx, amount| Transaction {
+            tx_type: TransactionType::Withdrawal {
+                amount: checked(amount),
+            },
+            client,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        };
+
+        state.execute(withdraw(1, 3, 40.0)).unwrap();
+        assert_eq!(state.accounts.get(&1).unwrap().available, -30.0);
+
+        assert_eq!(
+            state.execute(withdraw(1, 4, 20.5)),
+            Err(TxError::NotEnoughFunds)
+        );
+        state.execute(withdraw(1, 4, 20.0)).unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.available, -50.0);
+        assert_eq!(account.total, -50.0);
+
+        // Clients without an overdraft limit are unaffected.
+        assert_eq!(
+            state.execute(withdraw(2, 5, 10.5)),
+            Err(TxError::NotEnoughFunds)
+        );
+
+        assert_eq!(
+            state.set_overdraft_limit(1, -1.0),
+            Err(BuildError::InvalidCreditLimit)
+        );
+
+        let mut state = State::builder()
+            .prevent_negative_available(true)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            state.set_overdraft_limit(1, 10.0),
+            Err(BuildError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_migrate_client_id() {
+        let mut state = State::default();
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Dispute,
-                client: 1,
-                tx: 1,
-            })
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                2,
+                2,
+            ))
             .unwrap();
 
+        assert_eq!(state.migrate_client_id(3, 4), Err(TxError::AccountNotFound));
+        assert_eq!(
+            state.migrate_client_id(1, 2),
+            Err(TxError::DuplicateClientId)
+        );
+
+        state.migrate_client_id(1, 7).unwrap();
+        assert!(!state.accounts.contains_key(&1));
+        assert_eq!(state.accounts.get(&7).unwrap().id, 7);
+
+        // The old id no longer owns the transaction.
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 1, 1)),
+            Err(TxError::Unauthorized)
+        );
+
+        state.execute(tx(TransactionType::Dispute, 7, 1)).unwrap();
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Chargeback,
-                client: 1,
-                tx: 1,
+            .execute(tx(TransactionType::Chargeback, 7, 1))
+            .unwrap();
+
+        let account = state.accounts.get(&7).unwrap();
+        assert_eq!(account.total, 0.0);
+        assert_eq!(account.held, 0.0);
+        assert!(account.locked);
+        assert_eq!(state.accounts.get(&2).unwrap().total, 5.0);
+
+        // Withdrawals follow the client, so they can still be refunded.
+        state
+            .execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(2.0),
+                },
+                2,
+                3,
+            ))
+            .unwrap();
+        state.migrate_client_id(2, 8).unwrap();
+        let refund = |client, id| {
+            tx(
+                TransactionType::Refund {
+                    of_tx: 3,
+                    amount: 2.0,
+                },
+                client,
+                id,
+            )
+        };
+        assert_eq!(state.execute(refund(9, 4)), Err(TxError::Unauthorized));
+        state.execute(refund(8, 5)).unwrap();
+        assert_eq!(state.accounts.get(&8).unwrap().total, 5.0);
+    }
+
+    #[test]
+    fn test_balance_limit() {
+        let mut state = State::builder().balance_limit(100.0).build().unwrap();
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(60.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(40.0),
+                },
+                1,
+                2,
+            ))
+            .unwrap();
+        assert_eq!(
+            state.execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(0.01)
+                },
+                1,
+                3
+            )),
+            Err(TxError::BalanceLimitExceeded {
+                limit: "100".to_string(),
+                attempted: "100.01".to_string(),
             })
+        );
+        assert_eq!(state.accounts[&1].total, 100.0);
+
+        // Refunds and interest bring in new money as well.
+        state
+            .execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(5.0),
+                },
+                1,
+                4,
+            ))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                5,
+            ))
             .unwrap();
+        let refund = TransactionType::Refund {
+            of_tx: 4,
+            amount: 5.0,
+        };
+        assert!(matches!(
+            state.execute(tx(refund, 1, 6)),
+            Err(TxError::BalanceLimitExceeded { .. })
+        ));
+        assert!(matches!(
+            state.execute(tx(TransactionType::Interest { rate_bps: 1000 }, 1, 7)),
+            Err(TxError::BalanceLimitExceeded { .. })
+        ));
+
+        // Disputes dont, even once resolved.
+        state.execute(tx(TransactionType::Dispute, 1, 1)).unwrap();
+        state.execute(tx(TransactionType::Resolve, 1, 1)).unwrap();
+        assert_eq!(state.accounts[&1].total, 96.0);
 
+        // Per account limits override the default one.
+        state.set_balance_limit(2, 10.0).unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                2,
+                8,
+            ))
+            .unwrap();
+        assert!(state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(0.01)
+                },
+                2,
+                9
+            ))
+            .is_err());
         assert_eq!(
-            state.execute(Transaction {
-                tx_type: TransactionType::Chargeback,
-                client: 1,
-                tx: 1,
-            }),
-            Err(TxError::AccountLocked)
+            state.set_balance_limit(2, -1.0),
+            Err(BuildError::InvalidBalanceLimit)
         );
+        assert_eq!(state.set_balance_limit(2, 0.0), Ok(()));
+        assert!(State::builder().balance_limit(0.0).build().is_ok());
+        assert!(State::builder().balance_limit(f64::NAN).build().is_err());
     }
 
     #[test]
-    fn test_double_resolve() {
+    fn test_merge_accounts_pending_disputes() {
+        let mut state = State::builder().deferred_disputes(2).build().unwrap();
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        let deposit = |client, id, amount| {
+            let amount = checked(amount);
+            tx(TransactionType::Deposit { amount }, client, id)
+        };
+
+        state.execute(deposit(1, 1, 10.0)).unwrap();
+        state.execute(deposit(2, 2, 5.0)).unwrap();
+        state.execute(tx(TransactionType::Dispute, 2, 3)).unwrap();
+        assert_eq!(state.pending_dispute_count(), 1);
+
+        state.merge_accounts(1, 2, 1).unwrap();
+        state.execute(deposit(1, 3, 4.0)).unwrap();
+        assert_eq!(state.pending_dispute_count(), 0);
+
+        let account = &state.accounts[&1];
+        assert_eq!((account.available, account.held), (15.0, 4.0));
+        assert_eq!(account.disputed_hold(), 4.0);
+    }
+
+    #[test]
+    fn test_merge_accounts() {
+        let mut state = State::default();
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                2,
+                2,
+            ))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(1.5),
+                },
+                2,
+                3,
+            ))
+            .unwrap();
+
+        assert_eq!(
+            state.merge_accounts(1, 2, 3),
+            Err(TxError::InvalidMergeTarget)
+        );
+        assert_eq!(
+            state.merge_accounts(1, 1, 1),
+            Err(TxError::InvalidMergeTarget)
+        );
+        assert_eq!(state.merge_accounts(1, 4, 1), Err(TxError::AccountNotFound));
+
+        // Accounts with an open dispute cannot be merged.
+        state.execute(tx(TransactionType::Dispute, 2, 2)).unwrap();
+        assert_eq!(
+            state.merge_accounts(1, 2, 1),
+            Err(TxError::TxAlreadyDisputed)
+        );
+        state.execute(tx(TransactionType::Resolve, 2, 2)).unwrap();
+
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(2.0),
+                },
+                2,
+                4,
+            ))
+            .unwrap();
+        state.merge_accounts(1, 2, 1).unwrap();
+        assert!(!state.accounts.contains_key(&2));
+        let account = &state.accounts[&1];
+        assert_eq!(
+            (account.available, account.held, account.total),
+            (15.5, 0.0, 15.5)
+        );
+
+        // Transactions of either original client are now disputed by the target.
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 2, 4)),
+            Err(TxError::Unauthorized)
+        );
+        state.execute(tx(TransactionType::Dispute, 1, 4)).unwrap();
+        state.execute(tx(TransactionType::Dispute, 1, 1)).unwrap();
+        let account = &state.accounts[&1];
+        assert_eq!((account.available, account.held), (3.5, 12.0));
+        assert_eq!(
+            state
+                .open_disputes()
+                .iter()
+                .map(|x| x.transaction().client())
+                .collect::<Vec<_>>(),
+            [1, 1]
+        );
+
+        // Refunds follow the withdrawal over to the target.
+        let refund = TransactionType::Refund {
+            of_tx: 3,
+            amount: 1.5,
+        };
+        state.execute(tx(refund, 1, 5)).unwrap();
+        assert_eq!(state.accounts[&1].total, 17.0);
+
+        state
+            .execute(tx(TransactionType::Chargeback, 1, 1))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                6,
+                6,
+            ))
+            .unwrap();
+        assert_eq!(state.merge_accounts(6, 1, 6), Err(TxError::AccountLocked));
+    }
+
+    #[test]
+    fn test_drain_locked_accounts() {
+        let mut state = State::default();
+
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        for client in 1..=5 {
+            let id = client as u32;
+            state
+                .execute(tx(
+                    TransactionType::Deposit {
+                        amount: checked(10.0),
+                    },
+                    client,
+                    id,
+                ))
+                .unwrap();
+            state
+                .execute(tx(
+                    TransactionType::Deposit {
+                        amount: checked(5.0),
+                    },
+                    client,
+                    id + 10,
+                ))
+                .unwrap();
+        }
+        for client in [2, 4] {
+            let id = client as u32;
+            state
+                .execute(tx(TransactionType::Dispute, client, id))
+                .unwrap();
+            // A residual dispute left open on the account once it gets locked.
+            state
+                .execute(tx(TransactionType::Dispute, client, id + 10))
+                .unwrap();
+            state
+                .execute(tx(TransactionType::Chargeback, client, id))
+                .unwrap();
+        }
+        state.execute(tx(TransactionType::Dispute, 3, 3)).unwrap();
+
+        let count = state.account_count();
+        let drained = state.drain_locked_accounts();
+        assert_eq!(drained.iter().map(|x| x.id).collect::<Vec<_>>(), [2, 4]);
+        assert!(drained.iter().all(|x| x.locked && x.held == 5.0));
+        assert_eq!(state.account_count(), count - drained.len());
+        assert!(state.accounts().all(|x| !x.locked));
+        assert!(state
+            .tx_cache
+            .values()
+            .all(|x| ![2, 4].contains(&x.tx.client)));
+        assert_eq!(state.accounts[&3].held, 10.0);
+        assert!(state.drain_locked_accounts().is_empty());
+
+        // Drained clients start over on a new account.
+        assert_eq!(
+            state.execute(tx(TransactionType::Resolve, 2, 12)),
+            Err(TxError::TxDoesntExist)
+        );
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                4,
+                20,
+            ))
+            .unwrap();
+        assert_eq!(
+            state.accounts[&4],
+            Account {
+                id: 4,
+                available: 1.0,
+                total: 1.0,
+                ..Account::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_freeze_tx_cache_before() {
+        let mut state = State::default();
+        let tx = |tx_type, tx| Transaction::new(tx_type, 1, tx);
+
+        for id in 1..=4 {
+            state
+                .execute(tx(
+                    TransactionType::Deposit {
+                        amount: checked(1.0),
+                    },
+                    id,
+                ))
+                .unwrap();
+        }
+        state.execute(tx(TransactionType::Dispute, 2)).unwrap();
+
+        // The disputed deposit stays in the cache.
+        assert_eq!(state.freeze_tx_cache_before(3), 2);
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 1)),
+            Err(TxError::TransactionFrozen(1))
+        );
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 3)),
+            Err(TxError::TransactionFrozen(3))
+        );
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 5)),
+            Err(TxError::TxDoesntExist)
+        );
+
+        state.execute(tx(TransactionType::Resolve, 2)).unwrap();
+        state.execute(tx(TransactionType::Dispute, 4)).unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 3.0);
+        assert_eq!(account.held, 1.0);
+
+        // Freezing again only counts newly frozen transactions.
+        assert_eq!(state.freeze_tx_cache_before(3), 0);
+    }
+
+    #[test]
+    fn test_consolidate_tx_cache() {
+        let mut state = State::default();
+        let tx = |tx_type, tx| Transaction::new(tx_type, 1, tx);
+
+        assert_eq!(state.consolidate_tx_cache(5), 0);
+        for id in 1..=10 {
+            state
+                .execute(tx(
+                    TransactionType::Deposit {
+                        amount: checked(1.0),
+                    },
+                    id,
+                ))
+                .unwrap();
+        }
+        state.execute(tx(TransactionType::Dispute, 2)).unwrap();
+
+        // Ids 1, 3 and 4 are evicted, the disputed deposit 2 is kept regardless of its age.
+        assert_eq!(state.consolidate_tx_cache(5), 3);
+        let mut ids: Vec<_> = state.tx_cache.keys().copied().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, [2, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 4)),
+            Err(TxError::TxDoesntExist)
+        );
+
+        // Consolidating again only counts newly evicted transactions.
+        state.execute(tx(TransactionType::Dispute, 5)).unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                12,
+            ))
+            .unwrap();
+        assert_eq!(state.consolidate_tx_cache(5), 1);
+        assert_eq!(state.consolidate_tx_cache(5), 0);
+        assert_eq!(state.consolidate_tx_cache(u32::MAX), 0);
+
+        state.execute(tx(TransactionType::Chargeback, 2)).unwrap();
+        assert_eq!(state.accounts[&1].held, 1.0);
+    }
+
+    #[test]
+    fn test_from_iterator_grouped() {
+        let grouped = State::from_iterator_grouped(workload(10_000).into_iter());
+        let expected = State::from_iterator(workload(10_000).into_iter());
+
+        assert_eq!(grouped.accounts, expected.accounts);
+
+        // Disputes still follow the deposits they reference within each client.
+        let txs = vec![
+            Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                2,
+                1,
+            ),
+            Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(2.0),
+                },
+                1,
+                2,
+            ),
+            Transaction::new(TransactionType::Dispute, 2, 1),
+            Transaction::new(TransactionType::Chargeback, 2, 1),
+            Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(3.0),
+                },
+                2,
+                3,
+            ),
+        ];
+        let state = State::from_iterator_grouped(txs.into_iter());
+
+        let account = state.accounts.get(&2).unwrap();
+        assert_eq!(account.total, 0.0);
+        assert!(account.locked);
+        assert_eq!(state.accounts.get(&1).unwrap().total, 2.0);
+    }
+
+    #[test]
+    fn test_execute_silent() {
+        let mut state = State::default();
+        for tx in workload(1000) {
+            state.execute_silent(tx);
+        }
+
+        let expected = State::from_iterator(workload(1000).into_iter());
+        assert_eq!(state.accounts, expected.accounts);
+    }
+
+    #[test]
+    fn test_from_iterator_limit() {
+        let deposits = (1..=10).map(|tx| {
+            Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                tx,
+            )
+        });
+
+        let mut consumed = 0;
+        let state = State::from_iterator_limit(deposits.inspect(|_| consumed += 1), 4);
+
+        assert_eq!(consumed, 4);
+        assert_eq!(state.accounts.get(&1).unwrap().total, 4.0);
+        assert_eq!(state.tx_cache.len(), 4);
+
+        let state = State::from_iterator_limit(workload(100).into_iter(), 1000);
+        let expected = State::from_iterator(workload(100).into_iter());
+        assert_eq!(state.accounts, expected.accounts);
+    }
+
+    #[test]
+    fn test_accounts_iter_by_id() {
+        let state = State::from_iterator(workload(1000).into_iter());
+
+        let mut expected: Vec<&Account> = state.accounts().collect();
+        expected.sort_by_key(|x| x.id);
+        assert!(expected.len() > 5);
+
+        assert_eq!(state.accounts_iter_by_id().collect::<Vec<_>>(), expected);
+        assert_eq!(
+            state.accounts_iter_by_id().take(5).collect::<Vec<_>>(),
+            expected[..5]
+        );
+    }
+
+    #[test]
+    fn test_sorted_tx_cache() {
         let mut state = State::default();
+        for tx in [5, 3, 7, 1] {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(tx as f64),
+                    },
+                    client: 1,
+                    tx,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
         state
             .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
+                tx_type: TransactionType::Dispute,
                 client: 1,
-                tx: 1,
+                tx: 3,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
+        let sorted = state.sorted_tx_cache();
+        assert_eq!(
+            sorted.iter().map(|(id, ..)| *id).collect::<Vec<_>>(),
+            vec![1, 3, 5, 7]
+        );
+
+        for (id, tx, dispute) in sorted {
+            assert_eq!(tx.tx(), id);
+            assert_eq!(
+                tx.tx_type(),
+                &TransactionType::Deposit {
+                    amount: checked(id as f64)
+                }
+            );
+            assert_eq!(dispute.is_some(), id == 3);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_write_accounts_chunked() {
+        let mut state = State::default();
+        for client in 1..=25 {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(1.0),
+                    },
+                    client,
+                    tx: client as u32,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        state.write_accounts_chunked(&mut writer, 10).unwrap();
+
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let mut lines = output.lines();
+        assert_eq!(lines.next(), Some("client,available,held,total,locked"));
+
+        let mut clients = lines
+            .map(|x| x.split(',').next().unwrap().parse::<u16>().unwrap())
+            .collect::<Vec<_>>();
+        clients.sort_unstable();
+        assert_eq!(clients, (1..=25).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serialize_negative_zero() {
+        // NOTE: `normalize` snaps balances to zero while executing, so the account is built by
+        // hand to land on `-0.0`.
+        let netted: f64 = 2.5 - 2.5;
+        let account = Account {
+            id: 1,
+            available: -netted,
+            held: -0.0,
+            disputed_hold: 0.0,
+            total: -0.0,
+            locked: false,
+        };
+        assert!(account.available.is_sign_negative());
+        assert!(account.total.is_sign_negative());
+
+        fn to_csv(row: impl Serialize) -> String {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.serialize(row).unwrap();
+            String::from_utf8(writer.into_inner().unwrap()).unwrap()
+        }
+
+        assert_eq!(
+            to_csv(&account),
+            "client,available,held,total,locked\n1,0.0,0.0,0.0,false\n"
+        );
+        assert_eq!(
+            to_csv(account.with_status()),
+            "client,available,held,total,status\n1,0.0,0.0,0.0,active\n"
+        );
+        assert!(!to_csv(account.with_hold_columns()).contains("-0"));
+    }
+
+    #[test]
+    fn test_dispute_most_recent_duplicate() {
+        let mut state = State::default();
+        for amount in [10.0, 25.0] {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(amount),
+                    },
+                    client: 1,
+                    tx: 1,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
         state
             .execute(Transaction {
                 tx_type: TransactionType::Dispute,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
+        {
+            let account = state.accounts.get(&1).unwrap();
+            assert_eq!(account.held, 25.0);
+            assert_eq!(account.available, 10.0);
+        }
+
         state
             .execute(Transaction {
-                tx_type: TransactionType::Resolve,
+                tx_type: TransactionType::Chargeback,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
-        assert_eq!(
-            state.execute(Transaction {
-                tx_type: TransactionType::Resolve,
-                client: 1,
-                tx: 1,
-            }),
-            Err(TxError::TxDoesntExist)
-        );
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.held, 0.0);
+        assert_eq!(account.available, 10.0);
+        assert_eq!(account.total, 10.0);
     }
 
     #[test]
-    fn test_double_dispute() {
+    fn test_duplicate_deposit_during_dispute() {
         let mut state = State::default();
-        state
-            .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
-                client: 1,
-                tx: 1,
-            })
-            .unwrap();
+        let deposit = |amount| Transaction {
+            tx_type: TransactionType::Deposit {
+                amount: checked(amount),
+            },
+            client: 1,
+            tx: 1,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        };
 
+        state.execute(deposit(10.0)).unwrap();
         state
             .execute(Transaction {
                 tx_type: TransactionType::Dispute,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
+        // The disputed deposit stays the target of the dispute.
+        state.execute(deposit(25.0)).unwrap();
         state
             .execute(Transaction {
                 tx_type: TransactionType::Resolve,
                 client: 1,
                 tx: 1,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
             })
             .unwrap();
 
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.held, 0.0);
+        assert_eq!(account.available, 35.0);
+        assert_eq!(account.total, 35.0);
+        assert!(state.tx_cache.is_empty());
+    }
+
+    #[test]
+    fn test_interest() {
+        let mut state = State::default();
+        state.set_overdraft_limit(3, 50.0).unwrap();
+
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        let deposit = |client, id, amount| {
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(amount),
+                },
+                client,
+                id,
+            )
+        };
+        let interest =
+            |client, id, rate_bps| tx(TransactionType::Interest { rate_bps }, client, id);
+        let credited = |state: &mut State, tx| {
+            let mut credited = None;
+            state
+                .execute_with_events(tx, |event| {
+                    if let AccountEvent::InterestCredited { amount, .. } = event {
+                        credited = Some(amount);
+                    }
+                })
+                .map(|_| credited.unwrap())
+        };
+
+        // Interest accrues on `available` only, funds on hold dont earn any.
+        state.execute(deposit(1, 1, 1000.0)).unwrap();
+        state.execute(deposit(1, 2, 500.0)).unwrap();
+        state.execute(tx(TransactionType::Dispute, 1, 2)).unwrap();
+        assert_eq!(credited(&mut state, interest(1, 3, 125)), Ok(12.5));
+
+        let account = &state.accounts[&1];
+        assert_eq!(account.available, 1012.5);
+        assert_eq!(account.held, 500.0);
+        assert_eq!(account.total, 1512.5);
+
+        // Interest isnt disputable.
         assert_eq!(
-            state.execute(Transaction {
-                tx_type: TransactionType::Dispute,
-                client: 1,
-                tx: 1,
-            }),
+            state.execute(tx(TransactionType::Dispute, 1, 3)),
             Err(TxError::TxDoesntExist)
         );
+
+        // Zero balances accrue nothing.
+        state.execute(deposit(2, 4, 10.0)).unwrap();
+        state
+            .execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(10.0),
+                },
+                2,
+                5,
+            ))
+            .unwrap();
+        assert_eq!(credited(&mut state, interest(2, 6, 125)), Ok(0.0));
+
+        // Negative balances accrue nothing by default.
+        state.execute(deposit(3, 7, 10.0)).unwrap();
+        state
+            .execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(30.0),
+                },
+                3,
+                8,
+            ))
+            .unwrap();
+        assert_eq!(credited(&mut state, interest(3, 9, 125)), Ok(0.0));
+        assert_eq!(state.accounts[&3].available, -20.0);
+
+        // Locked accounts are rejected.
+        state.execute(deposit(4, 10, 10.0)).unwrap();
+        state.execute(tx(TransactionType::Dispute, 4, 10)).unwrap();
+        state
+            .execute(tx(TransactionType::Chargeback, 4, 10))
+            .unwrap();
+        assert_eq!(
+            state.execute(interest(4, 11, 125)),
+            Err(TxError::AccountLocked)
+        );
+        assert_eq!(state.count_transactions_by_type()["interest"], 3);
+
+        let mut state = State::builder()
+            .negative_interest(NegativeInterestPolicy::Reject)
+            .rounding_mode(RoundingMode::Down)
+            .default_credit_limit(50.0)
+            .build()
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(30.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        assert_eq!(
+            state.execute(interest(1, 2, 125)),
+            Err(TxError::NegativeBalanceInterest)
+        );
+
+        // The credited amount is rounded to the minor unit with the configured mode.
+        state.execute(deposit(2, 3, 123.5)).unwrap();
+        assert_eq!(credited(&mut state, interest(2, 4, 5)), Ok(0.0617));
+
+        let mut state = State::default();
+        state.execute(deposit(2, 3, 123.5)).unwrap();
+        assert_eq!(credited(&mut state, interest(2, 4, 5)), Ok(0.0618));
+
+        let entry = state.execute_audited(interest(2, 5, 100)).unwrap();
+        assert_eq!(entry.credited_interest(), Some(1.2356));
+        assert!((state.accounts[&2].total - 124.7974).abs() < MINOR_UNIT);
     }
 
     #[test]
-    fn test_dispute_from_different_uid() {
+    fn test_refund() {
         let mut state = State::default();
+
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        let withdrawal = |client, id, amount| {
+            tx(
+                TransactionType::Withdrawal {
+                    amount: checked(amount),
+                },
+                client,
+                id,
+            )
+        };
+        let refund =
+            |client, id, of_tx, amount| tx(TransactionType::Refund { of_tx, amount }, client, id);
+
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
-                client: 1,
-                tx: 1,
-            })
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(100.0),
+                },
+                1,
+                1,
+            ))
             .unwrap();
+        state.execute(withdrawal(1, 2, 30.0)).unwrap();
+        state.execute(withdrawal(1, 3, 20.0)).unwrap();
 
+        // Full refund.
+        state.execute(refund(1, 4, 2, 30.0)).unwrap();
+        assert_eq!(state.accounts[&1].available, 80.0);
+        assert_eq!(state.accounts[&1].total, 80.0);
         assert_eq!(
-            state.execute(Transaction {
-                tx_type: TransactionType::Dispute,
-                client: 2,
-                tx: 1,
-            }),
-            Err(TxError::Unauthorized)
+            state.execute(refund(1, 5, 2, 0.0001)),
+            Err(TxError::RefundExceedsWithdrawal)
         );
 
+        // Two partial refunds up to the amount withdrawn, then an over-refund.
+        state.execute(refund(1, 6, 3, 12.5)).unwrap();
+        state.execute(refund(1, 7, 3, 7.5)).unwrap();
         assert_eq!(
-            state.execute(Transaction {
-                tx_type: TransactionType::Resolve,
-                client: 2,
-                tx: 1,
-            }),
-            Err(TxError::Unauthorized)
+            state.execute(refund(1, 8, 3, 1.0)),
+            Err(TxError::RefundExceedsWithdrawal)
         );
+        assert_eq!(state.accounts[&1].available, 100.0);
+        assert_eq!(state.accounts[&1].total, 100.0);
 
+        // Unknown transactions, deposits and withdrawals of other clients cant be refunded.
         assert_eq!(
-            state.execute(Transaction {
-                tx_type: TransactionType::Chargeback,
-                client: 2,
-                tx: 1,
-            }),
+            state.execute(refund(1, 9, 42, 1.0)),
+            Err(TxError::TxDoesntExist)
+        );
+        assert_eq!(
+            state.execute(refund(1, 10, 1, 1.0)),
+            Err(TxError::TxDoesntExist)
+        );
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                2,
+                11,
+            ))
+            .unwrap();
+        state.execute(withdrawal(2, 12, 5.0)).unwrap();
+        assert_eq!(
+            state.execute(refund(1, 13, 12, 1.0)),
             Err(TxError::Unauthorized)
         );
+        assert_eq!(state.accounts[&2].available, 5.0);
+        assert_eq!(state.count_transactions_by_type()["refund"], 3);
+
+        // Refunds arent disputable.
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 1, 6)),
+            Err(TxError::TxDoesntExist)
+        );
+
+        // Replaying the events enforces the same cap.
+        let mut events = Vec::new();
+        let mut state = State::default();
+        for x in [
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            withdrawal(1, 2, 10.0),
+            refund(1, 3, 2, 10.0),
+        ] {
+            state
+                .execute_with_events(x, |event| events.push(event))
+                .unwrap();
+        }
+
+        let mut replayed = State::default();
+        for event in &events {
+            replayed.apply_event(event).unwrap();
+        }
+        assert_eq!(replayed.accounts[&1], state.accounts[&1]);
+        assert_eq!(
+            replayed.apply_event(&events[events.len() - 1]),
+            Err(TxError::CorruptEventLog)
+        );
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    #[should_panic]
-    fn test_withdrawal_dispute() {
+    fn test_deserialize_refund() {
+        let data = "type,client,tx,amount,of_tx\nrefund,1,3,1.5,2\nrefund,1,4,1.5,\n";
+        let mut reader = csv::ReaderBuilder::new().from_reader(data.as_bytes());
+        let mut records = reader.deserialize::<Transaction>();
+
+        assert_eq!(
+            records.next().unwrap().unwrap(),
+            Transaction::new(
+                TransactionType::Refund {
+                    of_tx: 2,
+                    amount: 1.5
+                },
+                1,
+                3
+            )
+        );
+        assert!(records.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_manual_hold() {
         let mut state = State::default();
-        state
-            .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
-                client: 1,
-                tx: 1,
-            })
-            .unwrap();
+
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        let hold = |client, id, amount| tx(TransactionType::Hold { amount }, client, id);
+        let release = |client, id, of_tx| tx(TransactionType::Release { of_tx }, client, id);
 
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Withdrawal { amount: 75.5 },
-                client: 1,
-                tx: 2,
-            })
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(100.0),
+                },
+                1,
+                1,
+            ))
             .unwrap();
-
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Dispute,
-                client: 1,
-                tx: 2,
-            })
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(20.0),
+                },
+                1,
+                2,
+            ))
             .unwrap();
 
-        let account = state.accounts.get(&1).unwrap();
+        // A manual hold and a dispute held on the same account at once.
+        state.execute(hold(1, 3, 30.0)).unwrap();
+        state.execute(tx(TransactionType::Dispute, 1, 2)).unwrap();
+        let account = &state.accounts[&1];
+        assert_eq!(account.available, 70.0);
+        assert_eq!(account.held, 50.0);
         assert_eq!(account.total, 120.0);
-        assert_eq!(account.held, 75.5);
-        assert_eq!(account.available, 44.5);
-    }
+        assert_eq!(account.disputed_hold(), 20.0);
+        assert_eq!(account.pending_hold(), 30.0);
+        assert!(account.is_balanced());
 
-    #[test]
-    fn test_negative_txs() {
-        let mut state = State::default();
+        // Holds cant exceed the available funds, nor reuse an id.
         assert_eq!(
-            state.execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: -120.0 },
-                client: 1,
-                tx: 1,
-            }),
-            Err(TxError::InternalError)
+            state.execute(hold(1, 4, 70.0001)),
+            Err(TxError::NotEnoughFunds)
         );
+        assert_eq!(state.execute(hold(1, 3, 1.0)), Err(TxError::DuplicateTx));
+
+        // Resolving the dispute leaves the manual hold alone, and releasing it restores the
+        // available funds in full.
+        state.execute(tx(TransactionType::Resolve, 1, 2)).unwrap();
+        assert_eq!(state.accounts[&1].held, 30.0);
+        state.execute(release(1, 5, 3)).unwrap();
+        assert_eq!(state.accounts[&1].available, 120.0);
+        assert_eq!(state.accounts[&1].held, 0.0);
+        assert_eq!(state.accounts[&1].total, 120.0);
 
+        // Unknown, already released and foreign holds cant be released.
+        assert_eq!(state.execute(release(1, 6, 3)), Err(TxError::TxDoesntExist));
         assert_eq!(
-            state.execute(Transaction {
-                tx_type: TransactionType::Withdrawal { amount: -120.0 },
-                client: 1,
-                tx: 1,
-            }),
-            Err(TxError::InternalError)
+            state.execute(release(1, 7, 42)),
+            Err(TxError::TxDoesntExist)
+        );
+        state.execute(hold(1, 8, 10.0)).unwrap();
+        assert_eq!(state.execute(release(2, 9, 8)), Err(TxError::Unauthorized));
+        assert_eq!(state.accounts[&1].held, 10.0);
+        assert_eq!(state.count_transactions_by_type()["hold"], 2);
+        assert_eq!(state.count_transactions_by_type()["release"], 1);
+
+        // Replaying the events tracks the holds the same way.
+        let mut events = Vec::new();
+        let mut state = State::default();
+        for x in [
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            hold(1, 2, 4.0),
+            release(1, 3, 2),
+        ] {
+            state
+                .execute_with_events(x, |event| events.push(event))
+                .unwrap();
+        }
+
+        let mut replayed = State::default();
+        for event in &events {
+            replayed.apply_event(event).unwrap();
+        }
+        assert_eq!(replayed.accounts[&1], state.accounts[&1]);
+        assert_eq!(
+            replayed.apply_event(&events[events.len() - 1]),
+            Err(TxError::CorruptEventLog)
         );
     }
 
     #[test]
-    fn test_dispute_after_withdrawal() {
+    fn test_reset_account() {
         let mut state = State::default();
-        state
-            .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
-                client: 1,
-                tx: 1,
-            })
-            .unwrap();
 
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        for x in [
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                1,
+                2,
+            ),
+            tx(
+                TransactionType::Withdrawal {
+                    amount: checked(3.0),
+                },
+                1,
+                3,
+            ),
+            tx(TransactionType::Hold { amount: 2.0 }, 1, 4),
+            tx(TransactionType::Dispute, 1, 1),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(7.0),
+                },
+                2,
+                5,
+            ),
+        ] {
+            state.execute(x).unwrap();
+        }
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Withdrawal { amount: 120.0 },
-                client: 1,
-                tx: 2,
-            })
+            .execute(tx(TransactionType::Chargeback, 1, 1))
             .unwrap();
+        assert!(state.accounts[&1].locked);
 
-        state
-            .execute(Transaction {
-                tx_type: TransactionType::Dispute,
-                client: 1,
-                tx: 1,
-            })
-            .unwrap();
+        state.reset_account(1);
+        state.reset_account(3);
+
+        let account = state.accounts().find(|x| x.id == 1).unwrap();
+        assert_eq!(
+            *account,
+            Account {
+                id: 1,
+                ..Account::default()
+            }
+        );
+        assert!(!state.accounts.contains_key(&3));
+        assert_eq!(state.accounts[&2].available, 7.0);
+        assert!(state.tx_cache.values().all(|x| x.tx.client == 2));
 
+        // The history of the account is gone, while the account can be used again.
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 1, 2)),
+            Err(TxError::TxDoesntExist)
+        );
+        assert_eq!(
+            state.execute(tx(TransactionType::Release { of_tx: 4 }, 1, 6)),
+            Err(TxError::TxDoesntExist)
+        );
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Chargeback,
-                client: 1,
-                tx: 1,
-            })
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                7,
+            ))
             .unwrap();
+        assert_eq!(state.accounts[&1].total, 1.0);
+    }
 
-        let account = state.accounts.get(&1).unwrap();
-        assert_eq!(account.total, -120.0);
-        assert_eq!(account.held, 0.0);
-        assert_eq!(account.available, -120.0);
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_manual_hold() {
+        let data = "type,client,tx,amount,of_tx\nhold,1,3,1.5,\nrelease,1,4,,3\nhold,1,5,,\nrelease,1,6,,\n";
+        let mut reader = csv::ReaderBuilder::new().from_reader(data.as_bytes());
+        let mut records = reader.deserialize::<Transaction>();
+
+        assert_eq!(
+            records.next().unwrap().unwrap(),
+            Transaction::new(TransactionType::Hold { amount: 1.5 }, 1, 3)
+        );
+        assert_eq!(
+            records.next().unwrap().unwrap(),
+            Transaction::new(TransactionType::Release { of_tx: 3 }, 1, 4)
+        );
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().unwrap().is_err());
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_account_lock() {
+    fn test_deserialize_ref_tx() {
+        // Old style rows leave `ref_tx` blank or omit it, and refer to the disputed deposit
+        // through `tx`.
+        let data = "type,client,tx,amount,ref_tx\n\
+                    deposit,1,1,10.0,\n\
+                    deposit,1,2,5.0\n\
+                    dispute,1,1,,\n\
+                    dispute,1,100,,2\n\
+                    resolve,1,101,,2\n\
+                    deposit,1,3,1.0,1\n";
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(data.as_bytes());
+        let mut records: Vec<_> = reader.deserialize::<Transaction>().collect();
+        assert!(records.pop().unwrap().is_err());
+
+        let txs: Vec<Transaction> = records.into_iter().map(Result::unwrap).collect();
+        assert_eq!(txs[2], Transaction::new(TransactionType::Dispute, 1, 1));
+        assert_eq!(
+            txs[3],
+            Transaction::new(TransactionType::Dispute, 1, 2).with_event_id(100)
+        );
+        assert_eq!(
+            txs[4],
+            Transaction::new(TransactionType::Resolve, 1, 2).with_event_id(101)
+        );
+
+        let state = State::from_iterator(txs.into_iter());
+        assert_eq!(state.accounts[&1].available, 5.0);
+        assert_eq!(state.accounts[&1].held, 10.0);
+        let disputes = state.open_disputes();
+        assert_eq!(disputes.len(), 1);
+        assert_eq!(disputes[0].transaction().tx(), 1);
+        assert_eq!(disputes[0].dispute_event_id(), None);
+    }
+
+    #[test]
+    fn test_dispute_event_ids() {
         let mut state = State::default();
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
-                client: 1,
-                tx: 1,
-            })
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ))
             .unwrap();
 
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Dispute,
-                client: 1,
-                tx: 1,
-            })
+            .execute(tx(TransactionType::Dispute, 1, 1).with_event_id(100))
             .unwrap();
+        assert_eq!(state.open_disputes()[0].dispute_event_id(), Some(100));
 
+        // Dispute event ids are unique across disputes, resolves and chargebacks.
+        assert_eq!(
+            state.execute(tx(TransactionType::Resolve, 1, 1).with_event_id(100)),
+            Err(TxError::DuplicateTx)
+        );
         state
-            .execute(Transaction {
-                tx_type: TransactionType::Chargeback,
-                client: 1,
-                tx: 1,
-            })
+            .execute(tx(TransactionType::Resolve, 1, 1).with_event_id(101))
             .unwrap();
+        assert!(state.open_disputes().is_empty());
 
+        // Ids of rejected disputes arent taken.
         assert_eq!(
-            state.execute(Transaction {
-                tx_type: TransactionType::Deposit { amount: 120.0 },
-                client: 1,
-                tx: 1,
-            }),
-            Err(TxError::AccountLocked)
+            state.execute(tx(TransactionType::Dispute, 1, 99).with_event_id(102)),
+            Err(TxError::TxDoesntExist)
         );
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                1,
+                2,
+            ))
+            .unwrap();
+        let receipts = state
+            .execute_atomic(&[tx(TransactionType::Dispute, 1, 2).with_event_id(102)])
+            .unwrap();
+        assert_eq!(receipts[0].event_id, Some(102));
+        assert_eq!(state.accounts[&1].held, 5.0);
+
+        // Deposits may share their id with a dispute event.
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                100,
+            ))
+            .unwrap();
+    }
+
+    fn sorted_accounts<H: BuildHasher>(state: &State<H>) -> Vec<Account> {
+        let mut accounts = state.accounts().cloned().collect::<Vec<_>>();
+        accounts.sort_by_key(|x| x.id);
+        accounts
+    }
+
+    #[test]
+    fn test_std_hasher() {
+        let mut state = State::new_with_hasher(std::collections::hash_map::RandomState::new());
+        for tx in workload(1000) {
+            let _ = state.execute(tx);
+        }
+
+        let expected = State::from_iterator(workload(1000).into_iter());
+        assert_eq!(sorted_accounts(&state), sorted_accounts(&expected));
+    }
+
+    #[test]
+    fn test_fnv_hasher() {
+        let mut state = State::new_with_hasher(fnv::FnvBuildHasher::default());
+        for tx in workload(1000) {
+            let _ = state.execute(tx);
+        }
+
+        let expected = State::from_iterator(workload(1000).into_iter());
+        assert_eq!(sorted_accounts(&state), sorted_accounts(&expected));
     }
 }
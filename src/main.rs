@@ -1,39 +1,557 @@
 use std::env;
+use std::error::Error;
+#[cfg(any(feature = "server", feature = "grpc", feature = "schemars"))]
+use std::ffi::OsString;
 use std::fs::File;
 use std::io;
+#[cfg(any(feature = "schemars", feature = "json-output"))]
+use std::io::Write;
 use std::path::Path;
+use std::path::PathBuf;
 use std::process;
 
+use corken::currency::{Currency, MultiCurrencyState};
+use corken::fallible::{FallibleFailure, RejectionRecord};
+use corken::period::PeriodSpec;
 use corken::*;
+use serde::Serialize;
 
-fn main() -> io::Result<()> {
-    let args = env::args_os().collect::<Vec<_>>();
-    let input_file = match args.as_slice() {
-        [] => unreachable!(),
+/// Error returned when the input file cannot be opened, carrying the offending path.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to open input file {}", path.display())]
+struct OpenError {
+    path: PathBuf,
+    #[source]
+    source: io::Error,
+}
+
+/// Enum represents the formats the accounts can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Csv,
+    /// A single JSON array of accounts.
+    #[cfg(feature = "json-output")]
+    Json,
+    /// One JSON object per line.
+    #[cfg(feature = "json-output")]
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// Function will parse the name of a format, as passed to `--output-format`.
+    fn parse(name: &str) -> io::Result<Self> {
+        match name {
+            "csv" => Ok(OutputFormat::Csv),
+            #[cfg(feature = "json-output")]
+            "json" => Ok(OutputFormat::Json),
+            #[cfg(feature = "json-output")]
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported output format {}", name),
+            )),
+        }
+    }
+
+    /// Returns the file extension of the format.
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            #[cfg(feature = "json-output")]
+            OutputFormat::Json => "json",
+            #[cfg(feature = "json-output")]
+            OutputFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Enum represents a writer of rows in one of the output formats.
+enum RowWriter<W: io::Write> {
+    Csv(Box<csv::Writer<W>>),
+    #[cfg(feature = "json-output")]
+    Json {
+        writer: io::BufWriter<W>,
+        rows: usize,
+    },
+    #[cfg(feature = "json-output")]
+    Jsonl(io::BufWriter<W>),
+}
+
+impl<W: io::Write> RowWriter<W> {
+    fn new(format: OutputFormat, writer: W) -> Self {
+        match format {
+            OutputFormat::Csv => RowWriter::Csv(Box::new(csv::Writer::from_writer(writer))),
+            #[cfg(feature = "json-output")]
+            OutputFormat::Json => RowWriter::Json {
+                writer: io::BufWriter::new(writer),
+                rows: 0,
+            },
+            #[cfg(feature = "json-output")]
+            OutputFormat::Jsonl => RowWriter::Jsonl(io::BufWriter::new(writer)),
+        }
+    }
+
+    /// Function will write a single row.
+    fn serialize<T: Serialize>(&mut self, row: T) -> io::Result<()> {
+        match self {
+            RowWriter::Csv(writer) => Ok(writer.serialize(row)?),
+            #[cfg(feature = "json-output")]
+            RowWriter::Json { writer, rows } => {
+                writer.write_all(if *rows == 0 { b"[" } else { b"," })?;
+                *rows += 1;
+                Ok(serde_json::to_writer(writer, &row)?)
+            }
+            #[cfg(feature = "json-output")]
+            RowWriter::Jsonl(writer) => {
+                serde_json::to_writer(&mut *writer, &row)?;
+                writer.write_all(b"\n")
+            }
+        }
+    }
+
+    /// Function will terminate the output and flush it.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            RowWriter::Csv(mut writer) => writer.flush(),
+            #[cfg(feature = "json-output")]
+            RowWriter::Json { mut writer, rows } => {
+                if rows == 0 {
+                    writer.write_all(b"[")?;
+                }
+                writer.write_all(b"]\n")?;
+                writer.flush()
+            }
+            #[cfg(feature = "json-output")]
+            RowWriter::Jsonl(mut writer) => writer.flush(),
+        }
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+
+        let mut source = e.source();
+        while let Some(cause) = source {
+            eprintln!("  Caused by: {}", cause);
+            source = cause.source();
+        }
+
+        process::exit(1);
+    }
+}
+
+fn run() -> io::Result<()> {
+    let mut args = env::args_os();
+    let exe = args.next().unwrap_or_default();
+
+    let mut inputs = Vec::new();
+    let mut errors_out = None;
+    let mut status_column = false;
+    let mut hold_columns = false;
+    let mut base_currency = None;
+    let mut daily_snapshots = None;
+    let mut format = OutputFormat::Csv;
+    while let Some(arg) = args.next() {
+        #[cfg(feature = "server")]
+        if arg == "serve" && inputs.is_empty() {
+            return serve(args);
+        }
+        #[cfg(feature = "grpc")]
+        if arg == "serve-grpc" && inputs.is_empty() {
+            return serve_grpc(args);
+        }
+        #[cfg(feature = "schemars")]
+        if arg == "schema" && inputs.is_empty() {
+            return schema(args);
+        }
+
+        if arg == "--errors-out" {
+            errors_out = args.next().map(PathBuf::from);
+        } else if arg == "--status-column" {
+            status_column = true;
+        } else if arg == "--hold-columns" {
+            hold_columns = true;
+        } else if arg == "--base-currency" {
+            let code = args.next().unwrap_or_default();
+            let currency = code
+                .to_string_lossy()
+                .parse::<Currency>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            base_currency = Some(currency);
+        } else if arg == "--daily-snapshots" {
+            daily_snapshots = args.next().map(PathBuf::from);
+        } else if arg == "--output-format" {
+            format = OutputFormat::parse(&args.next().unwrap_or_default().to_string_lossy())?;
+        } else {
+            inputs.push(PathBuf::from(arg));
+        }
+    }
+
+    if inputs.is_empty() {
+        eprintln!("Corken Payments Engine\n");
+        eprintln!(
+            "USAGE:\n    {} <input_file>... [--errors-out <errors_file>] [--status-column | \
+             --hold-columns | --base-currency <currency>] [--daily-snapshots <dir>] \
+             [--output-format <csv|json|jsonl>]\n",
+            exe.to_string_lossy()
+        );
+        #[cfg(feature = "server")]
+        eprintln!(
+            "    {} serve [--listen <address>] [--initial-balances <balances_file>] \
+             [--snapshot <snapshot_file>]\n",
+            exe.to_string_lossy()
+        );
+        #[cfg(feature = "grpc")]
+        eprintln!(
+            "    {} serve-grpc [--listen <address>] [--snapshot <snapshot_file>]\n",
+            exe.to_string_lossy()
+        );
+        #[cfg(feature = "schemars")]
+        eprintln!(
+            "    {} schema --type <{}>\n",
+            exe.to_string_lossy(),
+            corken::schema::SCHEMA_TYPES.join("|")
+        );
+
+        process::exit(1);
+    }
+
+    if [status_column, hold_columns, base_currency.is_some()]
+        .iter()
+        .filter(|x| **x)
+        .count()
+        > 1
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--status-column, --hold-columns and --base-currency cannot be combined",
+        ));
+    }
+
+    if daily_snapshots.is_some() && (base_currency.is_some() || inputs.len() > 1) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--daily-snapshots requires a single input and cannot be combined with --base-currency",
+        ));
+    }
+
+    let open = |path: &Path| {
+        File::open(path).map_err(|source| {
+            io::Error::new(
+                source.kind(),
+                OpenError {
+                    path: path.to_path_buf(),
+                    source,
+                },
+            )
+        })
+    };
+
+    // NOTE: `--base-currency` segregates balances per currency, writing one row per client and
+    // currency with a `currency` column. Several inputs are replayed back to back.
+    if let Some(base) = base_currency {
+        let readers = inputs
+            .iter()
+            .map(|path| {
+                Ok(csv::ReaderBuilder::new()
+                    .trim(csv::Trim::All)
+                    .from_reader(open(path)?))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let txs = readers.into_iter().flat_map(|x| x.into_deserialize());
+        let (state, failures) = MultiCurrencyState::from_fallible_iterator(base, txs);
+        for failure in failures.iter() {
+            eprintln!("Skipped transaction {}", failure);
+        }
+
+        write_errors(errors_out, &failures)?;
+
+        let mut writer = RowWriter::new(format, io::stdout());
+        state
+            .accounts()
+            .iter()
+            .try_for_each(|x| writer.serialize(x))?;
+        writer.finish()?;
+
+        return Ok(());
+    }
+
+    let (state, failures) = if let [input] = &inputs[..] {
+        // NOTE: csv wraps all streams in BufReader.
+        let csv_rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(open(input)?);
 
-        [exe] => {
-            eprintln!("Corken Payments Engine\n");
-            eprintln!("USAGE:\n    {} <input_file>\n", exe.to_string_lossy());
+        // NOTE: `--daily-snapshots` writes the accounts as of the end of every day found in the
+        // timestamps of the input into `<dir>/<date>.csv`, in the same format as the output.
+        let (state, failures) = match &daily_snapshots {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)?;
 
-            process::exit(1);
+                let mut result = Ok(());
+                let (state, failures) = State::from_fallible_iterator_with_periods(
+                    csv_rdr.into_deserialize(),
+                    PeriodSpec::Daily,
+                    |period, accounts| {
+                        if result.is_ok() {
+                            let path = dir.join(format!("{}.{}", period, format.extension()));
+                            result = File::create(path).and_then(|file| {
+                                let mut writer = RowWriter::new(format, file);
+                                write_accounts(
+                                    &mut writer,
+                                    &accounts,
+                                    status_column,
+                                    hold_columns,
+                                )?;
+                                writer.finish()
+                            });
+                        }
+                    },
+                );
+                result?;
+
+                (state, failures)
+            }
+            None => State::from_fallible_iterator(csv_rdr.into_deserialize()),
+        };
+        for failure in failures.iter() {
+            eprintln!("Skipped transaction {}", failure);
+        }
+
+        (state, failures)
+    } else {
+        // NOTE: Several inputs are replayed in order, reporting the transaction ids they disagree
+        // on.
+        let files = inputs
+            .iter()
+            .map(|path| Ok((path.display().to_string(), open(path)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let (state, report) = State::from_csv_inputs(files);
+        for (input, failure) in report.failures.iter() {
+            eprintln!("Skipped transaction of {} {}", input, failure);
+        }
+        for conflict in report.conflicts.iter() {
+            eprintln!("Conflicting transaction {}", conflict);
         }
 
-        [_, first, ..] => File::open(Path::new(first))?,
+        (state, report.failures.into_iter().map(|(_, x)| x).collect())
     };
 
-    // NOTE: csv wraps all streams in BufReader.
-    let csv_rdr = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .from_reader(input_file);
+    write_errors(errors_out, &failures)?;
 
-    let state = State::from_iterator(csv_rdr.into_deserialize().filter_map(Result::ok));
+    let mut writer = RowWriter::new(format, io::stdout());
+    write_accounts(&mut writer, state.accounts(), status_column, hold_columns)?;
+    writer.finish()
+}
 
-    let mut writer = csv::WriterBuilder::new().from_writer(io::stdout());
+/// Function will write `accounts` into `writer`.
+///
+/// # Notes
+/// `status_column` writes `active`/`frozen` in a `status` column instead of `locked`, while
+/// `hold_columns` adds `pending_hold` and `disputed_hold` columns ahead of `held`.
+fn write_accounts<'a, W: io::Write>(
+    writer: &mut RowWriter<W>,
+    accounts: impl IntoIterator<Item = &'a Account>,
+    status_column: bool,
+    hold_columns: bool,
+) -> io::Result<()> {
+    for account in accounts {
+        if status_column {
+            writer.serialize(account.with_status())?;
+        } else if hold_columns {
+            writer.serialize(account.with_hold_columns())?;
+        } else {
+            writer.serialize(account)?;
+        }
+    }
 
-    state
-        .accounts()
-        .try_for_each(|x| writer.serialize(x))
-        .expect("Failed to serialize accounts.");
+    Ok(())
+}
+
+/// Function will write every skipped transaction into the CSV file at `path`, if set.
+fn write_errors(path: Option<PathBuf>, failures: &[FallibleFailure<csv::Error>]) -> io::Result<()> {
+    if let Some(path) = path {
+        let mut writer = csv::Writer::from_path(path)?;
+        failures
+            .iter()
+            .try_for_each(|x| writer.serialize(RejectionRecord::from(x)))?;
+        writer.flush()?;
+    }
 
     Ok(())
 }
+
+/// Function will run the HTTP service until it receives a ctrl-c or a SIGTERM.
+///
+/// # Arguments
+/// * `--listen` - Address to listen on, `127.0.0.1:8080` by default.
+/// * `--initial-balances` - CSV file of accounts, in the format written by this binary, to start
+///   from.
+/// * `--snapshot` - Checkpoint file restored on startup, if it exists, and written on shutdown.
+#[cfg(feature = "server")]
+fn serve(mut args: impl Iterator<Item = OsString>) -> io::Result<()> {
+    use corken::server;
+
+    let mut listen = String::from("127.0.0.1:8080");
+    let mut initial_balances = None;
+    let mut snapshot = None;
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Missing value for {}", arg.to_string_lossy()),
+                )
+            })
+        };
+
+        match arg.to_str() {
+            Some("--listen") => listen = value()?.to_string_lossy().into_owned(),
+            Some("--initial-balances") => initial_balances = Some(PathBuf::from(value()?)),
+            Some("--snapshot") => snapshot = Some(PathBuf::from(value()?)),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unexpected argument {}", arg.to_string_lossy()),
+                ))
+            }
+        }
+    }
+
+    let restore = snapshot.as_deref().filter(|x| x.exists());
+    let state = match (restore, initial_balances) {
+        (Some(_), Some(_)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Initial balances cannot be loaded on top of an existing snapshot",
+            ))
+        }
+        (Some(path), None) => State::load_checkpoint_from_file(path).map_err(io::Error::other)?,
+        (None, Some(path)) => server::load_initial_balances(path).map_err(io::Error::other)?,
+        (None, None) => State::default(),
+    };
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(&listen).await?;
+        eprintln!("Listening on {}", listener.local_addr()?);
+
+        let state = std::sync::Arc::new(std::sync::Mutex::new(state));
+        server::serve(listener, state, snapshot.as_deref(), shutdown_signal()).await
+    })
+}
+
+/// Function will run the gRPC service until it receives a ctrl-c or a SIGTERM.
+///
+/// # Arguments
+/// * `--listen` - Address to listen on, `127.0.0.1:50051` by default.
+/// * `--snapshot` - Checkpoint file restored on startup, if it exists, and written on shutdown.
+#[cfg(feature = "grpc")]
+fn serve_grpc(mut args: impl Iterator<Item = OsString>) -> io::Result<()> {
+    use corken::grpc;
+
+    let mut listen = String::from("127.0.0.1:50051");
+    let mut snapshot = None;
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Missing value for {}", arg.to_string_lossy()),
+                )
+            })
+        };
+
+        match arg.to_str() {
+            Some("--listen") => listen = value()?.to_string_lossy().into_owned(),
+            Some("--snapshot") => snapshot = Some(PathBuf::from(value()?)),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unexpected argument {}", arg.to_string_lossy()),
+                ))
+            }
+        }
+    }
+
+    let state = match snapshot.as_deref().filter(|x| x.exists()) {
+        Some(path) => State::load_checkpoint_from_file(path).map_err(io::Error::other)?,
+        None => State::default(),
+    };
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(&listen).await?;
+        eprintln!("Listening on {}", listener.local_addr()?);
+
+        let state = std::sync::Arc::new(std::sync::Mutex::new(state));
+        grpc::serve(listener, state, snapshot.as_deref(), shutdown_signal()).await
+    })
+}
+
+/// Function will print the JSON Schema of one of the documents read or written by corken.
+///
+/// # Arguments
+/// * `--type` - Name of the document, one of `corken::schema::SCHEMA_TYPES`.
+#[cfg(feature = "schemars")]
+fn schema(mut args: impl Iterator<Item = OsString>) -> io::Result<()> {
+    use corken::schema;
+
+    let mut name = None;
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--type") => name = args.next(),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unexpected argument {}", arg.to_string_lossy()),
+                ))
+            }
+        }
+    }
+
+    let name = name
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Missing value for --type"))?;
+    let schema = schema::schema_by_name(&name.to_string_lossy()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Unknown schema type {}, expected one of {}",
+                name.to_string_lossy(),
+                schema::SCHEMA_TYPES.join(", ")
+            ),
+        )
+    })?;
+
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &schema)?;
+    writeln!(stdout)
+}
+
+/// Function completes once the process receives a ctrl-c or a SIGTERM.
+#[cfg(any(feature = "server", feature = "grpc"))]
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
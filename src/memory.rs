@@ -0,0 +1,86 @@
+//! This module contains helpers used to estimate the memory consumed by the state machine.
+
+use super::*;
+use std::mem;
+
+/// Struct represents an estimate of the heap memory consumed by a state machine, in bytes.
+///
+/// # Notes
+/// The estimate only accounts for the entries stored in the state machine, so it is a lower bound.
+/// The overhead of the hash tables themselves (unused buckets, control bytes and the slack left by
+/// growing in powers of two) is ignored, and can add up to roughly twice the estimate right after a
+/// table has grown. The ids remembered in idempotent mode are not included either.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Estimated bytes used by the accounts.
+    pub accounts_bytes: usize,
+    /// Estimated bytes used by the cached transactions.
+    pub tx_cache_bytes: usize,
+    /// Estimated bytes used by the statements of the accounts. Always zero unless the crate is
+    /// built with the `statement-logging` feature.
+    pub statements_bytes: usize,
+    /// Sum of all the estimates above.
+    pub total_bytes: usize,
+}
+
+/// Returns the size of a single entry of the transaction cache.
+pub(crate) fn size_of_tx_cache_entry<A>() -> usize {
+    mem::size_of::<(u32, CachedTx<A>)>()
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will estimate the heap memory consumed by the state machine. See `MemoryUsage`
+    /// for the caveats of the estimate.
+    pub fn estimate_memory_usage(&self) -> MemoryUsage {
+        let accounts_bytes = self.accounts.len() * mem::size_of::<(u16, Account<A>)>();
+        let tx_cache_bytes = self.tx_cache.len() * size_of_tx_cache_entry::<A>();
+
+        #[cfg(feature = "statement-logging")]
+        let statements_bytes = self.statements_bytes();
+        #[cfg(not(feature = "statement-logging"))]
+        let statements_bytes = 0;
+
+        MemoryUsage {
+            accounts_bytes,
+            tx_cache_bytes,
+            statements_bytes,
+            total_bytes: accounts_bytes + tx_cache_bytes + statements_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    #[test]
+    fn test_estimate_memory_usage() {
+        let mut state = State::default();
+        assert_eq!(state.estimate_memory_usage(), MemoryUsage::default());
+
+        for client in 1..=10 {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(1.0),
+                    },
+                    client,
+                    tx: client as u32,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
+        let usage = state.estimate_memory_usage();
+        assert_eq!(usage.accounts_bytes, 10 * mem::size_of::<(u16, Account)>());
+        assert_eq!(usage.tx_cache_bytes, 10 * size_of_tx_cache_entry::<f64>());
+        assert_eq!(
+            usage.total_bytes,
+            usage.accounts_bytes + usage.tx_cache_bytes + usage.statements_bytes
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! This module contains the ingestion of large CSV files through a memory map, built with the
+//! `mmap` feature.
+//!
+//! Files with the standard `type, client, tx, amount` header are parsed in place, splitting rows on
+//! commas without copying them. Anything the fast path doesnt understand, such as quoted fields or
+//! other columns, is handed to the `csv` crate instead.
+
+use super::*;
+use crate::ingest::{parse_record, HEADERS};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::str;
+
+/// Function will split a line into its fields, trimming the trailing carriage return. Fields past
+/// the fourth are reported as a single extra field.
+fn split_fields(line: &[u8]) -> Result<[Option<&str>; 4], RecordError> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let line = str::from_utf8(line).map_err(|_| RecordError::InvalidField {
+        field: HEADERS[0],
+        reason: "not valid UTF-8".into(),
+    })?;
+
+    let mut fields = [None; 4];
+    let mut split = line.split(',');
+    for field in fields.iter_mut() {
+        *field = split.next();
+    }
+
+    match split.next() {
+        Some(_) => Err(RecordError::InvalidField {
+            field: HEADERS[3],
+            reason: "too many fields".into(),
+        }),
+        None => Ok(fields),
+    }
+}
+
+/// Function will parse a line of the standard four columns, falling back to the `csv` crate for
+/// lines with quoted fields.
+fn parse_line(line: &[u8]) -> Result<Transaction, RecordError> {
+    if line.contains(&b'"') {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(line);
+
+        let mut record = csv::ByteRecord::new();
+        return match rdr.read_byte_record(&mut record) {
+            Ok(true) => Transaction::try_from(&record),
+            _ => Err(RecordError::MissingField(HEADERS[0])),
+        };
+    }
+
+    let fields = split_fields(line)?;
+    parse_record(|i| Ok(fields[i]))
+}
+
+/// Function returns whether `line` is the standard `type, client, tx, amount` header.
+fn is_standard_header(line: &[u8]) -> bool {
+    match split_fields(line) {
+        Ok(fields) => fields
+            .iter()
+            .zip(HEADERS.iter())
+            .all(|(field, header)| field.map(str::trim) == Some(*header)),
+        Err(_) => false,
+    }
+}
+
+/// Function returns an iterator over the transactions of a CSV document with a header row,
+/// parsing the rows in place if the header is the standard one. Malformed rows are skipped.
+fn transactions(data: &[u8]) -> Box<dyn Iterator<Item = Transaction> + '_> {
+    let mut lines = data.split(|x| *x == b'\n');
+    if !lines.next().is_some_and(is_standard_header) {
+        let rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(data);
+
+        return Box::new(rdr.into_deserialize().filter_map(Result::ok));
+    }
+
+    Box::new(
+        lines
+            .filter(|x| !x.trim_ascii().is_empty())
+            .filter_map(|x| parse_line(x).ok()),
+    )
+}
+
+impl State {
+    /// Function will construct the state machine and replay all the transactions of the CSV file
+    /// at `path`, memory mapping it rather than reading it through a buffer. Rejected and
+    /// malformed rows are skipped, as with `from_iterator`.
+    ///
+    /// # Returns
+    /// Returns `CorkenError::Io` if the file cannot be opened. Files that cannot be memory mapped,
+    /// such as pipes, are read through a buffered reader instead.
+    ///
+    /// # Notes
+    /// The file must not be modified while it is being replayed.
+    pub fn from_mmap_csv(path: &Path) -> error::Result<Self> {
+        let file = File::open(path)?;
+
+        // SAFETY: The map is only read from, and the caller guarantees that the file isnt
+        // modified for as long as it is mapped.
+        let map = match unsafe { Mmap::map(&file) } {
+            Ok(map) => map,
+            Err(_) => {
+                let rdr = csv::ReaderBuilder::new()
+                    .trim(csv::Trim::All)
+                    .from_reader(io::BufReader::new(file));
+
+                return Ok(Self::from_iterator(
+                    rdr.into_deserialize().filter_map(Result::ok),
+                ));
+            }
+        };
+
+        Ok(Self::from_iterator(transactions(&map)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::workload;
+    use std::io::Write;
+
+    /// Returns the accounts of `state` in ascending order of their id.
+    fn accounts(state: &State) -> Vec<Account> {
+        state.accounts_iter_by_id().cloned().collect()
+    }
+
+    #[test]
+    fn test_matches_buffered_reader() {
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        wtr.write_record(HEADERS).unwrap();
+        for tx in workload(1000) {
+            let amount = match tx.tx_type {
+                TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                    amount.to_string()
+                }
+                _ => String::new(),
+            };
+            let tag = tx.tx_type.tag().to_string();
+            wtr.write_record([tag, tx.client.to_string(), tx.tx.to_string(), amount])
+                .unwrap();
+        }
+        let data = wtr.into_inner().unwrap();
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&data).unwrap();
+
+        let rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(&data[..]);
+        let expected = State::from_iterator(rdr.into_deserialize().filter_map(Result::ok));
+
+        let state = State::from_mmap_csv(file.path()).unwrap();
+        assert_eq!(accounts(&state), accounts(&expected));
+    }
+
+    #[test]
+    fn test_rows() {
+        let data = "type, client, tx, amount\r\n\
+                    deposit, 1, 1, 10.0\r\n\
+                    \n\
+                    deposit,2,2,\"2.5\"\n\
+                    withdrawal, 1, 3, 1.0, 7\n\
+                    deposit, x, 4, 1.0\n\
+                    dispute, 1, 1,\n\
+                    deposit, 2, 5, 1.0";
+        let state = State::from_iterator(transactions(data.as_bytes()));
+        assert_eq!(state.accounts[&1].held, 10.0);
+        assert_eq!(state.accounts[&2].available, 3.5);
+
+        // Other headers go through the `csv` crate.
+        let data = "client,type,amount,tx\n1,deposit,4.0,1\n";
+        let state = State::from_iterator(transactions(data.as_bytes()));
+        assert_eq!(state.accounts[&1].available, 4.0);
+    }
+
+    #[test]
+    fn test_fallback() {
+        assert!(matches!(
+            State::from_mmap_csv(Path::new("/nonexistent/transactions.csv")),
+            Err(CorkenError::Io(_))
+        ));
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert_eq!(State::from_mmap_csv(file.path()).unwrap().accounts.len(), 0);
+    }
+}
@@ -0,0 +1,497 @@
+//! This module contains the trait abstracting over the representation of monetary amounts.
+
+use super::*;
+use crate::validate::ValidationError;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use rust_decimal::RoundingStrategy;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::ops;
+use std::str::FromStr;
+
+/// Trait implemented by the types the state machine can use to represent monetary amounts.
+///
+/// Implementations are provided for `f64`, the default, and for `rust_decimal::Decimal`. Floats
+/// can drift by a few ulps after many operations, so balances are compared with a tolerance of
+/// half a `MINOR_UNIT`, while decimals are exact and compared as is.
+pub trait Money:
+    Copy
+    + Default
+    + fmt::Debug
+    + fmt::Display
+    + PartialEq
+    + PartialOrd
+    + ops::Add<Output = Self>
+    + ops::Sub<Output = Self>
+    + ops::Neg<Output = Self>
+    + ops::AddAssign
+    + ops::SubAssign
+    + FromStr
+    + MaybeSerde
+    + Send
+    + Sync
+    + 'static
+{
+    /// The zero amount.
+    const ZERO: Self;
+
+    /// Returns whether the amount is strictly below zero.
+    fn is_negative(&self) -> bool;
+
+    /// Returns whether the amount is a finite number. Types that cannot represent infinities or
+    /// NaNs always return `true`.
+    fn is_finite(&self) -> bool;
+
+    /// Returns the largest difference between two amounts that is still considered equal.
+    fn tolerance() -> Self;
+
+    /// Returns whether the amount is within `tolerance` of zero.
+    fn is_negligible(&self) -> bool;
+
+    /// Returns the absolute value of the amount.
+    fn abs(self) -> Self;
+
+    /// Function will convert `x` into an amount, returning `None` if it cannot be represented.
+    fn from_f64(x: f64) -> Option<Self>;
+
+    /// Returns the number of significant decimal places of the amount.
+    fn decimal_places(&self) -> u32;
+
+    /// Returns the amount multiplied by a rate of `rate_bps` basis points, i.e. hundredths of a
+    /// percent, without any rounding.
+    fn mul_bps(self, rate_bps: u32) -> Self;
+
+    /// Returns the amount rounded to `decimal_places` using `mode`.
+    fn round_to(self, decimal_places: u32, mode: RoundingMode) -> Self;
+}
+
+/// Struct represents an amount that has been checked to be finite and non-negative, as carried
+/// by deposits, withdrawals, refunds and manual holds. Negative amounts would flip the direction
+/// funds move in, e.g. turning a deposit into a withdrawal that skips the funds check.
+///
+/// Deposits and withdrawals carry an `Amount`, so they can only be built with a valid amount.
+/// Refunds and manual holds carry a raw amount, which is checked the same way by
+/// `Transaction::validate` and `State::execute`.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Amount<A = f64>(A);
+
+impl<A: Money> Amount<A> {
+    /// Function will check `amount`.
+    ///
+    /// # Returns
+    /// Returns `ValidationError::NonFiniteAmount` if `amount` is infinite or not a number, and
+    /// `ValidationError::NegativeAmount` if it is negative. Zero is a valid amount.
+    pub fn new(amount: A) -> Result<Self, ValidationError> {
+        if !amount.is_finite() {
+            return Err(ValidationError::NonFiniteAmount);
+        }
+
+        if amount.is_negative() {
+            return Err(ValidationError::NegativeAmount);
+        }
+
+        Ok(Self(amount))
+    }
+
+    /// Returns the checked amount.
+    pub fn get(self) -> A {
+        self.0
+    }
+}
+
+impl<A: fmt::Display> fmt::Display for Amount<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Returns `amount`, with negative zero replaced by zero. Some consumers of the output treat `-0.0`
+/// as distinct from `0.0`, so balances are passed through this before they are serialized.
+#[cfg(feature = "serde")]
+pub(crate) fn without_negative_zero<A: Money>(amount: A) -> A {
+    if amount == A::ZERO {
+        A::ZERO
+    } else {
+        amount
+    }
+}
+
+/// Enum represents how amounts computed by the state machine, e.g. accrued interest, are rounded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    /// Ties are rounded to the nearest even digit, also known as bankers rounding.
+    #[default]
+    HalfEven,
+    /// Ties are rounded away from zero.
+    HalfUp,
+    /// Amounts are truncated towards zero.
+    Down,
+}
+
+/// Trait requiring `Serialize` and `DeserializeOwned` when the `serde` feature is enabled, and
+/// nothing otherwise.
+#[cfg(feature = "serde")]
+pub trait MaybeSerde: Serialize + DeserializeOwned {}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize + DeserializeOwned> MaybeSerde for T {}
+
+/// Trait requiring `Serialize` and `DeserializeOwned` when the `serde` feature is enabled, and
+/// nothing otherwise.
+#[cfg(not(feature = "serde"))]
+pub trait MaybeSerde {}
+
+#[cfg(not(feature = "serde"))]
+impl<T> MaybeSerde for T {}
+
+impl Money for f64 {
+    const ZERO: Self = 0.0;
+
+    fn is_negative(&self) -> bool {
+        *self < 0.0
+    }
+
+    fn is_finite(&self) -> bool {
+        f64::is_finite(*self)
+    }
+
+    fn tolerance() -> Self {
+        MINOR_UNIT / 2.0
+    }
+
+    fn is_negligible(&self) -> bool {
+        f64::abs(*self) < Self::tolerance()
+    }
+
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+
+    fn from_f64(x: f64) -> Option<Self> {
+        Some(x)
+    }
+
+    fn decimal_places(&self) -> u32 {
+        // NOTE: `Display` renders the shortest representation that round trips, which has the
+        // same decimal places as the literal the amount was parsed from.
+        let repr = self.to_string();
+        repr.find('.').map_or(0, |i| (repr.len() - i - 1) as u32)
+    }
+
+    fn mul_bps(self, rate_bps: u32) -> Self {
+        self * f64::from(rate_bps) / 10_000.0
+    }
+
+    fn round_to(self, decimal_places: u32, mode: RoundingMode) -> Self {
+        // NOTE: Scaling isnt exact either, so amounts sitting a few ulps off a whole number or a
+        // tie are snapped onto it before rounding, the same way balances are compared with a
+        // tolerance.
+        let scale = 10f64.powi(decimal_places as i32);
+        let scaled = self * scale;
+        let distance = (scaled - scaled.round()).abs();
+        let scaled = if distance < 1e-9 {
+            scaled.round()
+        } else if (distance - 0.5).abs() < 1e-9 {
+            scaled.trunc() + 0.5 * scaled.signum()
+        } else {
+            scaled
+        };
+
+        let rounded = match mode {
+            RoundingMode::HalfEven => scaled.round_ties_even(),
+            RoundingMode::HalfUp => scaled.round(),
+            RoundingMode::Down => scaled.trunc(),
+        };
+
+        rounded / scale
+    }
+}
+
+impl Money for Decimal {
+    const ZERO: Self = Decimal::ZERO;
+
+    fn is_negative(&self) -> bool {
+        self.is_sign_negative() && !self.is_zero()
+    }
+
+    fn is_finite(&self) -> bool {
+        true
+    }
+
+    fn tolerance() -> Self {
+        Decimal::ZERO
+    }
+
+    fn is_negligible(&self) -> bool {
+        self.is_zero()
+    }
+
+    fn abs(self) -> Self {
+        Decimal::abs(&self)
+    }
+
+    fn from_f64(x: f64) -> Option<Self> {
+        <Decimal as FromPrimitive>::from_f64(x)
+    }
+
+    fn decimal_places(&self) -> u32 {
+        self.normalize().scale()
+    }
+
+    fn mul_bps(self, rate_bps: u32) -> Self {
+        self * Decimal::from(rate_bps) / Decimal::from(10_000)
+    }
+
+    fn round_to(self, decimal_places: u32, mode: RoundingMode) -> Self {
+        let strategy = match mode {
+            RoundingMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+            RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            RoundingMode::Down => RoundingStrategy::ToZero,
+        };
+
+        self.round_dp_with_strategy(decimal_places, strategy)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{checked, workload};
+
+    /// Converts a transaction over `f64` amounts into one over `A`.
+    fn convert<A: Money>(tx: &Transaction) -> Transaction<A> {
+        let amount = |x| A::from_f64(x).unwrap();
+        let tx_type = match tx.tx_type {
+            TransactionType::Deposit { amount: x } => TransactionType::Deposit {
+                amount: checked(amount(x.get())),
+            },
+            TransactionType::Withdrawal { amount: x } => TransactionType::Withdrawal {
+                amount: checked(amount(x.get())),
+            },
+            TransactionType::Dispute => TransactionType::Dispute,
+            TransactionType::Resolve => TransactionType::Resolve,
+            TransactionType::Chargeback => TransactionType::Chargeback,
+            TransactionType::Interest { rate_bps } => TransactionType::Interest { rate_bps },
+            TransactionType::Refund { of_tx, amount: x } => TransactionType::Refund {
+                of_tx,
+                amount: amount(x),
+            },
+            TransactionType::Hold { amount: x } => TransactionType::Hold { amount: amount(x) },
+            TransactionType::Release { of_tx } => TransactionType::Release { of_tx },
+            TransactionType::Other { .. } => unreachable!(),
+        };
+
+        Transaction {
+            tx_type,
+            client: tx.client,
+            tx: tx.tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    fn decimal(x: &str) -> Decimal {
+        x.parse().unwrap()
+    }
+
+    #[test]
+    fn test_amount() {
+        for x in [0.0, -0.0, 1.5, 0.0001, f64::MAX] {
+            assert_eq!(Amount::new(x).map(Amount::get), Ok(x));
+        }
+        assert_eq!(Amount::new(-1.0), Err(ValidationError::NegativeAmount));
+        assert_eq!(Amount::new(-0.0001), Err(ValidationError::NegativeAmount));
+        for x in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(Amount::new(x), Err(ValidationError::NonFiniteAmount));
+        }
+
+        assert_eq!(
+            Amount::new(decimal("-0")).map(Amount::get),
+            Ok(Decimal::ZERO)
+        );
+        assert_eq!(
+            Amount::new(decimal("-0.1")),
+            Err(ValidationError::NegativeAmount)
+        );
+
+        let mut state = State::default();
+        let tx = |tx_type, tx| Transaction::new(tx_type, 1, tx);
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+            ))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(2.5),
+                },
+                2,
+            ))
+            .unwrap();
+        assert_eq!(state.accounts[&1].available, 7.5);
+        assert_eq!(checked(2.5).to_string(), "2.5");
+    }
+
+    #[test]
+    fn test_decimal_matches_f64() {
+        let txs = workload(10_000);
+
+        let mut float = State::default();
+        let mut exact = StateBuilder::<Decimal>::default().build().unwrap();
+        for tx in txs.iter() {
+            assert_eq!(
+                float.execute(tx.clone()),
+                exact.execute(convert(tx)),
+                "{:?}",
+                tx
+            );
+        }
+
+        assert_eq!(float.accounts().count(), exact.accounts().count());
+        for account in float.accounts() {
+            let other = exact.accounts.get(&account.id).unwrap();
+            assert_eq!(
+                <Decimal as Money>::from_f64(account.available),
+                Some(other.available)
+            );
+            assert_eq!(<Decimal as Money>::from_f64(account.held), Some(other.held));
+            assert_eq!(
+                <Decimal as Money>::from_f64(account.total),
+                Some(other.total)
+            );
+            assert_eq!(account.locked, other.locked);
+        }
+    }
+
+    #[test]
+    fn test_decimal_is_exact() {
+        let mut state = StateBuilder::<Decimal>::default().build().unwrap();
+        for (tx, amount) in [(1, "0.1"), (2, "0.2")] {
+            state
+                .execute(Transaction {
+                    tx_type: TransactionType::Deposit {
+                        amount: checked(decimal(amount)),
+                    },
+                    client: 1,
+                    tx,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                })
+                .unwrap();
+        }
+
+        state
+            .execute(Transaction {
+                tx_type: TransactionType::Withdrawal {
+                    amount: checked(decimal("0.3")),
+                },
+                client: 1,
+                tx: 3,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            })
+            .unwrap();
+
+        let account = state.accounts.get(&1).unwrap();
+        assert_eq!(account.available, Decimal::ZERO);
+        assert_eq!(account.total, Decimal::ZERO);
+
+        // Without a tolerance even the smallest shortfall is rejected.
+        assert_eq!(
+            state.execute(Transaction {
+                tx_type: TransactionType::Withdrawal {
+                    amount: checked(decimal("0.0000000001")),
+                },
+                client: 1,
+                tx: 4,
+                currency: None,
+                timestamp: None,
+                row_id: None,
+                event_id: None,
+            }),
+            Err(TxError::NotEnoughFunds)
+        );
+    }
+
+    #[test]
+    fn test_invalid_amounts() {
+        // NOTE: Deposits and withdrawals cannot carry an invalid amount, the other amounts are
+        // still checked on execution.
+        let mut state = State::default();
+        for amount in [-1.0, f64::NAN, f64::INFINITY] {
+            assert!(Amount::new(amount).is_err());
+            assert_eq!(
+                state.execute(Transaction::new(TransactionType::Hold { amount }, 1, 1)),
+                Err(TxError::InternalError)
+            );
+        }
+
+        let mut state = StateBuilder::<Decimal>::default().build().unwrap();
+        assert!(Amount::new(decimal("-1")).is_err());
+        assert_eq!(
+            state.execute(Transaction::new(
+                TransactionType::Refund {
+                    of_tx: 1,
+                    amount: decimal("-1"),
+                },
+                1,
+                2
+            )),
+            Err(TxError::InternalError)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_decimal_csv_row() {
+        let mut state = StateBuilder::<Decimal>::default().build().unwrap();
+        state.execute_csv_row("deposit, 1, 1, 1.2345").unwrap();
+        state.execute_csv_row("withdrawal, 1, 2, 0.2345").unwrap();
+
+        assert_eq!(state.accounts.get(&1).unwrap().available, decimal("1"));
+    }
+
+    #[test]
+    fn test_round() {
+        let modes = [
+            RoundingMode::HalfEven,
+            RoundingMode::HalfUp,
+            RoundingMode::Down,
+        ];
+        let cases = [
+            ("123.3", 5, ["0.0616", "0.0617", "0.0616"]),
+            ("123.5", 5, ["0.0618", "0.0618", "0.0617"]),
+            ("-123.5", 5, ["-0.0618", "-0.0618", "-0.0617"]),
+            ("1000", 125, ["12.5", "12.5", "12.5"]),
+            ("0.0001", 1, ["0", "0", "0"]),
+        ];
+
+        for (amount, rate_bps, expected) in cases {
+            for (mode, expected) in modes.iter().zip(expected) {
+                let exact = decimal(amount).mul_bps(rate_bps).round_to(4, *mode);
+                assert_eq!(exact, decimal(expected), "{} {:?}", amount, mode);
+
+                let float = amount.parse::<f64>().unwrap().mul_bps(rate_bps);
+                assert_eq!(
+                    float.round_to(4, *mode),
+                    expected.parse::<f64>().unwrap(),
+                    "{} {:?}",
+                    amount,
+                    mode
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! This module contains the MessagePack encoding of snapshots and of the accounts and open
+//! disputes of the state machine, built with the `msgpack` feature, for consumers not written in
+//! Rust.
+//!
+//! Every struct is encoded as a map keyed by field name rather than as a tuple, so the documents
+//! can be read without knowing the field order.
+
+use super::*;
+use crate::snapshot::StateSnapshot;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+/// The current version of the export document format.
+pub const EXPORT_VERSION: u32 = 2;
+
+/// Struct represents the document written by `export_msgpack`, holding every account, open
+/// dispute and manual hold of the state machine, ordered by client id for the accounts and by
+/// transaction id for the rest.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+pub struct ExportDocument<A = f64> {
+    /// Version of the document format.
+    pub version: u32,
+    pub accounts: Vec<AccountRecord<A>>,
+    pub disputes: Vec<DisputeRecord<A>>,
+    pub holds: Vec<HoldRecord<A>>,
+}
+
+/// Struct represents an account in an `ExportDocument`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+pub struct AccountRecord<A = f64> {
+    pub client: u16,
+    pub available: A,
+    pub held: A,
+    pub total: A,
+    pub locked: bool,
+}
+
+/// Struct represents an open dispute in an `ExportDocument`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+pub struct DisputeRecord<A = f64> {
+    pub client: u16,
+    pub tx: u32,
+    /// Either `deposit` or `withdrawal`.
+    pub kind: String,
+    /// The amount of the disputed transaction.
+    pub amount: A,
+}
+
+/// Struct represents a manual hold that hasnt been released yet in an `ExportDocument`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+pub struct HoldRecord<A = f64> {
+    pub client: u16,
+    pub tx: u32,
+    /// The amount on hold.
+    pub amount: A,
+}
+
+impl<A: Money> From<&Account<A>> for AccountRecord<A> {
+    fn from(account: &Account<A>) -> Self {
+        Self {
+            client: account.id,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+        }
+    }
+}
+
+/// Function will map an encoding error to an IO error.
+fn encode_error(e: rmp_serde::encode::Error) -> CorkenError {
+    match e {
+        rmp_serde::encode::Error::InvalidValueWrite(e) => CorkenError::Io(e.into()),
+        e => CorkenError::Io(io::Error::other(e)),
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will write a snapshot of the state machine into `writer`, encoded as MessagePack.
+    /// The snapshot is the same versioned envelope as the one written by `to_bincode_writer`. The
+    /// writer isnt buffered.
+    ///
+    /// # Returns
+    /// Returns `CorkenError::Io` if writing fails.
+    pub fn save_snapshot_msgpack<W: Write>(&self, mut writer: W) -> error::Result<()> {
+        rmp_serde::encode::write_named(&mut writer, &self.snapshot()).map_err(encode_error)
+    }
+
+    /// Function returns every account, open dispute and manual hold of the state machine.
+    pub fn export_document(&self) -> ExportDocument<A> {
+        let mut accounts: Vec<_> = self.accounts.values().map(AccountRecord::from).collect();
+        accounts.sort_by_key(|x| x.client);
+
+        let mut disputes: Vec<_> = self
+            .tx_cache
+            .values()
+            .filter(|x| x.dispute == Some(DisputeState::Disputed))
+            .filter_map(|x| {
+                let (kind, amount) = match x.tx.tx_type {
+                    TransactionType::Deposit { amount } => ("deposit", amount.get()),
+                    TransactionType::Withdrawal { amount } => ("withdrawal", amount.get()),
+                    _ => return None,
+                };
+
+                Some(DisputeRecord {
+                    client: x.tx.client,
+                    tx: x.tx.tx,
+                    kind: kind.to_string(),
+                    amount,
+                })
+            })
+            .collect();
+        disputes.sort_by_key(|x| x.tx);
+
+        let mut holds: Vec<_> = self
+            .manual_holds
+            .iter()
+            .map(|(tx, hold)| HoldRecord {
+                client: hold.client,
+                tx: *tx,
+                amount: hold.amount,
+            })
+            .collect();
+        holds.sort_by_key(|x| x.tx);
+
+        ExportDocument {
+            version: EXPORT_VERSION,
+            accounts,
+            disputes,
+            holds,
+        }
+    }
+
+    /// Function will write the `export_document` of the state machine into `writer`, encoded as
+    /// MessagePack. The writer isnt buffered.
+    ///
+    /// # Returns
+    /// Returns `CorkenError::Io` if writing fails.
+    pub fn export_msgpack<W: Write>(&self, mut writer: W) -> error::Result<()> {
+        rmp_serde::encode::write_named(&mut writer, &self.export_document()).map_err(encode_error)
+    }
+}
+
+impl State {
+    /// Function will construct the state machine from a MessagePack snapshot read out of
+    /// `reader`, as written by `save_snapshot_msgpack`. The reader isnt buffered.
+    ///
+    /// # Returns
+    /// Returns `CorkenError::Snapshot` if the stream doesnt hold a valid snapshot, including one
+    /// created by an incompatible version of the state machine.
+    pub fn load_snapshot_msgpack<R: Read>(reader: R) -> error::Result<Self> {
+        let snapshot: StateSnapshot =
+            rmp_serde::from_read(reader).map_err(|e| SnapshotError::Corrupt(e.to_string()))?;
+
+        Ok(Self::from_snapshot(snapshot)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{checked, workload};
+    use rmpv::Value;
+
+    /// Returns the keys of a decoded map, or panics if `value` isnt a map.
+    fn keys(value: &Value) -> Vec<&str> {
+        value
+            .as_map()
+            .unwrap()
+            .iter()
+            .map(|(k, _)| k.as_str().unwrap())
+            .collect()
+    }
+
+    /// Returns the first element of the array held by `key` in a decoded map.
+    fn first<'a>(value: &'a Value, key: &str) -> &'a Value {
+        let array = value
+            .as_map()
+            .unwrap()
+            .iter()
+            .find(|(k, _)| k.as_str() == Some(key))
+            .and_then(|(_, v)| v.as_array())
+            .unwrap();
+
+        &array[0]
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let state = State::from_iterator(workload(1000).into_iter());
+
+        let mut buf = Vec::new();
+        state.save_snapshot_msgpack(&mut buf).unwrap();
+        let restored = State::load_snapshot_msgpack(&buf[..]).unwrap();
+        assert_eq!(restored.accounts, state.accounts);
+        assert_eq!(restored.tx_cache, state.tx_cache);
+        assert_eq!(restored.seq, state.seq);
+
+        assert!(matches!(
+            State::load_snapshot_msgpack(&buf[..buf.len() / 2]),
+            Err(CorkenError::Snapshot(SnapshotError::Corrupt(_)))
+        ));
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let mut state = State::default();
+        let txs = [
+            (
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            (
+                TransactionType::Deposit {
+                    amount: checked(2.5),
+                },
+                2,
+                2,
+            ),
+            (
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                2,
+                3,
+            ),
+            (TransactionType::Dispute, 2, 2),
+            (TransactionType::Hold { amount: 4.0 }, 1, 4),
+        ];
+        for (tx_type, client, tx) in txs {
+            state
+                .execute(Transaction::new(tx_type, client, tx))
+                .unwrap();
+        }
+
+        let mut buf = Vec::new();
+        state.export_msgpack(&mut buf).unwrap();
+        let document: ExportDocument = rmp_serde::from_slice(&buf).unwrap();
+        assert_eq!(document, state.export_document());
+        assert_eq!(
+            document.disputes,
+            [DisputeRecord {
+                client: 2,
+                tx: 2,
+                kind: "deposit".into(),
+                amount: 2.5,
+            }]
+        );
+        assert_eq!(document.accounts[1].held, 2.5);
+        assert_eq!(
+            document.holds,
+            [HoldRecord {
+                client: 1,
+                tx: 4,
+                amount: 4.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_field_names() {
+        let mut state = State::default();
+        state
+            .execute(Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        state
+            .execute(Transaction::new(TransactionType::Dispute, 1, 1))
+            .unwrap();
+
+        let mut buf = Vec::new();
+        state.export_msgpack(&mut buf).unwrap();
+        let document = rmpv::decode::read_value(&mut &buf[..]).unwrap();
+        assert_eq!(
+            keys(&document),
+            ["version", "accounts", "disputes", "holds"]
+        );
+        assert_eq!(
+            keys(first(&document, "accounts")),
+            ["client", "available", "held", "total", "locked"]
+        );
+        assert_eq!(
+            keys(first(&document, "disputes")),
+            ["client", "tx", "kind", "amount"]
+        );
+
+        let mut buf = Vec::new();
+        state.save_snapshot_msgpack(&mut buf).unwrap();
+        let snapshot = rmpv::decode::read_value(&mut &buf[..]).unwrap();
+        assert_eq!(
+            keys(&snapshot),
+            [
+                "version",
+                "accounts",
+                "tx_cache",
+                "refundable",
+                "manual_holds",
+                "seen",
+                "dispute_event_ids",
+                "seq",
+                "last_tx",
+                "balance_limits",
+                "default_balance_limit"
+            ]
+        );
+        assert_eq!(
+            keys(first(&snapshot, "tx_cache")),
+            [
+                "tx",
+                "client",
+                "kind",
+                "amount",
+                "dispute",
+                "dispute_event_id",
+                "seq"
+            ]
+        );
+    }
+}
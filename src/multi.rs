@@ -0,0 +1,229 @@
+//! This module contains the ingestion of transactions split across several CSV inputs, reporting
+//! the transaction ids that different inputs disagree on.
+
+use super::*;
+use crate::fallible::{FailureKind, FallibleFailure};
+use std::fmt;
+use std::io;
+
+/// Struct represents where a transaction was read from and what it contained.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxOrigin {
+    /// The name of the input the transaction was read from.
+    pub input: String,
+    /// Zero based position of the row in the input, not counting the header row.
+    pub position: usize,
+    pub client: u16,
+    /// The `type` of the transaction.
+    pub tag: String,
+    pub amount: f64,
+}
+
+/// Struct represents a deposit or withdrawal id that appeared in two different inputs with a
+/// different client, type or amount.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxConflict {
+    pub tx: u32,
+    /// The first occurence of the transaction id.
+    pub first: TxOrigin,
+    /// The conflicting occurence.
+    pub second: TxOrigin,
+}
+
+/// Struct represents the problems found while ingesting several inputs.
+#[derive(Debug, Default)]
+pub struct MultiInputReport {
+    /// Every row that was skipped, along with the name of its input.
+    pub failures: Vec<(String, FallibleFailure<csv::Error>)>,
+    /// Every transaction id reused across inputs with conflicting contents, in input order.
+    pub conflicts: Vec<TxConflict>,
+}
+
+impl fmt::Display for TxOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} for client {} at {} #{}",
+            self.tag, self.amount, self.client, self.input, self.position
+        )
+    }
+}
+
+impl fmt::Display for TxConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tx {} is a {} but also a {}",
+            self.tx, self.first, self.second
+        )
+    }
+}
+
+impl TxOrigin {
+    /// Returns whether both origins describe the same transaction.
+    fn same_transaction(&self, other: &TxOrigin) -> bool {
+        self.client == other.client && self.tag == other.tag && self.amount == other.amount
+    }
+}
+
+impl State {
+    /// Function will construct the state machine and replay the CSV documents of all `inputs` in
+    /// order, as if they were a single input. Accounts of clients appearing in several inputs
+    /// accumulate as usual.
+    ///
+    /// # Arguments
+    /// * `inputs` - Names and readers of CSV documents with a header row. Names are only used in
+    ///   the report.
+    ///
+    /// # Returns
+    /// Returns the state machine along with the rows that were skipped, and every deposit or
+    /// withdrawal whose id was already used by an earlier input for a different client, type or
+    /// amount. Conflicting transactions are still executed, the same way a single input reusing
+    /// an id would be. Identical rows repeated across inputs are not conflicts, and ids reused
+    /// within a single input are not reported.
+    pub fn from_csv_inputs<R: io::Read>(
+        inputs: impl IntoIterator<Item = (String, R)>,
+    ) -> (Self, MultiInputReport) {
+        let mut this = Self::default();
+        let mut report = MultiInputReport::default();
+        let mut first_seen: HashMap<u32, TxOrigin> = HashMap::new();
+
+        for (input, rdr) in inputs {
+            let rdr = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(rdr);
+
+            for (position, tx) in rdr.into_deserialize::<Transaction>().enumerate() {
+                let failure = |kind| (input.clone(), FallibleFailure { position, kind });
+
+                let tx = match tx {
+                    Ok(tx) => tx,
+                    Err(e) => {
+                        report.failures.push(failure(FailureKind::Upstream(e)));
+                        continue;
+                    }
+                };
+
+                if let TransactionType::Deposit { amount }
+                | TransactionType::Withdrawal { amount } = tx.tx_type
+                {
+                    let origin = TxOrigin {
+                        input: input.clone(),
+                        position,
+                        client: tx.client,
+                        tag: tx.tx_type.tag().to_string(),
+                        amount: amount.get(),
+                    };
+
+                    match first_seen.entry(tx.tx) {
+                        Entry::Occupied(entry) => {
+                            let first = entry.get();
+                            if first.input != input && !first.same_transaction(&origin) {
+                                report.conflicts.push(TxConflict {
+                                    tx: tx.tx,
+                                    first: first.clone(),
+                                    second: origin,
+                                });
+                            }
+                        }
+                        Entry::Vacant(entry) => {
+                            entry.insert(origin);
+                        }
+                    }
+                }
+
+                let (client, id, tag) = (tx.client, tx.tx, tx.tx_type.tag().to_string());
+                if let Err(error) = this.execute(tx) {
+                    report.failures.push(failure(FailureKind::Rejected {
+                        client,
+                        tx: id,
+                        tag,
+                        error,
+                    }));
+                }
+            }
+        }
+
+        (this, report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_conflicting_inputs() {
+        let first = "type, client, tx, amount\n\
+                     deposit, 1, 1, 10.0\n\
+                     deposit, 2, 2, 5.0\n\
+                     deposit, 1, 3, 1.0\n";
+        let second = "type, client, tx, amount\n\
+                      deposit, 2, 2, 5.0\n\
+                      deposit, 1, 1, 12.0\n\
+                      withdrawal, x, 4, 1.0\n\
+                      withdrawal, 3, 3, 1.0\n\
+                      withdrawal, 1, 5, 2.0\n";
+
+        let (state, report) = State::from_csv_inputs(vec![
+            ("first.csv".to_string(), first.as_bytes()),
+            ("second.csv".to_string(), second.as_bytes()),
+        ]);
+
+        // Both files feed the same accounts, conflicting rows included.
+        assert_eq!(state.accounts[&1].total, 21.0);
+        assert_eq!(state.accounts[&2].total, 10.0);
+
+        assert_eq!(
+            report.conflicts,
+            [
+                TxConflict {
+                    tx: 1,
+                    first: TxOrigin {
+                        input: "first.csv".into(),
+                        position: 0,
+                        client: 1,
+                        tag: "deposit".into(),
+                        amount: 10.0,
+                    },
+                    second: TxOrigin {
+                        input: "second.csv".into(),
+                        position: 1,
+                        client: 1,
+                        tag: "deposit".into(),
+                        amount: 12.0,
+                    },
+                },
+                TxConflict {
+                    tx: 3,
+                    first: TxOrigin {
+                        input: "first.csv".into(),
+                        position: 2,
+                        client: 1,
+                        tag: "deposit".into(),
+                        amount: 1.0,
+                    },
+                    second: TxOrigin {
+                        input: "second.csv".into(),
+                        position: 3,
+                        client: 3,
+                        tag: "withdrawal".into(),
+                        amount: 1.0,
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            report.conflicts[0].to_string(),
+            "tx 1 is a deposit of 10 for client 1 at first.csv #0 but also a deposit of 12 for \
+             client 1 at second.csv #1"
+        );
+
+        let failures: Vec<_> = report
+            .failures
+            .iter()
+            .map(|(input, x)| (input.as_str(), x.position))
+            .collect();
+        assert_eq!(failures, [("second.csv", 2), ("second.csv", 3)]);
+    }
+}
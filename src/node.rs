@@ -0,0 +1,217 @@
+//! This module contains the Node.js bindings of the state machine, built with the `node` feature
+//! and packaged with the napi-rs CLI through `package.json`.
+//!
+//! Amounts cross the boundary as strings, both ways, so that they never get rounded by JavaScript
+//! numbers.
+
+use super::*;
+use crate::ingest::parse_record;
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Env, Task};
+use napi_derive::napi;
+
+/// Struct represents a transaction passed in from JavaScript, with the same fields as the
+/// columns of a CSV document.
+#[napi(object)]
+pub struct TransactionInput {
+    #[napi(js_name = "type")]
+    pub tag: String,
+    pub client: u32,
+    pub tx: u32,
+    pub amount: Option<String>,
+}
+
+/// Struct represents a transaction that was executed, along with the resulting account.
+#[napi(object)]
+pub struct Receipt {
+    #[napi(js_name = "type")]
+    pub tag: String,
+    pub client: u32,
+    pub tx: u32,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+}
+
+/// Struct represents a transaction that was rejected.
+#[napi(object)]
+pub struct TxFailure {
+    /// The name of the `TxError` variant, or `InvalidRecord` if the transaction couldnt be
+    /// parsed.
+    pub code: String,
+    pub message: String,
+    pub client: u32,
+    pub tx: u32,
+}
+
+/// Struct represents the outcome of executing a transaction. Exactly one of the fields is set.
+#[napi(object)]
+pub struct ExecuteResult {
+    pub receipt: Option<Receipt>,
+    pub error: Option<TxFailure>,
+}
+
+/// Struct represents an account as returned to JavaScript.
+#[napi(object)]
+pub struct AccountObject {
+    pub client: u32,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+}
+
+/// Class wrapping a state machine with the default policies.
+#[napi]
+#[derive(Default)]
+pub struct Engine {
+    state: State,
+}
+
+#[napi]
+impl Engine {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Executes a single transaction, returning either its receipt or the reason it was
+    /// rejected.
+    #[napi]
+    pub fn execute(&mut self, tx: TransactionInput) -> ExecuteResult {
+        let failure = |code: &str, message: String| ExecuteResult {
+            receipt: None,
+            error: Some(TxFailure {
+                code: code.to_string(),
+                message,
+                client: tx.client,
+                tx: tx.tx,
+            }),
+        };
+
+        let (client, id) = (tx.client.to_string(), tx.tx.to_string());
+        let fields = [
+            Some(tx.tag.as_str()),
+            Some(client.as_str()),
+            Some(id.as_str()),
+            tx.amount.as_deref(),
+        ];
+        let parsed: Transaction = match parse_record(|i| Ok(fields.get(i).copied().flatten())) {
+            Ok(parsed) => parsed,
+            Err(e) => return failure("InvalidRecord", e.to_string()),
+        };
+
+        let client = parsed.client;
+        if let Err(e) = self.state.execute(parsed) {
+            return failure(e.code(), e.to_string());
+        }
+
+        let account = &self.state.accounts[&client];
+        ExecuteResult {
+            receipt: Some(Receipt {
+                tag: tx.tag.clone(),
+                client: tx.client,
+                tx: tx.tx,
+                available: account.available.to_string(),
+                held: account.held.to_string(),
+                total: account.total.to_string(),
+                locked: account.locked,
+            }),
+            error: None,
+        }
+    }
+
+    /// Executes a list of transactions in order, returning the outcome of each of them.
+    #[napi]
+    pub fn execute_batch(&mut self, txs: Vec<TransactionInput>) -> Vec<ExecuteResult> {
+        txs.into_iter().map(|tx| self.execute(tx)).collect()
+    }
+
+    /// Returns all the accounts, ordered by client id.
+    #[napi]
+    pub fn accounts(&self) -> Vec<AccountObject> {
+        self.state
+            .accounts_iter_by_id()
+            .map(|x| AccountObject {
+                client: x.id.into(),
+                available: x.available.to_string(),
+                held: x.held.to_string(),
+                total: x.total.to_string(),
+                locked: x.locked,
+            })
+            .collect()
+    }
+
+    /// Builds an engine out of the transactions of a CSV document with a header row, on the
+    /// libuv thread pool. Rejected transactions are skipped, and the promise is rejected if the
+    /// file cannot be read.
+    #[napi(ts_return_type = "Promise<Engine>")]
+    pub fn load_csv(path: String) -> AsyncTask<LoadCsv> {
+        AsyncTask::new(LoadCsv { path })
+    }
+}
+
+/// Struct represents the background task behind `Engine.loadCsv`.
+pub struct LoadCsv {
+    path: String,
+}
+
+impl Task for LoadCsv {
+    type Output = State;
+    type JsValue = Engine;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(&self.path)
+            .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+
+        let (state, _) = State::from_fallible_iterator(rdr.into_deserialize());
+
+        Ok(state)
+    }
+
+    fn resolve(&mut self, _: Env, state: Self::Output) -> napi::Result<Self::JsValue> {
+        Ok(Engine { state })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tx(tag: &str, client: u32, tx: u32, amount: Option<&str>) -> TransactionInput {
+        TransactionInput {
+            tag: tag.to_string(),
+            client,
+            tx,
+            amount: amount.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_engine() {
+        let mut engine = Engine::new();
+
+        let receipt = engine.execute(tx("deposit", 1, 1, Some("0.1"))).receipt;
+        assert_eq!(receipt.unwrap().total, "0.1");
+
+        let results = engine.execute_batch(vec![
+            tx("deposit", 1, 2, Some("0.2")),
+            tx("withdrawal", 1, 3, Some("5")),
+            tx("deposit", 70000, 4, Some("1")),
+        ]);
+        assert_eq!(
+            results[0].receipt.as_ref().unwrap().available,
+            "0.30000000000000004"
+        );
+        assert_eq!(results[1].error.as_ref().unwrap().code, "NotEnoughFunds");
+        assert_eq!(results[2].error.as_ref().unwrap().code, "InvalidRecord");
+        assert_eq!(results[2].error.as_ref().unwrap().client, 70000);
+
+        let accounts = engine.accounts();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].client, 1);
+    }
+}
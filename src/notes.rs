@@ -0,0 +1,116 @@
+//! This module contains the freeform notes dispute handlers can attach to transactions, such as
+//! case numbers or agent ids. Notes are pure metadata and never affect execution.
+
+use super::*;
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will append `note` to the notes of transaction `tx_id`.
+    ///
+    /// # Returns
+    /// Returns `TxError::TxDoesntExist` if the transaction isnt in the `tx_cache`, i.e. it cannot
+    /// be disputed, or its dispute has already been resolved or charged back.
+    ///
+    /// # Notes
+    /// Notes are not part of snapshots.
+    pub fn add_dispute_note(&mut self, tx_id: u32, note: &str) -> Result<(), TxError> {
+        if !self.tx_cache.contains_key(&tx_id) {
+            return Err(TxError::TxDoesntExist);
+        }
+
+        self.dispute_notes
+            .entry(tx_id)
+            .or_default()
+            .push(note.to_string());
+
+        Ok(())
+    }
+
+    /// Returns the notes of transaction `tx_id` in the order they were added, or `None` if it
+    /// has none.
+    pub fn get_dispute_notes(&self, tx_id: u32) -> Option<&[String]> {
+        self.dispute_notes.get(&tx_id).map(Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    fn run(state: &mut State, tx_type: TransactionType, tx: u32) {
+        state.execute(Transaction::new(tx_type, 1, tx)).unwrap();
+    }
+
+    #[test]
+    fn test_dispute_notes() {
+        let mut state = State::default();
+        run(
+            &mut state,
+            TransactionType::Deposit {
+                amount: checked(10.0),
+            },
+            1,
+        );
+        run(
+            &mut state,
+            TransactionType::Deposit {
+                amount: checked(5.0),
+            },
+            2,
+        );
+
+        assert_eq!(
+            state.add_dispute_note(7, "case #1"),
+            Err(TxError::TxDoesntExist)
+        );
+        assert_eq!(state.get_dispute_notes(1), None);
+
+        state.add_dispute_note(1, "case #1").unwrap();
+        run(&mut state, TransactionType::Dispute, 1);
+        state.add_dispute_note(1, "agent 42").unwrap();
+        assert_eq!(
+            state.get_dispute_notes(1),
+            Some(&["case #1".to_string(), "agent 42".to_string()][..])
+        );
+
+        // Notes dont change how the dispute plays out.
+        run(&mut state, TransactionType::Resolve, 1);
+        assert_eq!(state.accounts[&1].available, 15.0);
+        assert_eq!(state.get_dispute_notes(1).unwrap().len(), 2);
+        assert_eq!(
+            state.add_dispute_note(1, "reopened"),
+            Err(TxError::TxDoesntExist)
+        );
+
+        let mut state = State::builder()
+            .clear_dispute_notes_on_resolve(true)
+            .build()
+            .unwrap();
+        run(
+            &mut state,
+            TransactionType::Deposit {
+                amount: checked(10.0),
+            },
+            1,
+        );
+        run(
+            &mut state,
+            TransactionType::Deposit {
+                amount: checked(5.0),
+            },
+            2,
+        );
+        for tx in [1, 2] {
+            run(&mut state, TransactionType::Dispute, tx);
+            state.add_dispute_note(tx, "case").unwrap();
+        }
+
+        run(&mut state, TransactionType::Resolve, 1);
+        assert_eq!(state.get_dispute_notes(1), None);
+        assert_eq!(state.get_dispute_notes(2).unwrap().len(), 1);
+
+        run(&mut state, TransactionType::Chargeback, 2);
+        assert_eq!(state.get_dispute_notes(2), None);
+        assert!(state.accounts[&1].locked);
+    }
+}
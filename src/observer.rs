@@ -0,0 +1,175 @@
+//! This module contains the observer hooks used to monitor the state machine without touching its
+//! execution logic.
+
+use super::*;
+use crate::diff::AccountDiff;
+
+/// Trait implemented by types monitoring the transactions executed by a state machine. All
+/// methods default to doing nothing.
+pub trait StateObserver<A = f64> {
+    /// Called before `tx` gets executed.
+    fn on_execute_start(&self, _tx: &Transaction<A>) {}
+
+    /// Called after `tx` has been commited, with the changes it commited to the account of its
+    /// client.
+    fn on_execute_success(&self, _tx: &Transaction<A>, _diff: &AccountDiff<A>) {}
+
+    /// Called after `tx` has been rejected with `err`.
+    fn on_execute_error(&self, _tx: &Transaction<A>, _err: &TxError) {}
+
+    /// Called before `tx` gets executed if its timestamp is behind `latest`, the latest timestamp
+    /// of its client, with `OutOfOrderPolicy::Warn`.
+    fn on_out_of_order(&self, _tx: &Transaction<A>, _latest: u64) {}
+}
+
+/// Struct is an observer logging every transaction through the `log` crate. Transactions are
+/// logged in their redacted form, rejections at the `warn` level and everything else at the
+/// `debug` level.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoggingObserver;
+
+impl<A: Money> StateObserver<A> for LoggingObserver {
+    fn on_execute_start(&self, tx: &Transaction<A>) {
+        log::debug!("Executing {}", tx.redacted());
+    }
+
+    fn on_execute_success(&self, tx: &Transaction<A>, diff: &AccountDiff<A>) {
+        log::debug!(
+            "Executed {} (available {}, held {}, total {})",
+            tx.redacted(),
+            diff.available_delta,
+            diff.held_delta,
+            diff.total_delta
+        );
+    }
+
+    fn on_execute_error(&self, tx: &Transaction<A>, err: &TxError) {
+        log::warn!("Rejected {}: {}", tx.redacted(), err);
+    }
+
+    fn on_out_of_order(&self, tx: &Transaction<A>, latest: u64) {
+        log::warn!(
+            "Out of order {} (latest timestamp {})",
+            tx.redacted(),
+            latest
+        );
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will register `observer` to be notified of every transaction executed from now
+    /// on, replacing any previously registered observer.
+    pub fn register_observer(&mut self, observer: Arc<dyn StateObserver<A> + Send + Sync>) {
+        self.observer = Some(observer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// Counts the calls made to every hook.
+    #[derive(Debug, Default)]
+    struct Counter {
+        started: AtomicUsize,
+        succeeded: AtomicUsize,
+        failed: AtomicUsize,
+        diffs: Mutex<Vec<AccountDiff>>,
+    }
+
+    impl StateObserver for Counter {
+        fn on_execute_start(&self, _: &Transaction) {
+            self.started.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_execute_success(&self, _: &Transaction, diff: &AccountDiff) {
+            self.succeeded.fetch_add(1, Ordering::SeqCst);
+            self.diffs.lock().unwrap().push(*diff);
+        }
+
+        fn on_execute_error(&self, _: &Transaction, _: &TxError) {
+            self.failed.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let counter = Arc::new(Counter::default());
+        let mut state = State::default();
+
+        // Transactions executed before registering are not observed.
+        state
+            .execute(Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+
+        state.register_observer(counter.clone());
+
+        let txs = [
+            (
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                2,
+            ),
+            (
+                TransactionType::Withdrawal {
+                    amount: checked(4.0),
+                },
+                1,
+                3,
+            ),
+            (
+                TransactionType::Withdrawal {
+                    amount: checked(40.0),
+                },
+                1,
+                4,
+            ),
+            (TransactionType::Dispute, 1, 2),
+            (TransactionType::Dispute, 1, 2),
+            (TransactionType::Resolve, 2, 9),
+        ];
+        for (tx_type, client, tx) in txs {
+            let _ = state.execute(Transaction::new(tx_type, client, tx));
+        }
+
+        assert_eq!(counter.started.load(Ordering::SeqCst), 6);
+        assert_eq!(counter.succeeded.load(Ordering::SeqCst), 3);
+        assert_eq!(counter.failed.load(Ordering::SeqCst), 3);
+
+        let diffs = counter.diffs.lock().unwrap();
+        assert_eq!(diffs[0].total_delta, 10.0);
+        assert_eq!(diffs[1].available_delta, -4.0);
+        assert_eq!(diffs[2].held_delta, 10.0);
+    }
+
+    #[test]
+    fn test_logging_observer() {
+        let mut state = State::default();
+        state.register_observer(Arc::new(LoggingObserver));
+
+        state
+            .execute(Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        assert_eq!(
+            state.execute(Transaction::new(TransactionType::Dispute, 1, 2)),
+            Err(TxError::TxDoesntExist)
+        );
+    }
+}
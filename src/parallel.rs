@@ -0,0 +1,370 @@
+//! This module contains the parallel ingestion of a CSV document, split into byte ranges that are
+//! parsed and executed on several threads.
+//!
+//! # Correctness
+//! A transaction only ever reads and writes two pieces of state: the account of its client, and
+//! the cached transactions it refers to by id, which may belong to another client. Disputes,
+//! resolves and chargebacks refer to the deposit or withdrawal sharing their `tx`, refunds to
+//! the withdrawal `of_tx` and releases to the manual hold `of_tx`. Two transactions can therefore
+//! only influence each other if they share a client, or if they share one of those ids.
+//!
+//! The first pass parses every chunk and indexes, for every such id, the clients whose
+//! transactions carry it. Clients sharing an id are merged into the same group, so that every
+//! deposit, withdrawal, manual hold and every transaction referring to them end up in the same
+//! group, no matter which chunk they were read from. The second pass replays each group on its
+//! own state machine, in file order. Each group sees the exact same sequence of transactions touching its accounts
+//! and cached transactions as a serial replay, ids reused across clients included, so it commits
+//! the exact same changes. As groups never share an account or an id, merging their state
+//! machines is a plain union.
+//!
+//! The argument relies on the default policies, under which nothing else is shared between
+//! clients: dispute windows and velocity limits, which depend on the logical time of the whole
+//! state machine, are disabled, and so is idempotent mode, which remembers ids across clients.
+
+use super::*;
+use std::thread;
+
+/// Returns the byte ranges `data` is split into, at most `chunks` of them, each ending on a line
+/// boundary.
+fn split_lines(data: &[u8], chunks: usize) -> Vec<&[u8]> {
+    let mut ranges = Vec::with_capacity(chunks);
+    let mut rest = data;
+    for i in (1..=chunks).rev() {
+        if rest.is_empty() {
+            break;
+        }
+
+        let target = rest.len() / i;
+        let end = match rest[target..].iter().position(|x| *x == b'\n') {
+            Some(newline) => target + newline + 1,
+            None => rest.len(),
+        };
+
+        let (range, tail) = rest.split_at(end);
+        ranges.push(range);
+        rest = tail;
+    }
+
+    ranges
+}
+
+/// Struct represents a chunk parsed by the first pass, along with the clients of every id its
+/// transactions carry.
+struct ParsedChunk {
+    txs: Vec<Transaction>,
+    index: HashMap<u32, Vec<u16>>,
+}
+
+/// Returns the id of the deposit, withdrawal or manual hold a transaction carries or refers to, if
+/// any.
+fn referenced_id(tx: &Transaction) -> Option<u32> {
+    match tx.tx_type {
+        TransactionType::Deposit { .. }
+        | TransactionType::Withdrawal { .. }
+        | TransactionType::Dispute
+        | TransactionType::Resolve
+        | TransactionType::Chargeback
+        | TransactionType::Hold { .. } => Some(tx.tx),
+        TransactionType::Refund { of_tx, .. } | TransactionType::Release { of_tx } => Some(of_tx),
+        TransactionType::Interest { .. } | TransactionType::Other { .. } => None,
+    }
+}
+
+/// Function will parse the rows of `chunk` with `headers`, skipping malformed rows.
+fn parse_chunk(chunk: &[u8], headers: &csv::StringRecord) -> ParsedChunk {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(chunk);
+
+    let mut parsed = ParsedChunk {
+        txs: Vec::new(),
+        index: HashMap::new(),
+    };
+
+    for record in rdr.records().filter_map(Result::ok) {
+        let tx: Transaction = match record.deserialize(Some(headers)) {
+            Ok(tx) => tx,
+            Err(_) => continue,
+        };
+
+        if let Some(id) = referenced_id(&tx) {
+            let clients = parsed.index.entry(id).or_default();
+            if !clients.contains(&tx.client) {
+                clients.push(tx.client);
+            }
+        }
+
+        parsed.txs.push(tx);
+    }
+
+    parsed
+}
+
+/// Struct represents a union-find over client ids, grouping the clients sharing an id.
+struct ClientGroups(HashMap<u16, u16>);
+
+impl ClientGroups {
+    /// Returns the representative of the group of `client`.
+    fn find(&mut self, client: u16) -> u16 {
+        let mut root = client;
+        while let Some(&parent) = self.0.get(&root) {
+            if parent == root {
+                break;
+            }
+            root = parent;
+        }
+
+        // NOTE: Path compression keeps later lookups close to constant time.
+        let mut current = client;
+        while current != root {
+            current = self.0.insert(current, root).unwrap_or(root);
+        }
+
+        root
+    }
+
+    /// Function will merge the groups of `a` and `b`.
+    fn union(&mut self, a: u16, b: u16) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.0.insert(a.max(b), a.min(b));
+        }
+    }
+}
+
+impl State {
+    /// Function will construct the state machine and replay all the transactions of the CSV
+    /// document `data`, splitting it into byte ranges that are parsed and executed on up to
+    /// `threads` threads. Rejected and malformed rows are skipped, as with `from_iterator`, and the
+    /// accounts are the same as those of a serial replay. See the module documentation for why.
+    ///
+    /// # Arguments
+    /// * `data` - CSV document with a header row, e.g. a file read or memory mapped in full.
+    /// * `threads` - Maximum number of threads to use. Zero is treated as one.
+    ///
+    /// # Notes
+    /// Rows are split on newlines, so quoted fields must not span several lines. Transactions only
+    /// run in parallel across clients that never share a transaction id: a document whose clients
+    /// all reference each other's transactions is replayed on a single thread.
+    ///
+    /// The state machine uses the default policies. The logical times recorded for cached
+    /// transactions are local to the thread that executed them.
+    pub fn from_csv_parallel(data: &[u8], threads: usize) -> Self {
+        let threads = threads.max(1);
+
+        let (header, body) = match data.iter().position(|x| *x == b'\n') {
+            Some(newline) => data.split_at(newline + 1),
+            None => (data, &[][..]),
+        };
+        let headers = match csv::ReaderBuilder::new()
+            .has_headers(false)
+            .trim(csv::Trim::All)
+            .from_reader(header)
+            .records()
+            .next()
+        {
+            Some(Ok(headers)) => headers,
+            _ => return Self::default(),
+        };
+
+        // First pass: parse every chunk, indexing the clients of every id.
+        let headers = &headers;
+        let chunks: Vec<ParsedChunk> = thread::scope(|scope| {
+            let handles: Vec<_> = split_lines(body, threads)
+                .into_iter()
+                .map(|chunk| scope.spawn(move || parse_chunk(chunk, headers)))
+                .collect();
+
+            handles.into_iter().map(|x| x.join().unwrap()).collect()
+        });
+
+        let mut groups = ClientGroups(HashMap::new());
+        let mut owners: HashMap<u32, u16> = HashMap::new();
+        for (id, clients) in chunks.iter().flat_map(|x| x.index.iter()) {
+            let first = *owners.entry(*id).or_insert(clients[0]);
+            for client in clients {
+                groups.union(first, *client);
+            }
+        }
+
+        // Second pass: replay every group in file order, spreading the groups over the threads.
+        let mut partitions: Vec<Vec<Transaction>> = vec![Vec::new(); threads];
+        for tx in chunks.into_iter().flat_map(|x| x.txs) {
+            let group = groups.find(tx.client);
+            partitions[group as usize % threads].push(tx);
+        }
+
+        let states: Vec<State> = thread::scope(|scope| {
+            let handles: Vec<_> = partitions
+                .into_iter()
+                .map(|txs| scope.spawn(move || Self::from_iterator(txs.into_iter())))
+                .collect();
+
+            handles.into_iter().map(|x| x.join().unwrap()).collect()
+        });
+
+        let mut this = Self::default();
+        for state in states {
+            this.merge_disjoint(state);
+        }
+
+        this
+    }
+
+    /// Function will move the data of `other` into this state machine. The two must not share
+    /// any client nor transaction id.
+    fn merge_disjoint(&mut self, other: State) {
+        self.accounts.extend(other.accounts);
+        self.tx_cache.extend(other.tx_cache);
+        self.refundable.extend(other.refundable);
+        self.manual_holds.extend(other.manual_holds);
+        self.seen.extend(other.seen);
+        self.dispute_event_ids.extend(other.dispute_event_ids);
+        self.recent_withdrawals.extend(other.recent_withdrawals);
+        self.client_timestamps.extend(other.client_timestamps);
+        #[cfg(feature = "statement-logging")]
+        self.statements.extend(other.statements);
+
+        self.seq += other.seq;
+        self.latest_timestamp = self.latest_timestamp.max(other.latest_timestamp);
+        self.type_counts.merge(&other.type_counts);
+        self.executed_count += other.executed_count;
+        self.failed_count += other.failed_count;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{checked, workload};
+    use rand::prelude::*;
+
+    /// Returns `txs` as a CSV document.
+    fn to_csv(txs: &[Transaction]) -> String {
+        let mut csv = String::from("type, client, tx, amount\n");
+        for tx in txs {
+            let amount = match tx.tx_type {
+                TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                    amount.to_string()
+                }
+                _ => String::new(),
+            };
+
+            csv += &format!(
+                "{}, {}, {}, {}\n",
+                tx.tx_type.tag(),
+                tx.client,
+                tx.tx,
+                amount
+            );
+        }
+
+        csv
+    }
+
+    /// Returns a dispute-heavy workload over many clients. Most disputes refer to an earlier
+    /// deposit of their own client, some to the deposits of other clients, and some ids are
+    /// reused across clients.
+    fn disputed_workload(len: u32) -> Vec<Transaction> {
+        let mut rng = StdRng::seed_from_u64(0xD15_9073);
+        let mut deposits: Vec<(u16, u32)> = Vec::new();
+
+        (0..len)
+            .map(|id| {
+                let client = rng.gen_range(1..=200);
+                let (client, id, tx_type) = match rng.gen_range(0..10) {
+                    _ if deposits.is_empty() => (
+                        client,
+                        id,
+                        TransactionType::Deposit {
+                            amount: checked(10.0),
+                        },
+                    ),
+                    0..=2 => {
+                        // NOTE: Some ids get reused by another client.
+                        let id = if rng.gen_bool(0.05) {
+                            deposits[rng.gen_range(0..deposits.len())].1
+                        } else {
+                            id
+                        };
+                        deposits.push((client, id));
+                        let amount = rng.gen_range(1..400) as f64 / 4.0;
+                        (
+                            client,
+                            id,
+                            TransactionType::Deposit {
+                                amount: checked(amount),
+                            },
+                        )
+                    }
+                    3 => {
+                        let amount = rng.gen_range(1..400) as f64 / 4.0;
+                        (
+                            client,
+                            id,
+                            TransactionType::Withdrawal {
+                                amount: checked(amount),
+                            },
+                        )
+                    }
+                    kind => {
+                        let (owner, id) = deposits[rng.gen_range(0..deposits.len())];
+                        let client = if rng.gen_bool(0.02) { client } else { owner };
+                        let tx_type = match kind {
+                            4..=6 => TransactionType::Dispute,
+                            7..=8 => TransactionType::Resolve,
+                            _ => TransactionType::Chargeback,
+                        };
+                        (client, id, tx_type)
+                    }
+                };
+
+                Transaction::new(tx_type, client, id)
+            })
+            .collect()
+    }
+
+    fn sorted_accounts(state: &State) -> Vec<Account> {
+        let mut accounts: Vec<_> = state.accounts().cloned().collect();
+        accounts.sort_by_key(|x| x.id);
+        accounts
+    }
+
+    #[test]
+    fn test_split_lines() {
+        let data = b"a\nbb\nccc\ndddd\n";
+        for chunks in 1..=6 {
+            let ranges = split_lines(data, chunks);
+            assert!(ranges.len() <= chunks);
+            assert!(ranges.iter().all(|x| x.ends_with(b"\n")));
+            assert_eq!(ranges.concat(), data);
+        }
+
+        assert_eq!(split_lines(b"a\nb", 2), [&b"a\n"[..], &b"b"[..]]);
+        assert!(split_lines(b"", 4).is_empty());
+    }
+
+    #[test]
+    fn test_from_csv_parallel() {
+        for txs in [disputed_workload(20_000), workload(5_000)] {
+            let csv = to_csv(&txs);
+            let serial = State::from_iterator(txs.into_iter());
+
+            for threads in [0, 1, 4, 7] {
+                let parallel = State::from_csv_parallel(csv.as_bytes(), threads);
+                assert_eq!(sorted_accounts(&parallel), sorted_accounts(&serial));
+                assert_eq!(
+                    parallel.count_transactions_by_type(),
+                    serial.count_transactions_by_type()
+                );
+                assert_eq!(parallel.tx_cache.len(), serial.tx_cache.len());
+            }
+        }
+
+        let state = State::from_csv_parallel(b"type, client, tx, amount\n", 4);
+        assert_eq!(state.accounts().count(), 0);
+        assert_eq!(State::from_csv_parallel(b"", 4).accounts().count(), 0);
+    }
+}
@@ -0,0 +1,285 @@
+//! This module contains the settlement snapshots cut at period boundaries, e.g. at the end of
+//! every day, found in the timestamps of the transactions.
+//!
+//! Transactions without a timestamp belong to the period of the transactions before them.
+//! Stragglers, transactions stamped within a period that has already been cut, are either
+//! rejected by the `OutOfOrderPolicy` of the state machine or commited into the current period;
+//! snapshots that have been cut are never amended.
+
+use super::*;
+use crate::fallible::FallibleFailure;
+use crate::timestamp::format_timestamp;
+use std::fmt;
+use std::num::NonZeroU64;
+
+/// The amount of milliseconds in a day.
+const DAY: u64 = 86_400_000;
+
+/// Enum represents the length of the periods snapshots are cut at the end of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeriodSpec {
+    /// Periods of a calendar day in UTC.
+    Daily,
+    /// Periods of the given amount of milliseconds, starting at the Unix epoch.
+    Every(NonZeroU64),
+}
+
+impl PeriodSpec {
+    /// Returns the period the timestamp `timestamp` lies in.
+    pub fn period_of(&self, timestamp: u64) -> PeriodId {
+        let length = match self {
+            PeriodSpec::Daily => DAY,
+            PeriodSpec::Every(length) => length.get(),
+        };
+
+        PeriodId(timestamp - timestamp % length)
+    }
+}
+
+/// Struct represents a period by the timestamp it starts at, in milliseconds since the Unix epoch.
+///
+/// Periods starting at midnight are displayed as their date, e.g. `2021-09-24`, and any other
+/// period as an RFC 3339 date and time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeriodId(u64);
+
+impl PeriodId {
+    /// Returns the timestamp the period starts at.
+    pub fn start(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for PeriodId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let formatted = format_timestamp(self.0);
+        if self.0.is_multiple_of(DAY) {
+            f.write_str(&formatted[..formatted.len() - "T00:00:00.000Z".len()])
+        } else {
+            f.write_str(&formatted)
+        }
+    }
+}
+
+/// Struct tracks the period of the transactions executed by a state machine, cutting a snapshot
+/// of its accounts whenever a transaction starts a later period.
+#[derive(Clone, Copy, Debug)]
+pub struct PeriodBoundaries {
+    spec: PeriodSpec,
+    current: Option<PeriodId>,
+}
+
+impl PeriodBoundaries {
+    /// Function will construct a tracker cutting snapshots at the end of every period of `spec`.
+    pub fn new(spec: PeriodSpec) -> Self {
+        Self {
+            spec,
+            current: None,
+        }
+    }
+
+    /// Returns the period of the latest timestamp seen, if any.
+    pub fn current(&self) -> Option<PeriodId> {
+        self.current
+    }
+
+    /// Function will check whether `tx` starts a later period than the current one, in which
+    /// case the accounts of `state` are passed to `sink` as of the end of the current period.
+    /// Must be called right before `tx` gets executed.
+    ///
+    /// # Notes
+    /// Only periods with at least one timestamp lying in them are cut, so days without any
+    /// transaction are skipped.
+    pub fn observe<H: BuildHasher, A: Money>(
+        &mut self,
+        state: &State<H, A>,
+        tx: &Transaction<A>,
+        mut sink: impl FnMut(PeriodId, Vec<Account<A>>),
+    ) {
+        let period = match tx.timestamp {
+            Some(timestamp) => self.spec.period_of(timestamp),
+            None => return,
+        };
+
+        match self.current {
+            Some(current) if current < period => sink(current, state.cut_snapshot()),
+            Some(_) => return,
+            None => {}
+        }
+
+        self.current = Some(period);
+    }
+
+    /// Function will pass the accounts of `state` to `sink` as of the end of the current period,
+    /// once every transaction has been executed. Does nothing if no timestamp has been seen.
+    pub fn finish<H: BuildHasher, A: Money>(
+        self,
+        state: &State<H, A>,
+        mut sink: impl FnMut(PeriodId, Vec<Account<A>>),
+    ) {
+        if let Some(current) = self.current {
+            sink(current, state.cut_snapshot());
+        }
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function returns a copy of every account, in ascending order of their id, e.g. to settle
+    /// them at the end of a period while transactions keep being executed.
+    pub fn cut_snapshot(&self) -> Vec<Account<A>> {
+        self.accounts_iter_by_id().cloned().collect()
+    }
+}
+
+impl State {
+    /// Function behaves like `from_fallible_iterator`, additionally passing the accounts to
+    /// `sink` at the end of every period of `spec` found in the timestamps of the transactions.
+    /// The last period is passed once every transaction has been executed.
+    pub fn from_fallible_iterator_with_periods<E>(
+        txs: impl Iterator<Item = Result<Transaction, E>>,
+        spec: PeriodSpec,
+        mut sink: impl FnMut(PeriodId, Vec<Account>),
+    ) -> (Self, Vec<FallibleFailure<E>>) {
+        let mut boundaries = PeriodBoundaries::new(spec);
+        let (state, failures) = Self::from_fallible_iterator_with(txs, |state, tx| {
+            boundaries.observe(state, tx, &mut sink);
+        });
+        boundaries.finish(&state, sink);
+
+        (state, failures)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    /// Midnight of 2021-09-24 in UTC.
+    const START: u64 = 1_632_441_600_000;
+
+    fn tx(tx_type: TransactionType, client: u16, tx: u32, hours: Option<u64>) -> Transaction {
+        let tx = Transaction::new(tx_type, client, tx);
+        match hours {
+            Some(hours) => tx.with_timestamp(START + hours * 3_600_000),
+            None => tx,
+        }
+    }
+
+    /// Three days of transactions, with a gap on 2021-09-26 and a straggler from the first day.
+    fn fixture() -> Vec<Result<Transaction, TxError>> {
+        let deposit = |amount| TransactionType::Deposit {
+            amount: checked(amount),
+        };
+        vec![
+            Ok(tx(deposit(10.0), 1, 1, Some(1))),
+            Ok(tx(deposit(5.0), 2, 2, Some(23))),
+            Ok(tx(deposit(1.0), 1, 3, None)),
+            Ok(tx(TransactionType::Dispute, 1, 1, Some(25))),
+            Ok(tx(deposit(2.0), 2, 4, Some(12))),
+            Ok(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(1.5),
+                },
+                2,
+                5,
+                Some(30),
+            )),
+            Ok(tx(TransactionType::Resolve, 1, 1, Some(72))),
+            Err(TxError::ParseError("row".into())),
+            Ok(tx(deposit(3.0), 3, 6, Some(80))),
+        ]
+    }
+
+    /// Returns the `(client, available, held)` balances of `accounts`.
+    fn balances(accounts: &[Account]) -> Vec<(u16, f64, f64)> {
+        accounts
+            .iter()
+            .map(|x| (x.id, x.available, x.held))
+            .collect()
+    }
+
+    #[test]
+    fn test_daily_snapshots() {
+        let mut snapshots = Vec::new();
+        let (state, failures) = State::from_fallible_iterator_with_periods(
+            fixture().into_iter(),
+            PeriodSpec::Daily,
+            |period, accounts| snapshots.push((period.to_string(), balances(&accounts))),
+        );
+        assert_eq!(failures.len(), 1);
+
+        // The straggler from the first day is commited into the second one.
+        assert_eq!(
+            snapshots,
+            [
+                (
+                    "2021-09-24".to_string(),
+                    vec![(1, 11.0, 0.0), (2, 5.0, 0.0)]
+                ),
+                (
+                    "2021-09-25".to_string(),
+                    vec![(1, 1.0, 10.0), (2, 5.5, 0.0)]
+                ),
+                (
+                    "2021-09-27".to_string(),
+                    vec![(1, 11.0, 0.0), (2, 5.5, 0.0), (3, 3.0, 0.0)]
+                ),
+            ]
+        );
+        assert_eq!(balances(&state.cut_snapshot()), snapshots[2].1);
+    }
+
+    #[test]
+    fn test_rejected_stragglers() {
+        let mut boundaries = PeriodBoundaries::new(PeriodSpec::Daily);
+        let mut state = State::builder()
+            .out_of_order_timestamps(OutOfOrderPolicy::Reject, 0)
+            .build()
+            .unwrap();
+
+        let mut snapshots = Vec::new();
+        for tx in fixture().into_iter().filter_map(Result::ok) {
+            boundaries.observe(&state, &tx, |period, accounts| {
+                snapshots.push((period, balances(&accounts)))
+            });
+            let _ = state.execute(tx);
+        }
+        assert_eq!(boundaries.current(), Some(PeriodId(START + 3 * DAY)));
+        boundaries.finish(&state, |period, accounts| {
+            snapshots.push((period, balances(&accounts)))
+        });
+
+        let periods: Vec<_> = snapshots.iter().map(|(x, _)| x.start()).collect();
+        assert_eq!(periods, [START, START + DAY, START + 3 * DAY]);
+        assert_eq!(snapshots[1].1, [(1, 1.0, 10.0), (2, 3.5, 0.0)]);
+    }
+
+    #[test]
+    fn test_periods() {
+        let spec = PeriodSpec::Every(NonZeroU64::new(3_600_000).unwrap());
+        let period = spec.period_of(START + 5_400_000);
+        assert_eq!(period.start(), START + 3_600_000);
+        assert_eq!(period.to_string(), "2021-09-24T01:00:00.000Z");
+        assert_eq!(
+            PeriodSpec::Daily.period_of(START - 1).to_string(),
+            "2021-09-23"
+        );
+
+        // Nothing gets cut without timestamps.
+        let (_, failures) = State::from_fallible_iterator_with_periods(
+            vec![Ok::<_, TxError>(tx(
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                1,
+                1,
+                None,
+            ))]
+            .into_iter(),
+            PeriodSpec::Daily,
+            |_, _| panic!("unexpected snapshot"),
+        );
+        assert!(failures.is_empty());
+    }
+}
@@ -0,0 +1,488 @@
+//! This module contains the policies deciding how disputes move balances around.
+
+use super::*;
+use std::fmt;
+
+/// Struct represents the balance movements a dispute policy decided on. All fields are deltas
+/// that the state machine will add to the corresponding balances of the account.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HoldOutcome<A = f64> {
+    /// Delta applied to the `available` balance.
+    pub available: A,
+    /// Delta applied to the `held` balance.
+    pub held: A,
+    /// Delta applied to the `total` balance.
+    pub total: A,
+    /// Whether the account should be locked.
+    pub lock: bool,
+}
+
+/// Trait implemented by types deciding how disputes, resolves and chargebacks affect the balances
+/// of an account.
+///
+/// The state machine keeps ownership of the `tx_cache` bookkeeping and of the authorization checks,
+/// so by the time a policy is invoked the referenced transaction is known to exist, to be
+/// disputable by the client and to be in the correct dispute state. The returned outcome must keep
+/// `total == held + available` true, otherwise the state machine will reject it with
+/// `TxError::InternalError`.
+pub trait DisputePolicy<A = f64>: fmt::Debug + Send + Sync {
+    /// Called when `disputed` is put under dispute.
+    fn on_dispute(
+        &self,
+        account: &Account<A>,
+        disputed: &CachedTx<A>,
+    ) -> Result<HoldOutcome<A>, TxError>;
+    /// Called when the dispute on `disputed` gets resolved.
+    fn on_resolve(
+        &self,
+        account: &Account<A>,
+        disputed: &CachedTx<A>,
+    ) -> Result<HoldOutcome<A>, TxError>;
+    /// Called when the dispute on `disputed` ends in a chargeback.
+    fn on_chargeback(
+        &self,
+        account: &Account<A>,
+        disputed: &CachedTx<A>,
+    ) -> Result<HoldOutcome<A>, TxError>;
+}
+
+/// Trait implemented by types deciding which clients may dispute, resolve and charge back a cached
+/// transaction. Clients that may not are rejected with `TxError::Unauthorized`.
+///
+/// Balances are always moved on the account of the client the disputed transaction belongs to,
+/// whoever raised the dispute.
+pub trait DisputeAuthorizer<A = f64>: fmt::Debug + Send + Sync {
+    /// Returns whether `client` may dispute, resolve or charge back `disputed`.
+    fn may_dispute(&self, client: u16, disputed: &CachedTx<A>) -> bool;
+}
+
+/// The default dispute authorizer, only letting clients dispute their own transactions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OwnerOnly;
+
+impl<A> DisputeAuthorizer<A> for OwnerOnly {
+    fn may_dispute(&self, client: u16, disputed: &CachedTx<A>) -> bool {
+        client == disputed.tx.client
+    }
+}
+
+/// The default dispute policy.
+///
+/// Disputed deposits get moved from `available` to `held`, disputed withdrawals get added to
+/// `held` and `total`. Resolves reverse the dispute, while chargebacks reverse the disputed
+/// transaction and lock the account.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StandardPolicy;
+
+impl<A: Money> DisputePolicy<A> for StandardPolicy {
+    fn on_dispute(
+        &self,
+        _: &Account<A>,
+        disputed: &CachedTx<A>,
+    ) -> Result<HoldOutcome<A>, TxError> {
+        match disputed.tx.tx_type {
+            TransactionType::Deposit { amount } => Ok(HoldOutcome {
+                available: -amount.get(),
+                held: amount.get(),
+                ..HoldOutcome::default()
+            }),
+            // The spec doesnt specify how we should handle the balance transfer for withdrawal
+            // disputes so we kinda just wing it here.
+            //
+            // For deposit transaction disputes the available balance is decreased while the held
+            // balance is increased, total balance is not affected. If we get a withdrawal dispute
+            // we can just increase the held balance and total balance.
+            TransactionType::Withdrawal { amount } => Ok(HoldOutcome {
+                held: amount.get(),
+                total: amount.get(),
+                ..HoldOutcome::default()
+            }),
+            _ => Err(TxError::InvalidDispute),
+        }
+    }
+
+    fn on_resolve(
+        &self,
+        _: &Account<A>,
+        disputed: &CachedTx<A>,
+    ) -> Result<HoldOutcome<A>, TxError> {
+        match disputed.tx.tx_type {
+            TransactionType::Deposit { amount } => Ok(HoldOutcome {
+                available: amount.get(),
+                held: -amount.get(),
+                ..HoldOutcome::default()
+            }),
+            // The dispute was rejected, so the withdrawal stands.
+            TransactionType::Withdrawal { amount } => Ok(HoldOutcome {
+                held: -amount.get(),
+                total: -amount.get(),
+                ..HoldOutcome::default()
+            }),
+            _ => Err(TxError::InvalidDispute),
+        }
+    }
+
+    fn on_chargeback(
+        &self,
+        _: &Account<A>,
+        disputed: &CachedTx<A>,
+    ) -> Result<HoldOutcome<A>, TxError> {
+        match disputed.tx.tx_type {
+            TransactionType::Deposit { amount } => Ok(HoldOutcome {
+                held: -amount.get(),
+                total: -amount.get(),
+                lock: true,
+                ..HoldOutcome::default()
+            }),
+            // The withdrawn funds are returned to the client.
+            TransactionType::Withdrawal { amount } => Ok(HoldOutcome {
+                available: amount.get(),
+                held: -amount.get(),
+                lock: true,
+                ..HoldOutcome::default()
+            }),
+            _ => Err(TxError::InvalidDispute),
+        }
+    }
+}
+
+/// Dispute policy behaving like `StandardPolicy`, except that every chargeback additionally
+/// charges a fixed fee from the `available` balance of the account.
+#[derive(Clone, Copy, Debug)]
+pub struct ChargebackFeePolicy<A = f64> {
+    /// The fee charged for every chargeback.
+    pub fee: A,
+}
+
+impl<A: Money> DisputePolicy<A> for ChargebackFeePolicy<A> {
+    fn on_dispute(
+        &self,
+        account: &Account<A>,
+        disputed: &CachedTx<A>,
+    ) -> Result<HoldOutcome<A>, TxError> {
+        StandardPolicy.on_dispute(account, disputed)
+    }
+
+    fn on_resolve(
+        &self,
+        account: &Account<A>,
+        disputed: &CachedTx<A>,
+    ) -> Result<HoldOutcome<A>, TxError> {
+        StandardPolicy.on_resolve(account, disputed)
+    }
+
+    fn on_chargeback(
+        &self,
+        account: &Account<A>,
+        disputed: &CachedTx<A>,
+    ) -> Result<HoldOutcome<A>, TxError> {
+        let outcome = StandardPolicy.on_chargeback(account, disputed)?;
+
+        Ok(HoldOutcome {
+            available: outcome.available - self.fee,
+            total: outcome.total - self.fee,
+            ..outcome
+        })
+    }
+}
+
+impl<A: Money> HoldOutcome<A> {
+    /// Function will apply the balance deltas and lock decision to `account`.
+    pub(crate) fn apply(&self, account: &mut Account<A>) {
+        account.available += self.available;
+        account.held += self.held;
+        account.total += self.total;
+        account.locked |= self.lock;
+    }
+
+    /// Function will apply the outcome of a dispute, resolve or chargeback, accounting the change
+    /// of the `held` balance to the disputed part of it.
+    pub(crate) fn apply_dispute(&self, account: &mut Account<A>) {
+        self.apply(account);
+        account.disputed_hold += self.held;
+    }
+
+    /// Returns whether applying the outcome to `account` keeps its `held` balance within its
+    /// `total` balance.
+    pub(crate) fn keeps_held_within_total(&self, account: &Account<A>) -> bool {
+        account.held + self.held <= account.total + self.total + A::tolerance()
+    }
+
+    /// Returns whether the outcome keeps `total == held + available` true.
+    pub(crate) fn is_balanced(&self) -> bool {
+        (self.total - (self.held + self.available)).is_negligible()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use rust_decimal::Decimal;
+
+    fn tx<A>(tx_type: TransactionType<A>, client: u16, tx: u32) -> Transaction<A> {
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
+        }
+    }
+
+    fn account<A>(state: &State<ahash::RandomState, A>) -> &Account<A> {
+        state.accounts.get(&1).unwrap()
+    }
+
+    /// Runs the dispute test suite against `builder`, where chargebacks are expected to cost an
+    /// additional `fee`.
+    fn dispute_suite<A: Money>(builder: StateBuilder<A>, fee: f64) {
+        let m = |x: f64| A::from_f64(x).unwrap();
+        let deposit = |amount| {
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(m(amount)),
+                },
+                1,
+                1,
+            )
+        };
+
+        // dispute -> resolve
+        let mut state = builder.clone().build().unwrap();
+        state.execute(deposit(120.0)).unwrap();
+        state.execute(tx(TransactionType::Dispute, 1, 1)).unwrap();
+        assert_eq!(account(&state).available, m(0.0));
+        assert_eq!(account(&state).held, m(120.0));
+        assert_eq!(account(&state).total, m(120.0));
+
+        state.execute(tx(TransactionType::Resolve, 1, 1)).unwrap();
+        assert_eq!(account(&state).available, m(120.0));
+        assert_eq!(account(&state).held, m(0.0));
+        assert_eq!(
+            state.execute(tx(TransactionType::Resolve, 1, 1)),
+            Err(TxError::TxDoesntExist)
+        );
+
+        // dispute -> chargeback
+        let mut state = builder.clone().build().unwrap();
+        state.execute(deposit(120.0)).unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(m(30.0)),
+                },
+                1,
+                2,
+            ))
+            .unwrap();
+        assert_eq!(
+            state.execute(tx(TransactionType::Chargeback, 1, 1)),
+            Err(TxError::TxNotUnderDispute)
+        );
+        state.execute(tx(TransactionType::Dispute, 1, 1)).unwrap();
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 1, 1)),
+            Err(TxError::TxAlreadyDisputed)
+        );
+        state
+            .execute(tx(TransactionType::Chargeback, 1, 1))
+            .unwrap();
+        assert_eq!(account(&state).available, m(30.0 - fee));
+        assert_eq!(account(&state).held, m(0.0));
+        assert_eq!(account(&state).total, m(30.0 - fee));
+        assert!(account(&state).locked);
+        assert_eq!(
+            state.execute(tx(TransactionType::Chargeback, 1, 1)),
+            Err(TxError::AccountLocked)
+        );
+
+        // unauthorized
+        let mut state = builder.clone().build().unwrap();
+        state.execute(deposit(120.0)).unwrap();
+        for tx_type in [
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Chargeback,
+        ] {
+            assert_eq!(state.execute(tx(tx_type, 2, 1)), Err(TxError::Unauthorized));
+        }
+
+        // withdrawal dispute -> chargeback
+        let mut state = builder
+            .withdrawal_disputes(WithdrawalDisputePolicy::Allow)
+            .build()
+            .unwrap();
+        state.execute(deposit(120.0)).unwrap();
+        state
+            .execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(m(75.5)),
+                },
+                1,
+                2,
+            ))
+            .unwrap();
+        state.execute(tx(TransactionType::Dispute, 1, 2)).unwrap();
+        assert_eq!(account(&state).held, m(75.5));
+        assert_eq!(account(&state).total, m(120.0));
+        state
+            .execute(tx(TransactionType::Chargeback, 1, 2))
+            .unwrap();
+        assert_eq!(account(&state).available, m(120.0 - fee));
+        assert_eq!(account(&state).held, m(0.0));
+        assert_eq!(account(&state).total, m(120.0 - fee));
+        assert!(account(&state).locked);
+    }
+
+    #[test]
+    fn test_standard_policy() {
+        dispute_suite(State::builder(), 0.0);
+        dispute_suite(State::builder().dispute_policy(StandardPolicy), 0.0);
+    }
+
+    #[test]
+    fn test_chargeback_fee_policy() {
+        dispute_suite(
+            State::builder().dispute_policy(ChargebackFeePolicy { fee: 2.5 }),
+            2.5,
+        );
+    }
+
+    #[test]
+    fn test_decimal_policies() {
+        dispute_suite(StateBuilder::<Decimal>::default(), 0.0);
+        dispute_suite(
+            StateBuilder::default().dispute_policy(ChargebackFeePolicy {
+                fee: Decimal::new(25, 1),
+            }),
+            2.5,
+        );
+    }
+
+    #[test]
+    fn test_dispute_authorizer() {
+        /// Lets client `2` dispute the withdrawals of client `1`, as the recipient of its
+        /// transfers.
+        #[derive(Debug)]
+        struct Recipient;
+
+        impl DisputeAuthorizer for Recipient {
+            fn may_dispute(&self, client: u16, disputed: &CachedTx) -> bool {
+                OwnerOnly.may_dispute(client, disputed)
+                    || client == 2
+                        && disputed.tx.client == 1
+                        && matches!(disputed.tx.tx_type, TransactionType::Withdrawal { .. })
+            }
+        }
+
+        let mut state = State::builder()
+            .withdrawal_disputes(WithdrawalDisputePolicy::Allow)
+            .dispute_authorizer(Recipient)
+            .build()
+            .unwrap();
+        let txs = || {
+            [
+                tx(
+                    TransactionType::Deposit {
+                        amount: checked(100.0),
+                    },
+                    1,
+                    1,
+                ),
+                tx(
+                    TransactionType::Withdrawal {
+                        amount: checked(40.0),
+                    },
+                    1,
+                    2,
+                ),
+                tx(
+                    TransactionType::Deposit {
+                        amount: checked(5.0),
+                    },
+                    2,
+                    3,
+                ),
+            ]
+        };
+        for tx in txs() {
+            state.execute(tx).unwrap();
+        }
+
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 2, 1)),
+            Err(TxError::Unauthorized)
+        );
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 3, 2)),
+            Err(TxError::Unauthorized)
+        );
+
+        // The hold lands on the account of client `1`, which can resolve the dispute itself.
+        state.execute(tx(TransactionType::Dispute, 2, 2)).unwrap();
+        assert_eq!(account(&state).held, 40.0);
+        assert_eq!(account(&state).total, 100.0);
+        assert_eq!(state.accounts[&2].available, 5.0);
+        assert_eq!(state.accounts[&2].held, 0.0);
+
+        state.execute(tx(TransactionType::Resolve, 1, 2)).unwrap();
+        assert_eq!(account(&state).held, 0.0);
+        assert_eq!(account(&state).total, 60.0);
+
+        // Only clients the authorizer lets through can raise the dispute.
+        let mut state = State::builder()
+            .withdrawal_disputes(WithdrawalDisputePolicy::Allow)
+            .build()
+            .unwrap();
+        for tx in txs() {
+            state.execute(tx).unwrap();
+        }
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 2, 2)),
+            Err(TxError::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_policy() {
+        #[derive(Debug)]
+        struct Broken;
+
+        impl DisputePolicy for Broken {
+            fn on_dispute(&self, _: &Account, _: &CachedTx) -> Result<HoldOutcome, TxError> {
+                Ok(HoldOutcome {
+                    held: 10.0,
+                    ..HoldOutcome::default()
+                })
+            }
+
+            fn on_resolve(&self, _: &Account, _: &CachedTx) -> Result<HoldOutcome, TxError> {
+                Ok(HoldOutcome::default())
+            }
+
+            fn on_chargeback(&self, _: &Account, _: &CachedTx) -> Result<HoldOutcome, TxError> {
+                Ok(HoldOutcome::default())
+            }
+        }
+
+        let mut state = State::builder().dispute_policy(Broken).build().unwrap();
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        assert_eq!(
+            state.execute(tx(TransactionType::Dispute, 1, 1)),
+            Err(TxError::InternalError)
+        );
+        assert_eq!(account(&state).held, 0.0);
+        assert!(state.tx_cache[&1].dispute.is_none());
+    }
+}
@@ -0,0 +1,458 @@
+//! This module contains the protobuf encoding of transactions and accounts, built with the
+//! `proto` feature. The message types are generated by prost out of `proto/corken.proto`.
+//!
+//! Amounts are carried as decimal strings, and `client` and `tx` are required even though proto3
+//! makes every field optional.
+
+use crate::error::ProtoError;
+use crate::money::{Amount, Money};
+use prost::Message;
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+use std::marker::PhantomData;
+
+include!(concat!(env!("OUT_DIR"), "/corken.v1.rs"));
+
+impl<A: Money> TryFrom<Transaction> for crate::Transaction<A> {
+    type Error = ProtoError;
+
+    /// Function will convert a decoded message. The `amount` of disputes, resolves and
+    /// chargebacks is ignored, like in CSV documents.
+    fn try_from(msg: Transaction) -> Result<Self, Self::Error> {
+        let kind = TransactionType::try_from(msg.r#type)
+            .map_err(|_| ProtoError::UnknownType(msg.r#type))?;
+
+        let client = msg.client.ok_or(ProtoError::MissingField("client"))?;
+        let client = u16::try_from(client).map_err(|_| ProtoError::InvalidField {
+            field: "client",
+            reason: format!("{} doesnt fit in 16 bits", client),
+        })?;
+        let tx = msg.tx.ok_or(ProtoError::MissingField("tx"))?;
+
+        let amount = msg
+            .amount
+            .as_deref()
+            .map(|x| {
+                x.trim().parse::<A>().map_err(|_| ProtoError::InvalidField {
+                    field: "amount",
+                    reason: format!("`{}` is not a valid number", x),
+                })
+            })
+            .transpose()?;
+        let required = || amount.ok_or(ProtoError::MissingField("amount"));
+        let checked = || {
+            Amount::new(required()?).map_err(|e| ProtoError::InvalidField {
+                field: "amount",
+                reason: e.to_string(),
+            })
+        };
+
+        let tx_type = match kind {
+            TransactionType::Unspecified => return Err(ProtoError::MissingField("type")),
+            TransactionType::Deposit => crate::TransactionType::Deposit { amount: checked()? },
+            TransactionType::Withdrawal => {
+                crate::TransactionType::Withdrawal { amount: checked()? }
+            }
+            TransactionType::Dispute => crate::TransactionType::Dispute,
+            TransactionType::Resolve => crate::TransactionType::Resolve,
+            TransactionType::Chargeback => crate::TransactionType::Chargeback,
+            TransactionType::Interest => {
+                let rate = msg.amount.ok_or(ProtoError::MissingField("amount"))?;
+                crate::TransactionType::Interest {
+                    rate_bps: rate.trim().parse().map_err(|_| ProtoError::InvalidField {
+                        field: "amount",
+                        reason: format!("`{}` is not a whole number of basis points", rate),
+                    })?,
+                }
+            }
+            TransactionType::Refund => crate::TransactionType::Refund {
+                of_tx: msg.of_tx.ok_or(ProtoError::MissingField("of_tx"))?,
+                amount: required()?,
+            },
+            TransactionType::Hold => crate::TransactionType::Hold {
+                amount: required()?,
+            },
+            TransactionType::Release => crate::TransactionType::Release {
+                of_tx: msg.of_tx.ok_or(ProtoError::MissingField("of_tx"))?,
+            },
+            TransactionType::Custom => crate::TransactionType::Other {
+                tag: msg.tag.ok_or(ProtoError::MissingField("tag"))?,
+                amount,
+            },
+        };
+
+        Ok(crate::Transaction::new(tx_type, client, tx))
+    }
+}
+
+impl<A: Money> From<&crate::Transaction<A>> for Transaction {
+    fn from(tx: &crate::Transaction<A>) -> Self {
+        let of_tx = match tx.tx_type() {
+            crate::TransactionType::Refund { of_tx, .. }
+            | crate::TransactionType::Release { of_tx } => Some(*of_tx),
+            _ => None,
+        };
+
+        let (kind, amount, tag) = match tx.tx_type() {
+            crate::TransactionType::Deposit { amount } => (
+                TransactionType::Deposit,
+                Some(amount.get().to_string()),
+                None,
+            ),
+            crate::TransactionType::Withdrawal { amount } => (
+                TransactionType::Withdrawal,
+                Some(amount.get().to_string()),
+                None,
+            ),
+            crate::TransactionType::Dispute => (TransactionType::Dispute, None, None),
+            crate::TransactionType::Resolve => (TransactionType::Resolve, None, None),
+            crate::TransactionType::Chargeback => (TransactionType::Chargeback, None, None),
+            crate::TransactionType::Interest { rate_bps } => {
+                (TransactionType::Interest, Some(rate_bps.to_string()), None)
+            }
+            crate::TransactionType::Refund { amount, .. } => {
+                (TransactionType::Refund, Some(amount.to_string()), None)
+            }
+            crate::TransactionType::Hold { amount } => {
+                (TransactionType::Hold, Some(amount.to_string()), None)
+            }
+            crate::TransactionType::Release { .. } => (TransactionType::Release, None, None),
+            crate::TransactionType::Other { tag, amount } => (
+                TransactionType::Custom,
+                amount.as_ref().map(ToString::to_string),
+                Some(tag.clone()),
+            ),
+        };
+
+        Self {
+            r#type: kind.into(),
+            client: Some(tx.client().into()),
+            tx: Some(tx.tx()),
+            amount,
+            tag,
+            of_tx,
+        }
+    }
+}
+
+impl<A: Money> From<&crate::Account<A>> for Account {
+    fn from(account: &crate::Account<A>) -> Self {
+        Self {
+            client: account.id().into(),
+            available: account.available().to_string(),
+            held: account.held().to_string(),
+            total: account.total().to_string(),
+            locked: account.locked(),
+        }
+    }
+}
+
+impl ExecuteResult {
+    fn rejection(code: &str, message: String) -> Self {
+        Self {
+            result: Some(execute_result::Result::Rejection(Rejection {
+                code: code.to_string(),
+                message,
+            })),
+        }
+    }
+}
+
+/// Struct is an iterator converting the messages of a stream of length-delimited `Transaction`s,
+/// as written by `Message::encode_length_delimited`. Returned by `decode_length_delimited`.
+///
+/// A message that cannot be decoded breaks the framing of the stream, so the iterator ends after
+/// yielding a `ProtoError::Decode` for it.
+#[derive(Clone, Debug)]
+pub struct LengthDelimited<'a, A = f64> {
+    buf: &'a [u8],
+    _amount: PhantomData<A>,
+}
+
+/// Function returns an iterator over the transactions of a length-delimited stream.
+pub fn decode_length_delimited<A: Money>(buf: &[u8]) -> LengthDelimited<'_, A> {
+    LengthDelimited {
+        buf,
+        _amount: PhantomData,
+    }
+}
+
+impl<A: Money> Iterator for LengthDelimited<'_, A> {
+    type Item = Result<crate::Transaction<A>, ProtoError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        match Transaction::decode_length_delimited(&mut self.buf) {
+            Ok(msg) => Some(crate::Transaction::try_from(msg)),
+            Err(e) => {
+                self.buf = &[];
+                Some(Err(ProtoError::Decode(e.to_string())))
+            }
+        }
+    }
+}
+
+impl<H: BuildHasher, A: Money> crate::State<H, A> {
+    /// Function will execute every transaction of a stream of length-delimited `Transaction`s.
+    ///
+    /// # Returns
+    /// Returns the `ExecuteResult` of every message, in order. Messages that cannot be converted
+    /// are rejected with the `InvalidRecord` code. Returns `ProtoError::Decode` without executing
+    /// anything if the framing of the stream is broken.
+    pub fn execute_length_delimited(
+        &mut self,
+        mut buf: &[u8],
+    ) -> Result<Vec<ExecuteResult>, ProtoError> {
+        let mut msgs = Vec::new();
+        while !buf.is_empty() {
+            msgs.push(
+                Transaction::decode_length_delimited(&mut buf)
+                    .map_err(|e| ProtoError::Decode(e.to_string()))?,
+            );
+        }
+
+        Ok(msgs
+            .into_iter()
+            .map(|msg| self.execute_message(msg))
+            .collect())
+    }
+
+    /// Function will convert and execute a single decoded message. Messages that cannot be
+    /// converted are rejected with the `InvalidRecord` code.
+    pub(crate) fn execute_message(&mut self, msg: Transaction) -> ExecuteResult {
+        let tx = match crate::Transaction::try_from(msg) {
+            Ok(tx) => tx,
+            Err(e) => return ExecuteResult::rejection("InvalidRecord", e.to_string()),
+        };
+
+        let client = tx.client();
+        match self.execute(tx) {
+            Ok(()) => ExecuteResult {
+                result: Some(execute_result::Result::Account(Account::from(
+                    &self.accounts[&client],
+                ))),
+            },
+            Err(e) => ExecuteResult::rejection(e.code(), e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use crate::State;
+
+    #[test]
+    fn test_round_trip() {
+        let types = [
+            crate::TransactionType::Deposit {
+                amount: checked(1.5),
+            },
+            crate::TransactionType::Withdrawal {
+                amount: checked(0.0001),
+            },
+            crate::TransactionType::Dispute,
+            crate::TransactionType::Resolve,
+            crate::TransactionType::Chargeback,
+            crate::TransactionType::Refund {
+                of_tx: 7,
+                amount: 0.5,
+            },
+            crate::TransactionType::Hold { amount: 3.0 },
+            crate::TransactionType::Release { of_tx: 9 },
+            crate::TransactionType::Other {
+                tag: "fee".into(),
+                amount: Some(2.25),
+            },
+            crate::TransactionType::Other {
+                tag: "freeze".into(),
+                amount: None,
+            },
+        ];
+
+        for tx_type in types {
+            let tx = crate::Transaction::new(tx_type, u16::MAX, u32::MAX);
+            let bytes = Transaction::from(&tx).encode_to_vec();
+            let decoded = Transaction::decode(bytes.as_slice()).unwrap();
+
+            assert_eq!(crate::Transaction::try_from(decoded), Ok(tx));
+        }
+
+        let tx = crate::Transaction::new(
+            crate::TransactionType::Deposit {
+                amount: checked(rust_decimal::Decimal::new(1, 4)),
+            },
+            1,
+            2,
+        );
+        let msg = Transaction::from(&tx);
+        assert_eq!(msg.amount.as_deref(), Some("0.0001"));
+        assert_eq!(crate::Transaction::try_from(msg), Ok(tx));
+    }
+
+    #[test]
+    fn test_account() {
+        let mut state = State::default();
+        state
+            .execute(crate::Transaction::new(
+                crate::TransactionType::Deposit {
+                    amount: checked(10.5),
+                },
+                3,
+                1,
+            ))
+            .unwrap();
+
+        assert_eq!(
+            Account::from(state.accounts().next().unwrap()),
+            Account {
+                client: 3,
+                available: "10.5".into(),
+                held: "0".into(),
+                total: "10.5".into(),
+                locked: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        let msg = Transaction {
+            r#type: TransactionType::Deposit.into(),
+            client: Some(1),
+            tx: Some(1),
+            amount: Some("1".into()),
+            tag: None,
+            of_tx: None,
+        };
+        let convert = |msg: Transaction| crate::Transaction::<f64>::try_from(msg).unwrap_err();
+
+        assert_eq!(
+            convert(Transaction {
+                r#type: 42,
+                ..msg.clone()
+            }),
+            ProtoError::UnknownType(42)
+        );
+        assert_eq!(
+            convert(Transaction {
+                r#type: 0,
+                ..msg.clone()
+            }),
+            ProtoError::MissingField("type")
+        );
+        assert_eq!(
+            convert(Transaction {
+                client: None,
+                ..msg.clone()
+            }),
+            ProtoError::MissingField("client")
+        );
+        assert_eq!(
+            convert(Transaction {
+                tx: None,
+                ..msg.clone()
+            }),
+            ProtoError::MissingField("tx")
+        );
+        assert_eq!(
+            convert(Transaction {
+                amount: None,
+                ..msg.clone()
+            }),
+            ProtoError::MissingField("amount")
+        );
+        assert_eq!(
+            convert(Transaction {
+                r#type: TransactionType::Custom.into(),
+                ..msg.clone()
+            }),
+            ProtoError::MissingField("tag")
+        );
+        assert!(matches!(
+            convert(Transaction {
+                client: Some(70000),
+                ..msg.clone()
+            }),
+            ProtoError::InvalidField {
+                field: "client",
+                ..
+            }
+        ));
+        assert!(matches!(
+            convert(Transaction {
+                amount: Some("abc".into()),
+                ..msg
+            }),
+            ProtoError::InvalidField {
+                field: "amount",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_hand_crafted_stream() {
+        #[rustfmt::skip]
+        let bytes = [
+            // deposit, client 1, tx 1, amount "1.5"
+            11, 0x08, 1, 0x10, 1, 0x18, 1, 0x22, 3, b'1', b'.', b'5',
+            // withdrawal, client 1, tx 2, amount "4"
+            9, 0x08, 2, 0x10, 1, 0x18, 2, 0x22, 1, b'4',
+            // unknown type 42, client 1, tx 3
+            6, 0x08, 42, 0x10, 1, 0x18, 3,
+            // dispute, client 1, tx 1
+            6, 0x08, 3, 0x10, 1, 0x18, 1,
+        ];
+
+        let txs: Vec<_> = decode_length_delimited::<f64>(&bytes).collect();
+        assert_eq!(txs.len(), 4);
+        assert_eq!(
+            txs[0],
+            Ok(crate::Transaction::new(
+                crate::TransactionType::Deposit {
+                    amount: checked(1.5)
+                },
+                1,
+                1
+            ))
+        );
+        assert_eq!(txs[2], Err(ProtoError::UnknownType(42)));
+
+        let mut state = State::default();
+        let results = state.execute_length_delimited(&bytes).unwrap();
+        let codes: Vec<_> = results
+            .iter()
+            .map(|x| match x.result.as_ref().unwrap() {
+                execute_result::Result::Account(_) => "ok",
+                execute_result::Result::Rejection(x) => x.code.as_str(),
+            })
+            .collect();
+        assert_eq!(codes, ["ok", "NotEnoughFunds", "InvalidRecord", "ok"]);
+        assert_eq!(
+            results[3].result,
+            Some(execute_result::Result::Account(Account {
+                client: 1,
+                available: "0".into(),
+                held: "1.5".into(),
+                total: "1.5".into(),
+                locked: false,
+            }))
+        );
+
+        // A truncated stream is rejected before anything is executed.
+        let mut state = State::default();
+        assert!(matches!(
+            state.execute_length_delimited(&bytes[..bytes.len() - 1]),
+            Err(ProtoError::Decode(_))
+        ));
+        assert_eq!(state.accounts().count(), 0);
+
+        let txs: Vec<_> = decode_length_delimited::<f64>(&bytes[..5]).collect();
+        assert!(matches!(txs[..], [Err(ProtoError::Decode(_))]));
+    }
+}
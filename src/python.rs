@@ -0,0 +1,219 @@
+//! This module contains the Python bindings of the state machine, built with the `python` feature
+//! and packaged with maturin through `pyproject.toml`.
+//!
+//! Transactions are passed in as dicts with the same keys as the columns of a CSV document,
+//! `type`, `client`, `tx` and an optional `amount`. Values are converted with `str()` and parsed
+//! like CSV fields, so amounts can be given as strings to avoid float rounding.
+
+use super::*;
+use crate::ingest::{parse_record, HEADERS};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyIOError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+create_exception!(
+    corken,
+    CorkenError,
+    PyException,
+    "Raised when a transaction is rejected. Carries the `code` of the error along with the \
+     `client` and `tx` of the transaction, when known."
+);
+
+/// Function will build a `CorkenError` with the given `code`, message and transaction context.
+fn error(
+    py: Python<'_>,
+    code: &str,
+    message: String,
+    client: Option<u16>,
+    tx: Option<u32>,
+) -> PyResult<PyErr> {
+    let err = CorkenError::new_err(message);
+    let value = err.value(py);
+    value.setattr("code", code)?;
+    value.setattr("client", client)?;
+    value.setattr("tx", tx)?;
+
+    Ok(err)
+}
+
+/// Function will convert a dict into a transaction.
+fn transaction(py: Python<'_>, tx: &Bound<'_, PyAny>) -> PyResult<Transaction> {
+    let dict = tx.downcast::<PyDict>()?;
+
+    let mut fields = Vec::with_capacity(HEADERS.len());
+    for key in HEADERS {
+        let value = match dict.get_item(key)? {
+            Some(x) if !x.is_none() => Some(x.str()?.to_cow()?.into_owned()),
+            _ => None,
+        };
+        fields.push(value);
+    }
+
+    match parse_record(|i| Ok(fields.get(i).and_then(Option::as_deref))) {
+        Ok(tx) => Ok(tx),
+        Err(e) => Err(error(py, "InvalidRecord", e.to_string(), None, None)?),
+    }
+}
+
+/// Class wrapping a state machine with the default policies.
+#[pyclass(name = "State", module = "corken")]
+#[derive(Default)]
+pub struct PyState {
+    state: State,
+}
+
+impl PyState {
+    /// Function will convert `tx` and execute it.
+    fn execute_one(&mut self, py: Python<'_>, tx: &Bound<'_, PyAny>) -> PyResult<()> {
+        let tx = transaction(py, tx)?;
+        let (client, id) = (tx.client, tx.tx);
+
+        match self.state.execute(tx) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(error(py, e.code(), e.to_string(), Some(client), Some(id))?),
+        }
+    }
+}
+
+#[pymethods]
+impl PyState {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Executes a single transaction, raising `CorkenError` if it is rejected.
+    fn execute(&mut self, py: Python<'_>, tx: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.execute_one(py, tx)
+    }
+
+    /// Executes a list of transactions in order, returning a list with `None` for every
+    /// transaction that was executed and the `CorkenError` for every one that was rejected.
+    fn execute_many(&mut self, py: Python<'_>, txs: &Bound<'_, PyList>) -> PyResult<PyObject> {
+        let results = PyList::empty(py);
+        for tx in txs.iter() {
+            match self.execute_one(py, &tx) {
+                Ok(()) => results.append(py.None())?,
+                Err(e) if e.is_instance_of::<CorkenError>(py) => results.append(e.value(py))?,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(results.into_any().unbind())
+    }
+
+    /// Returns the accounts, ordered by client id, as a dict of columns that can be passed
+    /// straight to `pandas.DataFrame`.
+    fn accounts(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let accounts: Vec<_> = self.state.accounts_iter_by_id().collect();
+
+        let columns = PyDict::new(py);
+        columns.set_item("client", accounts.iter().map(|x| x.id).collect::<Vec<_>>())?;
+        columns.set_item(
+            "available",
+            accounts.iter().map(|x| x.available).collect::<Vec<_>>(),
+        )?;
+        columns.set_item("held", accounts.iter().map(|x| x.held).collect::<Vec<_>>())?;
+        columns.set_item(
+            "total",
+            accounts.iter().map(|x| x.total).collect::<Vec<_>>(),
+        )?;
+        columns.set_item(
+            "locked",
+            accounts.iter().map(|x| x.locked).collect::<Vec<_>>(),
+        )?;
+
+        Ok(columns.into_any().unbind())
+    }
+
+    /// Builds a state machine out of the transactions of a CSV document with a header row.
+    /// Rejected transactions are skipped, and `OSError` is raised if the file cannot be read.
+    #[staticmethod]
+    fn from_csv(path: &str) -> PyResult<Self> {
+        let rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+
+        let (state, _) = State::from_fallible_iterator(rdr.into_deserialize());
+
+        Ok(Self { state })
+    }
+}
+
+/// The `corken` Python module.
+#[pymodule]
+fn corken(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyState>()?;
+    m.add("CorkenError", m.py().get_type::<CorkenError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pyo3::ffi::c_str;
+    use std::ffi::CString;
+
+    /// Function will run every `test_` function of the pytest module at `python/tests`, with this
+    /// crate importable as `corken`.
+    #[test]
+    fn test_python() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "corken").unwrap();
+            corken(&module).unwrap();
+            py.import("sys")
+                .unwrap()
+                .getattr("modules")
+                .unwrap()
+                .set_item("corken", module)
+                .unwrap();
+
+            let tests = PyModule::from_code(
+                py,
+                &CString::new(include_str!("../python/tests/test_corken.py")).unwrap(),
+                c_str!("test_corken.py"),
+                c_str!("test_corken"),
+            )
+            .unwrap();
+
+            let dir = tempfile::tempdir().unwrap();
+            let tmp_path = py
+                .import("pathlib")
+                .unwrap()
+                .getattr("Path")
+                .unwrap()
+                .call1((dir.path(),))
+                .unwrap();
+
+            for name in tests.dir().unwrap() {
+                let name: String = name.extract().unwrap();
+                if !name.starts_with("test_") {
+                    continue;
+                }
+
+                let test = tests.getattr(name.as_str()).unwrap();
+                let params = test
+                    .getattr("__code__")
+                    .unwrap()
+                    .getattr("co_argcount")
+                    .unwrap()
+                    .extract::<usize>()
+                    .unwrap();
+                let result = if params == 0 {
+                    test.call0()
+                } else {
+                    test.call1((&tmp_path,))
+                };
+
+                if let Err(e) = result {
+                    e.print(py);
+                    panic!("{} failed", name);
+                }
+            }
+        });
+    }
+}
@@ -4,11 +4,21 @@ use super::*;
 use rand::distributions::{Distribution, Standard};
 use rand::Rng;
 
-impl Distribution<TransactionType> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TransactionType {
+/// Function will generate a random amount between `0` and `1`.
+fn amount<A: Money, R: Rng + ?Sized>(rng: &mut R) -> Amount<A> {
+    let amount = A::from_f64(rng.gen()).unwrap_or(A::ZERO);
+    Amount::new(amount).expect("amounts between 0 and 1 are valid")
+}
+
+impl<A: Money> Distribution<TransactionType<A>> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TransactionType<A> {
         match rng.gen_range(0..=4) {
-            0 => TransactionType::Deposit { amount: rng.gen() },
-            1 => TransactionType::Withdrawal { amount: rng.gen() },
+            0 => TransactionType::Deposit {
+                amount: amount(rng),
+            },
+            1 => TransactionType::Withdrawal {
+                amount: amount(rng),
+            },
             2 => TransactionType::Dispute,
             3 => TransactionType::Resolve,
             _ => TransactionType::Chargeback,
@@ -16,12 +26,16 @@ impl Distribution<TransactionType> for Standard {
     }
 }
 
-impl Distribution<Transaction> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Transaction {
+impl<A: Money> Distribution<Transaction<A>> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Transaction<A> {
         Transaction {
             tx_type: rng.gen(),
             client: rng.gen(),
             tx: rng.gen(),
+            currency: None,
+            timestamp: None,
+            row_id: None,
+            event_id: None,
         }
     }
 }
@@ -0,0 +1,271 @@
+//! This module contains the ranking of accounts by their balance.
+
+use super::*;
+use std::cmp::Ordering;
+
+/// Struct orders amounts totally, so that they can be kept in a `BinaryHeap`.
+///
+/// # Notes
+/// Balances are always finite, so incomparable amounts are treated as equal.
+#[derive(Clone, Copy, PartialEq)]
+struct Balance<A>(A);
+
+impl<A: PartialEq> Eq for Balance<A> {}
+
+impl<A: PartialOrd> PartialOrd for Balance<A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A: PartialOrd> Ord for Balance<A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Returns the `n` smallest keys in ascending order, in O(m log n) time and O(n) space.
+fn smallest_n<K: Ord>(keys: impl Iterator<Item = K>, n: usize) -> Vec<K> {
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for key in keys {
+        heap.push(key);
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+
+    heap.into_sorted_vec()
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Returns up to `n` accounts with the highest `available` balance, in descending order of
+    /// their balance. Ties are broken in favour of the lowest client id.
+    pub fn top_n_accounts_by_balance(&self, n: usize) -> Vec<&Account<A>> {
+        let keys = self
+            .accounts
+            .values()
+            .map(|x| (Reverse(Balance(x.available)), x.id));
+
+        smallest_n(keys, n)
+            .into_iter()
+            .map(|(_, id)| &self.accounts[&id])
+            .collect()
+    }
+
+    /// Returns up to `n` accounts with the lowest `available` balance, in ascending order of their
+    /// balance. Ties are broken in favour of the lowest client id.
+    pub fn bottom_n_accounts_by_balance(&self, n: usize) -> Vec<&Account<A>> {
+        let keys = self.accounts.values().map(|x| (Balance(x.available), x.id));
+
+        smallest_n(keys, n)
+            .into_iter()
+            .map(|(_, id)| &self.accounts[&id])
+            .collect()
+    }
+
+    /// Returns the client id and `available` balance of the account with the highest
+    /// `available` balance, in O(n) time. Ties are broken in favour of the lowest client id.
+    pub fn max_balance_account(&self) -> Option<(u16, A)> {
+        self.accounts
+            .values()
+            .max_by_key(|x| (Balance(x.available), Reverse(x.id)))
+            .map(|x| (x.id, x.available))
+    }
+
+    /// Returns the client id and `available` balance of the account with the lowest `available`
+    /// balance, which may be negative after a chargeback, in O(n) time. Ties are broken in favour
+    /// of the lowest client id.
+    pub fn min_balance_account(&self) -> Option<(u16, A)> {
+        self.accounts
+            .values()
+            .min_by_key(|x| (Balance(x.available), x.id))
+            .map(|x| (x.id, x.available))
+    }
+
+    /// Returns the client id and `total` balance of every locked account, in descending order of
+    /// the absolute value of their balance, so that recovery of charged back accounts can start
+    /// with the largest amounts at stake. Ties are broken in favour of the lowest client id.
+    pub fn locked_account_balance_report(&self) -> Vec<(u16, A)> {
+        let mut report: Vec<_> = self
+            .accounts
+            .values()
+            .filter(|x| x.locked)
+            .map(|x| (x.id, x.total))
+            .collect();
+
+        report.sort_by_key(|(id, total)| (Reverse(Balance(total.abs())), *id));
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    #[test]
+    fn test_accounts_by_balance() {
+        let mut state = State::default();
+        assert!(state.top_n_accounts_by_balance(3).is_empty());
+
+        let balances = [50.0, 10.0, 75.5, 3.0, 120.0, 0.5, 75.5, 42.0, 8.0, 99.0];
+        for (client, amount) in (1..).zip(balances) {
+            let deposit = TransactionType::Deposit {
+                amount: checked(amount),
+            };
+            state
+                .execute(Transaction::new(deposit, client, client as u32))
+                .unwrap();
+        }
+
+        let ids = |accounts: Vec<&Account>| accounts.iter().map(|x| x.id).collect::<Vec<_>>();
+        assert_eq!(ids(state.top_n_accounts_by_balance(3)), [5, 10, 3]);
+        assert_eq!(ids(state.top_n_accounts_by_balance(4)), [5, 10, 3, 7]);
+        assert_eq!(ids(state.bottom_n_accounts_by_balance(3)), [6, 4, 9]);
+        assert!(state.bottom_n_accounts_by_balance(0).is_empty());
+        assert_eq!(state.top_n_accounts_by_balance(20).len(), 10);
+
+        // Only the available balance is ranked.
+        state
+            .execute(Transaction::new(TransactionType::Dispute, 5, 5))
+            .unwrap();
+        assert_eq!(ids(state.top_n_accounts_by_balance(3)), [10, 3, 7]);
+        assert_eq!(ids(state.bottom_n_accounts_by_balance(3)), [5, 6, 4]);
+    }
+
+    #[test]
+    fn test_extreme_balance_accounts() {
+        let mut state = State::default();
+        assert_eq!(state.max_balance_account(), None);
+        assert_eq!(state.min_balance_account(), None);
+
+        for client in [3, 1, 2] {
+            let deposit = TransactionType::Deposit {
+                amount: checked(5.0),
+            };
+            state
+                .execute(Transaction::new(deposit, client, client as u32))
+                .unwrap();
+        }
+        assert_eq!(state.max_balance_account(), Some((1, 5.0)));
+        assert_eq!(state.min_balance_account(), Some((1, 5.0)));
+
+        let txs = [
+            (
+                TransactionType::Deposit {
+                    amount: checked(1000.0),
+                },
+                2,
+                4,
+            ),
+            (
+                TransactionType::Withdrawal {
+                    amount: checked(3.0),
+                },
+                3,
+                5,
+            ),
+            (TransactionType::Dispute, 3, 3),
+            (TransactionType::Chargeback, 3, 3),
+        ];
+        for (tx_type, client, tx) in txs {
+            state
+                .execute(Transaction::new(tx_type, client, tx))
+                .unwrap();
+        }
+        assert_eq!(state.max_balance_account(), Some((2, 1005.0)));
+        assert_eq!(state.min_balance_account(), Some((3, -3.0)));
+    }
+
+    #[test]
+    fn test_locked_account_balance_report() {
+        let mut state = State::default();
+        assert!(state.locked_account_balance_report().is_empty());
+
+        let txs = [
+            // Charged back with funds left over.
+            (
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            (
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                1,
+                2,
+            ),
+            (TransactionType::Dispute, 1, 2),
+            (TransactionType::Chargeback, 1, 2),
+            // Charged back after the funds were withdrawn.
+            (
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                2,
+                3,
+            ),
+            (
+                TransactionType::Withdrawal {
+                    amount: checked(8.0),
+                },
+                2,
+                4,
+            ),
+            (TransactionType::Dispute, 2, 3),
+            (TransactionType::Chargeback, 2, 3),
+            // Charged back down to nothing.
+            (
+                TransactionType::Deposit {
+                    amount: checked(4.0),
+                },
+                3,
+                5,
+            ),
+            (TransactionType::Dispute, 3, 5),
+            (TransactionType::Chargeback, 3, 5),
+            // Ties with client 2 in absolute value.
+            (
+                TransactionType::Deposit {
+                    amount: checked(8.0),
+                },
+                4,
+                6,
+            ),
+            (
+                TransactionType::Deposit {
+                    amount: checked(1.0),
+                },
+                4,
+                7,
+            ),
+            (TransactionType::Dispute, 4, 7),
+            (TransactionType::Chargeback, 4, 7),
+            // Not locked, so not reported.
+            (
+                TransactionType::Deposit {
+                    amount: checked(100.0),
+                },
+                5,
+                8,
+            ),
+        ];
+        for (tx_type, client, tx) in txs {
+            state
+                .execute(Transaction::new(tx_type, client, tx))
+                .unwrap();
+        }
+
+        assert_eq!(
+            state.locked_account_balance_report(),
+            [(1, 10.0), (2, -8.0), (4, 8.0), (3, 0.0)]
+        );
+    }
+}
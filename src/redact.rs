@@ -0,0 +1,96 @@
+//! This module contains the redacted rendering of transactions used for compliant logging.
+
+use super::*;
+use std::fmt;
+
+/// Struct is a display adapter rendering a transaction without its amount and with all but the
+/// last digit of its client id masked, e.g. `deposit client=**7 tx=12 amount=***`. Returned by
+/// `Transaction::redacted`.
+///
+/// The full transaction, including the amount, is still available through its `Debug` impl for
+/// contexts that have explicitly opted into logging it.
+#[derive(Clone, Copy, Debug)]
+pub struct Redacted<'a, A = f64>(&'a Transaction<A>);
+
+impl<A> Transaction<A> {
+    /// Returns an adapter displaying the transaction with its amount and client id masked.
+    pub fn redacted(&self) -> Redacted<'_, A> {
+        Redacted(self)
+    }
+}
+
+impl<A> fmt::Display for Redacted<'_, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tx = self.0;
+        let has_amount = match &tx.tx_type {
+            TransactionType::Deposit { .. }
+            | TransactionType::Withdrawal { .. }
+            | TransactionType::Refund { .. }
+            | TransactionType::Hold { .. } => true,
+            TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Chargeback
+            | TransactionType::Interest { .. }
+            | TransactionType::Release { .. } => false,
+            TransactionType::Other { amount, .. } => amount.is_some(),
+        };
+
+        let client = tx.client.to_string();
+        let (masked, last) = client.split_at(client.len() - 1);
+        write!(
+            f,
+            "{} client={}{} tx={}",
+            tx.tx_type.tag(),
+            "*".repeat(masked.len()),
+            last,
+            tx.tx
+        )?;
+
+        if has_amount {
+            f.write_str(" amount=***")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    #[test]
+    fn test_redacted() {
+        let deposit = Transaction::new(
+            TransactionType::Deposit {
+                amount: checked(12.5),
+            },
+            1234,
+            7,
+        );
+        assert_eq!(
+            deposit.redacted().to_string(),
+            "deposit client=***4 tx=7 amount=***"
+        );
+        assert_eq!(
+            format!("{:?}", deposit),
+            "Transaction { tx_type: Deposit { amount: Amount(12.5) }, client: 1234, tx: 7, currency: None, timestamp: None, row_id: None, event_id: None }"
+        );
+
+        let dispute = Transaction::<f64>::new(TransactionType::Dispute, 5, 7);
+        assert_eq!(dispute.redacted().to_string(), "dispute client=5 tx=7");
+
+        let other = Transaction::new(
+            TransactionType::Other {
+                tag: "bonus".into(),
+                amount: Some(1.0),
+            },
+            42,
+            8,
+        );
+        assert_eq!(
+            other.redacted().to_string(),
+            "bonus client=*2 tx=8 amount=***"
+        );
+    }
+}
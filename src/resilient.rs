@@ -0,0 +1,137 @@
+//! This module contains a replay mode surviving panics raised while executing a single transaction.
+
+use super::*;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Function returns the message of a panic payload, if it carries one.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+impl State {
+    /// Function behaves like `from_iterator`, except that a panic raised while executing one
+    /// transaction is caught and logged, and the replay carries on with the next transaction
+    /// instead of taking the whole batch down.
+    ///
+    /// # Arguments
+    /// * `txs` - Iterator over owned `Transactions`.
+    ///
+    /// # Notes
+    /// `&mut State` is not `UnwindSafe`, and is asserted to be so here. A transaction panicking
+    /// half way through its execution may leave the state machine inconsistent, e.g. with an
+    /// account debited but the transaction missing from the cache, and every transaction after it
+    /// gets executed against that state. This is meant to keep a batch going in face of bugs, not
+    /// as a substitute for fixing them.
+    ///
+    /// Panics are only caught if the crate is built with `panic = "unwind"`, and the panic hook
+    /// still runs for each of them, printing the message to stderr by default. Panics raised by the
+    /// iterator itself are not caught.
+    pub fn from_iterator_resilient(txs: impl Iterator<Item = Transaction>) -> Self {
+        let mut this = Self::default();
+        this.execute_resilient(txs);
+
+        this
+    }
+
+    /// Function will execute `txs` in order, catching and logging the panics raised by each of
+    /// them. See `from_iterator_resilient`.
+    fn execute_resilient(&mut self, txs: impl Iterator<Item = Transaction>) {
+        for tx in txs {
+            let id = tx.tx;
+            match panic::catch_unwind(AssertUnwindSafe(|| self.execute(tx))) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => log::debug!("Transaction {} rejected: {}", id, e),
+                Err(payload) => log::error!(
+                    "Transaction {} panicked: {}",
+                    id,
+                    panic_message(payload.as_ref())
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::custom::{AccountHandle, CustomHandler, CustomRecord};
+    use crate::test::checked;
+
+    /// Panics on every transaction it is handed.
+    #[derive(Debug)]
+    struct Explode;
+
+    impl CustomHandler for Explode {
+        fn handle(&self, record: &CustomRecord, _: &mut AccountHandle) -> Result<(), TxError> {
+            panic!("engineered panic on transaction {}", record.tx);
+        }
+    }
+
+    #[test]
+    fn test_from_iterator_resilient() {
+        let deposit = |client, tx, amount| {
+            Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(amount),
+                },
+                client,
+                tx,
+            )
+        };
+        let explode = |client, tx| {
+            let tx_type = TransactionType::Other {
+                tag: "explode".into(),
+                amount: None,
+            };
+            Transaction::new(tx_type, client, tx)
+        };
+        let txs = || {
+            vec![
+                deposit(1, 1, 10.0),
+                explode(2, 2),
+                Transaction::new(
+                    TransactionType::Withdrawal {
+                        amount: checked(50.0),
+                    },
+                    1,
+                    3,
+                ),
+                deposit(1, 4, 1.0),
+                deposit(3, 5, 2.0),
+            ]
+        };
+        let state = || {
+            State::builder()
+                .custom_handler("explode", Explode)
+                .build()
+                .unwrap()
+        };
+
+        let mut resilient = state();
+        resilient.execute_resilient(txs().into_iter());
+        assert_eq!(resilient.accounts[&1].available, 11.0);
+        assert_eq!(resilient.accounts[&3].available, 2.0);
+        assert!(!resilient.accounts.get(&2).is_some_and(|x| x.total != 0.0));
+
+        let result = panic::catch_unwind(|| {
+            let mut state = state();
+            for tx in txs() {
+                let _ = state.execute(tx);
+            }
+        });
+        assert_eq!(
+            panic_message(result.unwrap_err().as_ref()),
+            "engineered panic on transaction 2"
+        );
+
+        let state = State::from_iterator_resilient(txs().into_iter().filter(|x| x.tx != 2));
+        assert_eq!(state.accounts[&1].available, 11.0);
+    }
+}
@@ -0,0 +1,390 @@
+//! This module contains the JSON Schemas of the documents read and written by the state machine,
+//! built with the `schemars` feature and printed by `corken schema --type <type>`.
+//!
+//! Transactions are described with the built-in `type`s only. Transactions of a custom type are
+//! still accepted by the state machine, but dont validate against the schema.
+
+use super::*;
+use crate::fallible::RejectionRecord;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{
+    InstanceType, Metadata, ObjectValidation, RootSchema, Schema, SchemaObject, StringValidation,
+    SubschemaValidation,
+};
+use schemars::{schema_for, JsonSchema};
+
+/// The names accepted by `schema_by_name`.
+pub const SCHEMA_TYPES: &[&str] = &[
+    "transaction",
+    "account",
+    "rejection",
+    #[cfg(feature = "server")]
+    "receipt",
+];
+
+/// The built-in transaction types.
+const TYPES: [&str; 9] = [
+    "deposit",
+    "withdrawal",
+    "dispute",
+    "resolve",
+    "chargeback",
+    "interest",
+    "refund",
+    "hold",
+    "release",
+];
+
+/// The transaction types requiring an amount.
+const FUNDED_TYPES: [&str; 5] = ["deposit", "withdrawal", "interest", "refund", "hold"];
+
+/// The transaction types referencing another transaction through `of_tx`.
+const REFERENCING_TYPES: [&str; 2] = ["refund", "release"];
+
+/// The transaction types that cannot reference another transaction through `ref_tx`.
+const NON_DISPUTE_TYPES: [&str; 6] = [
+    "deposit",
+    "withdrawal",
+    "interest",
+    "refund",
+    "hold",
+    "release",
+];
+
+/// Matches the amounts accepted as strings, surrounding whitespace included.
+const AMOUNT_PATTERN: &str = r"^\s*[+-]?([0-9]+(\.[0-9]*)?|\.[0-9]+)\s*$";
+
+/// Matches blank strings, which are read as a missing amount.
+const BLANK_PATTERN: &str = r"^\s*$";
+
+/// Returns the schema of a string that must be one of `values`.
+fn enum_schema(values: &[&str]) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        enum_values: Some(values.iter().map(|x| (*x).into()).collect()),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Returns the schema of a string matching `pattern`.
+fn pattern_schema(pattern: &str) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        string: Some(Box::new(StringValidation {
+            pattern: Some(pattern.into()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Returns the schema of an amount, given either as a number or as a string. Unless `required`
+/// is set, the amount may also be null or a blank string.
+fn amount_schema<A: JsonSchema>(gen: &mut SchemaGenerator, required: bool) -> Schema {
+    let mut any_of = vec![gen.subschema_for::<A>(), pattern_schema(AMOUNT_PATTERN)];
+    if !required {
+        any_of.push(pattern_schema(BLANK_PATTERN));
+        any_of.push(
+            SchemaObject {
+                instance_type: Some(InstanceType::Null.into()),
+                ..Default::default()
+            }
+            .into(),
+        );
+    }
+
+    SchemaObject {
+        subschemas: Some(Box::new(SubschemaValidation {
+            any_of: Some(any_of),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Returns the schema of an id of type `T`, bounded by `max` since the `format` of the integer
+/// doesnt get validated.
+fn id_schema<T: JsonSchema>(gen: &mut SchemaGenerator, max: u32) -> Schema {
+    let mut schema = gen.subschema_for::<T>().into_object();
+    schema.number().maximum = Some(max.into());
+    schema.into()
+}
+
+/// Returns the schema of an object validated by `object`.
+fn object_schema(object: ObjectValidation) -> SchemaObject {
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(object)),
+        ..Default::default()
+    }
+}
+
+/// Returns the schema forbidding `field` for the transactions of one of `types`.
+fn forbidden_for(types: &[&str], field: &str) -> Schema {
+    let mut condition = ObjectValidation::default();
+    condition
+        .properties
+        .insert("type".into(), enum_schema(types));
+
+    let mut requirement = ObjectValidation::default();
+    requirement.required.insert(field.into());
+
+    SchemaObject {
+        subschemas: Some(Box::new(SubschemaValidation {
+            if_schema: Some(Box::new(object_schema(condition).into())),
+            then_schema: Some(Box::new(
+                SchemaObject {
+                    subschemas: Some(Box::new(SubschemaValidation {
+                        not: Some(Box::new(object_schema(requirement).into())),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                }
+                .into(),
+            )),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Returns the schema requiring `field` to match `schema` for the transactions of one of `types`.
+fn required_for(types: &[&str], field: &str, schema: Schema) -> Schema {
+    let mut condition = ObjectValidation::default();
+    condition
+        .properties
+        .insert("type".into(), enum_schema(types));
+
+    let mut requirement = ObjectValidation::default();
+    requirement.properties.insert(field.into(), schema);
+    requirement.required.insert(field.into());
+
+    SchemaObject {
+        subschemas: Some(Box::new(SubschemaValidation {
+            if_schema: Some(Box::new(object_schema(condition).into())),
+            then_schema: Some(Box::new(object_schema(requirement).into())),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+impl<A: JsonSchema> JsonSchema for TransactionType<A> {
+    fn schema_name() -> String {
+        "TransactionType".into()
+    }
+
+    /// The `type`, `amount` and `of_tx` fields of a transaction.
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut object = ObjectValidation::default();
+        object.properties.insert("type".into(), enum_schema(&TYPES));
+        object
+            .properties
+            .insert("amount".into(), amount_schema::<A>(gen, false));
+        object
+            .properties
+            .insert("of_tx".into(), id_schema::<u32>(gen, u32::MAX));
+        object.required.insert("type".into());
+
+        // NOTE: The amount is only required, and cannot be left blank, for deposits, withdrawals,
+        // interest, where it carries the rate, refunds and manual holds. Refunds and releases
+        // require `of_tx`.
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                all_of: Some(vec![
+                    required_for(&FUNDED_TYPES, "amount", amount_schema::<A>(gen, true)),
+                    required_for(&REFERENCING_TYPES, "of_tx", id_schema::<u32>(gen, u32::MAX)),
+                ]),
+                ..Default::default()
+            })),
+            ..object_schema(object)
+        }
+        .into()
+    }
+}
+
+impl<A: JsonSchema> JsonSchema for Transaction<A> {
+    fn schema_name() -> String {
+        "Transaction".into()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = TransactionType::<A>::json_schema(gen).into_object();
+        schema.metadata = Some(Box::new(Metadata {
+            description: Some("A transaction, as read from a CSV row or a JSON object.".into()),
+            ..Default::default()
+        }));
+
+        let object = schema.object();
+        object
+            .properties
+            .insert("client".into(), id_schema::<u16>(gen, u16::MAX.into()));
+        object
+            .properties
+            .insert("tx".into(), id_schema::<u32>(gen, u32::MAX));
+        object
+            .properties
+            .insert("ref_tx".into(), id_schema::<u32>(gen, u32::MAX));
+        object.required.insert("client".into());
+        object.required.insert("tx".into());
+
+        // NOTE: Only disputes, resolves and chargebacks may carry their own id in `tx`, the
+        // transaction they refer to then being in `ref_tx`.
+        schema
+            .subschemas()
+            .all_of
+            .get_or_insert_with(Vec::new)
+            .push(forbidden_for(&NON_DISPUTE_TYPES, "ref_tx"));
+
+        schema.into()
+    }
+}
+
+/// Returns the schema of a transaction.
+pub fn transaction_schema() -> RootSchema {
+    schema_for!(Transaction)
+}
+
+/// Returns the schema of an account, as written by `corken`.
+pub fn account_schema() -> RootSchema {
+    schema_for!(Account)
+}
+
+/// Returns the schema of a row of the errors file written by `corken --errors-out`.
+pub fn rejection_schema() -> RootSchema {
+    schema_for!(RejectionRecord<'static>)
+}
+
+/// Returns the schema of the outcome of a transaction posted to the HTTP service.
+#[cfg(feature = "server")]
+pub fn receipt_schema() -> RootSchema {
+    schema_for!(server::TxResult)
+}
+
+/// Function returns the schema named `name`, one of `SCHEMA_TYPES`.
+pub fn schema_by_name(name: &str) -> Option<RootSchema> {
+    match name {
+        "transaction" => Some(transaction_schema()),
+        "account" => Some(account_schema()),
+        "rejection" => Some(rejection_schema()),
+        #[cfg(feature = "server")]
+        "receipt" => Some(receipt_schema()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use jsonschema::Validator;
+    use serde_json::{json, Value};
+
+    fn validator(schema: RootSchema) -> Validator {
+        jsonschema::validator_for(&serde_json::to_value(schema).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_transaction_schema() {
+        let validator = validator(transaction_schema());
+
+        let valid = [
+            json!({"type": "deposit", "client": 1, "tx": 1, "amount": 10.5}),
+            json!({"type": "withdrawal", "client": 1, "tx": 2, "amount": " 2.25 "}),
+            json!({"type": "dispute", "client": 1, "tx": 1}),
+            json!({"type": "resolve", "client": 1, "tx": 1, "amount": ""}),
+            json!({"type": "chargeback", "client": 1, "tx": 1, "amount": null}),
+            json!({"type": "refund", "client": 1, "tx": 3, "of_tx": 2, "amount": "1.0"}),
+            json!({"type": "hold", "client": 1, "tx": 4, "amount": 1.0}),
+            json!({"type": "release", "client": 1, "tx": 5, "of_tx": 4}),
+            json!({"type": "dispute", "client": 1, "tx": 6, "ref_tx": 1}),
+        ];
+        for doc in valid {
+            assert!(validator.is_valid(&doc), "{}", doc);
+            assert!(serde_json::from_value::<Transaction>(doc).is_ok());
+        }
+
+        let invalid = [
+            json!({"type": "deposit", "client": 1, "tx": 1}),
+            json!({"type": "withdrawal", "client": 1, "tx": 1, "amount": " "}),
+            json!({"type": "deposit", "client": 1, "tx": 1, "amount": "ten"}),
+            json!({"type": "deposit", "client": 70000, "tx": 1, "amount": 1.0}),
+            json!({"type": "deposit", "tx": 1, "amount": 1.0}),
+            json!({"client": 1, "tx": 1, "amount": 1.0}),
+            json!({"type": "refund", "client": 1, "tx": 3, "amount": 1.0}),
+            json!({"type": "refund", "client": 1, "tx": 3, "of_tx": 2}),
+            json!({"type": "hold", "client": 1, "tx": 4}),
+            json!({"type": "release", "client": 1, "tx": 5}),
+            json!({"type": "deposit", "client": 1, "tx": 6, "ref_tx": 1, "amount": 1.0}),
+        ];
+        for doc in invalid {
+            assert!(!validator.is_valid(&doc), "{}", doc);
+            assert!(serde_json::from_value::<Transaction>(doc).is_err());
+        }
+
+        // Custom types parse, but only the built-in ones are part of the schema.
+        let doc = json!({"type": "fee", "client": 1, "tx": 1, "amount": 1.0});
+        assert!(!validator.is_valid(&doc));
+    }
+
+    #[test]
+    fn test_account_schema() {
+        let validator = validator(account_schema());
+
+        let mut state = State::default();
+        state
+            .execute(Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(1.5),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        let account = serde_json::to_value(&state.accounts[&1]).unwrap();
+        assert!(validator.is_valid(&account));
+
+        let mut properties: Vec<_> = account.as_object().unwrap().keys().collect();
+        properties.sort();
+        assert_eq!(
+            properties,
+            ["available", "client", "held", "locked", "total"]
+        );
+        assert!(!validator.is_valid(&json!({"client": 1, "available": 1.5})));
+    }
+
+    #[test]
+    fn test_rejection_schema() {
+        let validator = validator(rejection_schema());
+
+        let record = RejectionRecord {
+            client: Some(1),
+            tx: Some(2),
+            tag: Some("withdrawal"),
+            error: TxError::NotEnoughFunds.to_string(),
+        };
+        assert!(validator.is_valid(&serde_json::to_value(&record).unwrap()));
+        assert!(validator.is_valid(&json!({
+            "client": null,
+            "tx": null,
+            "type": null,
+            "error": "invalid amount `x`",
+        })));
+        assert!(!validator.is_valid(&json!({"client": 1, "tx": 2, "type": "deposit"})));
+    }
+
+    #[test]
+    fn test_schema_by_name() {
+        for name in SCHEMA_TYPES {
+            let schema: Value = serde_json::to_value(schema_by_name(name).unwrap()).unwrap();
+            assert!(schema["$schema"].is_string(), "{}", name);
+        }
+
+        assert!(schema_by_name("ledger").is_none());
+    }
+}
@@ -0,0 +1,369 @@
+//! This module contains an HTTP service exposing a shared state machine, built with the `server`
+//! feature and started by `corken serve`.
+//!
+//! Transactions are posted as JSON objects with the same keys as the columns of a CSV document,
+//! with amounts given either as numbers or as strings. Every error is returned as a JSON body with
+//! the `code` of the error and a human readable `message`.
+
+use super::*;
+use axum::extract;
+use axum::extract::rejection::{JsonRejection, PathRejection};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::Value;
+use std::fs::File;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use tokio::net::TcpListener;
+
+/// The state machine shared by all requests. Requests are executed one at a time.
+pub type SharedState = Arc<Mutex<State>>;
+
+/// Struct represents the JSON body of an error.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ErrorBody {
+    /// The name of the `TxError` variant, or the kind of request error.
+    pub code: String,
+    pub message: String,
+}
+
+/// Struct represents the outcome of a posted transaction.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct TxResult {
+    /// Either `ok` or `rejected`.
+    pub status: String,
+    /// The client of the transaction, unless it couldnt be parsed.
+    pub client: Option<u16>,
+    /// The id of the transaction, unless it couldnt be parsed. For disputes, resolves and
+    /// chargebacks this is the id of the transaction they refer to.
+    pub tx: Option<u32>,
+    /// The own id of a dispute, resolve or chargeback, if it carried one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<u32>,
+    /// The reason the transaction was rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorBody>,
+    /// The amount credited by an interest transaction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credited: Option<f64>,
+}
+
+/// Struct represents an open dispute.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DisputeView {
+    pub client: u16,
+    pub tx: u32,
+    /// The amount of the disputed transaction.
+    pub amount: f64,
+    /// The own id of the dispute, if it carried one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<u32>,
+}
+
+/// Struct represents an error response.
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    body: ErrorBody,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: ErrorBody {
+                code: code.to_string(),
+                message: message.into(),
+            },
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body)).into_response()
+    }
+}
+
+/// Struct represents a row of an initial balances file.
+#[derive(Deserialize)]
+struct BalanceRecord {
+    client: u16,
+    available: f64,
+    held: f64,
+    total: f64,
+    locked: bool,
+}
+
+/// Function returns the router of the service, backed by `state`.
+///
+/// # Routes
+/// * `POST /transactions` - Executes a transaction, or an array of them. A single transaction
+///   returns its `TxResult` with `200 OK`, or `422 Unprocessable Entity` if it was rejected. An
+///   array returns the `TxResult` of every transaction, in order, with `200 OK`.
+/// * `GET /accounts` - Returns all accounts, ordered by client id.
+/// * `GET /accounts/{client}` - Returns a single account, or `404 Not Found`.
+/// * `GET /disputes` - Returns all open disputes, ordered by transaction id.
+/// * `GET /healthz` - Returns `{"status": "ok"}`.
+pub fn router(state: SharedState) -> Router {
+    Router::new()
+        .route("/transactions", post(post_transactions))
+        .route("/accounts", get(get_accounts))
+        .route("/accounts/{client}", get(get_account))
+        .route("/disputes", get(get_disputes))
+        .route("/healthz", get(healthz))
+        .with_state(state)
+}
+
+/// Function will serve the router over `listener` until `shutdown` completes. In-flight requests
+/// are allowed to finish, after which a checkpoint of the state is written into `snapshot`, if
+/// set.
+pub async fn serve(
+    listener: TcpListener,
+    state: SharedState,
+    snapshot: Option<&Path>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    axum::serve(listener, router(state.clone()))
+        .with_graceful_shutdown(shutdown)
+        .await?;
+
+    if let Some(path) = snapshot {
+        let state = state
+            .lock()
+            .map_err(|_| io::Error::other("state machine is poisoned"))?;
+        state.save_checkpoint_to_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Function will construct a state machine out of the accounts of a CSV document in the format
+/// written by the `corken` binary, `client, available, held, total, locked`.
+///
+/// # Returns
+/// Returns `CorkenError::Ingest` if a row cannot be parsed, holds an account whose balances dont
+/// add up, or repeats a client.
+pub fn load_initial_balances(path: impl AsRef<Path>) -> error::Result<State> {
+    let rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(File::open(path)?);
+
+    let mut state = State::default();
+    for record in rdr.into_deserialize() {
+        let record: BalanceRecord = record.map_err(|e| SourceError::Parse(e.to_string()))?;
+        let account = Account {
+            id: record.client,
+            available: record.available,
+            held: record.held,
+            total: record.total,
+            locked: record.locked,
+            ..Account::default()
+        };
+
+        let finite = [account.available, account.held, account.total]
+            .iter()
+            .all(|x| x.is_finite());
+        if !finite || account.held < 0.0 || !account.is_balanced() {
+            return Err(SourceError::Parse(format!(
+                "balances of client {} dont add up",
+                account.id
+            ))
+            .into());
+        }
+
+        if state.accounts.insert(account.id, account).is_some() {
+            return Err(SourceError::Parse(format!("client {} is repeated", record.client)).into());
+        }
+    }
+
+    Ok(state)
+}
+
+/// Function will lock the shared state machine.
+fn lock(state: &SharedState) -> Result<MutexGuard<'_, State>, ApiError> {
+    state.lock().map_err(|_| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            TxError::InternalError.code(),
+            "state machine is poisoned",
+        )
+    })
+}
+
+/// Function will parse and execute a single posted transaction.
+fn execute(state: &mut State, tx: Value) -> TxResult {
+    let rejected = |client, tx, code: &str, message: String| TxResult {
+        status: "rejected".to_string(),
+        client,
+        tx,
+        event_id: None,
+        error: Some(ErrorBody {
+            code: code.to_string(),
+            message,
+        }),
+        credited: None,
+    };
+
+    let tx: Transaction = match serde_json::from_value(tx) {
+        Ok(tx) => tx,
+        Err(e) => return rejected(None, None, "InvalidRecord", e.to_string()),
+    };
+
+    let (client, id, event_id) = (tx.client(), tx.tx(), tx.event_id());
+    let mut credited = None;
+    let result = state.execute_with_events(tx, |event| {
+        if let AccountEvent::InterestCredited { amount, .. } = event {
+            credited = Some(amount);
+        }
+    });
+
+    match result {
+        Ok(()) => TxResult {
+            status: "ok".to_string(),
+            client: Some(client),
+            tx: Some(id),
+            event_id,
+            error: None,
+            credited,
+        },
+        Err(e) => TxResult {
+            event_id,
+            ..rejected(Some(client), Some(id), e.code(), e.to_string())
+        },
+    }
+}
+
+async fn post_transactions(
+    extract::State(state): extract::State<SharedState>,
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Json(body) = body.map_err(|e| ApiError::new(e.status(), "InvalidJson", e.body_text()))?;
+
+    match body {
+        Value::Array(txs) => {
+            let mut state = lock(&state)?;
+            let results: Vec<_> = txs.into_iter().map(|x| execute(&mut state, x)).collect();
+
+            Ok(Json(results).into_response())
+        }
+        tx @ Value::Object(_) => {
+            let result = execute(&mut *lock(&state)?, tx);
+            let status = match result.error {
+                None => StatusCode::OK,
+                Some(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+
+            Ok((status, Json(result)).into_response())
+        }
+        _ => Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "InvalidBody",
+            "Expected a transaction or an array of transactions.",
+        )),
+    }
+}
+
+async fn get_accounts(
+    extract::State(state): extract::State<SharedState>,
+) -> Result<Json<Vec<Account>>, ApiError> {
+    let accounts = lock(&state)?.accounts_iter_by_id().cloned().collect();
+
+    Ok(Json(accounts))
+}
+
+async fn get_account(
+    extract::State(state): extract::State<SharedState>,
+    client: Result<extract::Path<u16>, PathRejection>,
+) -> Result<Json<Account>, ApiError> {
+    let extract::Path(client) =
+        client.map_err(|e| ApiError::new(e.status(), "InvalidClient", e.body_text()))?;
+
+    match lock(&state)?.accounts.get(&client) {
+        Some(account) => Ok(Json(account.clone())),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            TxError::AccountNotFound.code(),
+            TxError::AccountNotFound.to_string(),
+        )),
+    }
+}
+
+async fn get_disputes(
+    extract::State(state): extract::State<SharedState>,
+) -> Result<Json<Vec<DisputeView>>, ApiError> {
+    let state = lock(&state)?;
+    let disputes = state
+        .open_disputes()
+        .into_iter()
+        .filter_map(|cached| match cached.transaction().tx_type() {
+            TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                Some(DisputeView {
+                    client: cached.transaction().client(),
+                    tx: cached.transaction().tx(),
+                    amount: amount.get(),
+                    event_id: cached.dispute_event_id(),
+                })
+            }
+            _ => None,
+        })
+        .collect();
+
+    Ok(Json(disputes))
+}
+
+async fn healthz() -> Json<Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use std::fs;
+
+    #[test]
+    fn test_load_initial_balances() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("balances.csv");
+
+        fs::write(
+            &path,
+            "client,available,held,total,locked\n1, 10.5, 2, 12.5, false\n2, 0, 0, 0, true\n",
+        )
+        .unwrap();
+        let mut state = load_initial_balances(&path).unwrap();
+        assert_eq!(state.accounts[&1].available, 10.5);
+        assert_eq!(state.accounts[&1].held, 2.0);
+        assert!(state.accounts[&2].locked);
+
+        state
+            .execute(Transaction::new(
+                TransactionType::Withdrawal {
+                    amount: checked(10.5),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        assert_eq!(state.accounts[&1].total, 2.0);
+
+        for contents in [
+            "client,available,held,total,locked\n1, 10, 2, 10, false\n",
+            "client,available,held,total,locked\n1, 1, 0, 1, false\n1, 1, 0, 1, false\n",
+            "client,available,held,total,locked\n1, abc, 0, 1, false\n",
+        ] {
+            fs::write(&path, contents).unwrap();
+            assert!(matches!(
+                load_initial_balances(&path),
+                Err(CorkenError::Ingest(SourceError::Parse(_)))
+            ));
+        }
+    }
+}
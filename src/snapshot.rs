@@ -0,0 +1,672 @@
+//! This module contains serializable snapshots of the state machine, used for checkpointing.
+
+use super::*;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The current version of the snapshot format.
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// Struct represents a serializable snapshot of the data held by a state machine.
+///
+/// # Notes
+/// Only the data of the state machine is captured. The configured policies are not part of the
+/// snapshot, so a state machine restored from one uses the default policies. The one exception is
+/// the default balance limit, which is a compliance requirement rather than a policy and gets
+/// restored along with the per account limits.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+pub struct StateSnapshot<A = f64> {
+    /// Version of the snapshot format.
+    version: u32,
+    /// All accounts of the state machine.
+    accounts: Vec<AccountSnapshot<A>>,
+    /// All cached transactions of the state machine.
+    tx_cache: Vec<CachedTxSnapshot<A>>,
+    /// All withdrawals that can be refunded, along with the amount refunded so far.
+    refundable: Vec<RefundableSnapshot<A>>,
+    /// All manual holds that havent been released yet.
+    manual_holds: Vec<HoldSnapshot<A>>,
+    /// All transaction ids remembered in idempotent mode.
+    seen: Vec<u32>,
+    /// The own ids of all the disputes, resolves and chargebacks that have been commited.
+    dispute_event_ids: Vec<u32>,
+    /// The logical time of the state machine.
+    seq: u64,
+    /// The last transaction executed before the snapshot was taken, if any.
+    last_tx: Option<ProcessedTxSnapshot>,
+    /// The balance limits set with `State::set_balance_limit`.
+    balance_limits: Vec<(u16, A)>,
+    /// The default balance limit the state machine has been configured with, if any.
+    default_balance_limit: Option<A>,
+}
+
+/// Struct represents a serializable snapshot of an `Account`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+pub struct AccountSnapshot<A = f64> {
+    id: u16,
+    available: A,
+    held: A,
+    total: A,
+    locked: bool,
+}
+
+/// Enum represents the kind of a cached transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CachedTxKind {
+    Deposit,
+    Withdrawal,
+}
+
+/// Struct represents a serializable snapshot of a `CachedTx`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+pub struct CachedTxSnapshot<A = f64> {
+    tx: u32,
+    client: u16,
+    kind: CachedTxKind,
+    amount: A,
+    dispute: Option<DisputeState>,
+    dispute_event_id: Option<u32>,
+    seq: u64,
+}
+
+/// Struct represents a serializable snapshot of the last transaction executed by a state machine.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessedTxSnapshot {
+    tx: u32,
+    client: u16,
+    /// The type of the transaction, with all custom types under `custom`.
+    kind: String,
+}
+
+/// Struct represents a serializable snapshot of a withdrawal that can be refunded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+pub struct RefundableSnapshot<A = f64> {
+    tx: u32,
+    client: u16,
+    amount: A,
+    refunded: A,
+}
+
+/// Struct represents a serializable snapshot of a manual hold.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "A: Money"))]
+pub struct HoldSnapshot<A = f64> {
+    tx: u32,
+    client: u16,
+    amount: A,
+}
+
+impl<A: Money> From<&Account<A>> for AccountSnapshot<A> {
+    fn from(account: &Account<A>) -> Self {
+        Self {
+            id: account.id,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+        }
+    }
+}
+
+impl<A: Money> From<AccountSnapshot<A>> for Account<A> {
+    fn from(snapshot: AccountSnapshot<A>) -> Self {
+        Self {
+            id: snapshot.id,
+            available: snapshot.available,
+            held: snapshot.held,
+            total: snapshot.total,
+            locked: snapshot.locked,
+            ..Account::default()
+        }
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function returns a snapshot of the accounts and cached transactions of the state machine.
+    pub fn snapshot(&self) -> StateSnapshot<A> {
+        let tx_cache = self
+            .tx_cache
+            .values()
+            .filter_map(|cached| {
+                let (kind, amount) = match cached.tx.tx_type {
+                    TransactionType::Deposit { amount } => (CachedTxKind::Deposit, amount.get()),
+                    TransactionType::Withdrawal { amount } => {
+                        (CachedTxKind::Withdrawal, amount.get())
+                    }
+                    _ => return None,
+                };
+
+                Some(CachedTxSnapshot {
+                    tx: cached.tx.tx,
+                    client: cached.tx.client,
+                    kind,
+                    amount,
+                    dispute: cached.dispute,
+                    dispute_event_id: cached.dispute_event_id,
+                    seq: cached.seq,
+                })
+            })
+            .collect();
+
+        StateSnapshot {
+            version: SNAPSHOT_VERSION,
+            accounts: self.accounts.values().map(AccountSnapshot::from).collect(),
+            tx_cache,
+            refundable: self
+                .refundable
+                .iter()
+                .map(|(tx, withdrawal)| RefundableSnapshot {
+                    tx: *tx,
+                    client: withdrawal.client,
+                    amount: withdrawal.amount,
+                    refunded: withdrawal.refunded,
+                })
+                .collect(),
+            manual_holds: self
+                .manual_holds
+                .iter()
+                .map(|(tx, hold)| HoldSnapshot {
+                    tx: *tx,
+                    client: hold.client,
+                    amount: hold.amount,
+                })
+                .collect(),
+            seen: self.seen.iter().copied().collect(),
+            dispute_event_ids: self.dispute_event_ids.iter().copied().collect(),
+            seq: self.seq,
+            last_tx: self.last_tx.map(|x| ProcessedTxSnapshot {
+                tx: x.tx,
+                client: x.client,
+                kind: x.kind.to_string(),
+            }),
+            balance_limits: self
+                .balance_limits
+                .iter()
+                .map(|(client, limit)| (*client, *limit))
+                .collect(),
+            default_balance_limit: self.config.balance_limit,
+        }
+    }
+
+    /// Function will serialize a snapshot of the state machine into `writer`, in the same format
+    /// as `save_checkpoint_to_file`. The writer isnt buffered nor flushed.
+    ///
+    /// # Arguments
+    /// * `writer` - Destination of the snapshot, such as a socket or an upload stream.
+    pub fn to_bincode_writer<W: Write>(&self, writer: W) -> Result<(), bincode::Error> {
+        bincode::serialize_into(writer, &self.snapshot())
+    }
+
+    /// Function will serialize a snapshot of the state machine into `path` atomically. The
+    /// snapshot is first written into a temporary file in the same directory, which then gets
+    /// renamed over `path`, so a partial write never leaves a corrupt checkpoint behind.
+    ///
+    /// # Arguments
+    /// * `path` - Destination of the checkpoint.
+    pub fn save_checkpoint_to_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp_path = checkpoint_tmp_path(path);
+
+        let result = (|| {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            self.to_bincode_writer(&mut writer)
+                .map_err(io::Error::other)?;
+
+            writer.into_inner()?.sync_all()?;
+            fs::rename(&tmp_path, path)
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+
+        result
+    }
+}
+
+impl<A: Money> State<ahash::RandomState, A> {
+    /// Function will construct the state machine from a snapshot.
+    ///
+    /// # Returns
+    /// Returns `SnapshotError::UnsupportedVersion` if the snapshot was created by an incompatible
+    /// version of the state machine, and `SnapshotError::Corrupt` if it caches a transaction with
+    /// a negative or non finite amount.
+    pub fn from_snapshot(snapshot: StateSnapshot<A>) -> Result<Self, SnapshotError> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+        }
+
+        let last_tx = snapshot.last_tx.map(|x| ProcessedTx {
+            tx: x.tx,
+            client: x.client,
+            kind: TypeCounts::key_of(&x.kind),
+        });
+
+        let mut this = State {
+            seq: snapshot.seq,
+            last_tx,
+            ..Self::new_with_hasher(ahash::RandomState::default())
+        };
+        this.config.clock = Box::new(CounterClock::starting_at(snapshot.seq));
+        this.seen.extend(snapshot.seen);
+        this.dispute_event_ids.extend(snapshot.dispute_event_ids);
+        this.balance_limits.extend(snapshot.balance_limits);
+        this.config.balance_limit = snapshot.default_balance_limit;
+        this.accounts.extend(
+            snapshot
+                .accounts
+                .into_iter()
+                .map(|x| (x.id, Account::from(x))),
+        );
+        for x in snapshot.tx_cache {
+            let amount = Amount::new(x.amount)
+                .map_err(|e| SnapshotError::Corrupt(format!("transaction {}: {}", x.tx, e)))?;
+            let tx_type = match x.kind {
+                CachedTxKind::Deposit => TransactionType::Deposit { amount },
+                CachedTxKind::Withdrawal => TransactionType::Withdrawal { amount },
+            };
+
+            let cached = CachedTx {
+                tx: Transaction {
+                    tx_type,
+                    client: x.client,
+                    tx: x.tx,
+                    currency: None,
+                    timestamp: None,
+                    row_id: None,
+                    event_id: None,
+                },
+                dispute: x.dispute,
+                dispute_event_id: x.dispute_event_id,
+                seq: x.seq,
+            };
+
+            this.tx_cache.insert(x.tx, cached);
+        }
+        this.refundable
+            .extend(snapshot.refundable.into_iter().map(|x| {
+                let withdrawal = RefundableWithdrawal {
+                    client: x.client,
+                    amount: x.amount,
+                    refunded: x.refunded,
+                };
+
+                (x.tx, withdrawal)
+            }));
+        this.manual_holds
+            .extend(snapshot.manual_holds.into_iter().map(|x| {
+                let hold = ManualHold {
+                    client: x.client,
+                    amount: x.amount,
+                };
+
+                (x.tx, hold)
+            }));
+
+        // NOTE: The disputed part of `held` isnt part of the snapshot, so it is rebuilt out of the
+        // open disputes, each of which holds its amount under the default policy.
+        for cached in this.tx_cache.values() {
+            let amount = match cached.tx.tx_type {
+                TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount }
+                    if cached.dispute == Some(DisputeState::Disputed) =>
+                {
+                    amount.get()
+                }
+                _ => continue,
+            };
+
+            if let Some(account) = this.accounts.get_mut(&cached.tx.client) {
+                account.disputed_hold += amount;
+            }
+        }
+
+        Ok(this)
+    }
+
+    /// Function will construct the state machine from a snapshot read out of `reader`, as written
+    /// by `to_bincode_writer`. The reader isnt buffered.
+    ///
+    /// # Returns
+    /// Returns an error if the stream ends early or doesnt hold a valid snapshot, including one
+    /// created by an incompatible version of the state machine.
+    pub fn from_bincode_reader<R: Read>(reader: R) -> Result<Self, bincode::Error> {
+        let snapshot = bincode::deserialize_from(reader)?;
+
+        Self::from_snapshot(snapshot)
+            .map_err(|e| Box::new(bincode::ErrorKind::Custom(e.to_string())))
+    }
+}
+
+impl State {
+    /// Function will load a checkpoint written by `save_checkpoint_to_file`.
+    ///
+    /// # Arguments
+    /// * `path` - Location of the checkpoint.
+    ///
+    /// # Returns
+    /// Returns `CorkenError::Io` if the file cannot be read, and `CorkenError::Snapshot` if its
+    /// contents arent a valid snapshot.
+    pub fn load_checkpoint_from_file(path: impl AsRef<Path>) -> error::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let snapshot: StateSnapshot =
+            bincode::deserialize_from(reader).map_err(|e| SnapshotError::Corrupt(e.to_string()))?;
+
+        Ok(Self::from_snapshot(snapshot)?)
+    }
+
+    /// Function will restore the state machine from a checkpoint, then replay the transactions
+    /// executed after it was taken.
+    ///
+    /// # Arguments
+    /// * `snapshot` - Last checkpoint of the state machine.
+    /// * `delta` - Iterator over the transactions of the log the checkpoint was taken from, in
+    ///   their original order. It may start before the last transaction processed before the
+    ///   checkpoint was taken, in which case every transaction up to and including that one is
+    ///   skipped.
+    ///
+    /// # Returns
+    /// Returns the restored state machine along with the number of transactions of `delta` that
+    /// were commited and rejected, in that order, not counting the skipped ones, or
+    /// `SnapshotError::UnsupportedVersion` if the snapshot was created by an incompatible version
+    /// of the state machine.
+    ///
+    /// # Notes
+    /// The last transaction processed is recognized by its id, client and type, as disputes share
+    /// the id of the transaction they refer to. Transactions are held in memory until it is found,
+    /// so a `delta` that doesnt overlap the checkpoint is read in full before being replayed.
+    pub fn replay_from_snapshot_and_delta(
+        snapshot: StateSnapshot,
+        mut delta: impl Iterator<Item = Transaction>,
+    ) -> Result<(Self, usize, usize), SnapshotError> {
+        let mut this = Self::from_snapshot(snapshot)?;
+
+        let mut head = Vec::new();
+        if let Some(last_tx) = this.last_tx {
+            for tx in delta.by_ref() {
+                if ProcessedTx::of(&tx) == last_tx {
+                    head.clear();
+                    break;
+                }
+                head.push(tx);
+            }
+        }
+
+        let (mut success, mut fail) = (0, 0);
+        for tx in head.into_iter().chain(delta) {
+            match this.execute(tx) {
+                Ok(()) => success += 1,
+                Err(_) => fail += 1,
+            }
+        }
+
+        Ok((this, success, fail))
+    }
+}
+
+/// Returns the path of the temporary file used while writing a checkpoint to `path`.
+pub(crate) fn checkpoint_tmp_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".tmp");
+
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{checked, workload};
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let state = State::from_iterator(workload(1000).into_iter());
+        let restored = State::from_snapshot(state.snapshot()).unwrap();
+
+        assert_eq!(restored.accounts, state.accounts);
+        assert_eq!(restored.tx_cache, state.tx_cache);
+        assert_eq!(restored.seq, state.seq);
+    }
+
+    #[test]
+    fn test_manual_hold_round_trip() {
+        let mut state = State::default();
+
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        state
+            .execute(tx(TransactionType::Hold { amount: 4.0 }, 1, 2))
+            .unwrap();
+
+        let mut restored = State::from_snapshot(state.snapshot()).unwrap();
+        assert_eq!(restored.manual_holds, state.manual_holds);
+        assert_eq!(restored.accounts[&1].pending_hold(), 4.0);
+
+        restored
+            .execute(tx(TransactionType::Release { of_tx: 2 }, 1, 3))
+            .unwrap();
+        let account = &restored.accounts[&1];
+        assert_eq!(account.available, 10.0);
+        assert_eq!(account.held, 0.0);
+        assert!(restored.manual_holds.is_empty());
+    }
+
+    #[test]
+    fn test_refundable_round_trip() {
+        let mut state = State::default();
+
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        let refund = |id, of_tx, amount| tx(TransactionType::Refund { of_tx, amount }, 1, id);
+        state
+            .execute(tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ))
+            .unwrap();
+        state
+            .execute(tx(
+                TransactionType::Withdrawal {
+                    amount: checked(6.0),
+                },
+                1,
+                2,
+            ))
+            .unwrap();
+        state.execute(refund(3, 2, 4.0)).unwrap();
+
+        let mut restored = State::from_snapshot(state.snapshot()).unwrap();
+        assert_eq!(restored.refundable, state.refundable);
+
+        // Only the part of the withdrawal that hasnt been refunded yet can still be.
+        assert_eq!(
+            restored.execute(refund(4, 2, 2.5)),
+            Err(TxError::RefundExceedsWithdrawal)
+        );
+        restored.execute(refund(5, 2, 2.0)).unwrap();
+        assert_eq!(restored.accounts[&1].available, 10.0);
+    }
+
+    #[test]
+    fn test_dispute_event_ids_round_trip() {
+        let mut state = State::default();
+
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        for x in [
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                1,
+                2,
+            ),
+            tx(TransactionType::Dispute, 1, 1).with_event_id(100),
+            tx(TransactionType::Resolve, 1, 1).with_event_id(101),
+            tx(TransactionType::Dispute, 1, 2).with_event_id(102),
+        ] {
+            state.execute(x).unwrap();
+        }
+
+        let mut restored = State::from_snapshot(state.snapshot()).unwrap();
+        assert_eq!(restored.dispute_event_ids, state.dispute_event_ids);
+        assert_eq!(restored.open_disputes()[0].dispute_event_id(), Some(102));
+
+        for id in [100, 101, 102] {
+            assert_eq!(
+                restored.execute(tx(TransactionType::Resolve, 1, 2).with_event_id(id)),
+                Err(TxError::DuplicateTx)
+            );
+        }
+        restored
+            .execute(tx(TransactionType::Resolve, 1, 2).with_event_id(103))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_balance_limit_round_trip() {
+        let mut state = State::builder().balance_limit(100.0).build().unwrap();
+        state.set_balance_limit(2, 10.0).unwrap();
+
+        let tx = |client, tx, amount| {
+            Transaction::new(
+                TransactionType::Deposit {
+                    amount: checked(amount),
+                },
+                client,
+                tx,
+            )
+        };
+        state.execute(tx(1, 1, 90.0)).unwrap();
+        state.execute(tx(2, 2, 10.0)).unwrap();
+
+        let mut restored = State::from_snapshot(state.snapshot()).unwrap();
+        assert_eq!(restored.balance_limits, state.balance_limits);
+        assert!(matches!(
+            restored.execute(tx(1, 3, 10.01)),
+            Err(TxError::BalanceLimitExceeded { .. })
+        ));
+        assert!(matches!(
+            restored.execute(tx(2, 4, 0.01)),
+            Err(TxError::BalanceLimitExceeded { .. })
+        ));
+        restored.execute(tx(1, 5, 10.0)).unwrap();
+        restored.execute(tx(3, 6, 100.0)).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_interrupted_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.bin");
+
+        let first = State::from_iterator(workload(500).into_iter());
+        first.save_checkpoint_to_file(&path).unwrap();
+        assert!(!checkpoint_tmp_path(&path).exists());
+
+        // Simulate a write that got interrupted halfway through.
+        let second = State::from_iterator(workload(1000).into_iter());
+        let bytes = bincode::serialize(&second.snapshot()).unwrap();
+        fs::write(checkpoint_tmp_path(&path), &bytes[..bytes.len() / 2]).unwrap();
+
+        assert!(matches!(
+            State::load_checkpoint_from_file(checkpoint_tmp_path(&path)),
+            Err(CorkenError::Snapshot(SnapshotError::Corrupt(_)))
+        ));
+        let loaded = State::load_checkpoint_from_file(&path).unwrap();
+        assert_eq!(loaded.accounts, first.accounts);
+
+        second.save_checkpoint_to_file(&path).unwrap();
+        let loaded = State::load_checkpoint_from_file(&path).unwrap();
+        assert_eq!(loaded.accounts, second.accounts);
+        assert_eq!(loaded.tx_cache, second.tx_cache);
+    }
+
+    #[test]
+    fn test_bincode_stream() {
+        let state = State::from_iterator(workload(1000).into_iter());
+
+        let mut bytes = Vec::new();
+        state.to_bincode_writer(&mut bytes).unwrap();
+        let restored = State::from_bincode_reader(bytes.as_slice()).unwrap();
+        assert_eq!(restored.accounts, state.accounts);
+        assert_eq!(restored.tx_cache, state.tx_cache);
+        assert_eq!(restored.seq, state.seq);
+
+        for len in [0, 1, bytes.len() / 2, bytes.len() - 1] {
+            assert!(State::<_, f64>::from_bincode_reader(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_replay_from_snapshot_and_delta() {
+        let txs = workload(1000);
+        let full = State::from_iterator(txs.clone().into_iter());
+
+        let checkpoint = State::from_iterator(txs[..500].iter().cloned());
+        let (restored, success, fail) = State::replay_from_snapshot_and_delta(
+            checkpoint.snapshot(),
+            txs[500..].iter().cloned(),
+        )
+        .unwrap();
+
+        assert_eq!(restored.accounts, full.accounts);
+        assert_eq!(restored.tx_cache, full.tx_cache);
+        assert_eq!(restored.seq, full.seq);
+        assert_eq!(success + fail, 500);
+        assert!(success > 0 && fail > 0);
+
+        // Transactions covered by the checkpoint are skipped rather than applied twice.
+        let (restored, success, fail) = State::replay_from_snapshot_and_delta(
+            checkpoint.snapshot(),
+            txs[400..].iter().cloned(),
+        )
+        .unwrap();
+
+        assert_eq!(restored.accounts, full.accounts);
+        assert_eq!(restored.tx_cache, full.tx_cache);
+        assert_eq!(success + fail, 500);
+
+        let (restored, success, fail) =
+            State::replay_from_snapshot_and_delta(checkpoint.snapshot(), txs.iter().cloned())
+                .unwrap();
+
+        assert_eq!(restored.accounts, full.accounts);
+        assert_eq!(success + fail, 500);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut snapshot = State::default().snapshot();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+
+        assert_eq!(
+            State::from_snapshot(snapshot).err(),
+            Some(SnapshotError::UnsupportedVersion(SNAPSHOT_VERSION + 1))
+        );
+    }
+}
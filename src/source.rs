@@ -0,0 +1,180 @@
+//! This module contains the abstraction over the sources transactions can be read from.
+
+use super::*;
+#[cfg(feature = "serde")]
+use std::io;
+#[cfg(feature = "serde")]
+use std::marker::PhantomData;
+
+/// Trait implemented by types yielding transactions to be executed by the state machine.
+///
+/// Any iterator over owned `Transaction`s is a source that never fails. CSV encoded transactions
+/// can be read through `CsvSource` when the `serde` feature is enabled.
+pub trait TransactionSource<A = f64> {
+    /// Function will read the next transaction from the source.
+    ///
+    /// # Returns
+    /// Returns `None` once the source has been exhausted. `SourceError::Parse` indicates that a
+    /// single transaction was malformed and that the source can still be read from, while
+    /// `SourceError::Io` indicates that the source itself has failed.
+    fn next_transaction(&mut self) -> Option<Result<Transaction<A>, SourceError>>;
+}
+
+impl<A, I: Iterator<Item = Transaction<A>>> TransactionSource<A> for I {
+    fn next_transaction(&mut self) -> Option<Result<Transaction<A>, SourceError>> {
+        self.next().map(Ok)
+    }
+}
+
+/// Struct represents a source reading CSV encoded transactions through a `csv::Reader`.
+///
+/// # Notes
+/// The reader is expected to be configured to read the header row. If it isnt, the columns are
+/// assumed to be in the standard `type, client, tx, amount` order.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct CsvSource<R, A = f64> {
+    rdr: csv::Reader<R>,
+    headers: csv::StringRecord,
+    record: csv::StringRecord,
+    _amount: PhantomData<A>,
+}
+
+#[cfg(feature = "serde")]
+impl<R: io::Read, A: Money> CsvSource<R, A> {
+    /// Function will construct a source reading from `rdr`.
+    ///
+    /// # Returns
+    /// Returns `SourceError::Io` if the header row cannot be read.
+    pub fn new(mut rdr: csv::Reader<R>) -> Result<Self, SourceError> {
+        let headers = if rdr.has_headers() {
+            rdr.headers().map_err(SourceError::from)?.clone()
+        } else {
+            csv::StringRecord::from(&ingest::HEADERS[..])
+        };
+
+        Ok(Self {
+            rdr,
+            headers,
+            record: csv::StringRecord::new(),
+            _amount: PhantomData,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<R: io::Read, A: Money> TransactionSource<A> for CsvSource<R, A> {
+    fn next_transaction(&mut self) -> Option<Result<Transaction<A>, SourceError>> {
+        match self.rdr.read_record(&mut self.record) {
+            Ok(true) => Some(
+                self.record
+                    .deserialize(Some(&self.headers))
+                    .map_err(SourceError::from),
+            ),
+            Ok(false) => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<csv::Error> for SourceError {
+    fn from(e: csv::Error) -> Self {
+        if e.is_io_error() {
+            SourceError::Io(e.to_string())
+        } else {
+            SourceError::Parse(e.to_string())
+        }
+    }
+}
+
+impl State {
+    /// Function will construct the state machine and execute all the transactions read from
+    /// `src`.
+    ///
+    /// # Arguments
+    /// * `src` - Source of the transactions.
+    ///
+    /// # Returns
+    /// Returns the first `SourceError::Io` returned by the source. Malformed and rejected
+    /// transactions are skipped in the same way `from_iterator` skips rejected transactions.
+    pub fn from_source(mut src: impl TransactionSource) -> Result<Self, SourceError> {
+        let mut this = Self::default();
+        while let Some(tx) = src.next_transaction() {
+            let result = match tx {
+                Ok(tx) => this.execute(tx).map_err(|e| e.to_string()),
+                Err(e @ SourceError::Io(_)) => return Err(e),
+                Err(e) => Err(e.to_string()),
+            };
+
+            if cfg!(debug_assertions) {
+                if let Err(e) = result {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+
+        Ok(this)
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use super::*;
+    use crate::test::workload;
+
+    #[test]
+    fn test_sources_produce_identical_states() {
+        let txs = workload(1000);
+
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        wtr.write_record(["type", "client", "tx", "amount"])
+            .unwrap();
+        for tx in txs.iter() {
+            let (tag, amount) = match tx.tx_type {
+                TransactionType::Deposit { amount } => ("deposit", amount.to_string()),
+                TransactionType::Withdrawal { amount } => ("withdrawal", amount.to_string()),
+                TransactionType::Dispute => ("dispute", String::new()),
+                TransactionType::Resolve => ("resolve", String::new()),
+                TransactionType::Chargeback => ("chargeback", String::new()),
+                TransactionType::Interest { .. }
+                | TransactionType::Refund { .. }
+                | TransactionType::Hold { .. }
+                | TransactionType::Release { .. }
+                | TransactionType::Other { .. } => unreachable!(),
+            };
+
+            wtr.write_record([tag, &tx.client.to_string(), &tx.tx.to_string(), &amount])
+                .unwrap();
+        }
+        let csv = wtr.into_inner().unwrap();
+
+        let from_iter = State::from_source(txs.into_iter()).unwrap();
+        let from_csv =
+            State::from_source(CsvSource::new(csv::Reader::from_reader(&csv[..])).unwrap())
+                .unwrap();
+
+        assert!(from_iter.accounts().count() > 0);
+        assert_eq!(from_iter.accounts, from_csv.accounts);
+        assert_eq!(from_iter.tx_cache, from_csv.tx_cache);
+    }
+
+    #[test]
+    fn test_csv_source_errors() {
+        let csv = "type,client,tx,amount\ndeposit,1,1,abc\ndeposit,1,2,5.0\n";
+        let mut src: CsvSource<_, f64> =
+            CsvSource::new(csv::Reader::from_reader(csv.as_bytes())).unwrap();
+
+        assert!(matches!(
+            src.next_transaction(),
+            Some(Err(SourceError::Parse(_)))
+        ));
+        assert!(matches!(src.next_transaction(), Some(Ok(_))));
+        assert!(src.next_transaction().is_none());
+
+        let state =
+            State::from_source(CsvSource::new(csv::Reader::from_reader(csv.as_bytes())).unwrap())
+                .unwrap();
+        assert_eq!(state.accounts.get(&1).unwrap().available, 5.0);
+    }
+}
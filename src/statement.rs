@@ -0,0 +1,208 @@
+//! This module contains the per account statements of the transactions commited by the state
+//! machine, built with the `statement-logging` feature for single account exports.
+
+use super::*;
+use std::mem;
+
+/// Struct represents a line of the statement of an account, written as a CSV row with the
+/// `tx_id,type,amount,balance_after` columns.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(bound(serialize = "A: Money"))]
+pub(crate) struct StatementLine<A> {
+    /// The id of the transaction, which for disputes, resolves and chargebacks is the id of the
+    /// transaction they refer to.
+    tx_id: u32,
+    /// The `type` of the transaction as it appears in a serialized file.
+    #[serde(rename = "type")]
+    tag: String,
+    /// The amount moved by the transaction. For custom transactions this is the change of the
+    /// `available` balance.
+    amount: A,
+    /// The `available` balance of the account once the transaction has been commited.
+    #[serde(serialize_with = "serialize_balance")]
+    balance_after: A,
+}
+
+/// Struct represents a line of a statement captured from an event, before the balance of the
+/// account is known.
+pub(crate) struct PendingLine<A> {
+    client: u16,
+    tx_id: u32,
+    amount: A,
+}
+
+impl<A: Money> PendingLine<A> {
+    /// Returns the line `event` contributes to the statement of its account, if any.
+    pub(crate) fn of(event: &AccountEvent<A>) -> Option<Self> {
+        let (client, tx_id, amount) = match *event {
+            AccountEvent::AccountCreated { .. } => return None,
+            AccountEvent::Deposited { client, tx, amount }
+            | AccountEvent::Withdrawn { client, tx, amount }
+            | AccountEvent::DisputeOpened {
+                client, tx, amount, ..
+            }
+            | AccountEvent::DisputeResolved {
+                client, tx, amount, ..
+            }
+            | AccountEvent::ChargedBack {
+                client, tx, amount, ..
+            }
+            | AccountEvent::InterestCredited { client, tx, amount }
+            | AccountEvent::Refunded {
+                client, tx, amount, ..
+            }
+            | AccountEvent::HoldPlaced { client, tx, amount }
+            | AccountEvent::HoldReleased {
+                client, tx, amount, ..
+            }
+            | AccountEvent::Compensated { client, tx, amount } => (client, tx, amount),
+            AccountEvent::Adjusted {
+                client,
+                tx,
+                available,
+                ..
+            } => (client, tx, available),
+        };
+
+        Some(Self {
+            client,
+            tx_id,
+            amount,
+        })
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will append the lines of a transaction of type `tag` that has just been commited
+    /// to the statements of the accounts they refer to.
+    pub(crate) fn log_statement(&mut self, tag: &str, lines: Vec<PendingLine<A>>) {
+        for line in lines {
+            let balance_after = match self.accounts.get(&line.client) {
+                Some(account) => account.available,
+                None => continue,
+            };
+
+            self.statements
+                .entry(line.client)
+                .or_default()
+                .push(StatementLine {
+                    tx_id: line.tx_id,
+                    tag: tag.to_string(),
+                    amount: line.amount,
+                    balance_after,
+                });
+        }
+    }
+
+    /// Returns the estimated bytes used by the statements, for `estimate_memory_usage`.
+    pub(crate) fn statements_bytes(&self) -> usize {
+        self.statements
+            .values()
+            .map(|lines| {
+                mem::size_of::<(u16, Vec<StatementLine<A>>)>()
+                    + lines.capacity() * mem::size_of::<StatementLine<A>>()
+                    + lines.iter().map(|x| x.tag.capacity()).sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Function returns the statement of `client` as a CSV document with the
+    /// `tx_id,type,amount,balance_after` columns, holding one row per transaction commited to
+    /// the account in commit order. `balance_after` is the `available` balance once the
+    /// transaction has been commited.
+    ///
+    /// # Returns
+    /// Returns `None` if `client` has no account.
+    ///
+    /// # Notes
+    /// Rejected transactions are not part of the statement, and neither are the transactions
+    /// commited before a state machine has been restored from a snapshot or an event log.
+    pub fn account_statement_csv(&self, client: u16) -> Option<String> {
+        if !self.accounts.contains_key(&client) {
+            return None;
+        }
+
+        // NOTE: The header is written up front, so that statements without lines still have one.
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(vec![]);
+        wtr.write_record(["tx_id", "type", "amount", "balance_after"])
+            .expect("writing into memory cannot fail");
+        for line in self.statements.get(&client).into_iter().flatten() {
+            wtr.serialize(line)
+                .expect("writing into memory cannot fail");
+        }
+
+        let csv = wtr.into_inner().expect("writing into memory cannot fail");
+        Some(String::from_utf8(csv).expect("csv writes valid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    #[test]
+    fn test_account_statement_csv() {
+        let mut state = State::default();
+
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        for x in [
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(7.0),
+                },
+                2,
+                2,
+            ),
+            tx(
+                TransactionType::Withdrawal {
+                    amount: checked(2.5),
+                },
+                1,
+                3,
+            ),
+            tx(
+                TransactionType::Withdrawal {
+                    amount: checked(50.0),
+                },
+                1,
+                4,
+            ),
+            tx(TransactionType::Dispute, 1, 1),
+            tx(TransactionType::Resolve, 1, 1),
+            tx(
+                TransactionType::Deposit {
+                    amount: checked(1.25),
+                },
+                1,
+                5,
+            ),
+        ] {
+            let _ = state.execute(x);
+        }
+
+        assert_eq!(
+            state.account_statement_csv(1).unwrap(),
+            "tx_id,type,amount,balance_after\n\
+             1,deposit,10.0,10.0\n\
+             3,withdrawal,2.5,7.5\n\
+             1,dispute,10.0,-2.5\n\
+             1,resolve,10.0,7.5\n\
+             5,deposit,1.25,8.75\n"
+        );
+        assert_eq!(
+            state.account_statement_csv(2).unwrap(),
+            "tx_id,type,amount,balance_after\n2,deposit,7.0,7.0\n"
+        );
+        assert_eq!(state.account_statement_csv(3), None);
+    }
+}
@@ -0,0 +1,241 @@
+//! This module contains the per transaction type counters of the state machine.
+
+use super::*;
+
+/// Keys of the counters, indexed by `TypeCounts::index`. Transactions of custom types are all
+/// counted under `custom`.
+const TYPE_KEYS: [&str; 10] = [
+    "deposit",
+    "withdrawal",
+    "dispute",
+    "resolve",
+    "chargeback",
+    "interest",
+    "refund",
+    "hold",
+    "release",
+    "custom",
+];
+
+/// Struct counts the transactions executed successfully by the state machine, per type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct TypeCounts([usize; TYPE_KEYS.len()]);
+
+impl TypeCounts {
+    /// Returns the index of the counter of `tx_type`.
+    fn index<A>(tx_type: &TransactionType<A>) -> usize {
+        match tx_type {
+            TransactionType::Deposit { .. } => 0,
+            TransactionType::Withdrawal { .. } => 1,
+            TransactionType::Dispute => 2,
+            TransactionType::Resolve => 3,
+            TransactionType::Chargeback => 4,
+            TransactionType::Interest { .. } => 5,
+            TransactionType::Refund { .. } => 6,
+            TransactionType::Hold { .. } => 7,
+            TransactionType::Release { .. } => 8,
+            TransactionType::Other { .. } => 9,
+        }
+    }
+
+    /// Returns the key of the counter of `tx_type`.
+    pub(crate) fn key<A>(tx_type: &TransactionType<A>) -> &'static str {
+        TYPE_KEYS[Self::index(tx_type)]
+    }
+
+    /// Returns the key matching `key` out of the keys of the counters, or `custom` if it isnt one
+    /// of them.
+    #[cfg(feature = "serde")]
+    pub(crate) fn key_of(key: &str) -> &'static str {
+        TYPE_KEYS
+            .iter()
+            .find(|x| **x == key)
+            .unwrap_or(&TYPE_KEYS[TYPE_KEYS.len() - 1])
+    }
+
+    /// Function will increment the counter of `tx_type`.
+    pub(crate) fn record<A>(&mut self, tx_type: &TransactionType<A>) {
+        self.0[Self::index(tx_type)] += 1;
+    }
+
+    /// Function will add the counters of `other` to these.
+    #[cfg(feature = "serde")]
+    pub(crate) fn merge(&mut self, other: &TypeCounts) {
+        for (count, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *count += other;
+        }
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Returns the number of transactions executed successfully so far, keyed by type: `deposit`,
+    /// `withdrawal`, `dispute`, `resolve`, `chargeback`, `interest`, `refund`, `hold`, `release`
+    /// and `custom`. Every key is present, even if its count is zero.
+    ///
+    /// # Notes
+    /// Rejected transactions and footer rows are not counted. The counters are not part of
+    /// snapshots, so they start from zero on a state machine restored from one.
+    pub fn count_transactions_by_type(&self) -> HashMap<&'static str, usize> {
+        TYPE_KEYS
+            .iter()
+            .copied()
+            .zip(self.type_counts.0.iter().copied())
+            .collect()
+    }
+
+    /// Returns the number of transactions executed so far, whether they were commited or
+    /// rejected.
+    ///
+    /// # Notes
+    /// Transactions triggered by the execution of the transaction they were deferred on are
+    /// counted as well, and so are the transactions of batches that got rolled back. Like the
+    /// counters by type, this starts from zero on a state machine restored from a snapshot, and
+    /// is otherwise never reset.
+    pub fn total_transactions_executed(&self) -> u64 {
+        self.executed_count
+    }
+
+    /// Returns the number of transactions executed so far that have been commited.
+    pub fn total_transactions_succeeded(&self) -> u64 {
+        self.executed_count - self.failed_count
+    }
+
+    /// Returns the number of transactions executed so far that have been rejected.
+    pub fn total_transactions_failed(&self) -> u64 {
+        self.failed_count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    /// Credits one unit to the account.
+    #[derive(Debug)]
+    struct Bonus;
+
+    impl CustomHandler for Bonus {
+        fn handle(&self, _: &CustomRecord, account: &mut AccountHandle) -> Result<(), TxError> {
+            account.credit(1.0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_count_transactions_by_type() {
+        let mut state = State::builder()
+            .custom_handler("bonus", Bonus)
+            .build()
+            .unwrap();
+
+        let txs = [
+            (
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            (
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                1,
+                2,
+            ),
+            (
+                TransactionType::Deposit {
+                    amount: checked(5.0),
+                },
+                2,
+                3,
+            ),
+            (
+                TransactionType::Withdrawal {
+                    amount: checked(2.0),
+                },
+                1,
+                4,
+            ),
+            // Rejected for insufficient funds.
+            (
+                TransactionType::Withdrawal {
+                    amount: checked(50.0),
+                },
+                2,
+                5,
+            ),
+            (TransactionType::Dispute, 1, 1),
+            (TransactionType::Resolve, 1, 1),
+            (TransactionType::Dispute, 2, 3),
+            (TransactionType::Chargeback, 2, 3),
+            // Rejected as the transaction doesnt exist.
+            (TransactionType::Dispute, 1, 99),
+            (
+                TransactionType::Other {
+                    tag: "bonus".into(),
+                    amount: None,
+                },
+                1,
+                6,
+            ),
+        ];
+
+        let txs_len = txs.len() as u64;
+        for (tx_type, client, tx) in txs {
+            let _ = state.execute(Transaction::new(tx_type, client, tx));
+        }
+
+        let counts = state.count_transactions_by_type();
+        assert_eq!(counts.len(), 10);
+        assert_eq!(counts["deposit"], 3);
+        assert_eq!(counts["withdrawal"], 1);
+        assert_eq!(counts["dispute"], 2);
+        assert_eq!(counts["resolve"], 1);
+        assert_eq!(counts["chargeback"], 1);
+        assert_eq!(counts["interest"], 0);
+        assert_eq!(counts["refund"], 0);
+        assert_eq!(counts["hold"], 0);
+        assert_eq!(counts["release"], 0);
+        assert_eq!(counts["custom"], 1);
+
+        assert_eq!(state.total_transactions_executed(), txs_len);
+        assert_eq!(state.total_transactions_succeeded(), 9);
+        assert_eq!(state.total_transactions_failed(), 2);
+    }
+
+    #[test]
+    fn test_total_transactions() {
+        let mut state = State::default();
+        assert_eq!(state.total_transactions_executed(), 0);
+
+        let mut expected_failed = 0;
+        for (i, tx) in crate::test::workload(1000).into_iter().enumerate() {
+            if state.execute(tx).is_err() {
+                expected_failed += 1;
+            }
+
+            let executed = state.total_transactions_executed();
+            assert_eq!(executed, i as u64 + 1);
+            assert_eq!(
+                state.total_transactions_succeeded() + state.total_transactions_failed(),
+                executed
+            );
+        }
+        assert_eq!(state.total_transactions_failed(), expected_failed);
+
+        // Transactions of a batch that got rolled back still count as executed.
+        let deposit = Transaction::new(
+            TransactionType::Deposit {
+                amount: checked(1.0),
+            },
+            500,
+            99_998,
+        );
+        let dispute = Transaction::new(TransactionType::Dispute, 500, 99_999);
+        let _ = state.execute_try_all(&[deposit, dispute]);
+        assert_eq!(state.total_transactions_executed(), 1002);
+        assert_eq!(state.total_transactions_failed(), expected_failed + 1);
+    }
+}
@@ -0,0 +1,102 @@
+//! This module contains the rendering of accounts with a string `status` column in place of the
+//! boolean `locked` column, for consumers expecting `active`/`frozen` values.
+
+use super::*;
+use serde::ser::SerializeStruct;
+
+/// Enum represents the status of an account, serialized as `active` or `frozen`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    /// The account isnt locked.
+    Active,
+    /// The account is locked.
+    Frozen,
+}
+
+/// Struct is a serialization adapter writing an account with the same fields as `Account`, except
+/// for `locked` which is replaced by a `status` field holding an `AccountStatus`. Returned by
+/// `Account::with_status`.
+#[derive(Clone, Copy, Debug)]
+pub struct WithStatus<'a, A = f64>(&'a Account<A>);
+
+impl<A: Money> Account<A> {
+    /// Returns the status of the account, `Frozen` if it is locked.
+    pub fn status(&self) -> AccountStatus {
+        if self.locked {
+            AccountStatus::Frozen
+        } else {
+            AccountStatus::Active
+        }
+    }
+
+    /// Returns an adapter serializing the account with a `status` field instead of `locked`.
+    pub fn with_status(&self) -> WithStatus<'_, A> {
+        WithStatus(self)
+    }
+}
+
+impl<A: Money> Serialize for WithStatus<'_, A> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let account = self.0;
+
+        let mut s = serializer.serialize_struct("Account", 5)?;
+        s.serialize_field("client", &account.id)?;
+        s.serialize_field("available", &without_negative_zero(account.available))?;
+        s.serialize_field("held", &without_negative_zero(account.held))?;
+        s.serialize_field("total", &without_negative_zero(account.total))?;
+        s.serialize_field("status", &account.status())?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    #[test]
+    fn test_status_output() {
+        let mut state = State::default();
+        let txs = [
+            (
+                TransactionType::Deposit {
+                    amount: checked(10.0),
+                },
+                1,
+                1,
+            ),
+            (
+                TransactionType::Deposit {
+                    amount: checked(2.5),
+                },
+                2,
+                2,
+            ),
+            (TransactionType::Dispute, 2, 2),
+            (TransactionType::Chargeback, 2, 2),
+        ];
+        for (tx_type, client, tx) in txs {
+            state
+                .execute(Transaction::new(tx_type, client, tx))
+                .unwrap();
+        }
+
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        for account in state.accounts_iter_by_id() {
+            wtr.serialize(account.with_status()).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(wtr.into_inner().unwrap()).unwrap(),
+            "client,available,held,total,status\n1,10.0,0.0,10.0,active\n2,0.0,0.0,0.0,frozen\n"
+        );
+
+        // The default output keeps the boolean column.
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(&state.accounts[&2]).unwrap();
+        assert_eq!(
+            String::from_utf8(wtr.into_inner().unwrap()).unwrap(),
+            "client,available,held,total,locked\n2,0.0,0.0,0.0,true\n"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! This module contains helpers for feeding CSV encoded transactions from async streams into the
+//! state machine.
+
+use super::*;
+use tokio::io::AsyncRead;
+use tokio_stream::StreamExt;
+use tokio_util::codec::FramedRead;
+use tokio_util::codec::LinesCodec;
+use tokio_util::codec::LinesCodecError;
+
+impl State {
+    /// Function will construct the state machine and execute all the CSV encoded transactions
+    /// read from `stream`, returning the final state once the stream is closed.
+    ///
+    /// # Arguments
+    /// * `stream` - Stream of newline separated CSV rows, in the standard `type, client, tx,
+    /// amount` column order. An optional header row is skipped.
+    ///
+    /// # Returns
+    /// Returns `CorkenError::Io` if reading from the stream fails or a line isnt valid UTF-8.
+    /// Malformed and rejected transactions are skipped in the same way `from_iterator` skips
+    /// rejected transactions.
+    pub async fn execute_csv_stream<R: AsyncRead + Unpin>(stream: R) -> error::Result<Self> {
+        let mut this = Self::default();
+        let mut lines = FramedRead::new(stream, LinesCodec::new());
+
+        let mut line_number = 0;
+        while let Some(line) = lines.next().await {
+            line_number += 1;
+            let line = line.map_err(|e| match e {
+                LinesCodecError::Io(e) => CorkenError::Io(e),
+                e => CorkenError::Ingest(SourceError::Parse(e.to_string())),
+            })?;
+            if line.trim().is_empty() || is_header(&line) {
+                continue;
+            }
+
+            // NOTE: The raw line isnt logged as it contains the amount and client id.
+            let result = this.execute_csv_row(&line);
+            if cfg!(debug_assertions) {
+                if let Err(e) = result {
+                    eprintln!("line {}: {:?}", line_number, e);
+                }
+            }
+        }
+
+        Ok(this)
+    }
+}
+
+/// Returns whether `line` is the `type, client, tx, amount` header row.
+fn is_header(line: &str) -> bool {
+    line.split(',').next().map(str::trim) == Some(ingest::HEADERS[0])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::workload;
+    use tokio::io::AsyncWriteExt;
+
+    fn to_row(tx: &Transaction) -> String {
+        let (tag, amount) = match tx.tx_type {
+            TransactionType::Deposit { amount } => ("deposit", amount.to_string()),
+            TransactionType::Withdrawal { amount } => ("withdrawal", amount.to_string()),
+            TransactionType::Dispute => ("dispute", String::new()),
+            TransactionType::Resolve => ("resolve", String::new()),
+            TransactionType::Chargeback => ("chargeback", String::new()),
+            TransactionType::Interest { .. }
+            | TransactionType::Refund { .. }
+            | TransactionType::Hold { .. }
+            | TransactionType::Release { .. }
+            | TransactionType::Other { .. } => unreachable!(),
+        };
+
+        format!("{}, {}, {}, {}\n", tag, tx.client, tx.tx, amount)
+    }
+
+    #[tokio::test]
+    async fn test_execute_csv_stream() {
+        let txs = workload(1000);
+        let (mut tx_end, rx_end) = tokio::io::duplex(64);
+
+        let rows: Vec<String> = txs.iter().map(to_row).collect();
+        let writer = tokio::spawn(async move {
+            tx_end.write_all(b"type, client, tx, amount\n").await?;
+            for row in rows {
+                tx_end.write_all(row.as_bytes()).await?;
+            }
+            // Shutting down our end closes the stream for the reader.
+            tx_end.shutdown().await
+        });
+
+        let state = State::execute_csv_stream(rx_end).await.unwrap();
+        writer.await.unwrap().unwrap();
+
+        let expected = State::from_iterator(txs.into_iter());
+        assert!(state.accounts().count() > 0);
+        assert_eq!(state.accounts, expected.accounts);
+        assert_eq!(state.tx_cache, expected.tx_cache);
+    }
+
+    #[tokio::test]
+    async fn test_execute_csv_stream_invalid_utf8() {
+        let stream = &[0xff, 0xfe, b'\n'][..];
+        assert!(matches!(
+            State::execute_csv_stream(stream).await,
+            Err(CorkenError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,182 @@
+//! This module contains the subscriptions pushing the changes commited to an account to callbacks
+//! as they happen.
+
+use super::*;
+use crate::diff::AccountDiff;
+
+/// Callback notified of the changes commited to an account.
+pub type Subscriber<A = f64> = dyn Fn(&AccountDiff<A>) + Send + Sync;
+
+/// Subscribers of the state machine, indexed by the id of the client they are subscribed to.
+pub(crate) type Subscriptions<A> = HashMap<u16, Vec<(SubscriptionId, Arc<Subscriber<A>>)>>;
+
+/// Struct represents a subscription registered by `State::subscribe`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will register `callback` to be called with the changes of every transaction
+    /// commited to the account of `client` from now on, which doesnt need to exist yet.
+    ///
+    /// # Notes
+    /// Callbacks are called synchronously by `execute`, once per successful transaction affecting
+    /// the account, after the transaction has been commited and before `execute` returns. They are
+    /// called in the order they were registered. Rejected transactions are not reported. Clones of
+    /// the state machine share the subscriptions of the original one.
+    pub fn subscribe(&mut self, client: u16, callback: Box<Subscriber<A>>) -> SubscriptionId {
+        let id = SubscriptionId(self.next_subscription);
+        self.next_subscription += 1;
+
+        self.subscriptions
+            .entry(client)
+            .or_default()
+            .push((id, Arc::from(callback)));
+
+        id
+    }
+
+    /// Function will remove the subscription `id`, returning whether it was registered.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let client = self
+            .subscriptions
+            .iter()
+            .find(|(_, subscribers)| subscribers.iter().any(|(x, _)| *x == id))
+            .map(|(client, _)| *client);
+
+        let client = match client {
+            Some(client) => client,
+            None => return false,
+        };
+
+        let subscribers = self.subscriptions.get_mut(&client).unwrap();
+        subscribers.retain(|(x, _)| *x != id);
+        if subscribers.is_empty() {
+            self.subscriptions.remove(&client);
+        }
+
+        true
+    }
+
+    /// Function will commit a transaction like `commit`, then call the subscribers of the account
+    /// it affected with its changes.
+    pub(crate) fn commit_notifying(
+        &mut self,
+        tx: Transaction<A>,
+        mut sink: impl FnMut(AccountEvent<A>),
+    ) -> Result<(), TxError> {
+        if self.subscriptions.is_empty() {
+            return self.commit(tx, sink);
+        }
+
+        // NOTE: Disputes move balances on the account of the owner of the disputed transaction,
+        // which isnt known until the transaction has been commited, so both accounts are kept.
+        let owner = self.tx_cache.get(&tx.tx).map(|x| x.tx.client);
+        let before: Vec<Account<A>> = iter::once(tx.client)
+            .chain(owner)
+            .filter(|x| self.subscriptions.contains_key(x))
+            .map(|x| {
+                self.accounts.get(&x).cloned().unwrap_or(Account {
+                    id: x,
+                    ..Account::default()
+                })
+            })
+            .collect();
+
+        let mut affected = None;
+        self.commit(tx, |event| {
+            if !matches!(event, AccountEvent::AccountCreated { .. }) {
+                affected = Some(event.client());
+            }
+            sink(event);
+        })?;
+
+        let before = match affected.and_then(|x| before.iter().find(|y| y.id == x)) {
+            Some(before) => before,
+            None => return Ok(()),
+        };
+
+        let diff = self.diff_since(before.id, before);
+        for (_, callback) in &self.subscriptions[&before.id] {
+            callback(&diff);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_subscribe() {
+        let mut state = State::default();
+
+        let diffs = Arc::new(Mutex::new(Vec::new()));
+        let sink = diffs.clone();
+        let id = state.subscribe(1, Box::new(move |diff| sink.lock().unwrap().push(*diff)));
+
+        let tx = |tx_type, client, tx| Transaction::new(tx_type, client, tx);
+        let _ = state.execute(tx(
+            TransactionType::Deposit {
+                amount: checked(10.0),
+            },
+            1,
+            1,
+        ));
+        let _ = state.execute(tx(
+            TransactionType::Deposit {
+                amount: checked(5.0),
+            },
+            2,
+            2,
+        ));
+        let _ = state.execute(tx(
+            TransactionType::Withdrawal {
+                amount: checked(50.0),
+            },
+            1,
+            3,
+        ));
+        let _ = state.execute(tx(
+            TransactionType::Withdrawal {
+                amount: checked(4.0),
+            },
+            1,
+            4,
+        ));
+        let _ = state.execute(tx(TransactionType::Dispute, 2, 2));
+        let _ = state.execute(tx(TransactionType::Dispute, 1, 1));
+
+        assert_eq!(
+            *diffs.lock().unwrap(),
+            [
+                AccountDiff {
+                    client: 1,
+                    available_delta: 10.0,
+                    total_delta: 10.0,
+                    ..AccountDiff::default()
+                },
+                AccountDiff {
+                    client: 1,
+                    available_delta: -4.0,
+                    total_delta: -4.0,
+                    ..AccountDiff::default()
+                },
+                AccountDiff {
+                    client: 1,
+                    available_delta: -10.0,
+                    held_delta: 10.0,
+                    ..AccountDiff::default()
+                },
+            ]
+        );
+
+        assert!(state.unsubscribe(id));
+        assert!(!state.unsubscribe(id));
+        let _ = state.execute(tx(TransactionType::Resolve, 1, 1));
+        assert_eq!(diffs.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,462 @@
+//! This module contains the timestamps carried by transactions, and their chronological
+//! validation against the latest timestamp of each client.
+//!
+//! Timestamps are kept in milliseconds since the Unix epoch. Serialized transactions can carry
+//! them either as such, or as RFC 3339 strings, e.g. `2021-09-24T12:30:00.250Z`.
+
+use super::*;
+use std::convert::TryFrom;
+
+/// Error returned when parsing a string that isnt a timestamp.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("invalid timestamp `{0}`")]
+pub struct ParseTimestampError(String);
+
+/// Function will parse a timestamp given either in milliseconds since the Unix epoch, or as an
+/// RFC 3339 date and time, ignoring surrounding whitespace. Fractions of a second beyond the
+/// millisecond are truncated.
+///
+/// # Returns
+/// Returns `ParseTimestampError` if `s` is neither, or is earlier than the Unix epoch.
+pub fn parse_timestamp(s: &str) -> Result<u64, ParseTimestampError> {
+    let trimmed = s.trim();
+    let parsed = if !trimmed.is_empty() && trimmed.bytes().all(|x| x.is_ascii_digit()) {
+        trimmed.parse().ok()
+    } else {
+        parse_rfc3339(trimmed.as_bytes())
+    };
+
+    parsed.ok_or_else(|| ParseTimestampError(s.to_string()))
+}
+
+/// Returns the value of a field made of ASCII digits only.
+fn digits(field: &[u8]) -> Option<i64> {
+    if field.is_empty() || !field.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+
+    Some(field.iter().fold(0, |x, d| x * 10 + i64::from(d - b'0')))
+}
+
+/// Returns the amount of days between the Unix epoch and a date of the proleptic Gregorian
+/// calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+/// Returns the date of the proleptic Gregorian calendar lying `days` days after the Unix epoch, as
+/// a year, month and day.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Returns the amount of days in a month of the proleptic Gregorian calendar.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Function will parse an RFC 3339 date and time into milliseconds since the Unix epoch.
+fn parse_rfc3339(s: &[u8]) -> Option<u64> {
+    if s.len() < 20 || s[4] != b'-' || s[7] != b'-' || s[13] != b':' || s[16] != b':' {
+        return None;
+    }
+
+    if !matches!(s[10], b'T' | b't' | b' ') {
+        return None;
+    }
+
+    let year = digits(&s[0..4])?;
+    let month = digits(&s[5..7])?;
+    let day = digits(&s[8..10])?;
+    let hour = digits(&s[11..13])?;
+    let minute = digits(&s[14..16])?;
+    // NOTE: Leap seconds are let through, and simply roll over into the next minute.
+    let second = digits(&s[17..19])?;
+
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let mut rest = &s[19..];
+    let mut millis = 0;
+    if let Some(fraction) = rest.strip_prefix(b".") {
+        let len = fraction.iter().take_while(|x| x.is_ascii_digit()).count();
+        if len == 0 {
+            return None;
+        }
+
+        millis = fraction[..len]
+            .iter()
+            .chain(b"00")
+            .take(3)
+            .fold(0, |x, d| x * 10 + i64::from(d - b'0'));
+        rest = &fraction[len..];
+    }
+
+    let offset = match rest {
+        b"Z" | b"z" => 0,
+        [sign @ (b'+' | b'-'), hours @ .., b':', m1, m2] if hours.len() == 2 => {
+            let hours = digits(hours)?;
+            let minutes = digits(&[*m1, *m2])?;
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+
+    let seconds =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+
+    u64::try_from(seconds * 1000 + millis).ok()
+}
+
+/// Function will format a timestamp as an RFC 3339 date and time in UTC, e.g.
+/// `2021-09-24T12:30:00.250Z`. This is the inverse of `parse_timestamp`.
+pub fn format_timestamp(timestamp: u64) -> String {
+    // NOTE: `u64::MAX` milliseconds amount to less than `i64::MAX` days, so the casts never wrap.
+    let (days, millis) = (timestamp / 86_400_000, timestamp % 86_400_000);
+    let (year, month, day) = civil_from_days(days as i64);
+    let seconds = millis / 1000;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        millis % 1000
+    )
+}
+
+/// Function will deserialize an optional timestamp, given either as a number of milliseconds or as
+/// a string accepted by `parse_timestamp`. Empty strings are treated as a missing timestamp.
+#[cfg(feature = "serde")]
+pub(crate) fn deserialize_timestamp<'de, D: serde::Deserializer<'de>>(
+    de: D,
+) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        Millis(u64),
+        Text(String),
+    }
+
+    match Option::<Timestamp>::deserialize(de)? {
+        None => Ok(None),
+        Some(Timestamp::Millis(x)) => Ok(Some(x)),
+        Some(Timestamp::Text(x)) if x.trim().is_empty() => Ok(None),
+        Some(Timestamp::Text(x)) => parse_timestamp(&x)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Returns the latest timestamp carried by a transaction, across all clients.
+    ///
+    /// # Notes
+    /// Timestamps are tracked whether or not out of order transactions are handled, but arent part
+    /// of snapshots.
+    pub fn latest_timestamp(&self) -> Option<u64> {
+        self.latest_timestamp
+    }
+
+    /// Returns the latest timestamp carried by a transaction of `client`.
+    pub fn client_timestamp(&self, client: u16) -> Option<u64> {
+        self.client_timestamps.get(&client).copied()
+    }
+
+    /// Function will check the timestamp of `tx` against the latest timestamp of its client,
+    /// according to the configured `OutOfOrderPolicy`, and record it. The timestamps of
+    /// transactions that go on to get rejected for other reasons are recorded as well.
+    pub(crate) fn check_timestamp(&mut self, tx: &Transaction<A>) -> Result<(), TxError> {
+        let timestamp = match tx.timestamp {
+            Some(timestamp) => timestamp,
+            None => return Ok(()),
+        };
+
+        let latest = self.client_timestamps.entry(tx.client).or_insert(timestamp);
+
+        if timestamp.saturating_add(self.config.timestamp_tolerance) < *latest {
+            match self.config.out_of_order {
+                OutOfOrderPolicy::Ignore => {}
+                OutOfOrderPolicy::Warn => {
+                    if let Some(observer) = &self.observer {
+                        observer.on_out_of_order(tx, *latest);
+                    }
+                }
+                OutOfOrderPolicy::Reject => {
+                    return Err(TxError::OutOfOrderTimestamp {
+                        timestamp,
+                        latest: *latest,
+                    });
+                }
+            }
+        }
+
+        *latest = timestamp.max(*latest);
+        self.latest_timestamp = self.latest_timestamp.max(Some(timestamp));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use std::sync::Mutex;
+
+    /// Records the transactions reported out of order.
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<(u32, u64)>>);
+
+    impl StateObserver for Recorder {
+        fn on_out_of_order(&self, tx: &Transaction, latest: u64) {
+            self.0.lock().unwrap().push((tx.tx, latest));
+        }
+    }
+
+    fn deposit(client: u16, tx: u32, timestamp: Option<u64>) -> Transaction {
+        let tx = Transaction::new(
+            TransactionType::Deposit {
+                amount: checked(1.0),
+            },
+            client,
+            tx,
+        );
+        match timestamp {
+            Some(timestamp) => tx.with_timestamp(timestamp),
+            None => tx,
+        }
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp(" 1632486600250 "), Ok(1632486600250));
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Ok(0));
+        assert_eq!(
+            parse_timestamp("2021-09-24T12:30:00.25Z"),
+            Ok(1632486600250)
+        );
+        assert_eq!(
+            parse_timestamp("2021-09-24t14:30:00.250999+02:00"),
+            Ok(1632486600250)
+        );
+        assert_eq!(
+            parse_timestamp("2021-09-24 07:00:00-05:30"),
+            Ok(1632486600000)
+        );
+        assert_eq!(parse_timestamp("2024-02-29T00:00:00Z"), Ok(1709164800000));
+
+        for invalid in [
+            "",
+            "-1",
+            "2021-09-24",
+            "2021-09-24T12:30:00",
+            "2021-09-24T12:30:00.Z",
+            "2021-02-29T00:00:00Z",
+            "2021-09-24T24:00:00Z",
+            "2021-09-24T12:30:00+2:00",
+            "1969-12-31T23:59:59Z",
+            "99999999999999999999",
+        ] {
+            assert_eq!(
+                parse_timestamp(invalid),
+                Err(ParseTimestampError(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_timestamp(1632486600250), "2021-09-24T12:30:00.250Z");
+        assert_eq!(format_timestamp(1709164800000), "2024-02-29T00:00:00.000Z");
+
+        for timestamp in [0, 951_782_400_000, 1632486600250, 4_102_444_799_999] {
+            assert_eq!(parse_timestamp(&format_timestamp(timestamp)), Ok(timestamp));
+        }
+    }
+
+    #[test]
+    fn test_ignore() {
+        let mut state = State::default();
+        state.execute(deposit(1, 1, Some(1000))).unwrap();
+        state.execute(deposit(1, 2, Some(10))).unwrap();
+        state.execute(deposit(2, 3, Some(500))).unwrap();
+
+        assert_eq!(state.accounts[&1].available, 2.0);
+        assert_eq!(state.client_timestamp(1), Some(1000));
+        assert_eq!(state.client_timestamp(2), Some(500));
+        assert_eq!(state.client_timestamp(3), None);
+        assert_eq!(state.latest_timestamp(), Some(1000));
+    }
+
+    #[test]
+    fn test_warn() {
+        let recorder = Arc::new(Recorder::default());
+        let mut state = State::builder()
+            .out_of_order_timestamps(OutOfOrderPolicy::Warn, 100)
+            .build()
+            .unwrap();
+        state.register_observer(recorder.clone());
+
+        let txs = [
+            deposit(1, 1, Some(1000)),
+            // Within the tolerance.
+            deposit(1, 2, Some(900)),
+            deposit(1, 3, Some(899)),
+            // Only the timestamps of the same client count.
+            deposit(2, 4, Some(10)),
+        ];
+        for tx in txs {
+            state.execute(tx).unwrap();
+        }
+
+        assert_eq!(*recorder.0.lock().unwrap(), [(3, 1000)]);
+        assert_eq!(state.accounts[&1].available, 3.0);
+    }
+
+    #[test]
+    fn test_reject() {
+        let mut state = State::builder()
+            .out_of_order_timestamps(OutOfOrderPolicy::Reject, 0)
+            .build()
+            .unwrap();
+
+        state.execute(deposit(1, 1, Some(1000))).unwrap();
+        state.execute(deposit(1, 2, Some(1000))).unwrap();
+        assert_eq!(
+            state.execute(deposit(1, 3, Some(999))),
+            Err(TxError::OutOfOrderTimestamp {
+                timestamp: 999,
+                latest: 1000
+            })
+        );
+        assert_eq!(state.accounts[&1].available, 2.0);
+
+        // The latest timestamp is kept even if the transaction gets rejected for other reasons.
+        let withdrawal = Transaction::new(
+            TransactionType::Withdrawal {
+                amount: checked(5.0),
+            },
+            1,
+            4,
+        );
+        assert_eq!(
+            state.execute(withdrawal.with_timestamp(2000)),
+            Err(TxError::NotEnoughFunds)
+        );
+        assert_eq!(state.client_timestamp(1), Some(2000));
+        assert!(state.execute(deposit(1, 5, Some(1500))).is_err());
+    }
+
+    #[test]
+    fn test_mixed_input() {
+        let mut state = State::builder()
+            .out_of_order_timestamps(OutOfOrderPolicy::Reject, 0)
+            .build()
+            .unwrap();
+
+        state.execute(deposit(1, 1, None)).unwrap();
+        assert_eq!(state.client_timestamp(1), None);
+        state.execute(deposit(1, 2, Some(1000))).unwrap();
+        // Transactions without a timestamp are always accepted, and dont move the latest one.
+        state.execute(deposit(1, 3, None)).unwrap();
+        assert_eq!(state.client_timestamp(1), Some(1000));
+        assert!(state.execute(deposit(1, 4, Some(10))).is_err());
+        assert_eq!(state.accounts[&1].available, 3.0);
+    }
+
+    #[test]
+    fn test_timestamp_clock() {
+        let mut state = State::builder()
+            .clock(TimestampClock::default())
+            .dispute_window(DisputeWindow::Transactions(60_000))
+            .build()
+            .unwrap();
+
+        state.execute(deposit(1, 1, Some(1_000_000))).unwrap();
+        state.execute(deposit(1, 2, Some(1_030_000))).unwrap();
+        // Time stands still for transactions without a timestamp, however many there are.
+        for tx in 3..100 {
+            state.execute(deposit(1, tx, None)).unwrap();
+        }
+
+        state
+            .execute(Transaction::new(TransactionType::Dispute, 1, 1).with_timestamp(1_060_000))
+            .unwrap();
+        assert_eq!(
+            state.execute(
+                Transaction::new(TransactionType::Dispute, 1, 2).with_timestamp(1_090_001)
+            ),
+            Err(TxError::DisputeWindowExpired)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize() {
+        let data = "type,client,tx,amount,timestamp\n\
+                    deposit,1,1,1.0,1632486600250\n\
+                    deposit,1,2,1.0,2021-09-24T12:30:00.25Z\n\
+                    deposit,1,3,1.0,\n";
+        let txs: Vec<Transaction> = csv::Reader::from_reader(data.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(txs[0].timestamp(), Some(1632486600250));
+        assert_eq!(txs[1].timestamp(), Some(1632486600250));
+        assert_eq!(txs[2].timestamp(), None);
+
+        // Files without the column are still accepted.
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\n";
+        let tx: Transaction = csv::Reader::from_reader(data.as_bytes())
+            .deserialize()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(tx.timestamp(), None);
+
+        let data = "type,client,tx,amount,timestamp\ndeposit,1,1,1.0,yesterday\n";
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        assert!(rdr.deserialize::<Transaction>().next().unwrap().is_err());
+    }
+}
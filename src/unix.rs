@@ -0,0 +1,87 @@
+//! This module contains a Unix domain socket server writing the accounts of the state machine to
+//! every client that connects, built with the `unix-socket` feature.
+//!
+//! The server is a simple one-shot server: each connection receives a snapshot of every account as
+//! newline-delimited JSON, in ascending order of their `client` id, after which the connection is
+//! closed. Monitoring tools stream updates by reconnecting.
+
+use super::*;
+use std::fs;
+use std::io;
+use std::io::BufWriter;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Function will write every account into `writer` as newline-delimited JSON, in ascending
+    /// order of their `client` id. The writer isnt buffered.
+    ///
+    /// # Returns
+    /// Returns an error if writing fails.
+    pub fn write_accounts_ndjson<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for account in self.accounts_iter_by_id() {
+            serde_json::to_writer(&mut writer, account)?;
+            writer.write_all(b"\n")?;
+        }
+
+        writer.flush()
+    }
+
+    /// Function will serve the accounts over a Unix domain socket bound at `path`, writing them
+    /// to each client that connects with `write_accounts_ndjson` before closing the connection.
+    /// This blocks the calling thread for as long as the server runs.
+    ///
+    /// # Arguments
+    /// * `path` - Path the socket is bound at. A socket left behind by a previous server is
+    ///   replaced, while any other file makes binding fail.
+    ///
+    /// # Returns
+    /// Only returns if binding the socket or accepting a connection fails. Clients that hang up
+    /// early dont stop the server.
+    pub fn serve_unix(&self, path: &str) -> io::Result<()> {
+        let path = Path::new(path);
+        if fs::symlink_metadata(path).is_ok_and(|x| x.file_type().is_socket()) {
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        for stream in listener.incoming() {
+            if let Err(e) = self.write_accounts_ndjson(BufWriter::new(stream?)) {
+                log::debug!("Failed to write accounts to a unix socket client: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+
+    #[test]
+    fn test_write_accounts_ndjson() {
+        let mut state = State::default();
+        for (client, tx) in [(2, 1), (1, 2)] {
+            state
+                .execute(Transaction::new(
+                    TransactionType::Deposit {
+                        amount: checked(1.5),
+                    },
+                    client,
+                    tx,
+                ))
+                .unwrap();
+        }
+
+        let mut buf = Vec::new();
+        state.write_accounts_ndjson(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"client\":1,\"available\":1.5,\"held\":0.0,\"total\":1.5,\"locked\":false}\n\
+             {\"client\":2,\"available\":1.5,\"held\":0.0,\"total\":1.5,\"locked\":false}\n"
+        );
+    }
+}
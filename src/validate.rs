@@ -0,0 +1,329 @@
+//! This module contains the state-independent validation of transactions.
+
+use super::*;
+
+/// Struct contains the rules a transaction is validated against by `Transaction::validate`. The
+/// rules mirror the policies of the state machine, use `State::validation_rules` to get the rules
+/// matching a configured state machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationRules {
+    /// Whether disputes, resolves and chargebacks are accepted.
+    pub disputes: bool,
+    /// Maximum number of decimal places of deposit and withdrawal amounts, unlimited if `None`.
+    pub max_decimal_places: Option<u32>,
+    /// Whether deposits and withdrawals of a zero amount are rejected.
+    pub reject_zero_amounts: bool,
+    /// Whether transactions of client `0` are rejected.
+    pub reserve_zero_client: bool,
+    /// Tags of the custom transaction types that are accepted.
+    pub custom_types: HashSet<String>,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        Self {
+            disputes: true,
+            max_decimal_places: None,
+            reject_zero_amounts: false,
+            reserve_zero_client: false,
+            custom_types: HashSet::new(),
+        }
+    }
+}
+
+/// Enum represents the reason a transaction is structurally invalid.
+#[derive(Clone, Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("Amount is negative.")]
+    NegativeAmount,
+    #[error("Amount is not a finite number.")]
+    NonFiniteAmount,
+    #[error("Amount is zero.")]
+    ZeroAmount,
+    #[error("Amount has {places} decimal places, at most {max} are allowed.")]
+    ExcessivePrecision { places: u32, max: u32 },
+    #[error("Disputes are disabled.")]
+    DisputesDisabled,
+    #[error("Unsupported transaction type `{0}`.")]
+    UnsupportedType(String),
+    #[error("Client id 0 is reserved.")]
+    InvalidClient,
+}
+
+impl From<ValidationError> for TxError {
+    fn from(e: ValidationError) -> Self {
+        match e {
+            // NOTE: These predate validation and are kept for compatibility.
+            ValidationError::NegativeAmount | ValidationError::NonFiniteAmount => {
+                TxError::InternalError
+            }
+            ValidationError::DisputesDisabled => TxError::DisputesDisabled,
+            ValidationError::UnsupportedType(_) => TxError::UnsupportedType,
+            ValidationError::InvalidClient => TxError::InvalidClient,
+            ValidationError::ZeroAmount => TxError::ZeroAmount,
+            e => TxError::Invalid(e),
+        }
+    }
+}
+
+impl<A: Money> Transaction<A> {
+    /// Function will perform all the checks of `execute` that dont depend on the state of the
+    /// state machine. A transaction passing validation against the rules of a state machine can
+    /// only be rejected by it for stateful reasons, like missing funds or locked accounts.
+    ///
+    /// # Arguments
+    /// * `rules` - Rules to validate against.
+    pub fn validate(&self, rules: &ValidationRules) -> Result<(), ValidationError> {
+        self.validate_with(
+            rules.disputes,
+            rules.max_decimal_places,
+            rules.reject_zero_amounts,
+            rules.reserve_zero_client,
+            |tag| rules.custom_types.contains(tag),
+        )
+    }
+
+    /// Function will validate the transaction, looking custom transaction types up through
+    /// `is_supported`. Shared by `validate` and `execute` so that the two never diverge.
+    pub(crate) fn validate_with(
+        &self,
+        disputes: bool,
+        max_decimal_places: Option<u32>,
+        reject_zero_amounts: bool,
+        reserve_zero_client: bool,
+        is_supported: impl Fn(&str) -> bool,
+    ) -> Result<(), ValidationError> {
+        if reserve_zero_client && self.client == 0 {
+            return Err(ValidationError::InvalidClient);
+        }
+
+        // NOTE: Deposits and withdrawals carry an `Amount`, which has been checked when it was
+        // built.
+        let amount = match self.tx_type {
+            TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => {
+                amount.get()
+            }
+            TransactionType::Refund { amount, .. } | TransactionType::Hold { amount } => {
+                Amount::new(amount)?.get()
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                if !disputes {
+                    return Err(ValidationError::DisputesDisabled);
+                }
+
+                return Ok(());
+            }
+            TransactionType::Interest { rate_bps } => {
+                if reject_zero_amounts && rate_bps == 0 {
+                    return Err(ValidationError::ZeroAmount);
+                }
+
+                return Ok(());
+            }
+            TransactionType::Release { .. } => return Ok(()),
+            TransactionType::Other { ref tag, .. } => {
+                if !is_supported(tag) {
+                    return Err(ValidationError::UnsupportedType(tag.clone()));
+                }
+
+                return Ok(());
+            }
+        };
+
+        if reject_zero_amounts && amount == A::ZERO {
+            return Err(ValidationError::ZeroAmount);
+        }
+
+        if let Some(max) = max_decimal_places {
+            let places = amount.decimal_places();
+            if places > max {
+                return Err(ValidationError::ExcessivePrecision { places, max });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<H: BuildHasher, A: Money> State<H, A> {
+    /// Returns the validation rules matching the policies of the state machine.
+    pub fn validation_rules(&self) -> ValidationRules {
+        ValidationRules {
+            disputes: self.config.disputes,
+            max_decimal_places: self.config.max_decimal_places,
+            reject_zero_amounts: self.config.zero_amounts == ZeroAmountPolicy::Reject,
+            reserve_zero_client: self.config.reserve_zero_client,
+            custom_types: self.config.custom_handlers.keys().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::checked;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[derive(Debug)]
+    struct Noop;
+
+    impl CustomHandler for Noop {
+        fn handle(&self, _: &CustomRecord, _: &mut AccountHandle) -> Result<(), TxError> {
+            Ok(())
+        }
+    }
+
+    fn deposit(amount: f64) -> Transaction {
+        Transaction::new(
+            TransactionType::Deposit {
+                amount: checked(amount),
+            },
+            1,
+            1,
+        )
+    }
+
+    #[test]
+    fn test_validate() {
+        let rules = ValidationRules::default();
+        assert_eq!(deposit(1.23456).validate(&rules), Ok(()));
+        assert_eq!(deposit(0.0).validate(&rules), Ok(()));
+
+        let hold = |amount| Transaction::new(TransactionType::Hold { amount }, 1, 1);
+        assert_eq!(
+            hold(-1.0).validate(&rules),
+            Err(ValidationError::NegativeAmount)
+        );
+        assert_eq!(
+            hold(f64::NAN).validate(&rules),
+            Err(ValidationError::NonFiniteAmount)
+        );
+
+        let rules = ValidationRules {
+            disputes: false,
+            max_decimal_places: Some(4),
+            reject_zero_amounts: true,
+            reserve_zero_client: false,
+            custom_types: HashSet::from(["bonus".to_string()]),
+        };
+        assert_eq!(deposit(1.2345).validate(&rules), Ok(()));
+        assert_eq!(
+            deposit(1.23456).validate(&rules),
+            Err(ValidationError::ExcessivePrecision { places: 5, max: 4 })
+        );
+        assert_eq!(
+            deposit(0.0).validate(&rules),
+            Err(ValidationError::ZeroAmount)
+        );
+        assert_eq!(
+            Transaction::<f64>::new(TransactionType::Resolve, 1, 1).validate(&rules),
+            Err(ValidationError::DisputesDisabled)
+        );
+
+        let other = |tag: &str| {
+            Transaction::<f64>::new(
+                TransactionType::Other {
+                    tag: tag.into(),
+                    amount: None,
+                },
+                1,
+                1,
+            )
+        };
+        assert_eq!(other("bonus").validate(&rules), Ok(()));
+        assert_eq!(
+            other("transfer").validate(&rules),
+            Err(ValidationError::UnsupportedType("transfer".into()))
+        );
+    }
+
+    #[test]
+    fn test_validation_rules() {
+        let state = State::builder()
+            .disputes(false)
+            .max_decimal_places(4)
+            .reject_zero_amounts(true)
+            .reserve_zero_client(true)
+            .custom_handler("bonus", Noop)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            state.validation_rules(),
+            ValidationRules {
+                disputes: false,
+                max_decimal_places: Some(4),
+                reject_zero_amounts: true,
+                reserve_zero_client: true,
+                custom_types: HashSet::from(["bonus".to_string()]),
+            }
+        );
+    }
+
+    /// Transactions passing validation are only ever rejected for stateful reasons, and
+    /// transactions failing it are rejected by `execute` with the same error.
+    #[test]
+    fn test_execute_agrees_with_validate() {
+        let mut rng = StdRng::seed_from_u64(0xDEC1DE);
+
+        for _ in 0..20 {
+            let mut builder = State::builder()
+                .disputes(rng.gen())
+                .reject_zero_amounts(rng.gen())
+                .reserve_zero_client(rng.gen())
+                .custom_handler("bonus", Noop);
+            if rng.gen() {
+                builder = builder.max_decimal_places(rng.gen_range(0..=4));
+            }
+
+            let mut state = builder.build().unwrap();
+            let rules = state.validation_rules();
+
+            for _ in 0..500 {
+                let amount = match rng.gen_range(0..6) {
+                    0 => 0.0,
+                    1 => -1.0,
+                    2 => f64::NAN,
+                    3 => rng.gen_range(0..100) as f64 / 4.0,
+                    _ => rng.gen::<f64>() * 100.0,
+                };
+                // NOTE: Invalid amounts cannot be carried by deposits and withdrawals, so they
+                // are put on hold instead.
+                let tx_type = match (rng.gen_range(0..8), Amount::new(amount)) {
+                    (0 | 1, Ok(amount)) => TransactionType::Deposit { amount },
+                    (2, Ok(amount)) => TransactionType::Withdrawal { amount },
+                    (0..=2 | 7, _) => TransactionType::Hold { amount },
+                    (3, _) => TransactionType::Dispute,
+                    (4, _) => TransactionType::Resolve,
+                    (5, _) => TransactionType::Chargeback,
+                    _ => TransactionType::Other {
+                        tag: ["bonus", "transfer"][rng.gen_range(0..2)].into(),
+                        amount: None,
+                    },
+                };
+                let tx = Transaction::new(tx_type, rng.gen_range(0..4), rng.gen_range(0..50));
+
+                match (tx.validate(&rules), state.execute(tx.clone())) {
+                    (Err(e), result) => assert_eq!(result, Err(e.into()), "{:?}", tx),
+                    (Ok(()), Err(e)) => assert!(
+                        matches!(
+                            e,
+                            TxError::NotEnoughFunds
+                                | TxError::TxDoesntExist
+                                | TxError::Unauthorized
+                                | TxError::TxAlreadyDisputed
+                                | TxError::TxNotUnderDispute
+                                | TxError::AccountLocked
+                                | TxError::DuplicateTx
+                        ),
+                        "{:?} {:?}",
+                        tx,
+                        e
+                    ),
+                    (Ok(()), Ok(())) => {}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! This module contains the JavaScript bindings of the state machine, built with the `wasm`
+//! feature.
+//!
+//! Amounts cross the boundary as strings, both ways, so that they never get rounded by JavaScript
+//! numbers.
+
+use super::*;
+use serde::Serialize;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+/// Struct represents an account as returned to JavaScript.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AccountView {
+    pub client: u16,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+}
+
+/// Struct represents a skipped transaction as returned to JavaScript.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RejectionView {
+    /// Zero based position of the transaction among all the transactions fed to the engine.
+    pub position: usize,
+    /// The id of the client, unless the transaction couldnt be parsed.
+    pub client: Option<u16>,
+    /// The id of the transaction, unless the transaction couldnt be parsed.
+    pub tx: Option<u32>,
+    pub error: String,
+}
+
+/// Struct represents a state machine that can be driven from JavaScript.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Engine {
+    state: State,
+    rejections: Vec<RejectionView>,
+    /// Number of transactions fed to the engine so far.
+    fed: usize,
+}
+
+impl Engine {
+    /// Function will execute all the transactions of `txs`, recording the ones that were skipped.
+    fn feed<E: fmt::Display>(&mut self, txs: impl Iterator<Item = Result<Transaction, E>>) {
+        for tx in txs {
+            let position = self.fed;
+            self.fed += 1;
+
+            let rejection = match tx {
+                Ok(tx) => {
+                    let (client, id) = (tx.client, tx.tx);
+                    match self.state.execute(tx) {
+                        Ok(()) => continue,
+                        Err(e) => RejectionView {
+                            position,
+                            client: Some(client),
+                            tx: Some(id),
+                            error: e.to_string(),
+                        },
+                    }
+                }
+                Err(e) => RejectionView {
+                    position,
+                    client: None,
+                    tx: None,
+                    error: e.to_string(),
+                },
+            };
+
+            self.rejections.push(rejection);
+        }
+    }
+
+    /// Function will execute all the transactions of a CSV document with a header row.
+    fn feed_csv(&mut self, csv: &str) {
+        let rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(csv.as_bytes());
+
+        self.feed(rdr.into_deserialize());
+    }
+
+    /// Returns all the accounts in ascending order of their `id`.
+    fn account_views(&self) -> Vec<AccountView> {
+        self.state
+            .accounts_iter_by_id()
+            .map(|x| AccountView {
+                client: x.id,
+                available: x.available.to_string(),
+                held: x.held.to_string(),
+                total: x.total.to_string(),
+                locked: x.locked,
+            })
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+impl Engine {
+    /// Function will construct an engine with the default policies.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Function will execute all the transactions of a CSV document with a header row. Rows that
+    /// fail to parse or execute are recorded as rejections.
+    #[wasm_bindgen(js_name = executeCsv)]
+    pub fn execute_csv(&mut self, csv: &str) {
+        self.feed_csv(csv);
+    }
+
+    /// Function will execute an array of transaction objects shaped like the rows of a CSV
+    /// document, e.g. `{ type: "deposit", client: 1, tx: 1, amount: "1.5" }`.
+    ///
+    /// # Returns
+    /// Throws if `txs` isnt an array. Elements that fail to parse or execute are recorded as
+    /// rejections.
+    #[wasm_bindgen(js_name = executeTransactions)]
+    pub fn execute_transactions(&mut self, txs: Vec<JsValue>) {
+        self.feed(txs.into_iter().map(serde_wasm_bindgen::from_value));
+    }
+
+    /// Returns an array of all the accounts, ordered by client id.
+    pub fn accounts(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.account_views())?)
+    }
+
+    /// Returns an array of all the transactions that were skipped, in the order they were fed.
+    pub fn rejections(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.rejections)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_engine() {
+        let mut engine = Engine::new();
+        engine.feed_csv(
+            "type,client,tx,amount\n\
+             deposit,2,1,1.5\n\
+             deposit,1,2,0.1\n\
+             deposit,1,3,0.2\n\
+             withdrawal,2,4,5.0\n\
+             deposit,x,5,1.0\n",
+        );
+        engine.feed_csv("type,client,tx,amount\ndispute,2,1,\n");
+
+        assert_eq!(
+            engine.account_views(),
+            vec![
+                AccountView {
+                    client: 1,
+                    available: "0.30000000000000004".into(),
+                    held: "0".into(),
+                    total: "0.30000000000000004".into(),
+                    locked: false,
+                },
+                AccountView {
+                    client: 2,
+                    available: "0".into(),
+                    held: "1.5".into(),
+                    total: "1.5".into(),
+                    locked: false,
+                },
+            ]
+        );
+
+        assert_eq!(engine.rejections.len(), 2);
+        assert_eq!(
+            engine.rejections[0],
+            RejectionView {
+                position: 3,
+                client: Some(2),
+                tx: Some(4),
+                error: TxError::NotEnoughFunds.to_string(),
+            }
+        );
+        assert_eq!(engine.rejections[1].position, 4);
+        assert_eq!(engine.rejections[1].client, None);
+    }
+}
@@ -0,0 +1,356 @@
+#![cfg(feature = "serde")]
+
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn test_errors_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("transactions.csv");
+    let errors = dir.path().join("errors.csv");
+
+    fs::write(
+        &input,
+        "type, client, tx, amount\n\
+         deposit, 1, 1, 10.0\n\
+         withdrawal, 1, 2, 50.0\n\
+         deposit, x, 3, 1.0\n\
+         dispute, 2, 1,\n\
+         deposit, 2, 4, 2.5\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .arg(&input)
+        .arg("--errors-out")
+        .arg(&errors)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let mut accounts = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(String::from)
+        .collect::<Vec<_>>();
+    accounts.sort();
+    assert_eq!(accounts, ["1,10.0,0.0,10.0,false", "2,2.5,0.0,2.5,false"]);
+
+    let errors = fs::read_to_string(errors).unwrap();
+    let rows = errors.lines().collect::<Vec<_>>();
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[0], "client,tx,type,error");
+    assert_eq!(rows[1], "1,2,withdrawal,Account doesnt have enough funds");
+    assert!(rows[2].starts_with(",,,"), "{}", rows[2]);
+    assert_eq!(
+        rows[3],
+        "2,1,dispute,Cannot dispute tx that the client doesnt own."
+    );
+}
+
+#[test]
+fn test_without_errors_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("transactions.csv");
+    fs::write(&input, "type, client, tx, amount\nwithdrawal, 1, 1, 5.0\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Skipped transaction #0: withdrawal 1 of client 1 rejected"));
+}
+
+#[test]
+fn test_status_column() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("transactions.csv");
+    fs::write(
+        &input,
+        "type, client, tx, amount\n\
+         deposit, 1, 1, 10.0\n\
+         dispute, 1, 1,\n\
+         chargeback, 1, 1,\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .arg(&input)
+        .arg("--status-column")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,status\n1,0.0,0.0,0.0,frozen\n"
+    );
+}
+
+#[test]
+fn test_multiple_inputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.csv");
+    let second = dir.path().join("second.csv");
+    fs::write(&first, "type, client, tx, amount\ndeposit, 1, 1, 10.0\n").unwrap();
+    fs::write(
+        &second,
+        "type, client, tx, amount\ndeposit, 1, 1, 12.0\nwithdrawal, 1, 2, 50.0\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .arg(&first)
+        .arg(&second)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked\n1,22.0,0.0,22.0,false\n"
+    );
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!(
+        "Conflicting transaction tx 1 is a deposit of 10 for client 1 at {} #0 but also a \
+         deposit of 12 for client 1 at {} #0",
+        first.display(),
+        second.display()
+    )));
+    assert!(stderr.contains(&format!(
+        "Skipped transaction of {} #1: withdrawal 2 of client 1 rejected",
+        second.display()
+    )));
+}
+
+#[test]
+fn test_hold_columns() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("transactions.csv");
+    fs::write(
+        &input,
+        "type, client, tx, amount\n\
+         deposit, 1, 1, 10.0\n\
+         deposit, 1, 2, 2.5\n\
+         dispute, 1, 2,\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .arg(&input)
+        .arg("--hold-columns")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,pending_hold,disputed_hold,held,total,locked\n\
+         1,10.0,0.0,2.5,2.5,12.5,false\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .arg(&input)
+        .arg("--hold-columns")
+        .arg("--status-column")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_base_currency() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("transactions.csv");
+    fs::write(
+        &input,
+        "type, client, tx, amount, currency\n\
+         deposit, 1, 1, 10.0,\n\
+         deposit, 1, 2, 2.5, EUR\n\
+         dispute, 1, 2,,\n\
+         withdrawal, 2, 3, 1.0, GBP\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .arg(&input)
+        .arg("--base-currency")
+        .arg("usd")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,currency,available,held,total,locked\n\
+         1,EUR,0.00,2.50,2.50,false\n\
+         1,USD,10.00,0.00,10.00,false\n"
+    );
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("withdrawal 3 of client 2 rejected"));
+}
+
+#[test]
+fn test_daily_snapshots() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("transactions.csv");
+    let snapshots = dir.path().join("snapshots");
+    fs::write(
+        &input,
+        "type, client, tx, amount, timestamp\n\
+         deposit, 1, 1, 10.0, 2021-09-24T08:00:00Z\n\
+         deposit, 2, 2, 5.0,\n\
+         withdrawal, 1, 3, 4.0, 2021-09-25T10:00:00Z\n\
+         dispute, 2, 2,, 1632744000000\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .arg(&input)
+        .arg("--daily-snapshots")
+        .arg(&snapshots)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let mut files: Vec<_> = fs::read_dir(&snapshots)
+        .unwrap()
+        .map(|x| x.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        ["2021-09-24.csv", "2021-09-25.csv", "2021-09-27.csv"]
+    );
+
+    let read = |name| fs::read_to_string(snapshots.join(name)).unwrap();
+    assert_eq!(
+        read("2021-09-24.csv"),
+        "client,available,held,total,locked\n\
+         1,10.0,0.0,10.0,false\n\
+         2,5.0,0.0,5.0,false\n"
+    );
+    assert_eq!(
+        read("2021-09-25.csv"),
+        "client,available,held,total,locked\n\
+         1,6.0,0.0,6.0,false\n\
+         2,5.0,0.0,5.0,false\n"
+    );
+    assert_eq!(
+        read("2021-09-27.csv"),
+        "client,available,held,total,locked\n\
+         1,6.0,0.0,6.0,false\n\
+         2,0.0,5.0,5.0,false\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .arg(&input)
+        .arg(&input)
+        .arg("--daily-snapshots")
+        .arg(&snapshots)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[cfg(feature = "schemars")]
+#[test]
+fn test_schema() {
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .args(["schema", "--type", "transaction"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(schema["title"], "Transaction");
+    assert_eq!(
+        schema["properties"]["type"]["enum"],
+        serde_json::json!([
+            "deposit",
+            "withdrawal",
+            "dispute",
+            "resolve",
+            "chargeback",
+            "interest",
+            "refund",
+            "hold",
+            "release"
+        ])
+    );
+    assert_eq!(
+        schema["required"],
+        serde_json::json!(["client", "tx", "type"])
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_corken"))
+        .args(["schema", "--type", "ledger"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[cfg(feature = "json-output")]
+#[test]
+fn test_output_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("transactions.csv");
+    fs::write(
+        &input,
+        "type, client, tx, amount\n\
+         deposit, 1, 1, 10.0\n\
+         deposit, 2, 2, 2.5\n\
+         withdrawal, 1, 3, 4.0\n",
+    )
+    .unwrap();
+
+    let run = |format: &str| {
+        Command::new(env!("CARGO_BIN_EXE_corken"))
+            .arg(&input)
+            .args(["--output-format", format])
+            .output()
+            .unwrap()
+    };
+
+    // Accounts are written in no particular order.
+    let output = run("csv");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines: Vec<&str> = stdout.lines().collect();
+    lines[1..].sort_unstable();
+    assert_eq!(
+        lines,
+        [
+            "client,available,held,total,locked",
+            "1,6.0,0.0,6.0,false",
+            "2,2.5,0.0,2.5,false"
+        ]
+    );
+
+    let output = run("json");
+    assert!(output.status.success());
+    let mut accounts: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+    accounts.sort_by_key(|x| x["client"].as_u64());
+    assert_eq!(
+        accounts,
+        [
+            serde_json::json!({"client": 1, "available": 6.0, "held": 0.0, "total": 6.0, "locked": false}),
+            serde_json::json!({"client": 2, "available": 2.5, "held": 0.0, "total": 2.5, "locked": false}),
+        ]
+    );
+
+    let output = run("jsonl");
+    assert!(output.status.success());
+    let mut lines: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|x| serde_json::from_str(x).unwrap())
+        .collect();
+    lines.sort_by_key(|x| x["client"].as_u64());
+    assert_eq!(lines, accounts);
+
+    let output = run("xml");
+    assert!(!output.status.success());
+}
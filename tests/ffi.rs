@@ -0,0 +1,51 @@
+#![cfg(all(feature = "ffi", target_os = "linux"))]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Returns the directory the `corken` cdylib gets built to.
+fn lib_dir() -> PathBuf {
+    // NOTE: Integration tests are built next to the cdylib in `target/<profile>/deps`. The copy in
+    // `target/<profile>` is only refreshed by `cargo build`, so it can lack the `ffi` feature.
+    let mut dir = std::env::current_exe().unwrap();
+    dir.pop();
+    dir
+}
+
+#[test]
+fn test_c_program() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let tmp = tempfile::tempdir().unwrap();
+    let exe = tmp.path().join("test");
+    let csv = tmp.path().join("accounts.csv");
+
+    let status = Command::new("cc")
+        .arg(root.join("tests/ffi/test.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-L")
+        .arg(lib_dir())
+        .arg("-lcorken")
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success());
+
+    let output = Command::new(&exe)
+        .arg(&csv)
+        .env("LD_LIBRARY_PATH", lib_dir())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"ok\n");
+
+    assert_eq!(
+        std::fs::read_to_string(csv).unwrap(),
+        "client,available,held,total,locked\n1,0.0,0.0,0.0,true\n2,5.0,0.0,5.0,false\n"
+    );
+}
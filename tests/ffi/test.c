@@ -0,0 +1,44 @@
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "corken.h"
+
+int main(int argc, char **argv) {
+  assert(argc == 2);
+
+  corken_state_t *state = corken_state_new();
+  assert(state != NULL);
+
+  corken_error_t error;
+  assert(corken_execute(state, CORKEN_DEPOSIT, 1, 1, 10.0, &error));
+  assert(error == CORKEN_OK);
+  assert(corken_execute(state, CORKEN_DEPOSIT, 2, 2, 5.0, NULL));
+  assert(!corken_execute(state, CORKEN_WITHDRAWAL, 2, 3, 50.0, &error));
+  assert(error == CORKEN_NOT_ENOUGH_FUNDS);
+  assert(corken_execute(state, CORKEN_DISPUTE, 1, 1, 0.0, &error));
+  assert(corken_execute(state, CORKEN_CHARGEBACK, 1, 1, 0.0, &error));
+  assert(!corken_execute(state, CORKEN_DEPOSIT, 1, 4, 1.0, &error));
+  assert(error == CORKEN_ACCOUNT_LOCKED);
+  assert(!corken_execute(state, 42, 1, 5, 1.0, &error));
+  assert(error == CORKEN_INVALID_ARGUMENT);
+  assert(!corken_execute(NULL, CORKEN_DEPOSIT, 1, 6, 1.0, &error));
+  assert(error == CORKEN_NULL_POINTER);
+
+  corken_account_t account;
+  assert(corken_account_get(state, 1, &account) == CORKEN_OK);
+  assert(account.client == 1 && account.total == 0.0 && account.locked);
+  assert(corken_account_get(state, 2, &account) == CORKEN_OK);
+  assert(account.available == 5.0 && account.held == 0.0 && !account.locked);
+  assert(corken_account_get(state, 3, &account) == CORKEN_ACCOUNT_NOT_FOUND);
+  assert(corken_account_get(state, 1, NULL) == CORKEN_NULL_POINTER);
+
+  assert(corken_accounts_export_csv(state, argv[1]) == CORKEN_OK);
+  assert(corken_accounts_export_csv(state, NULL) == CORKEN_NULL_POINTER);
+
+  corken_state_free(state);
+  corken_state_free(NULL);
+
+  puts("ok");
+  return 0;
+}
@@ -0,0 +1,147 @@
+#![cfg(feature = "grpc")]
+
+use std::sync::{Arc, Mutex};
+
+use corken::grpc::EngineService;
+use corken::proto::engine_client::EngineClient;
+use corken::proto::{execute_result, Account, ClientId, Transaction, TransactionType};
+use corken::State;
+use hyper_util::rt::TokioIo;
+use tokio_stream::StreamExt;
+use tonic::transport::{Channel, Endpoint, Server};
+use tonic::Code;
+
+/// Function will serve a fresh state machine over an in-process duplex stream, returning a client
+/// connected to it.
+async fn connect() -> EngineClient<Channel> {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+
+    let service = EngineService::new(Arc::new(Mutex::new(State::default())));
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(service.into_server())
+            .serve_with_incoming(tokio_stream::once(Ok::<_, std::io::Error>(server)))
+            .await
+            .unwrap();
+    });
+
+    // NOTE: The address is never resolved, the connector hands out the duplex stream instead.
+    let mut client = Some(client);
+    let channel = Endpoint::from_static("http://[::]:50051")
+        .connect_with_connector(tower::service_fn(move |_| {
+            let client = client.take();
+            async move {
+                client
+                    .map(TokioIo::new)
+                    .ok_or_else(|| std::io::Error::other("client already taken"))
+            }
+        }))
+        .await
+        .unwrap();
+
+    EngineClient::new(channel)
+}
+
+fn tx(kind: TransactionType, client: u32, tx: u32, amount: Option<&str>) -> Transaction {
+    Transaction {
+        r#type: kind.into(),
+        client: Some(client),
+        tx: Some(tx),
+        amount: amount.map(String::from),
+        tag: None,
+        of_tx: None,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_dispute_lifecycle() {
+    let mut client = connect().await;
+
+    let result = client
+        .execute(tx(TransactionType::Deposit, 1, 1, Some("10.5")))
+        .await
+        .unwrap()
+        .into_inner();
+    match result.result {
+        Some(execute_result::Result::Account(account)) => assert_eq!(account.available, "10.5"),
+        x => panic!("unexpected result {:?}", x),
+    }
+
+    let summary = client
+        .execute_stream(tokio_stream::iter(vec![
+            tx(TransactionType::Deposit, 2, 2, Some("4")),
+            tx(TransactionType::Withdrawal, 2, 3, Some("100")),
+            tx(TransactionType::Deposit, 2, 4, None),
+            tx(TransactionType::Dispute, 1, 1, None),
+            tx(TransactionType::Chargeback, 1, 1, None),
+        ]))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!((summary.executed, summary.rejected), (3, 2));
+
+    let result = client
+        .execute(tx(TransactionType::Deposit, 1, 5, Some("1")))
+        .await
+        .unwrap()
+        .into_inner();
+    match result.result {
+        Some(execute_result::Result::Rejection(rejection)) => {
+            assert_eq!(rejection.code, "AccountLocked")
+        }
+        x => panic!("unexpected result {:?}", x),
+    }
+
+    let account = client
+        .get_account(ClientId { client: 1 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(account.locked);
+    assert_eq!(account.total, "0");
+
+    let accounts: Vec<Account> = client
+        .list_accounts(())
+        .await
+        .unwrap()
+        .into_inner()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert_eq!(
+        accounts,
+        [
+            Account {
+                client: 1,
+                available: "0".into(),
+                held: "0".into(),
+                total: "0".into(),
+                locked: true,
+            },
+            Account {
+                client: 2,
+                available: "4".into(),
+                held: "0".into(),
+                total: "4".into(),
+                locked: false,
+            },
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_account_errors() {
+    let mut client = connect().await;
+
+    let status = client
+        .get_account(ClientId { client: 7 })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let status = client
+        .get_account(ClientId { client: 70000 })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
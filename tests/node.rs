@@ -0,0 +1,31 @@
+#![cfg(all(feature = "node", target_os = "linux"))]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn test_node_suite() {
+    // NOTE: Integration tests are built next to the cdylib in `target/<profile>/deps`.
+    let mut lib = std::env::current_exe().unwrap();
+    lib.set_file_name("libcorken.so");
+
+    // Node only loads addons with a `.node` extension.
+    let tmp = tempfile::tempdir().unwrap();
+    let module = tmp.path().join("corken.node");
+    fs::copy(&lib, &module).unwrap();
+
+    let output = Command::new("node")
+        .arg("--test")
+        .arg(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("node/test/"))
+        .env("CORKEN_NODE_MODULE", &module)
+        .output()
+        .expect("failed to run node");
+
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
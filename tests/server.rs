@@ -0,0 +1,200 @@
+#![cfg(feature = "server")]
+
+use std::sync::{Arc, Mutex};
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use corken::server::{self, DisputeView, ErrorBody, SharedState, TxResult};
+use corken::State;
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+/// Function will send a request to `router`, returning the status and the JSON body of the
+/// response.
+async fn send(
+    router: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    let request = match body {
+        Some(body) => request.body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap();
+
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn shared(state: State) -> SharedState {
+    Arc::new(Mutex::new(state))
+}
+
+#[tokio::test]
+async fn test_dispute_lifecycle() {
+    let router = server::router(shared(State::default()));
+
+    let (status, body) = send(&router, "GET", "/healthz", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "status": "ok" }));
+
+    let (status, body) = send(
+        &router,
+        "POST",
+        "/transactions",
+        Some(json!({ "type": "deposit", "client": 1, "tx": 1, "amount": "10.5" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let result: TxResult = serde_json::from_value(body).unwrap();
+    assert_eq!((result.status.as_str(), result.client), ("ok", Some(1)));
+
+    let (status, body) = send(
+        &router,
+        "POST",
+        "/transactions",
+        Some(json!([
+            { "type": "deposit", "client": 1, "tx": 2, "amount": 4.5 },
+            { "type": "withdrawal", "client": 1, "tx": 3, "amount": 100 },
+            { "type": "deposit", "client": 1, "tx": 4 },
+            { "type": "dispute", "client": 1, "tx": 50, "ref_tx": 1 },
+        ])),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let results: Vec<TxResult> = serde_json::from_value(body).unwrap();
+    let statuses: Vec<_> = results.iter().map(|x| x.status.as_str()).collect();
+    assert_eq!(statuses, ["ok", "rejected", "rejected", "ok"]);
+    assert_eq!(results[1].error.as_ref().unwrap().code, "NotEnoughFunds");
+    assert_eq!(results[1].tx, Some(3));
+    assert_eq!(results[2].error.as_ref().unwrap().code, "InvalidRecord");
+    assert_eq!(results[2].tx, None);
+    assert_eq!((results[3].tx, results[3].event_id), (Some(1), Some(50)));
+
+    let (_, body) = send(&router, "GET", "/disputes", None).await;
+    let disputes: Vec<DisputeView> = serde_json::from_value(body).unwrap();
+    assert_eq!(
+        disputes,
+        [DisputeView {
+            client: 1,
+            tx: 1,
+            amount: 10.5,
+            event_id: Some(50),
+        }]
+    );
+
+    let (status, body) = send(&router, "GET", "/accounts/1", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({ "client": 1, "available": 4.5, "held": 10.5, "total": 15.0, "locked": false })
+    );
+
+    // A dispute cannot be opened twice.
+    let (status, body) = send(
+        &router,
+        "POST",
+        "/transactions",
+        Some(json!({ "type": "dispute", "client": 1, "tx": 1 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let result: TxResult = serde_json::from_value(body).unwrap();
+    assert_eq!(result.error.unwrap().code, "TxAlreadyDisputed");
+
+    let (status, _) = send(
+        &router,
+        "POST",
+        "/transactions",
+        Some(json!({ "type": "chargeback", "client": 1, "tx": 1 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = send(&router, "GET", "/disputes", None).await;
+    assert_eq!(body, json!([]));
+
+    let (_, body) = send(
+        &router,
+        "POST",
+        "/transactions",
+        Some(json!({ "type": "deposit", "client": 1, "tx": 5, "amount": 1 })),
+    )
+    .await;
+    let result: TxResult = serde_json::from_value(body).unwrap();
+    assert_eq!(result.error.unwrap().code, "AccountLocked");
+
+    let (_, body) = send(&router, "GET", "/accounts", None).await;
+    assert_eq!(
+        body,
+        json!([{ "client": 1, "available": 4.5, "held": 0.0, "total": 4.5, "locked": true }])
+    );
+}
+
+#[tokio::test]
+async fn test_request_errors() {
+    let router = server::router(shared(State::default()));
+
+    let (status, body) = send(&router, "GET", "/accounts/7", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let error: ErrorBody = serde_json::from_value(body).unwrap();
+    assert_eq!(error.code, "AccountNotFound");
+
+    let (status, body) = send(&router, "GET", "/accounts/70000", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let error: ErrorBody = serde_json::from_value(body).unwrap();
+    assert_eq!(error.code, "InvalidClient");
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/transactions")
+        .header("content-type", "application/json")
+        .body(Body::from("{\"type\": "))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let error: ErrorBody = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(error.code, "InvalidJson");
+
+    let (status, body) = send(&router, "POST", "/transactions", Some(json!(42))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let error: ErrorBody = serde_json::from_value(body).unwrap();
+    assert_eq!(error.code, "InvalidBody");
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.bin");
+
+    let state = shared(State::default());
+    let router = server::router(state.clone());
+    send(
+        &router,
+        "POST",
+        "/transactions",
+        Some(json!({ "type": "deposit", "client": 3, "tx": 1, "amount": 2.5 })),
+    )
+    .await;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    server::serve(listener, state, Some(&path), async {})
+        .await
+        .unwrap();
+
+    let restored = State::load_checkpoint_from_file(&path).unwrap();
+    let accounts: Vec<_> = restored.accounts().collect();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!((accounts[0].id(), accounts[0].total()), (3, 2.5));
+}
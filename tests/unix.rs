@@ -0,0 +1,71 @@
+#![cfg(all(unix, feature = "unix-socket"))]
+
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
+
+use corken::money::Amount;
+use corken::{State, Transaction, TransactionType};
+use serde_json::{json, Value};
+
+/// Function will connect to the socket at `path`, retrying until the server has bound it, and
+/// return every line written by the server.
+fn read_accounts(path: &str) -> Vec<Value> {
+    let stream = (0..100)
+        .find_map(|_| {
+            UnixStream::connect(path)
+                .map_err(|_| thread::sleep(Duration::from_millis(20)))
+                .ok()
+        })
+        .expect("server never bound the socket");
+
+    BufReader::new(stream)
+        .lines()
+        .map(|x| serde_json::from_str(&x.unwrap()).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_serve_unix() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("corken.sock");
+    let path = path.to_str().unwrap().to_string();
+
+    let mut state = State::default();
+    let txs = [
+        (
+            TransactionType::Deposit {
+                amount: Amount::new(10.0).unwrap(),
+            },
+            3,
+            1,
+        ),
+        (
+            TransactionType::Deposit {
+                amount: Amount::new(2.5).unwrap(),
+            },
+            1,
+            2,
+        ),
+        (TransactionType::Dispute, 1, 2),
+    ];
+    for (tx_type, client, tx) in txs {
+        state
+            .execute(Transaction::new(tx_type, client, tx))
+            .unwrap();
+    }
+
+    // NOTE: The server never returns, so its thread is left running until the test exits.
+    let socket = path.clone();
+    thread::spawn(move || state.serve_unix(&socket).unwrap());
+
+    let expected = [
+        json!({"client": 1, "available": 0.0, "held": 2.5, "total": 2.5, "locked": false}),
+        json!({"client": 3, "available": 10.0, "held": 0.0, "total": 10.0, "locked": false}),
+    ];
+
+    // Every client gets its own snapshot.
+    assert_eq!(read_accounts(&path), expected);
+    assert_eq!(read_accounts(&path), expected);
+}
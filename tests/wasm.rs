@@ -0,0 +1,55 @@
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use corken::wasm::Engine;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::*;
+
+/// Returns `field` of the `index`th element of a JavaScript array.
+fn get(array: &JsValue, index: u32, field: &str) -> JsValue {
+    let element = js_sys::Reflect::get_u32(array, index).unwrap();
+    js_sys::Reflect::get(&element, &field.into()).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn test_execute_csv() {
+    let mut engine = Engine::new();
+    engine.execute_csv("type,client,tx,amount\ndeposit,1,1,1.5\nwithdrawal,1,2,5.0\n");
+
+    let accounts = engine.accounts().unwrap();
+    assert_eq!(js_sys::Array::from(&accounts).length(), 1);
+    assert_eq!(get(&accounts, 0, "client"), JsValue::from(1));
+    assert_eq!(get(&accounts, 0, "available"), JsValue::from("1.5"));
+
+    let rejections = engine.rejections().unwrap();
+    assert_eq!(js_sys::Array::from(&rejections).length(), 1);
+    assert_eq!(get(&rejections, 0, "tx"), JsValue::from(2));
+}
+
+#[wasm_bindgen_test]
+fn test_execute_transactions() {
+    let tx = |tag: &str, tx: u32, amount: Option<&str>| {
+        let object = js_sys::Object::new();
+        js_sys::Reflect::set(&object, &"type".into(), &tag.into()).unwrap();
+        js_sys::Reflect::set(&object, &"client".into(), &1.into()).unwrap();
+        js_sys::Reflect::set(&object, &"tx".into(), &tx.into()).unwrap();
+        if let Some(amount) = amount {
+            js_sys::Reflect::set(&object, &"amount".into(), &amount.into()).unwrap();
+        }
+        JsValue::from(object)
+    };
+
+    let mut engine = Engine::new();
+    engine.execute_transactions(vec![
+        tx("deposit", 1, Some("2.25")),
+        tx("dispute", 1, None),
+        tx("teleport", 2, Some("1.0")),
+    ]);
+
+    let accounts = engine.accounts().unwrap();
+    assert_eq!(get(&accounts, 0, "held"), JsValue::from("2.25"));
+    assert_eq!(get(&accounts, 0, "total"), JsValue::from("2.25"));
+
+    let rejections = engine.rejections().unwrap();
+    assert_eq!(js_sys::Array::from(&rejections).length(), 1);
+    assert_eq!(get(&rejections, 0, "position"), JsValue::from(2));
+}